@@ -0,0 +1,6 @@
+CREATE TABLE oidc_login_requests(
+  state varchar(100) primary key,
+  code_verifier varchar(200) not null,
+  nonce varchar(100) not null,
+  date_created timestamp without time zone not null
+);
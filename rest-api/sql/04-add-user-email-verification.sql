@@ -0,0 +1,6 @@
+-- Existing users are considered verified, new users start out unverified until they confirm their email address.
+ALTER TABLE users ADD COLUMN email_verified boolean not null default true;
+ALTER TABLE users ALTER COLUMN email_verified SET DEFAULT false;
+
+-- Make sure to remove duplicate users before applying this index, otherwise it can't be created.
+CREATE UNIQUE INDEX users_email_address_key ON users (lower(email_address));
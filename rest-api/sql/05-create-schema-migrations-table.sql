@@ -0,0 +1,14 @@
+-- Keeps track of the migrations that were applied to the database, so the readiness check can report pending ones.
+-- Every new migration must insert its own name into this table and must be added to `db::MIGRATIONS`.
+CREATE TABLE schema_migrations (
+    name varchar(250) primary key,
+    date_applied timestamp without time zone not null default now()
+);
+
+INSERT INTO schema_migrations (name) VALUES
+    ('00-create-tasks-table'),
+    ('01-create-users-table'),
+    ('02-add-user-password'),
+    ('03-create-oidc-login-requests-table'),
+    ('04-add-user-email-verification'),
+    ('05-create-schema-migrations-table');
@@ -0,0 +1,36 @@
+-- The webhooks users subscribed to. The secret signs every delivery, so the receiver can check it came from us.
+CREATE TABLE webhooks (
+    id serial primary key,
+    user_id integer not null references users (id) on delete cascade,
+    url varchar(2000) not null,
+    secret varchar(100) not null,
+    events text[] not null,
+    active boolean not null default true,
+    date_created timestamp without time zone not null,
+    date_modified timestamp without time zone null
+);
+
+CREATE INDEX webhooks_user_id_idx ON webhooks (user_id);
+
+-- Every event for a webhook becomes a delivery. Pending deliveries form the queue the dispatcher works through, the
+-- delivered and failed ones are the delivery log users can inspect.
+CREATE TABLE webhook_deliveries (
+    id bigserial primary key,
+    webhook_id integer not null references webhooks (id) on delete cascade,
+    event_id varchar(50) not null,
+    event_type text not null,
+    payload jsonb not null,
+    status varchar(20) not null default 'pending',
+    attempts integer not null default 0,
+    next_attempt_at timestamp without time zone not null,
+    response_status integer null,
+    error text null,
+    redelivery_of bigint null references webhook_deliveries (id) on delete set null,
+    date_created timestamp without time zone not null,
+    date_delivered timestamp without time zone null
+);
+
+CREATE INDEX webhook_deliveries_webhook_id_idx ON webhook_deliveries (webhook_id, id);
+CREATE INDEX webhook_deliveries_pending_idx ON webhook_deliveries (next_attempt_at) WHERE status = 'pending';
+
+INSERT INTO schema_migrations (name) VALUES ('06-create-webhooks-tables');
@@ -0,0 +1,16 @@
+-- The changes to tasks, so clients of the event stream can catch up on the changes they missed while disconnected.
+-- Every write to the tasks table adds its events in the same transaction and notifies the `task_events` channel with
+-- the ID of each event. Events older than the configured retention are removed by the application.
+CREATE TABLE task_events (
+    id bigserial primary key,
+    user_id integer not null,
+    task_id integer not null,
+    event_type text not null,
+    payload jsonb not null,
+    date_created timestamp without time zone not null
+);
+
+CREATE INDEX task_events_user_id_idx ON task_events (user_id, id);
+CREATE INDEX task_events_date_created_idx ON task_events (date_created);
+
+INSERT INTO schema_migrations (name) VALUES ('07-create-task-events-table');
@@ -0,0 +1,23 @@
+-- The outbox of side effects that run after a change was committed, like queueing webhook deliveries. Jobs are added
+-- in the same transaction as the change, so a job exists if and only if the change was committed. Workers claim due
+-- jobs with `FOR UPDATE SKIP LOCKED`; jobs that keep failing end up with the `dead` status until an administrator
+-- retries them. Completed jobs are removed.
+CREATE TABLE jobs (
+    id bigserial primary key,
+    kind text not null,
+    payload jsonb not null,
+    status varchar(20) not null default 'pending',
+    attempts integer not null default 0,
+    run_at timestamp without time zone not null,
+    error text null,
+    date_created timestamp without time zone not null,
+    date_modified timestamp without time zone null
+);
+
+CREATE INDEX jobs_due_idx ON jobs (run_at) WHERE status IN ('pending', 'running');
+CREATE INDEX jobs_status_idx ON jobs (status, id);
+
+-- Jobs can run more than once, so queueing the deliveries of an event skips the webhooks that already have one.
+CREATE INDEX webhook_deliveries_event_id_idx ON webhook_deliveries (webhook_id, event_id);
+
+INSERT INTO schema_migrations (name) VALUES ('08-create-jobs-table');
@@ -0,0 +1,46 @@
+-- Tasks can have a deadline. Like the other dates, it's stored in UTC.
+ALTER TABLE tasks ADD COLUMN due_at timestamp without time zone null;
+
+-- Reminders are scheduled in the time zone of the user, and aren't sent during their quiet hours. Both times of the
+-- quiet hours are local times, the quiet hours wrap around midnight when the start is after the end.
+ALTER TABLE users ADD COLUMN time_zone varchar(64) not null default 'UTC';
+ALTER TABLE users ADD COLUMN quiet_hours_start time without time zone null;
+ALTER TABLE users ADD COLUMN quiet_hours_end time without time zone null;
+
+-- A reminder fires either a number of minutes before the task is due, or at a time of day on the date the task is
+-- due. `remind_at` is when the reminder fires in UTC, it's computed by the application whenever the deadline or the
+-- time zone changes. The scheduler sets `date_sent` in the same transaction as it queues the deliveries, so a reminder
+-- is sent once, no matter how many instances run the scheduler.
+CREATE TABLE reminders (
+    id serial primary key,
+    task_id integer not null references tasks (id) on delete cascade,
+    user_id integer not null references users (id) on delete cascade,
+    minutes_before integer null,
+    time_of_day time without time zone null,
+    channels text[] not null,
+    remind_at timestamp without time zone null,
+    date_sent timestamp without time zone null,
+    date_created timestamp without time zone not null,
+    CHECK ((minutes_before IS NULL) <> (time_of_day IS NULL))
+);
+
+CREATE INDEX reminders_task_id_idx ON reminders (task_id);
+CREATE INDEX reminders_due_idx ON reminders (remind_at) WHERE date_sent IS NULL;
+
+-- The in-app inbox. The deduplication key makes delivering the same notification twice harmless.
+CREATE TABLE notifications (
+    id bigserial primary key,
+    user_id integer not null references users (id) on delete cascade,
+    task_id integer null references tasks (id) on delete cascade,
+    kind text not null,
+    title varchar(200) not null,
+    body text not null,
+    dedup_key varchar(100) not null,
+    date_read timestamp without time zone null,
+    date_created timestamp without time zone not null
+);
+
+CREATE UNIQUE INDEX notifications_dedup_key_idx ON notifications (user_id, dedup_key);
+CREATE INDEX notifications_user_id_idx ON notifications (user_id, id);
+
+INSERT INTO schema_migrations (name) VALUES ('09-create-reminders-tables');
@@ -0,0 +1,51 @@
+-- Projects group the tasks of a user. A task belongs to at most one project, and stays when its project is removed.
+CREATE TABLE projects (
+    id serial primary key,
+    user_id integer not null references users (id) on delete cascade,
+    name varchar(250) not null,
+    date_created timestamp without time zone not null,
+    date_modified timestamp without time zone null
+);
+
+CREATE INDEX projects_user_id_idx ON projects (user_id);
+
+ALTER TABLE tasks ADD COLUMN project_id integer null references projects (id) on delete set null;
+
+CREATE INDEX tasks_project_id_idx ON tasks (project_id);
+
+-- A share gives another user access to a task or to a project, and with that to every task in the project. Viewers can
+-- read, editors can make changes as well. Only the owner can remove the item or share it with others.
+CREATE TABLE shares (
+    id serial primary key,
+    task_id integer null references tasks (id) on delete cascade,
+    project_id integer null references projects (id) on delete cascade,
+    user_id integer not null references users (id) on delete cascade,
+    role varchar(20) not null,
+    date_created timestamp without time zone not null,
+    date_modified timestamp without time zone null,
+    CHECK ((task_id IS NULL) <> (project_id IS NULL))
+);
+
+CREATE UNIQUE INDEX shares_task_id_user_id_idx ON shares (task_id, user_id) WHERE task_id IS NOT NULL;
+CREATE UNIQUE INDEX shares_project_id_user_id_idx ON shares (project_id, user_id) WHERE project_id IS NOT NULL;
+CREATE INDEX shares_user_id_idx ON shares (user_id);
+
+-- Who can access which task, and in which role. This is the only place that knows how ownership and shares combine, the
+-- application asks these views instead of repeating the rules in every query. A user can have more than one row for a
+-- task, for example when the task and its project are both shared with them. The highest role wins. Editors of a project
+-- can move their own tasks into it, the owner of the project can edit those tasks.
+CREATE VIEW task_access AS
+    SELECT id AS task_id, user_id, 'owner' AS role FROM tasks
+    UNION ALL
+    SELECT task_id, user_id, role FROM shares WHERE task_id IS NOT NULL
+    UNION ALL
+    SELECT tasks.id, shares.user_id, shares.role FROM shares JOIN tasks ON tasks.project_id = shares.project_id
+    UNION ALL
+    SELECT tasks.id, projects.user_id, 'editor' FROM tasks JOIN projects ON projects.id = tasks.project_id;
+
+CREATE VIEW project_access AS
+    SELECT id AS project_id, user_id, 'owner' AS role FROM projects
+    UNION ALL
+    SELECT project_id, user_id, role FROM shares WHERE project_id IS NOT NULL;
+
+INSERT INTO schema_migrations (name) VALUES ('10-create-sharing-tables');
@@ -0,0 +1,110 @@
+-- Workspaces let a team share tasks and projects. Every member has a role: owners manage the workspace and its members,
+-- admins manage the members and invitations, and members work on the tasks and projects in the workspace.
+CREATE TABLE workspaces (
+    id serial primary key,
+    name varchar(250) not null,
+    date_created timestamp without time zone not null,
+    date_modified timestamp without time zone null
+);
+
+CREATE TABLE workspace_members (
+    workspace_id integer not null references workspaces (id) on delete cascade,
+    user_id integer not null references users (id) on delete cascade,
+    role varchar(20) not null,
+    date_created timestamp without time zone not null,
+    primary key (workspace_id, user_id)
+);
+
+CREATE INDEX workspace_members_user_id_idx ON workspace_members (user_id);
+
+-- Invitations are addressed to an email address, so people can be invited before they register. The invited user
+-- accepts the invitation once they're registered with that address.
+CREATE TABLE workspace_invitations (
+    id serial primary key,
+    workspace_id integer not null references workspaces (id) on delete cascade,
+    email_address varchar(250) not null,
+    role varchar(20) not null,
+    invited_by integer null references users (id) on delete set null,
+    date_created timestamp without time zone not null
+);
+
+CREATE UNIQUE INDEX workspace_invitations_email_address_idx ON workspace_invitations (workspace_id, lower(email_address));
+CREATE INDEX workspace_invitations_lower_email_address_idx ON workspace_invitations (lower(email_address));
+
+-- API keys that act on behalf of a member, but only within one workspace. They're removed with the membership.
+CREATE TABLE workspace_keys (
+    id serial primary key,
+    workspace_id integer not null,
+    user_id integer not null,
+    name varchar(250) not null,
+    api_key varchar(500) not null unique,
+    date_created timestamp without time zone not null,
+    foreign key (workspace_id, user_id) references workspace_members (workspace_id, user_id) on delete cascade
+);
+
+ALTER TABLE tasks ADD COLUMN workspace_id integer null references workspaces (id) on delete cascade;
+ALTER TABLE projects ADD COLUMN workspace_id integer null references workspaces (id) on delete cascade;
+
+CREATE INDEX tasks_workspace_id_idx ON tasks (workspace_id);
+CREATE INDEX projects_workspace_id_idx ON projects (workspace_id);
+
+-- Members can edit every task and project in their workspace, admins and owners can remove them and share them as well.
+CREATE OR REPLACE VIEW task_access AS
+    SELECT id AS task_id, user_id, 'owner' AS role FROM tasks
+    UNION ALL
+    SELECT task_id, user_id, role FROM shares WHERE task_id IS NOT NULL
+    UNION ALL
+    SELECT tasks.id, shares.user_id, shares.role FROM shares JOIN tasks ON tasks.project_id = shares.project_id
+    UNION ALL
+    SELECT tasks.id, projects.user_id, 'editor' FROM tasks JOIN projects ON projects.id = tasks.project_id
+    UNION ALL
+    SELECT tasks.id, workspace_members.user_id, CASE workspace_members.role WHEN 'member' THEN 'editor' ELSE 'owner' END
+    FROM tasks JOIN workspace_members ON workspace_members.workspace_id = tasks.workspace_id;
+
+CREATE OR REPLACE VIEW project_access AS
+    SELECT id AS project_id, user_id, 'owner' AS role FROM projects
+    UNION ALL
+    SELECT project_id, user_id, role FROM shares WHERE project_id IS NOT NULL
+    UNION ALL
+    SELECT projects.id, workspace_members.user_id, CASE workspace_members.role WHEN 'member' THEN 'editor' ELSE 'owner' END
+    FROM projects JOIN workspace_members ON workspace_members.workspace_id = projects.workspace_id;
+
+-- Row-level security is a second line of defence behind the filters in the queries of the application. Queries on
+-- behalf of a user set `app.user_id` on their connection, which limits them to the rows that user can access. Without
+-- the setting, like in background jobs, every row is visible. The policies repeat the rules of the views above, because
+-- a policy on a table can't use a view on the same table.
+CREATE FUNCTION app_user_id() RETURNS integer LANGUAGE sql STABLE AS $$
+    SELECT nullif(current_setting('app.user_id', true), '')::integer
+$$;
+
+ALTER TABLE workspaces ENABLE ROW LEVEL SECURITY;
+ALTER TABLE workspaces FORCE ROW LEVEL SECURITY;
+
+CREATE POLICY workspaces_isolation ON workspaces USING (
+    app_user_id() IS NULL
+    OR id IN (SELECT workspace_id FROM workspace_members WHERE user_id = app_user_id())
+);
+
+ALTER TABLE projects ENABLE ROW LEVEL SECURITY;
+ALTER TABLE projects FORCE ROW LEVEL SECURITY;
+
+CREATE POLICY projects_isolation ON projects USING (
+    app_user_id() IS NULL
+    OR user_id = app_user_id()
+    OR id IN (SELECT project_id FROM shares WHERE user_id = app_user_id())
+    OR workspace_id IN (SELECT workspace_id FROM workspace_members WHERE user_id = app_user_id())
+);
+
+ALTER TABLE tasks ENABLE ROW LEVEL SECURITY;
+ALTER TABLE tasks FORCE ROW LEVEL SECURITY;
+
+CREATE POLICY tasks_isolation ON tasks USING (
+    app_user_id() IS NULL
+    OR user_id = app_user_id()
+    OR id IN (SELECT task_id FROM shares WHERE user_id = app_user_id())
+    OR project_id IN (SELECT project_id FROM shares WHERE user_id = app_user_id())
+    OR project_id IN (SELECT id FROM projects WHERE user_id = app_user_id())
+    OR workspace_id IN (SELECT workspace_id FROM workspace_members WHERE user_id = app_user_id())
+);
+
+INSERT INTO schema_migrations (name) VALUES ('11-create-workspaces-tables');
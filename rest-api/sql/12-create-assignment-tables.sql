@@ -0,0 +1,36 @@
+-- Users can pick a handle to be mentioned with, like `@jane`. Handles are unique regardless of case.
+ALTER TABLE users ADD COLUMN handle varchar(30) null;
+
+CREATE UNIQUE INDEX users_handle_idx ON users (lower(handle));
+
+-- The users a task is assigned to. Only users that can access the task can be assigned to it.
+CREATE TABLE task_assignees (
+    task_id integer not null references tasks (id) on delete cascade,
+    user_id integer not null references users (id) on delete cascade,
+    assigned_by integer null references users (id) on delete set null,
+    date_created timestamp without time zone not null,
+    primary key (task_id, user_id)
+);
+
+CREATE INDEX task_assignees_user_id_idx ON task_assignees (user_id);
+
+-- The users that follow the assignments of a task.
+CREATE TABLE task_watchers (
+    task_id integer not null references tasks (id) on delete cascade,
+    user_id integer not null references users (id) on delete cascade,
+    date_created timestamp without time zone not null,
+    primary key (task_id, user_id)
+);
+
+CREATE INDEX task_watchers_user_id_idx ON task_watchers (user_id);
+
+-- The users mentioned in the description of a task. We keep them, so that only users who are newly mentioned get a
+-- notification when the description changes.
+CREATE TABLE task_mentions (
+    task_id integer not null references tasks (id) on delete cascade,
+    user_id integer not null references users (id) on delete cascade,
+    date_created timestamp without time zone not null,
+    primary key (task_id, user_id)
+);
+
+INSERT INTO schema_migrations (name) VALUES ('12-create-assignment-tables');
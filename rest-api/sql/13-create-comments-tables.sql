@@ -0,0 +1,25 @@
+-- The comments on a task. We keep the markdown the author wrote, and the sanitised HTML it renders to, so that
+-- listing comments doesn't need to render them again.
+CREATE TABLE comments (
+    id serial primary key,
+    task_id integer not null references tasks (id) on delete cascade,
+    user_id integer not null references users (id) on delete cascade,
+    body text not null,
+    body_html text not null,
+    date_created timestamp without time zone not null,
+    date_modified timestamp without time zone null
+);
+
+CREATE INDEX comments_task_id_idx ON comments (task_id, id);
+
+-- The earlier versions of a comment. Every edit keeps the body the comment had before it.
+CREATE TABLE comment_revisions (
+    id serial primary key,
+    comment_id integer not null references comments (id) on delete cascade,
+    body text not null,
+    date_created timestamp without time zone not null
+);
+
+CREATE INDEX comment_revisions_comment_id_idx ON comment_revisions (comment_id, id);
+
+INSERT INTO schema_migrations (name) VALUES ('13-create-comments-tables');
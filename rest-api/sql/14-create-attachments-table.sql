@@ -0,0 +1,36 @@
+-- The files attached to a task. The contents live in the blob store under `storage_key`, the table only keeps what we
+-- need to list and download them.
+CREATE TABLE attachments (
+    id serial primary key,
+    task_id integer not null references tasks (id) on delete cascade,
+    user_id integer null references users (id) on delete set null,
+    file_name varchar(255) not null,
+    content_type varchar(255) not null,
+    size bigint not null,
+    storage_key varchar(255) not null unique,
+    date_created timestamp without time zone not null
+);
+
+CREATE INDEX attachments_task_id_idx ON attachments (task_id, id);
+
+-- Attachments disappear with their task, which can happen through a cascade when a user or workspace is removed. The
+-- trigger queues a job to remove the contents from the blob store in the same transaction, whichever way the row
+-- was deleted.
+CREATE FUNCTION queue_attachment_blob_deletion() RETURNS trigger LANGUAGE plpgsql AS $$
+BEGIN
+    INSERT INTO jobs (kind, payload, run_at, date_created)
+    VALUES (
+        'attachments.delete',
+        jsonb_build_object('storage_key', OLD.storage_key),
+        now() AT TIME ZONE 'utc',
+        now() AT TIME ZONE 'utc'
+    );
+
+    RETURN OLD;
+END;
+$$;
+
+CREATE TRIGGER attachments_delete_blob AFTER DELETE ON attachments
+    FOR EACH ROW EXECUTE FUNCTION queue_attachment_blob_deletion();
+
+INSERT INTO schema_migrations (name) VALUES ('14-create-attachments-table');
@@ -0,0 +1,28 @@
+-- A task that is blocked by another task can't be completed until the other task is done. The links form a directed
+-- graph without cycles, which the application checks before it adds a link.
+--
+-- We keep whether the blocking task is completed on the link, so we can tell whether a task is blocked without reading
+-- the blocking tasks. They may not be visible to the user because of row level security.
+CREATE TABLE task_dependencies (
+    task_id integer not null references tasks (id) on delete cascade,
+    blocked_by_id integer not null references tasks (id) on delete cascade,
+    blocker_completed boolean not null,
+    date_created timestamp without time zone not null,
+    primary key (task_id, blocked_by_id),
+    check (task_id <> blocked_by_id)
+);
+
+CREATE INDEX task_dependencies_blocked_by_id_idx ON task_dependencies (blocked_by_id);
+
+CREATE FUNCTION update_task_dependencies() RETURNS trigger LANGUAGE plpgsql AS $$
+BEGIN
+    UPDATE task_dependencies SET blocker_completed = NEW.completed WHERE blocked_by_id = NEW.id;
+
+    RETURN NEW;
+END;
+$$;
+
+CREATE TRIGGER tasks_update_dependencies AFTER UPDATE OF completed ON tasks
+    FOR EACH ROW WHEN (OLD.completed IS DISTINCT FROM NEW.completed) EXECUTE FUNCTION update_task_dependencies();
+
+INSERT INTO schema_migrations (name) VALUES ('15-create-task-dependencies-table');
@@ -0,0 +1,6 @@
+-- The moment the credentials of a user were last taken away from whoever held them, like when the owner of an email
+-- address logs in with the identity provider and claims an account someone else registered. Refresh tokens that were
+-- issued before this moment can no longer be exchanged for new tokens.
+ALTER TABLE users ADD COLUMN credentials_reset_at timestamp without time zone null;
+
+INSERT INTO schema_migrations (name) VALUES ('16-add-user-credentials-reset');
@@ -0,0 +1,5 @@
+-- The workspace the task was in when it changed. Credentials that are bound to a workspace only receive the events of
+-- that workspace, which we can't look up on the task itself once it was removed.
+ALTER TABLE task_events ADD COLUMN workspace_id integer null;
+
+INSERT INTO schema_migrations (name) VALUES ('17-add-task-event-workspace');
@@ -0,0 +1,197 @@
+//! This module decides who can do what with tasks and projects.
+//!
+//! Users own the tasks and projects they create, and can share them with other users as a viewer or an editor. A
+//! project that is shared gives access to every task in the project as well. Tasks and projects in a workspace are
+//! accessible to every member of the workspace. The rules that combine ownership, shares and workspaces live in the
+//! `task_access` and `project_access` views in the database, and this module is the only place that asks them.
+//! Handlers check the access of the current user first, and then work on the item on behalf of its owner with the
+//! queries in [`crate::db`]:
+//!
+//! ```ignore
+//! let access = access::task_access(pool, user_id, scope, task_id, Role::Editor).await?;
+//! db::update_task(pool, access.owner_id, task_id, ...).await?;
+//! ```
+//!
+//! The scope is the workspace the credentials of the user are bound to, like an API key of a workspace. Scoped
+//! credentials only reach the items in their workspace, as if the other items don't exist. They can't be used for
+//! anything that belongs to the user rather than a workspace, like their account, see [`require_unscoped`].
+//!
+//! Users without any access get the same error as when the item doesn't exist, so they can't find out which IDs are
+//! taken. Users that can see the item, but need a higher role for what they tried, get [`AppError::AccessDenied`].
+
+use sqlx::PgPool;
+
+use crate::{
+    db,
+    entity::{Role, WorkspaceRole},
+    error::{AppError, Result},
+};
+
+/// The access a user has to a task or project.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Access {
+    /// The user that owns the item. Changes to the item are made on their behalf.
+    pub owner_id: i32,
+
+    /// The workspace the item belongs to, if any.
+    pub workspace_id: Option<i32>,
+
+    /// The highest role the user has for the item.
+    pub role: Role,
+}
+
+impl Access {
+    /// Returns the access when its role is at least the required role.
+    fn require(self, required: Role) -> Result<Self> {
+        if self.role >= required {
+            Ok(self)
+        } else {
+            Err(AppError::AccessDenied)
+        }
+    }
+}
+
+/// Checks that a user has at least the required role for a task.
+///
+/// When the user can't see the task at all, or the task is outside the scope of their credentials, we return an error
+/// with the [`AppError::TaskNotFound`] variant.
+pub async fn task_access(
+    pool: &PgPool,
+    user_id: i32,
+    scope: Option<i32>,
+    task_id: i32,
+    required: Role,
+) -> Result<Access> {
+    let (owner_id, workspace_id, role) = db::find_task_access(pool, user_id, task_id)
+        .await?
+        .filter(|&(_, workspace_id, _)| in_scope(scope, workspace_id))
+        .ok_or(AppError::TaskNotFound)?;
+
+    Access {
+        owner_id,
+        workspace_id,
+        role,
+    }
+    .require(required)
+}
+
+/// Checks that a user has at least the required role for a project.
+///
+/// When the user can't see the project at all, or the project is outside the scope of their credentials, we return an
+/// error with the [`AppError::ProjectNotFound`] variant.
+pub async fn project_access(
+    pool: &PgPool,
+    user_id: i32,
+    scope: Option<i32>,
+    project_id: i32,
+    required: Role,
+) -> Result<Access> {
+    let (owner_id, workspace_id, role) = db::find_project_access(pool, user_id, project_id)
+        .await?
+        .filter(|&(_, workspace_id, _)| in_scope(scope, workspace_id))
+        .ok_or(AppError::ProjectNotFound)?;
+
+    Access {
+        owner_id,
+        workspace_id,
+        role,
+    }
+    .require(required)
+}
+
+/// Checks that a user is a member of a workspace with at least the required role, and returns their role.
+///
+/// When the user isn't a member, or the workspace is outside the scope of their credentials, we return an error with
+/// the [`AppError::WorkspaceNotFound`] variant.
+pub async fn workspace_access(
+    pool: &PgPool,
+    user_id: i32,
+    scope: Option<i32>,
+    workspace_id: i32,
+    required: WorkspaceRole,
+) -> Result<WorkspaceRole> {
+    if !in_scope(scope, Some(workspace_id)) {
+        return Err(AppError::WorkspaceNotFound);
+    }
+
+    let role = db::find_workspace_role(pool, user_id, workspace_id)
+        .await?
+        .ok_or(AppError::WorkspaceNotFound)?;
+
+    if role >= required {
+        Ok(role)
+    } else {
+        Err(AppError::AccessDenied)
+    }
+}
+
+/// Combines the scope of the credentials with the workspace a user asked for, like in a filter on a list.
+///
+/// Scoped credentials can only ask for their own workspace. This returns the workspace to limit the request to, or
+/// `None` when the request isn't limited to a workspace.
+pub fn scope(credentials: Option<i32>, requested: Option<i32>) -> Result<Option<i32>> {
+    match (credentials, requested) {
+        (Some(scope), Some(requested)) if scope != requested => Err(AppError::WorkspaceNotFound),
+        (credentials, requested) => Ok(credentials.or(requested)),
+    }
+}
+
+/// Checks that the credentials aren't bound to a workspace, for requests about the user rather than the items in a
+/// workspace, like their account, webhooks and notifications.
+///
+/// Scoped credentials get an error with the [`AppError::ScopedCredentials`] variant.
+pub fn require_unscoped(scope: Option<i32>) -> Result<()> {
+    match scope {
+        Some(_) => Err(AppError::ScopedCredentials),
+        None => Ok(()),
+    }
+}
+
+/// Returns whether an item in the given workspace is within the scope of the credentials.
+pub fn in_scope(scope: Option<i32>, workspace_id: Option<i32>) -> bool {
+    scope.is_none() || scope == workspace_id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn higher_roles_include_lower_roles() {
+        let editor = Access {
+            owner_id: 1,
+            workspace_id: None,
+            role: Role::Editor,
+        };
+
+        assert!(editor.require(Role::Viewer).is_ok());
+        assert!(editor.require(Role::Editor).is_ok());
+        assert!(matches!(
+            editor.require(Role::Owner),
+            Err(AppError::AccessDenied)
+        ));
+    }
+
+    #[test]
+    fn scoped_credentials_only_reach_their_workspace() {
+        assert!(in_scope(None, None));
+        assert!(in_scope(None, Some(1)));
+        assert!(in_scope(Some(1), Some(1)));
+        assert!(!in_scope(Some(1), Some(2)));
+        assert!(!in_scope(Some(1), None));
+
+        assert_eq!(scope(None, None).unwrap(), None);
+        assert_eq!(scope(None, Some(2)).unwrap(), Some(2));
+        assert_eq!(scope(Some(1), None).unwrap(), Some(1));
+        assert!(matches!(
+            scope(Some(1), Some(2)),
+            Err(AppError::WorkspaceNotFound)
+        ));
+
+        assert!(require_unscoped(None).is_ok());
+        assert!(matches!(
+            require_unscoped(Some(1)),
+            Err(AppError::ScopedCredentials)
+        ));
+    }
+}
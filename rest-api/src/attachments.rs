@@ -0,0 +1,244 @@
+//! This module contains the rules for the files attached to tasks.
+//!
+//! ## Uploads
+//! Editors of a task upload files with a multipart request. The file must be smaller than the configured limit and have
+//! one of the configured content types, see [`crate::config::AttachmentsConfig`]. The contents go to the blob store
+//! under a random key, see [`crate::blobs`], and the database keeps the name, type and size of the file.
+//!
+//! ## Downloads
+//! Everyone who can see a task can ask for a download URL of its attachments. The URL is only valid for a few minutes,
+//! so it can be handed to a browser without handing out credentials. When the blob store can sign URLs itself, like
+//! S3, the URL points to the store. Otherwise it points to the download endpoint of the application, with an
+//! expiration date and a signature made by the [`DownloadSigner`].
+//!
+//! ## Cleaning up
+//! Attachments are removed with their task. A trigger on the `attachments` table queues a [`DeleteBlob`] job for every
+//! attachment that is removed, whichever way that happened, and the job removes the contents from the blob store.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, thread_rng, Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tracing::{instrument, warn};
+
+use crate::{
+    entity::JobKind,
+    error::{AppError, Result},
+    jobs::{JobContext, JobPayload},
+};
+
+/// The number of random characters in the storage key of an attachment.
+const STORAGE_KEY_LENGTH: usize = 32;
+
+/// The number of characters of a file name that we keep.
+const MAX_FILE_NAME_LENGTH: usize = 255;
+
+/// Removes the contents of an attachment from the blob store, after the attachment was removed.
+///
+/// The job is queued by a trigger in the database, see `sql/14-create-attachments-table.sql`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeleteBlob {
+    /// The key of the contents in the blob store.
+    pub storage_key: String,
+}
+
+impl JobPayload for DeleteBlob {
+    const KIND: JobKind = JobKind::DeleteBlob;
+
+    #[instrument(skip_all, fields(storage_key = %self.storage_key))]
+    async fn perform(self, context: &JobContext) -> Result<()> {
+        context.blobs.delete(&self.storage_key).await?;
+
+        Ok(())
+    }
+}
+
+/// Signs the URLs of the download endpoint, so they can be used without credentials until they expire.
+pub struct DownloadSigner {
+    secret: Vec<u8>,
+}
+
+impl std::fmt::Debug for DownloadSigner {
+    /// Formats the signer without exposing its secret.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DownloadSigner").finish_non_exhaustive()
+    }
+}
+
+impl DownloadSigner {
+    /// Creates a signer with the configured secret, or with a random secret when none is configured.
+    pub fn new(secret: Option<&str>) -> Self {
+        let secret = match secret {
+            Some(secret) => secret.as_bytes().to_vec(),
+            None => {
+                warn!(
+                    "No secret for download URLs is configured, so they only work on this instance"
+                );
+
+                let mut secret = vec![0u8; 32];
+                thread_rng().fill_bytes(&mut secret);
+                secret
+            }
+        };
+
+        Self { secret }
+    }
+
+    /// Signs the download of an attachment until the given UNIX timestamp.
+    pub fn sign(&self, attachment_id: i32, expires: i64) -> String {
+        URL_SAFE_NO_PAD.encode(self.mac(attachment_id, expires).finalize().into_bytes())
+    }
+
+    /// Checks the signature of a download URL, and that it hasn't expired yet.
+    ///
+    /// When the signature is wrong or the URL has expired, we return an error with the
+    /// [`AppError::InvalidDownloadUrl`] variant.
+    pub fn verify(&self, attachment_id: i32, expires: i64, signature: &str) -> Result<()> {
+        if expires < chrono::Utc::now().timestamp() {
+            return Err(AppError::InvalidDownloadUrl);
+        }
+
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| AppError::InvalidDownloadUrl)?;
+
+        self.mac(attachment_id, expires)
+            .verify_slice(&signature)
+            .map_err(|_| AppError::InvalidDownloadUrl)
+    }
+
+    fn mac(&self, attachment_id: i32, expires: i64) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any length.");
+        mac.update(format!("{}.{}", attachment_id, expires).as_bytes());
+
+        mac
+    }
+}
+
+/// Generates the key to store a new attachment of a task under.
+pub fn new_storage_key(task_id: i32) -> String {
+    let random: String = thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(STORAGE_KEY_LENGTH)
+        .map(char::from)
+        .collect();
+
+    format!("tasks/{}/{}", task_id, random)
+}
+
+/// Returns whether a content type is one of the allowed types. Parameters like `charset` are ignored.
+pub fn is_allowed_content_type(allowed: &[String], content_type: &str) -> bool {
+    let Ok(content_type) = content_type.parse::<mime::Mime>() else {
+        return false;
+    };
+
+    allowed
+        .iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(content_type.essence_str()))
+}
+
+/// Cleans up the name of an uploaded file.
+///
+/// Browsers may send the full path of the file, so we only keep the last part. Control characters are removed and long
+/// names are cut off.
+pub fn clean_file_name(file_name: &str) -> Option<String> {
+    let name: String = file_name
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_FILE_NAME_LENGTH)
+        .collect();
+    let name = name.trim();
+
+    (!name.is_empty() && name != "." && name != "..").then(|| name.to_string())
+}
+
+/// Returns the `Content-Disposition` header that makes browsers save a file under its name.
+///
+/// The plain `filename` only holds ASCII, so we replace the other characters there and add the full name as
+/// `filename*`, which browsers prefer.
+pub fn content_disposition(file_name: &str) -> String {
+    let ascii_name: String = file_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_graphic() && c != '"' && c != '\\' || c == ' ' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    let encoded_name: String = file_name
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect();
+
+    format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        ascii_name, encoded_name
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn download_urls_are_signed() {
+        let signer = DownloadSigner::new(Some("secret"));
+        let expires = chrono::Utc::now().timestamp() + 60;
+        let signature = signer.sign(1, expires);
+
+        assert!(signer.verify(1, expires, &signature).is_ok());
+        assert!(matches!(
+            signer.verify(2, expires, &signature),
+            Err(AppError::InvalidDownloadUrl)
+        ));
+        assert!(matches!(
+            signer.verify(1, expires + 1, &signature),
+            Err(AppError::InvalidDownloadUrl)
+        ));
+
+        let expired = chrono::Utc::now().timestamp() - 1;
+        assert!(matches!(
+            signer.verify(1, expired, &signer.sign(1, expired)),
+            Err(AppError::InvalidDownloadUrl)
+        ));
+    }
+
+    #[test]
+    fn content_types_are_checked() {
+        let allowed = vec!["image/png".to_string(), "text/plain".to_string()];
+
+        assert!(is_allowed_content_type(&allowed, "image/png"));
+        assert!(is_allowed_content_type(
+            &allowed,
+            "text/plain; charset=utf-8"
+        ));
+        assert!(!is_allowed_content_type(&allowed, "text/html"));
+        assert!(!is_allowed_content_type(&allowed, "not a type"));
+    }
+
+    #[test]
+    fn file_names_are_cleaned() {
+        assert_eq!(
+            clean_file_name("C:\\Users\\jane\\screen shot.png").as_deref(),
+            Some("screen shot.png")
+        );
+        assert_eq!(clean_file_name("../..").as_deref(), None);
+        assert_eq!(
+            content_disposition("café \"1\".txt"),
+            "attachment; filename=\"caf_ _1_.txt\"; filename*=UTF-8''caf%C3%A9%20%221%22.txt"
+        );
+    }
+}
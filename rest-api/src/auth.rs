@@ -1,59 +1,156 @@
 //! This module contains the authentication logic for the API.
 //!
-//! We're using the X-Api-Key header to authenticate users. The API key is used to identify the user and authorize
-//! access to the API. This is not a very secure method of authentication, but it's simple and easy to implement.
+//! We support two ways to authenticate users:
+//!
+//! * The `X-Api-Key` header. The API key is used to identify the user and authorize access to the API. This is not a
+//!   very secure method of authentication, because the key is long-lived and sent with every request.
+//! * The `Authorization: Bearer <token>` header. The token is a short-lived JSON Web Token (JWT) that is obtained by
+//!   exchanging an API key or email address and password at the `/v1/auth/token` endpoint.
+//!   Please check out the [`crate::auth::token`] module for more information about the tokens.
+//!
+//! Users can also log in with an external OpenID Connect identity provider, after which they receive the same bearer
+//! tokens. Please check out the [`crate::auth::oidc`] module for more information.
+//!
+//! When the server terminates TLS itself and a client certificate authority is configured, clients can authenticate
+//! with a certificate instead. We identify the user by the email address in the certificate. Please check out the
+//! [`crate::tls`] module for more information.
+//!
+//! Users that haven't verified their email address yet have limited rights. They can use the [`AuthenticatedUser`]
+//! extractor like any other user, but the [`VerifiedUser`] extractor rejects them. Handlers that change data use the
+//! [`VerifiedUser`] extractor.
+//!
+//! Members of a workspace can create API keys that only give access to that workspace. Both extractors report the
+//! workspace in `workspace_id`, and the bearer tokens issued for such a key carry it as well. Handlers pass it on to
+//! the [`crate::access`] module as the scope of the request.
+//!
+//! Operators use the admin endpoints, like the job queue, with the [`Administrator`] extractor. It checks the
+//! `X-Admin-Token` header against the token in [`crate::config::AdminConfig`], and rejects every request when no
+//! token is configured.
 //!
-//! If you're looking for a more secure authentication method, you should consider using JWT tokens.
 //! For an example of how to implement JWT authentication: https://github.com/tokio-rs/axum/blob/main/examples/jwt/src/main.rs
 
+pub mod oidc;
+pub mod token;
+
 use std::sync::Arc;
 
 use crate::entity::ApiKey;
+use crate::error::ErrorDetails;
+use crate::monitoring;
 use crate::state::AppState;
-use axum::http::StatusCode;
+use crate::tls::ClientCertificate;
+use argon2::password_hash::{
+    rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+};
+use argon2::Argon2;
+use axum::http::{header, StatusCode};
 use axum::response::IntoResponse;
 use axum::{async_trait, Json};
 use axum::{
     extract::{FromRef, FromRequestParts},
     http::request::Parts,
 };
-use serde::Serialize;
 
 use crate::db;
+use token::TokenType;
 
 pub struct AuthenticatedUser {
     pub user_id: i32,
+    pub verified: bool,
+
+    /// The workspace the credentials are limited to, if any.
+    pub workspace_id: Option<i32>,
 }
 
+/// An authenticated user that has verified their email address.
+pub struct VerifiedUser {
+    pub user_id: i32,
+
+    /// The workspace the credentials are limited to, if any.
+    pub workspace_id: Option<i32>,
+}
+
+/// An operator that sent the admin token.
+pub struct Administrator;
+
+/// The header operators send the admin token in.
+pub const ADMIN_TOKEN_HEADER: &str = "X-Admin-Token";
+
 #[derive(Debug)]
 pub enum AuthError {
     InvalidApiKey,
     MissingApiKey,
+    InvalidToken,
+    AccountNotVerified,
+    UnknownCertificate,
+    InvalidAdminToken,
+    AdminDisabled,
 }
 
-#[derive(Serialize)]
-struct ErrorDetails {
-    message: String,
+impl AuthError {
+    /// Returns the name of the error that we use as a label in the metrics.
+    pub fn label(&self) -> &'static str {
+        match self {
+            AuthError::InvalidApiKey => "invalid_api_key",
+            AuthError::MissingApiKey => "missing_api_key",
+            AuthError::InvalidToken => "invalid_token",
+            AuthError::AccountNotVerified => "account_not_verified",
+            AuthError::UnknownCertificate => "unknown_certificate",
+            AuthError::InvalidAdminToken => "invalid_admin_token",
+            AuthError::AdminDisabled => "admin_disabled",
+        }
+    }
 }
 
 impl IntoResponse for AuthError {
     fn into_response(self) -> axum::response::Response {
+        monitoring::record_auth_failure(self.label());
+
         let response_data = match self {
             AuthError::InvalidApiKey => {
-                let error_details = ErrorDetails {
-                    message: "The provided API key in the X-Api-Key header is invalid.".to_string(),
-                };
+                let error_details =
+                    ErrorDetails::new("The provided API key in the X-Api-Key header is invalid.");
 
                 (StatusCode::NOT_FOUND, Json(error_details))
             }
             AuthError::MissingApiKey => {
-                let error_details = ErrorDetails {
-                    message: "Please provide an API Key in the X-Api-Key header of your request."
-                        .to_string(),
-                };
+                let error_details = ErrorDetails::new("Please provide an API Key in the X-Api-Key header or a bearer token in the Authorization header of your request.");
 
                 (StatusCode::BAD_REQUEST, Json(error_details))
             }
+            AuthError::InvalidToken => {
+                let error_details = ErrorDetails::new(
+                    "The provided bearer token in the Authorization header is invalid or expired.",
+                );
+
+                (StatusCode::UNAUTHORIZED, Json(error_details))
+            }
+            AuthError::AccountNotVerified => {
+                let error_details = ErrorDetails::new("Please verify your email address before making changes. Check your inbox for the verification token.");
+
+                (StatusCode::FORBIDDEN, Json(error_details))
+            }
+            AuthError::UnknownCertificate => {
+                let error_details = ErrorDetails::new(
+                    "There is no user with the email address in the provided client certificate.",
+                );
+
+                (StatusCode::UNAUTHORIZED, Json(error_details))
+            }
+            AuthError::InvalidAdminToken => {
+                let error_details = ErrorDetails::new(
+                    "The provided admin token in the X-Admin-Token header is missing or invalid.",
+                );
+
+                (StatusCode::UNAUTHORIZED, Json(error_details))
+            }
+            AuthError::AdminDisabled => {
+                let error_details = ErrorDetails::new(
+                    "The admin endpoints are disabled, because no admin token is configured.",
+                );
+
+                (StatusCode::NOT_FOUND, Json(error_details))
+            }
         };
 
         response_data.into_response()
@@ -71,12 +168,50 @@ where
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
         let state = SharedAppState::from_ref(state);
 
+        // When the client sends a bearer token, we validate it using the signing keys.
+        // This doesn't require a roundtrip to the database, because the token contains the user ID.
+        if let Some(authorization_header) = parts.headers.get(header::AUTHORIZATION) {
+            let token = authorization_header
+                .to_str()
+                .ok()
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .ok_or(AuthError::InvalidToken)?;
+
+            let claims = state
+                .token_issuer
+                .validate(token, TokenType::Access)
+                .map_err(|_| AuthError::InvalidToken)?;
+
+            let user_id = claims.user_id().map_err(|_| AuthError::InvalidToken)?;
+
+            return Ok(AuthenticatedUser {
+                user_id,
+                verified: claims.verified,
+                workspace_id: claims.workspace,
+            });
+        }
+
         // Try to locate the API key in the headers collection.
-        // If it's not there, we'll shortcut this operation and return an error.
-        let api_key_header = parts
-            .headers
-            .get("X-Api-Key")
-            .ok_or(AuthError::MissingApiKey)?;
+        // If it's not there, we'll fall back to the client certificate or return an error.
+        let Some(api_key_header) = parts.headers.get("X-Api-Key") else {
+            let client_certificate = parts
+                .extensions
+                .get::<ClientCertificate>()
+                .ok_or(AuthError::MissingApiKey)?;
+
+            let user = db::get_user_by_certificate_email(
+                &state.connection_pool,
+                &client_certificate.email,
+            )
+            .await
+            .map_err(|_| AuthError::UnknownCertificate)?;
+
+            return Ok(AuthenticatedUser {
+                user_id: user.id,
+                verified: user.email_verified,
+                workspace_id: None,
+            });
+        };
 
         // Convert the API key header into a string.
         // If the header is not a valid string, we'll return an error.
@@ -86,16 +221,130 @@ where
             .map_err(|_| AuthError::InvalidApiKey)?;
 
         // Parse the API key into a usable format with a hash.
-        let api_key = ApiKey::from_string(&raw_api_key.to_string());
+        let api_key = ApiKey::from_string(raw_api_key);
 
         // Use the hash value to look up the user in the database.
-        let user = db::get_user_by_key(&state.connection_pool, &api_key.hash)
-            .await
-            .map_err(|_| AuthError::InvalidApiKey)?;
+        // The key is either the key of the user, or a key the user created for one of their workspaces.
+        let (user, workspace_id) =
+            match db::get_user_by_key(&state.connection_pool, &api_key.hash).await {
+                Ok(user) => (user, None),
+                Err(_) => {
+                    let key = db::get_workspace_key(&state.connection_pool, &api_key.hash)
+                        .await
+                        .map_err(|_| AuthError::InvalidApiKey)?;
+
+                    let user = db::get_user_by_id(&state.connection_pool, key.user_id)
+                        .await
+                        .map_err(|_| AuthError::InvalidApiKey)?;
+
+                    (user, Some(key.workspace_id))
+                }
+            };
+
+        // The key exists, so from now on the rate limiter can give requests with the key a bucket of their own.
+        state.rate_limiter.remember_key(&api_key.hash);
 
         // Return an authentication ticket for the user.
-        Ok(AuthenticatedUser { user_id: user.id })
+        Ok(AuthenticatedUser {
+            user_id: user.id,
+            verified: user.email_verified,
+            workspace_id,
+        })
     }
 }
 
+#[async_trait]
+impl<S> FromRequestParts<S> for VerifiedUser
+where
+    SharedAppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let AuthenticatedUser {
+            user_id,
+            verified,
+            workspace_id,
+        } = AuthenticatedUser::from_request_parts(parts, state).await?;
+
+        if !verified {
+            return Err(AuthError::AccountNotVerified);
+        }
+
+        Ok(VerifiedUser {
+            user_id,
+            workspace_id,
+        })
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Administrator
+where
+    SharedAppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let state = SharedAppState::from_ref(state);
+        let expected_token = state.admin.token.as_ref().ok_or(AuthError::AdminDisabled)?;
+
+        let token = parts
+            .headers
+            .get(ADMIN_TOKEN_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .ok_or(AuthError::InvalidAdminToken)?;
+
+        // We compare the hashes instead of the tokens, so the time the comparison takes says nothing about the token.
+        if sha256::digest(token) != sha256::digest(expected_token.as_str()) {
+            return Err(AuthError::InvalidAdminToken);
+        }
+
+        Ok(Administrator)
+    }
+}
+
+/// Hashes a password with Argon2 so it can be stored in the database.
+///
+/// The result is a PHC string that contains the algorithm, parameters and salt alongside the hash.
+pub fn hash_password(password: &str) -> Result<String, argon2::password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    let password_hash = Argon2::default().hash_password(password.as_bytes(), &salt)?;
+
+    Ok(password_hash.to_string())
+}
+
+/// Verifies a password against a hash created with [`hash_password`].
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    PasswordHash::new(password_hash)
+        .map(|parsed_hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &parsed_hash)
+                .is_ok()
+        })
+        .unwrap_or(false)
+}
+
 type SharedAppState = Arc<AppState>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_password_accepts_original_password() {
+        let password_hash = hash_password("correct horse battery staple").unwrap();
+        assert!(verify_password(
+            "correct horse battery staple",
+            &password_hash
+        ));
+    }
+
+    #[test]
+    fn verify_password_rejects_other_password() {
+        let password_hash = hash_password("correct horse battery staple").unwrap();
+        assert!(!verify_password("Tr0ub4dor&3", &password_hash));
+    }
+}
@@ -0,0 +1,353 @@
+//! This module contains the logic to log in users with an external OpenID Connect (OIDC) identity provider.
+//!
+//! We implement the authorization code flow with PKCE (Proof Key for Code Exchange):
+//!
+//! 1. The user is redirected to the authorization endpoint of the identity provider. We send a code challenge that is
+//!    derived from a random code verifier that we keep to ourselves.
+//! 2. The identity provider redirects the user back to our callback endpoint with an authorization code.
+//! 3. We exchange the code for an ID token at the token endpoint, sending the original code verifier along. This proves
+//!    that we're the party that started the login, even if someone intercepted the authorization code.
+//! 4. We validate the ID token using the public keys (JWKS) of the identity provider.
+//!
+//! The endpoints of the identity provider are discovered from the issuer URL. Both the discovery document and the
+//! JWKS are cached, so we don't hit the identity provider for every login. When an ID token is signed with a key we
+//! don't know, we refresh the JWKS because the identity provider may have rotated its keys.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{jwk::JwkSet, DecodingKey, Validation};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use reqwest::Url;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::RwLock;
+
+use crate::config::OidcConfig;
+
+/// The time we keep the discovery document and JWKS before fetching them again.
+const CACHE_DURATION: Duration = Duration::from_secs(3600);
+
+/// The relevant parts of the discovery document of the identity provider.
+#[derive(Deserialize, Clone, Debug)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+/// The information we need to redirect the user to the identity provider.
+///
+/// The state, nonce and code verifier must be stored until the user returns to the callback endpoint.
+pub struct AuthorizationRequest {
+    pub url: String,
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+}
+
+/// The identity of a user as verified by the identity provider.
+#[derive(Debug)]
+pub struct VerifiedIdentity {
+    pub subject: String,
+    pub email_address: String,
+}
+
+/// The relevant parts of the token response of the identity provider.
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// The claims we need from the ID token.
+#[derive(Deserialize)]
+struct IdTokenClaims {
+    sub: String,
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: bool,
+    nonce: Option<String>,
+}
+
+/// A cached value with the time it was fetched.
+struct Cached<T> {
+    value: T,
+    fetched_at: Instant,
+}
+
+impl<T: Clone> Cached<T> {
+    fn fresh_value(&self) -> Option<T> {
+        if self.fetched_at.elapsed() < CACHE_DURATION {
+            Some(self.value.clone())
+        } else {
+            None
+        }
+    }
+}
+
+/// The different types of errors that can occur while logging in with the identity provider.
+#[derive(Debug)]
+pub enum OidcError {
+    /// We couldn't communicate with the identity provider.
+    Http(reqwest::Error),
+
+    /// The issuer or one of the discovered endpoints isn't a valid URL.
+    InvalidUrl(String),
+
+    /// The ID token was signed with a key that isn't in the JWKS of the identity provider.
+    UnknownKey,
+
+    /// The ID token is invalid, for example because it's expired or has the wrong audience.
+    InvalidIdToken(jsonwebtoken::errors::Error),
+
+    /// The nonce in the ID token doesn't match the nonce we sent.
+    NonceMismatch,
+
+    /// The ID token doesn't contain an email address that was verified by the identity provider.
+    EmailNotVerified,
+}
+
+impl fmt::Display for OidcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OidcError::Http(err) => write!(f, "Failed to contact the identity provider: {}", err),
+            OidcError::InvalidUrl(url) => write!(f, "Invalid identity provider URL: {}", url),
+            OidcError::UnknownKey => write!(f, "The ID token was signed with an unknown key."),
+            OidcError::InvalidIdToken(err) => write!(f, "The ID token is invalid: {}", err),
+            OidcError::NonceMismatch => write!(f, "The ID token has an unexpected nonce."),
+            OidcError::EmailNotVerified => {
+                write!(f, "The ID token doesn't contain a verified email address.")
+            }
+        }
+    }
+}
+
+impl From<reqwest::Error> for OidcError {
+    fn from(value: reqwest::Error) -> Self {
+        OidcError::Http(value)
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for OidcError {
+    fn from(value: jsonwebtoken::errors::Error) -> Self {
+        OidcError::InvalidIdToken(value)
+    }
+}
+
+/// Client for the configured OpenID Connect identity provider.
+pub struct OidcClient {
+    config: OidcConfig,
+    http_client: reqwest::Client,
+    provider: RwLock<Option<Cached<ProviderMetadata>>>,
+    jwks: RwLock<Option<Cached<JwkSet>>>,
+}
+
+impl fmt::Debug for OidcClient {
+    /// Formats the client without exposing the client secret.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OidcClient")
+            .field("issuer", &self.config.issuer)
+            .field("client_id", &self.config.client.id)
+            .finish()
+    }
+}
+
+impl OidcClient {
+    /// Creates a new client for the identity provider.
+    ///
+    /// This doesn't contact the identity provider yet. The discovery document is fetched on the first login.
+    pub fn new(config: OidcConfig) -> Self {
+        Self {
+            config,
+            http_client: reqwest::Client::new(),
+            provider: RwLock::new(None),
+            jwks: RwLock::new(None),
+        }
+    }
+
+    /// Creates a new authorization request with a fresh state, nonce and PKCE code verifier.
+    pub async fn authorization_request(&self) -> Result<AuthorizationRequest, OidcError> {
+        let provider = self.provider().await?;
+
+        let state = random_string(32);
+        let nonce = random_string(32);
+        let code_verifier = random_string(64);
+        let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+
+        let url = Url::parse_with_params(
+            &provider.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", self.config.client.id.as_str()),
+                ("redirect_uri", self.config.callback.as_str()),
+                ("scope", "openid email"),
+                ("state", state.as_str()),
+                ("nonce", nonce.as_str()),
+                ("code_challenge", code_challenge.as_str()),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(|_| OidcError::InvalidUrl(provider.authorization_endpoint.clone()))?;
+
+        Ok(AuthorizationRequest {
+            url: url.to_string(),
+            state,
+            nonce,
+            code_verifier,
+        })
+    }
+
+    /// Exchanges an authorization code for an ID token and returns the verified identity of the user.
+    pub async fn exchange_code(
+        &self,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> Result<VerifiedIdentity, OidcError> {
+        let provider = self.provider().await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.config.callback.as_str()),
+            ("client_id", self.config.client.id.as_str()),
+            ("code_verifier", code_verifier),
+        ];
+
+        if let Some(client_secret) = &self.config.client.secret {
+            form.push(("client_secret", client_secret.as_str()));
+        }
+
+        let token_response: TokenResponse = self
+            .http_client
+            .post(&provider.token_endpoint)
+            .form(&form)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let claims = self
+            .validate_id_token(&provider, &token_response.id_token)
+            .await?;
+
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(OidcError::NonceMismatch);
+        }
+
+        match claims.email {
+            Some(email_address) if claims.email_verified => Ok(VerifiedIdentity {
+                subject: claims.sub,
+                email_address,
+            }),
+            _ => Err(OidcError::EmailNotVerified),
+        }
+    }
+
+    async fn validate_id_token(
+        &self,
+        provider: &ProviderMetadata,
+        id_token: &str,
+    ) -> Result<IdTokenClaims, OidcError> {
+        let header = jsonwebtoken::decode_header(id_token)?;
+        let kid = header.kid.ok_or(OidcError::UnknownKey)?;
+        let decoding_key = self.decoding_key(provider, &kid).await?;
+
+        // The algorithm must match the family of the key from the JWKS, so a token can't switch to a symmetric
+        // algorithm and use the public key as the secret.
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&provider.issuer]);
+        validation.set_audience(&[&self.config.client.id]);
+
+        let token_data =
+            jsonwebtoken::decode::<IdTokenClaims>(id_token, &decoding_key, &validation)?;
+
+        Ok(token_data.claims)
+    }
+
+    /// Returns the discovery document of the identity provider, fetching it when it's not cached.
+    async fn provider(&self) -> Result<ProviderMetadata, OidcError> {
+        if let Some(provider) = self
+            .provider
+            .read()
+            .await
+            .as_ref()
+            .and_then(Cached::fresh_value)
+        {
+            return Ok(provider);
+        }
+
+        let discovery_url = format!(
+            "{}/.well-known/openid-configuration",
+            self.config.issuer.trim_end_matches('/')
+        );
+
+        let provider: ProviderMetadata = self
+            .http_client
+            .get(discovery_url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        *self.provider.write().await = Some(Cached {
+            value: provider.clone(),
+            fetched_at: Instant::now(),
+        });
+
+        Ok(provider)
+    }
+
+    /// Returns the decoding key with the given key ID.
+    ///
+    /// We look in the cached JWKS first. If the key isn't there, we fetch the JWKS again because the identity provider
+    /// may have rotated its keys since we last fetched them.
+    async fn decoding_key(
+        &self,
+        provider: &ProviderMetadata,
+        kid: &str,
+    ) -> Result<DecodingKey, OidcError> {
+        if let Some(jwks) = self
+            .jwks
+            .read()
+            .await
+            .as_ref()
+            .and_then(Cached::fresh_value)
+        {
+            if let Some(jwk) = jwks.find(kid) {
+                return Ok(DecodingKey::from_jwk(jwk)?);
+            }
+        }
+
+        let jwks: JwkSet = self
+            .http_client
+            .get(&provider.jwks_uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let decoding_key = jwks.find(kid).map(DecodingKey::from_jwk);
+
+        *self.jwks.write().await = Some(Cached {
+            value: jwks,
+            fetched_at: Instant::now(),
+        });
+
+        Ok(decoding_key.ok_or(OidcError::UnknownKey)??)
+    }
+}
+
+/// Generates a random string that is safe to use in URLs.
+fn random_string(length: usize) -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
+}
@@ -0,0 +1,379 @@
+//! This module contains the logic to issue and validate JSON Web Tokens (JWT).
+//!
+//! Tokens are signed with a shared secret using HMAC-SHA256. Because the API both issues and validates the tokens,
+//! there's no need to call an external identity provider to check a token. Everything we need is in the token itself
+//! and in the signing keys from the [`crate::config::AuthConfig`].
+//!
+//! We issue two kinds of tokens:
+//!
+//! * An access token that is short-lived and is sent in the `Authorization: Bearer <token>` header of each request.
+//! * A refresh token that lives longer and can only be exchanged for a new pair of tokens.
+//!
+//! In addition, we issue verification tokens that are emailed to users to prove they own their email address.
+//!
+//! Each token carries the ID of the key it was signed with in the `kid` header. This allows us to rotate keys without
+//! invalidating tokens that were issued before the rotation.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+
+use crate::config::AuthConfig;
+
+/// The kind of token, stored in the `typ` claim so an access token can't be used as a refresh token or vice versa.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenType {
+    Access,
+    Refresh,
+    Verification,
+}
+
+/// The claims we store in a token.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Claims {
+    /// The ID of the user the token was issued to.
+    pub sub: String,
+
+    /// The name of the party that issued the token.
+    pub iss: String,
+
+    /// The time the token was issued as a unix timestamp.
+    pub iat: i64,
+
+    /// The time the token expires as a unix timestamp.
+    pub exp: i64,
+
+    /// The kind of token.
+    pub typ: TokenType,
+
+    /// Whether the user had verified their email address when the token was issued.
+    #[serde(default)]
+    pub verified: bool,
+
+    /// The email address that is verified with a verification token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+
+    /// The workspace the token is limited to, when it was issued for an API key of a workspace.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<i32>,
+}
+
+impl Claims {
+    /// Returns the ID of the user the token was issued to.
+    pub fn user_id(&self) -> Result<i32, TokenError> {
+        self.sub.parse().map_err(|_| TokenError::InvalidSubject)
+    }
+}
+
+/// A pair of tokens issued to a user.
+pub struct IssuedTokens {
+    pub access_token: String,
+    pub refresh_token: String,
+
+    /// The number of seconds until the access token expires.
+    pub expires_in: i64,
+}
+
+/// The different types of errors that can occur when issuing or validating tokens.
+#[derive(Debug)]
+pub enum TokenError {
+    /// There's no signing key configured, so we can't issue tokens.
+    SigningKeyMissing,
+
+    /// The token was signed with a key we don't know.
+    UnknownKey,
+
+    /// The token is of the wrong type, for example a refresh token was used as an access token.
+    WrongTokenType,
+
+    /// The subject of the token isn't a valid user ID.
+    InvalidSubject,
+
+    /// The token couldn't be encoded or decoded. This includes expired tokens and invalid signatures.
+    Jwt(jsonwebtoken::errors::Error),
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenError::SigningKeyMissing => write!(f, "No signing key is configured."),
+            TokenError::UnknownKey => write!(f, "The token was signed with an unknown key."),
+            TokenError::WrongTokenType => write!(f, "The token has the wrong type."),
+            TokenError::InvalidSubject => write!(f, "The token has an invalid subject."),
+            TokenError::Jwt(err) => write!(f, "The token is invalid: {}", err),
+        }
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for TokenError {
+    fn from(value: jsonwebtoken::errors::Error) -> Self {
+        TokenError::Jwt(value)
+    }
+}
+
+/// A single signing key with its encoding and decoding forms.
+#[derive(Clone)]
+struct SigningKey {
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+}
+
+/// Issues and validates tokens using the signing keys from the configuration.
+#[derive(Clone)]
+pub struct TokenIssuer {
+    issuer: String,
+    signing_kid: Option<String>,
+    keys: HashMap<String, SigningKey>,
+    access_ttl: i64,
+    refresh_ttl: i64,
+    verification_ttl: i64,
+}
+
+impl fmt::Debug for TokenIssuer {
+    /// Formats the token issuer without exposing the signing keys.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenIssuer")
+            .field("issuer", &self.issuer)
+            .field("signing_kid", &self.signing_kid)
+            .field("kids", &self.keys.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl TokenIssuer {
+    /// Creates a new token issuer from the authentication configuration.
+    pub fn new(config: &AuthConfig) -> Self {
+        let keys = config
+            .keys
+            .iter()
+            .map(|(kid, secret)| {
+                let signing_key = SigningKey {
+                    encoding_key: EncodingKey::from_secret(secret.as_bytes()),
+                    decoding_key: DecodingKey::from_secret(secret.as_bytes()),
+                };
+
+                (kid.to_lowercase(), signing_key)
+            })
+            .collect();
+
+        Self {
+            issuer: config.issuer.clone(),
+            signing_kid: config.kid.as_ref().map(|kid| kid.to_lowercase()),
+            keys,
+            access_ttl: config.ttl.access,
+            refresh_ttl: config.ttl.refresh,
+            verification_ttl: config.ttl.verification,
+        }
+    }
+
+    /// Issues a new access token and refresh token for a user.
+    ///
+    /// The tokens record whether the user has verified their email address, so we can limit what unverified users
+    /// can do without looking them up in the database. Tokens issued for an API key of a workspace are limited to that
+    /// workspace, just like the key.
+    pub fn issue(
+        &self,
+        user_id: i32,
+        verified: bool,
+        workspace: Option<i32>,
+    ) -> Result<IssuedTokens, TokenError> {
+        let access_token = self.sign(
+            user_id,
+            TokenType::Access,
+            self.access_ttl,
+            verified,
+            None,
+            workspace,
+        )?;
+        let refresh_token = self.sign(
+            user_id,
+            TokenType::Refresh,
+            self.refresh_ttl,
+            verified,
+            None,
+            workspace,
+        )?;
+
+        Ok(IssuedTokens {
+            access_token,
+            refresh_token,
+            expires_in: self.access_ttl,
+        })
+    }
+
+    /// Issues a token that proves the user owns the given email address.
+    pub fn issue_verification_token(
+        &self,
+        user_id: i32,
+        email_address: &str,
+    ) -> Result<String, TokenError> {
+        self.sign(
+            user_id,
+            TokenType::Verification,
+            self.verification_ttl,
+            false,
+            Some(email_address.to_string()),
+            None,
+        )
+    }
+
+    /// Validates a token and returns its claims.
+    ///
+    /// The token must be signed with one of the configured keys, must not be expired, must be issued by us,
+    /// and must be of the expected type.
+    pub fn validate(&self, token: &str, expected_type: TokenType) -> Result<Claims, TokenError> {
+        let header = jsonwebtoken::decode_header(token)?;
+
+        let signing_key = header
+            .kid
+            .and_then(|kid| self.keys.get(&kid))
+            .ok_or(TokenError::UnknownKey)?;
+
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.set_issuer(&[&self.issuer]);
+
+        let token_data =
+            jsonwebtoken::decode::<Claims>(token, &signing_key.decoding_key, &validation)?;
+
+        if token_data.claims.typ != expected_type {
+            return Err(TokenError::WrongTokenType);
+        }
+
+        Ok(token_data.claims)
+    }
+
+    fn sign(
+        &self,
+        user_id: i32,
+        token_type: TokenType,
+        ttl: i64,
+        verified: bool,
+        email: Option<String>,
+        workspace: Option<i32>,
+    ) -> Result<String, TokenError> {
+        let (kid, signing_key) = self
+            .signing_kid
+            .as_ref()
+            .and_then(|kid| self.keys.get_key_value(kid))
+            .ok_or(TokenError::SigningKeyMissing)?;
+
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some(kid.clone());
+
+        let now = chrono::Utc::now().timestamp();
+
+        let claims = Claims {
+            sub: user_id.to_string(),
+            iss: self.issuer.clone(),
+            iat: now,
+            exp: now + ttl,
+            typ: token_type,
+            verified,
+            email,
+            workspace,
+        };
+
+        Ok(jsonwebtoken::encode(
+            &header,
+            &claims,
+            &signing_key.encoding_key,
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TokenTtlConfig;
+
+    fn create_config(kid: &str, keys: &[(&str, &str)]) -> AuthConfig {
+        AuthConfig {
+            issuer: "todo-api".to_string(),
+            kid: Some(kid.to_string()),
+            keys: keys
+                .iter()
+                .map(|(kid, secret)| (kid.to_string(), secret.to_string()))
+                .collect(),
+            ttl: TokenTtlConfig {
+                access: 900,
+                refresh: 3600,
+                verification: 600,
+            },
+        }
+    }
+
+    #[test]
+    fn issued_access_token_is_valid() {
+        let issuer = TokenIssuer::new(&create_config("a", &[("a", "secret-a")]));
+        let tokens = issuer.issue(42, true, None).unwrap();
+
+        let claims = issuer
+            .validate(&tokens.access_token, TokenType::Access)
+            .unwrap();
+
+        assert_eq!(claims.user_id().unwrap(), 42);
+        assert!(claims.verified);
+        assert_eq!(tokens.expires_in, 900);
+    }
+
+    #[test]
+    fn verification_token_contains_email_address() {
+        let issuer = TokenIssuer::new(&create_config("a", &[("a", "secret-a")]));
+        let token = issuer
+            .issue_verification_token(42, "jane@example.org")
+            .unwrap();
+
+        let claims = issuer.validate(&token, TokenType::Verification).unwrap();
+
+        assert_eq!(claims.email.as_deref(), Some("jane@example.org"));
+        assert!(issuer.validate(&token, TokenType::Access).is_err());
+    }
+
+    #[test]
+    fn refresh_token_is_rejected_as_access_token() {
+        let issuer = TokenIssuer::new(&create_config("a", &[("a", "secret-a")]));
+        let tokens = issuer.issue(42, true, None).unwrap();
+
+        let result = issuer.validate(&tokens.refresh_token, TokenType::Access);
+
+        assert!(matches!(result, Err(TokenError::WrongTokenType)));
+    }
+
+    #[test]
+    fn token_signed_with_rotated_key_remains_valid() {
+        let old_issuer = TokenIssuer::new(&create_config("a", &[("a", "secret-a")]));
+        let new_issuer =
+            TokenIssuer::new(&create_config("b", &[("a", "secret-a"), ("b", "secret-b")]));
+
+        let tokens = old_issuer.issue(42, true, None).unwrap();
+
+        assert!(new_issuer
+            .validate(&tokens.access_token, TokenType::Access)
+            .is_ok());
+    }
+
+    #[test]
+    fn token_signed_with_removed_key_is_rejected() {
+        let old_issuer = TokenIssuer::new(&create_config("a", &[("a", "secret-a")]));
+        let new_issuer = TokenIssuer::new(&create_config("b", &[("b", "secret-b")]));
+
+        let tokens = old_issuer.issue(42, true, None).unwrap();
+        let result = new_issuer.validate(&tokens.access_token, TokenType::Access);
+
+        assert!(matches!(result, Err(TokenError::UnknownKey)));
+    }
+
+    #[test]
+    fn issue_without_signing_key_fails() {
+        let issuer = TokenIssuer::new(&create_config("missing", &[("a", "secret-a")]));
+
+        assert!(matches!(
+            issuer.issue(42, true, None),
+            Err(TokenError::SigningKeyMissing)
+        ));
+    }
+}
@@ -0,0 +1,74 @@
+//! This module renders the comments on tasks.
+//!
+//! ## Markdown
+//! Comments are written in markdown, with the GitHub extensions for tables, strikethrough and task lists. We render
+//! the markdown to HTML when a comment is saved, and keep both: the markdown to edit the comment, and the HTML to show
+//! it.
+//!
+//! Markdown allows raw HTML, so the rendered comment could contain scripts, event handlers or `javascript:` links. We
+//! run the HTML through [`ammonia`], which only leaves a list of safe tags and attributes. Clients can show the HTML of
+//! a comment as is.
+//!
+//! ## Mentions
+//! Comments mention users the same way descriptions of tasks do, see [`crate::notifications::parse_mentions`]. The
+//! users who are mentioned are notified when the comment is created, and when an edit mentions them for the first
+//! time.
+
+use pulldown_cmark::{html, Options, Parser};
+
+/// The number of characters a comment can have at most.
+pub const MAX_COMMENT_LENGTH: usize = 10_000;
+
+/// Returns whether a comment has at least one and at most [`MAX_COMMENT_LENGTH`] characters, not counting the
+/// whitespace around it.
+pub fn is_valid_comment(body: &str) -> bool {
+    let body = body.trim();
+
+    !body.is_empty() && body.chars().count() <= MAX_COMMENT_LENGTH
+}
+
+/// Renders the markdown of a comment to HTML, and removes everything from it that isn't safe to show.
+pub fn render_markdown(body: &str) -> String {
+    let options =
+        Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let parser = Parser::new_ext(body, options);
+
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, parser);
+
+    ammonia::clean(&unsafe_html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markdown_is_rendered() {
+        let html = render_markdown("Looks **good**, see [the docs](https://example.org).");
+
+        assert_eq!(
+            html.trim(),
+            "<p>Looks <strong>good</strong>, see <a href=\"https://example.org\" rel=\"noopener noreferrer\">the docs</a>.</p>"
+        );
+    }
+
+    #[test]
+    fn unsafe_html_is_removed() {
+        let html = render_markdown(
+            "<script>alert(1)</script><img src=x onerror=alert(1)>\n\n[click](javascript:alert(1))",
+        );
+
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("onerror"));
+        assert!(!html.contains("href"));
+        assert!(html.contains("<img src=\"x\">"));
+    }
+
+    #[test]
+    fn comments_are_validated() {
+        assert!(is_valid_comment("Done!"));
+        assert!(!is_valid_comment(" \n "));
+        assert!(!is_valid_comment(&"a".repeat(MAX_COMMENT_LENGTH + 1)));
+    }
+}
@@ -5,33 +5,266 @@
 //! bit more low level but still very powerful.
 
 use crate::{
-    config::DatabaseConfig,
-    entity::{PagedResult, Task, User},
+    attachments::DeleteBlob,
+    comments::render_markdown,
+    config::{DatabaseConfig, SslMode},
+    entity::{
+        ApiKey, Attachment, Comment, CommentRevision, DueReminder, Job, JobCounts, JobStatus,
+        Notification, NotificationKind, OidcLoginRequest, PagedResult, PendingWebhookDelivery,
+        Project, Reminder, ReminderChannel, Role, Share, SharedItem, Task, TaskDependency,
+        TaskEvent, TaskEventType, TaskGraph, TaskNode, TaskUser, User, UserChanges, UserTask,
+        Webhook, WebhookDelivery, Workspace, WorkspaceInvitation, WorkspaceKey, WorkspaceMember,
+        WorkspaceRole,
+    },
     error::{AppError, Result},
+    jobs::JobPayload,
+    monitoring::{PoolWaitGuard, QueryTimer},
+    notifications::{parse_mentions, SendNotification},
+    redact::mask_email,
+    webhooks::PublishWebhooks,
 };
-use sqlx::postgres::{PgConnectOptions, PgPool, PgPoolOptions};
-use tracing::{event, instrument, Level};
+use serde::Serialize;
+use sqlx::pool::PoolConnection;
+use sqlx::postgres::{PgConnectOptions, PgConnection, PgPool, PgPoolOptions, PgSslMode};
+use sqlx::{Acquire, Postgres};
+use std::ops::{Deref, DerefMut};
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::{event, instrument, Level, Span};
+
+/// The migrations in the `sql` folder that the application expects to be applied to the database.
+///
+/// Make sure to add new migrations to this list, so the readiness check reports them when they're not applied yet.
+pub const MIGRATIONS: &[&str] = &[
+    "00-create-tasks-table",
+    "01-create-users-table",
+    "02-add-user-password",
+    "03-create-oidc-login-requests-table",
+    "04-add-user-email-verification",
+    "05-create-schema-migrations-table",
+    "06-create-webhooks-tables",
+    "07-create-task-events-table",
+    "08-create-jobs-table",
+    "09-create-reminders-tables",
+    "10-create-sharing-tables",
+    "11-create-workspaces-tables",
+    "12-create-assignment-tables",
+    "13-create-comments-tables",
+    "14-create-attachments-table",
+    "15-create-task-dependencies-table",
+    "16-add-user-credentials-reset",
+    "17-add-task-event-workspace",
+];
+
+/// The channel we notify with the ID of every new task event, see [`crate::events`].
+pub const TASK_EVENTS_CHANNEL: &str = "task_events";
 
 /// Creates a new database connection pool for the PostgreSQL database
 /// based on the provided configuration.
+///
+/// The database may still be starting when the application starts. We therefore retry the initial connection with an
+/// exponential backoff as configured in [`crate::config::RetryConfig`], and only fail when all attempts failed.
 #[instrument(skip(config))]
 pub async fn connect_db(config: &DatabaseConfig) -> Result<PgPool> {
-    event!(Level::INFO, "Connecting to the database");
+    let options = connect_options(config)?;
+
+    let pool_options = PgPoolOptions::new()
+        .max_connections(config.pool.max)
+        .min_connections(config.pool.min)
+        .acquire_timeout(Duration::from_secs(config.pool.acquire))
+        .idle_timeout(optional_seconds(config.pool.idle))
+        .max_lifetime(optional_seconds(config.pool.lifetime));
+
+    let mut attempt = 1;
+
+    loop {
+        event!(Level::INFO, attempt, "Connecting to the database");
+
+        match pool_options.clone().connect_with(options.clone()).await {
+            Ok(pool) => return Ok(pool),
+            Err(err) if attempt < config.retry.attempts => {
+                let delay = config.retry.backoff(attempt);
+
+                event!(
+                    Level::WARN,
+                    attempt,
+                    error = %err,
+                    "Failed to connect to the database, retrying in {} seconds",
+                    delay.as_secs()
+                );
+
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+/// Translates the database configuration into the options for a connection.
+fn connect_options(config: &DatabaseConfig) -> Result<PgConnectOptions> {
+    let mut options = match &config.url {
+        Some(url) => PgConnectOptions::from_str(url)?,
+        None => PgConnectOptions::new()
+            .host(&config.host)
+            .port(config.port)
+            .database(&config.name)
+            .username(&config.username)
+            .password(&config.password),
+    };
+
+    options = options.application_name(&config.application);
+
+    if let Some(mode) = config.ssl.mode {
+        options = options.ssl_mode(ssl_mode(mode));
+    }
+
+    if let Some(root) = &config.ssl.root {
+        options = options.ssl_root_cert(root);
+    }
+
+    // The timeout is sent as a startup parameter, so it applies to every statement on the connection.
+    if config.statement.timeout > 0 {
+        options = options.options([(
+            "statement_timeout",
+            format!("{}s", config.statement.timeout),
+        )]);
+    }
+
+    Ok(options)
+}
+
+fn ssl_mode(mode: SslMode) -> PgSslMode {
+    match mode {
+        SslMode::Disable => PgSslMode::Disable,
+        SslMode::Allow => PgSslMode::Allow,
+        SslMode::Prefer => PgSslMode::Prefer,
+        SslMode::Require => PgSslMode::Require,
+        SslMode::VerifyCa => PgSslMode::VerifyCa,
+        SslMode::VerifyFull => PgSslMode::VerifyFull,
+    }
+}
+
+/// Translates a number of seconds into a duration, where 0 means there's no limit.
+fn optional_seconds(seconds: u64) -> Option<Duration> {
+    (seconds > 0).then(|| Duration::from_secs(seconds))
+}
+
+/// Takes a connection from the pool for a single query.
+///
+/// We acquire connections explicitly instead of passing the pool to [`sqlx`], so we can record how long requests
+/// wait for a connection when the pool is exhausted.
+async fn acquire(pool: &PgPool) -> Result<PoolConnection<Postgres>> {
+    let wait_guard = PoolWaitGuard::start();
+    let connection = pool.acquire().await?;
+    wait_guard.acquired();
+
+    Ok(connection)
+}
+
+/// Takes a connection from the pool for queries on behalf of a user.
+///
+/// The connection has `app.user_id` set to the user, so the row-level security policies in the database limit the
+/// queries to the rows the user can access. This is a second line of defence: the queries filter on the user
+/// themselves as well. The setting is reset when the connection goes back to the pool.
+async fn acquire_as(pool: &PgPool, user_id: i32) -> Result<UserConnection> {
+    let mut connection = acquire(pool).await?;
+
+    sqlx::query("SELECT set_config('app.user_id', $1, false)")
+        .bind(user_id.to_string())
+        .execute(&mut *connection)
+        .await?;
+
+    Ok(UserConnection(Some(connection)))
+}
+
+/// A connection that runs queries on behalf of a user, see [`acquire_as`].
+struct UserConnection(Option<PoolConnection<Postgres>>);
+
+impl Deref for UserConnection {
+    type Target = PgConnection;
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+            .as_ref()
+            .expect("the connection is only taken when dropped")
+    }
+}
+
+impl DerefMut for UserConnection {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0
+            .as_mut()
+            .expect("the connection is only taken when dropped")
+    }
+}
+
+impl Drop for UserConnection {
+    /// Resets the user on the connection before it goes back to the pool.
+    ///
+    /// The reset runs in the background, so it doesn't slow down the request. A connection that can't be reset is
+    /// closed instead, so it's never used for queries of another user.
+    fn drop(&mut self) {
+        let Some(mut connection) = self.0.take() else {
+            return;
+        };
+
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            drop(connection.detach());
+            return;
+        };
+
+        runtime.spawn(async move {
+            if sqlx::query("RESET app.user_id")
+                .execute(&mut *connection)
+                .await
+                .is_err()
+            {
+                drop(connection.detach());
+            }
+        });
+    }
+}
 
-    let options = PgConnectOptions::new()
-        .host(&config.host)
-        .port(config.port)
-        .database(&config.name)
-        .username(&config.username)
-        .password(&config.password);
+/// Checks that we can run a query against the database.
+#[instrument(skip(pool))]
+pub async fn ping(pool: &PgPool) -> Result<()> {
+    let _timer = QueryTimer::start("ping");
 
-    let pool = PgPoolOptions::new()
-        .max_connections(12)
-        .min_connections(2)
-        .connect_with(options)
+    sqlx::query("SELECT 1")
+        .execute(&mut *acquire(pool).await?)
         .await?;
 
-    Ok(pool)
+    Ok(())
+}
+
+/// Returns the migrations from [`MIGRATIONS`] that aren't applied to the database yet.
+///
+/// When the `schema_migrations` table itself is missing, none of the tracked migrations are considered applied.
+#[instrument(skip(pool))]
+pub async fn pending_migrations(pool: &PgPool) -> Result<Vec<String>> {
+    let _timer = QueryTimer::start("pending_migrations");
+
+    let table_exists =
+        sqlx::query_scalar::<_, bool>("SELECT to_regclass('schema_migrations') IS NOT NULL")
+            .fetch_one(&mut *acquire(pool).await?)
+            .await?;
+
+    let applied: Vec<String> = if table_exists {
+        sqlx::query_scalar::<_, String>("SELECT name FROM schema_migrations")
+            .fetch_all(&mut *acquire(pool).await?)
+            .await?
+    } else {
+        Vec::new()
+    };
+
+    let pending = MIGRATIONS
+        .iter()
+        .filter(|name| !applied.iter().any(|applied_name| applied_name == *name))
+        .map(|name| name.to_string())
+        .collect();
+
+    Ok(pending)
 }
 
 /// List all tasks in the database for a specific user.
@@ -42,24 +275,46 @@ pub async fn connect_db(config: &DatabaseConfig) -> Result<PgPool> {
 ///
 /// It's important to note that [`sqlx`] is not an ORM, so you'll need to write the SQL queries yourself. But you get strong
 /// typing for result types so that's a good trade off when you want performance.
+///
+/// The tasks can be limited to a workspace, and to the tasks the user is assigned to. Every task includes the number of
+/// comments on it. We count them in a subquery of the same query, rather than with a query per task.
+#[instrument(skip(pool))]
 pub async fn list_tasks(
     pool: &PgPool,
     user_id: i32,
+    workspace_id: Option<i32>,
+    assigned_to_me: bool,
     page_index: i32,
     page_size: i32,
 ) -> Result<PagedResult<Task>> {
+    let _timer = QueryTimer::start("list_tasks");
+
     let items = sqlx::query_as::<_, Task>(
-        "SELECT id, title, description,completed, date_created, date_modified FROM tasks WHERE user_id =$1 ORDER BY id LIMIT $2 OFFSET $3",
+        "SELECT id, title, description, completed, due_at, project_id, workspace_id, date_created, date_modified,
+         (SELECT COUNT(*) FROM comments WHERE comments.task_id = tasks.id) AS comment_count,
+         EXISTS (SELECT 1 FROM task_dependencies WHERE task_dependencies.task_id = tasks.id AND NOT task_dependencies.blocker_completed) AS blocked FROM tasks
+         WHERE id IN (SELECT task_id FROM task_access WHERE user_id = $1) AND ($2::integer IS NULL OR workspace_id = $2)
+         AND (NOT $3 OR id IN (SELECT task_id FROM task_assignees WHERE user_id = $1))
+         ORDER BY id LIMIT $4 OFFSET $5",
     )
     .bind(user_id)
-    .bind(10)
+    .bind(workspace_id)
+    .bind(assigned_to_me)
+    .bind(page_size)
     .bind(page_index * page_size)
-    .fetch_all(pool)
+    .fetch_all(&mut *acquire_as(pool, user_id).await?)
     .await?;
 
-    let total_count: i64 = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM tasks")
-        .fetch_one(pool)
-        .await?;
+    let total_count: i64 = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM tasks
+         WHERE id IN (SELECT task_id FROM task_access WHERE user_id = $1) AND ($2::integer IS NULL OR workspace_id = $2)
+         AND (NOT $3 OR id IN (SELECT task_id FROM task_assignees WHERE user_id = $1))",
+    )
+    .bind(user_id)
+    .bind(workspace_id)
+    .bind(assigned_to_me)
+    .fetch_one(&mut *acquire_as(pool, user_id).await?)
+    .await?;
 
     Ok(PagedResult {
         items,
@@ -69,17 +324,57 @@ pub async fn list_tasks(
     })
 }
 
+/// Lists all tasks a user can access without pagination, including the tasks that are shared with them and the tasks
+/// in their workspaces.
+///
+/// This is used to export all data we store about a user, so it's not meant for regular listing of tasks.
+#[instrument(skip(pool))]
+pub async fn list_all_tasks(pool: &PgPool, user_id: i32) -> Result<Vec<Task>> {
+    let _timer = QueryTimer::start("list_all_tasks");
+
+    let items = sqlx::query_as::<_, Task>(
+        "SELECT id, title, description, completed, due_at, project_id, workspace_id, date_created, date_modified,
+         EXISTS (SELECT 1 FROM task_dependencies WHERE task_dependencies.task_id = tasks.id AND NOT task_dependencies.blocker_completed) AS blocked FROM tasks
+         WHERE id IN (SELECT task_id FROM task_access WHERE user_id = $1) ORDER BY id",
+    )
+    .bind(user_id)
+    .fetch_all(&mut *acquire_as(pool, user_id).await?)
+    .await?;
+
+    Ok(items)
+}
+
+/// Counts the open and completed todo items of all users.
+///
+/// This is used to report the number of tasks in the metrics.
+#[instrument(skip(pool))]
+pub async fn count_tasks_by_state(pool: &PgPool) -> Result<(i64, i64)> {
+    let _timer = QueryTimer::start("count_tasks_by_state");
+
+    let counts = sqlx::query_as::<_, (i64, i64)>(
+        "SELECT COUNT(*) FILTER (WHERE NOT completed), COUNT(*) FILTER (WHERE completed) FROM tasks",
+    )
+    .fetch_one(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(counts)
+}
+
 /// Finds a single todo item in the database by its ID.
 ///
 /// We'll return [`std::result::Result::Ok`] with the [`Task`] if the todo is found,
 /// otherwise we'll return [`std::result::Result::Err`] with the [`AppError::TodoNotFound`] error.
+#[instrument(skip(pool))]
 pub async fn find_task(pool: &PgPool, user_id: i32, task_id: i32) -> Result<Task> {
+    let _timer = QueryTimer::start("find_task");
+
     let result: Option<Task> = sqlx::query_as::<_, Task>(
-        "SELECT id, title, description, completed, date_created, date_modified FROM tasks WHERE user_id = $1 AND id = $2 LIMIT 1",
+        "SELECT id, title, description, completed, due_at, project_id, workspace_id, date_created, date_modified,
+         EXISTS (SELECT 1 FROM task_dependencies WHERE task_dependencies.task_id = tasks.id AND NOT task_dependencies.blocker_completed) AS blocked FROM tasks WHERE user_id = $1 AND id = $2 LIMIT 1",
     )
     .bind(user_id)
     .bind(task_id)
-    .fetch_optional(pool)
+    .fetch_optional(&mut *acquire_as(pool, user_id).await?)
     .await?;
 
     match result {
@@ -90,34 +385,119 @@ pub async fn find_task(pool: &PgPool, user_id: i32, task_id: i32) -> Result<Task
 
 /// Inserts a new todo item in the database returning its ID.
 ///
-/// We use the `RETURNING id` clause to return the ID of the newly inserted task.
-#[instrument]
+/// We use the `RETURNING` clause to get the newly inserted task, which we record in a `task.created` event in the
+/// same transaction. Tasks without a workspace are personal tasks of the user. The users mentioned in the description
+/// are notified, see [`crate::notifications`].
+#[instrument(skip(pool, title, description))]
 pub async fn insert_task(
     pool: &PgPool,
     user_id: i32,
+    workspace_id: Option<i32>,
+    title: String,
+    description: String,
+    due_at: Option<chrono::NaiveDateTime>,
+) -> Result<i32> {
+    insert_task_within_quota(
+        pool,
+        user_id,
+        workspace_id,
+        title,
+        description,
+        due_at,
+        None,
+    )
+    .await
+}
+
+/// Inserts a new todo item in the database returning its ID, as long as the user has fewer tasks than the maximum.
+///
+/// We lock the row of the user before we count their tasks, so requests that create tasks for the same user at the
+/// same time wait for each other. Otherwise they could all count the same number of tasks and together exceed the
+/// maximum. When the user already has the maximum number of tasks, we return an error with the
+/// [`AppError::QuotaExceeded`] variant.
+#[instrument(skip(pool, title, description), fields(task_id))]
+pub async fn insert_task_within_quota(
+    pool: &PgPool,
+    user_id: i32,
+    workspace_id: Option<i32>,
     title: String,
     description: String,
+    due_at: Option<chrono::NaiveDateTime>,
+    max_tasks: Option<i64>,
 ) -> Result<i32> {
+    let _timer = QueryTimer::start("insert_task");
+
     let date_created = chrono::Utc::now();
 
-    let id: i32 = sqlx::query_scalar(
-        "INSERT INTO tasks (title, description, completed, user_id, date_created) VALUES ($1, $2, false, $3, $4) RETURNING id",
+    let mut connection = acquire_as(pool, user_id).await?;
+    let mut transaction = connection.begin().await?;
+
+    if let Some(max_tasks) = max_tasks {
+        sqlx::query("SELECT id FROM users WHERE id = $1 FOR NO KEY UPDATE")
+            .bind(user_id)
+            .execute(&mut *transaction)
+            .await?;
+
+        let task_count =
+            sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM tasks WHERE user_id = $1")
+                .bind(user_id)
+                .fetch_one(&mut *transaction)
+                .await?;
+
+        if task_count >= max_tasks {
+            return Err(AppError::QuotaExceeded);
+        }
+    }
+
+    let task = sqlx::query_as::<_, Task>(
+        "INSERT INTO tasks (title, description, completed, user_id, workspace_id, due_at, date_created)
+         VALUES ($1, $2, false, $3, $4, $5, $6)
+         RETURNING id, title, description, completed, due_at, project_id, workspace_id, date_created, date_modified,
+         false AS blocked",
     )
     .bind(title)
     .bind(description)
     .bind(user_id)
+    .bind(workspace_id)
+    .bind(due_at)
     .bind(date_created)
-    .fetch_one(pool)
+    .fetch_one(&mut *transaction)
     .await?;
 
-    Ok(id)
+    insert_task_event(
+        &mut transaction,
+        user_id,
+        task.id,
+        task.workspace_id,
+        TaskEventType::TaskCreated,
+        &task,
+    )
+    .await?;
+
+    update_task_mentions(&mut transaction, &task).await?;
+
+    transaction.commit().await?;
+
+    Span::current().record("task_id", task.id);
+
+    Ok(task.id)
 }
 
 /// Updates an existing todo item in the database.
 ///
-/// We use the `rows_affected` method to check if the task was updated successfully.
-/// If no rows were affected, we return an error with the [`AppError::TaskNotFound`] variant.
-#[instrument]
+/// We lock the task first, so we know whether this update completes the task. In that case we record a
+/// `task.completed` event next to the `task.updated` event. When the task doesn't exist, we return an error with the
+/// [`AppError::TaskNotFound`] variant.
+///
+/// A task that waits for tasks that aren't completed can only be completed with `force`, otherwise we return an error
+/// with the [`AppError::TaskBlocked`] variant. We check this in the same transaction and lock the links of the task,
+/// so a blocker that is reopened at the same time either waits for us or is seen by the check. Adding a blocker locks
+/// the task, so it waits for us as well.
+///
+/// When the deadline changes, the reminders of the task are scheduled again, including the ones that were sent for the
+/// old deadline. Users who are newly mentioned in the description are notified.
+#[allow(clippy::too_many_arguments)]
+#[instrument(skip(pool, id, title, description), fields(task_id = id))]
 pub async fn update_task(
     pool: &PgPool,
     user_id: i32,
@@ -125,55 +505,284 @@ pub async fn update_task(
     title: String,
     description: String,
     completed: bool,
+    due_at: Option<chrono::NaiveDateTime>,
+    force: bool,
 ) -> Result<()> {
-    let rows_affected =
-        sqlx::query("UPDATE tasks SET title = $1, description = $2, completed = $3, date_modified = $4 WHERE user_id = $5 AND id = $6")
-            .bind(title)
-            .bind(description)
-            .bind(completed)
-            .bind(chrono::Utc::now())
-            .bind(user_id)
+    let _timer = QueryTimer::start("update_task");
+
+    let mut connection = acquire_as(pool, user_id).await?;
+    let mut transaction = connection.begin().await?;
+
+    let (was_completed, was_due_at) = sqlx::query_as::<_, (bool, Option<chrono::NaiveDateTime>)>(
+        "SELECT completed, due_at FROM tasks WHERE user_id = $1 AND id = $2 FOR UPDATE",
+    )
+    .bind(user_id)
+    .bind(id)
+    .fetch_optional(&mut *transaction)
+    .await?
+    .ok_or(AppError::TaskNotFound)?;
+
+    if completed && !was_completed && !force {
+        let blockers_completed = sqlx::query_scalar::<_, bool>(
+            "SELECT blocker_completed FROM task_dependencies WHERE task_id = $1 FOR SHARE",
+        )
+        .bind(id)
+        .fetch_all(&mut *transaction)
+        .await?;
+
+        if blockers_completed.contains(&false) {
+            return Err(AppError::TaskBlocked);
+        }
+    }
+
+    let task = sqlx::query_as::<_, Task>(
+        "UPDATE tasks SET title = $1, description = $2, completed = $3, due_at = $4, date_modified = $5
+         WHERE user_id = $6 AND id = $7
+         RETURNING id, title, description, completed, due_at, project_id, workspace_id, date_created, date_modified,
+         EXISTS (SELECT 1 FROM task_dependencies WHERE task_dependencies.task_id = tasks.id AND NOT task_dependencies.blocker_completed) AS blocked",
+    )
+    .bind(title)
+    .bind(description)
+    .bind(completed)
+    .bind(due_at)
+    .bind(chrono::Utc::now())
+    .bind(user_id)
+    .bind(id)
+    .fetch_one(&mut *transaction)
+    .await?;
+
+    if task.due_at != was_due_at {
+        sqlx::query("UPDATE reminders SET date_sent = NULL WHERE task_id = $1")
             .bind(id)
-            .execute(pool)
-            .await?
-            .rows_affected();
+            .execute(&mut *transaction)
+            .await?;
 
-    if rows_affected == 0 {
-        return Err(AppError::TaskNotFound);
+        schedule_reminders(&mut transaction, "r.task_id = $1", id).await?;
+    }
+
+    insert_task_event(
+        &mut transaction,
+        user_id,
+        id,
+        task.workspace_id,
+        TaskEventType::TaskUpdated,
+        &task,
+    )
+    .await?;
+
+    update_task_mentions(&mut transaction, &task).await?;
+
+    if task.completed && !was_completed {
+        insert_task_event(
+            &mut transaction,
+            user_id,
+            id,
+            task.workspace_id,
+            TaskEventType::TaskCompleted,
+            &task,
+        )
+        .await?;
     }
 
+    transaction.commit().await?;
+
     Ok(())
 }
 
 /// Deletes a task from the database.
 ///
-/// We use the `rows_affected` method to check if the task was deleted successfully.
-/// If no rows were affected, we return an error with the [`AppError::TaskNotFound`] variant.
-#[instrument]
+/// When no task was deleted, we return an error with the [`AppError::TaskNotFound`] variant. Otherwise we record a
+/// `task.deleted` event with the ID of the task in the same transaction.
+#[instrument(skip(pool, id), fields(task_id = id))]
 pub async fn delete_task(pool: &PgPool, user_id: i32, id: i32) -> Result<()> {
-    let rows_affected = sqlx::query("DELETE FROM tasks WHERE user_id = $1 AND id = $2")
+    let _timer = QueryTimer::start("delete_task");
+
+    let mut connection = acquire_as(pool, user_id).await?;
+    let mut transaction = connection.begin().await?;
+
+    let workspace_id = sqlx::query_scalar::<_, Option<i32>>(
+        "DELETE FROM tasks WHERE user_id = $1 AND id = $2 RETURNING workspace_id",
+    )
+    .bind(user_id)
+    .bind(id)
+    .fetch_optional(&mut *transaction)
+    .await?
+    .ok_or(AppError::TaskNotFound)?;
+
+    insert_task_event(
+        &mut transaction,
+        user_id,
+        id,
+        workspace_id,
+        TaskEventType::TaskDeleted,
+        &serde_json::json!({ "id": id }),
+    )
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+/// Records a change to a task, notifies the instances that stream events to clients and queues the event for the
+/// webhooks of the user in the outbox.
+///
+/// Call this in the same transaction as the change itself. PostgreSQL only delivers the notification when the
+/// transaction commits, so listeners never hear about a change that was rolled back. The notification only contains
+/// the ID of the event, because the payload of a notification is limited to 8000 bytes.
+async fn insert_task_event<T: Serialize>(
+    connection: &mut PgConnection,
+    user_id: i32,
+    task_id: i32,
+    workspace_id: Option<i32>,
+    event_type: TaskEventType,
+    data: &T,
+) -> Result<i64> {
+    let payload = serde_json::to_value(data).expect("Task events can always be serialized.");
+
+    let event_id: i64 = sqlx::query_scalar(
+        "INSERT INTO task_events (user_id, task_id, workspace_id, event_type, payload, date_created) VALUES ($1, $2, $3, $4, $5, $6) RETURNING id",
+    )
+    .bind(user_id)
+    .bind(task_id)
+    .bind(workspace_id)
+    .bind(event_type)
+    .bind(payload)
+    .bind(chrono::Utc::now())
+    .fetch_one(&mut *connection)
+    .await?;
+
+    sqlx::query("SELECT pg_notify($1, $2)")
+        .bind(TASK_EVENTS_CHANNEL)
+        .bind(event_id.to_string())
+        .execute(&mut *connection)
+        .await?;
+
+    insert_job(connection, &PublishWebhooks::new(user_id, event_type, data)).await?;
+
+    Ok(event_id)
+}
+
+/// Retrieves a single task event by its ID.
+#[instrument(skip(pool))]
+pub async fn get_task_event(pool: &PgPool, event_id: i64) -> Result<Option<TaskEvent>> {
+    let _timer = QueryTimer::start("get_task_event");
+
+    let event = sqlx::query_as::<_, TaskEvent>("SELECT * FROM task_events WHERE id = $1")
+        .bind(event_id)
+        .fetch_optional(&mut *acquire(pool).await?)
+        .await?;
+
+    Ok(event)
+}
+
+/// The condition for the task events a user can see: the events of the tasks they own or can access, and the events
+/// of the workspaces they're a member of. The latter includes the tasks that were removed from a workspace since.
+const TASK_EVENT_VISIBLE: &str = "(task_events.user_id = $1
+    OR task_events.task_id IN (SELECT task_id FROM task_access WHERE user_id = $1)
+    OR task_events.workspace_id IN (SELECT workspace_id FROM workspace_members WHERE user_id = $1))";
+
+/// Lists the task events a user can see that came after the given event, oldest first.
+///
+/// This is used to resume the event stream of a client that reconnects. When the credentials of the user are bound
+/// to a workspace, only the events of that workspace are returned.
+#[instrument(skip(pool))]
+pub async fn list_task_events(
+    pool: &PgPool,
+    user_id: i32,
+    scope: Option<i32>,
+    after_id: i64,
+    limit: i64,
+) -> Result<Vec<TaskEvent>> {
+    let _timer = QueryTimer::start("list_task_events");
+
+    let statement = format!(
+        "SELECT * FROM task_events WHERE {} AND ($2::integer IS NULL OR workspace_id = $2) AND id > $3
+         ORDER BY id LIMIT $4",
+        TASK_EVENT_VISIBLE
+    );
+
+    let events = sqlx::query_as::<_, TaskEvent>(&statement)
         .bind(user_id)
-        .bind(id)
-        .execute(pool)
+        .bind(scope)
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(&mut *acquire(pool).await?)
+        .await?;
+
+    Ok(events)
+}
+
+/// Lists the users that can see a task event, see [`list_task_events`].
+///
+/// The event stream asks this once for every event, so it doesn't have to ask it for every client.
+#[instrument(skip(pool, event), fields(event_id = event.id))]
+pub async fn list_task_event_audience(pool: &PgPool, event: &TaskEvent) -> Result<Vec<i32>> {
+    let _timer = QueryTimer::start("list_task_event_audience");
+
+    let audience = sqlx::query_scalar::<_, i32>(
+        "SELECT $1 UNION SELECT user_id FROM task_access WHERE task_id = $2
+         UNION SELECT user_id FROM workspace_members WHERE workspace_id = $3",
+    )
+    .bind(event.user_id)
+    .bind(event.task_id)
+    .bind(event.workspace_id)
+    .fetch_all(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(audience)
+}
+
+/// Lists the task events of all users that came after the given event, oldest first.
+///
+/// This is used to catch up on the notifications we missed while the listener was disconnected.
+#[instrument(skip(pool))]
+pub async fn list_all_task_events(
+    pool: &PgPool,
+    after_id: i64,
+    limit: i64,
+) -> Result<Vec<TaskEvent>> {
+    let _timer = QueryTimer::start("list_all_task_events");
+
+    let events = sqlx::query_as::<_, TaskEvent>(
+        "SELECT * FROM task_events WHERE id > $1 ORDER BY id LIMIT $2",
+    )
+    .bind(after_id)
+    .bind(limit)
+    .fetch_all(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(events)
+}
+
+/// Removes the task events that happened before the given date and returns how many were removed.
+#[instrument(skip(pool))]
+pub async fn delete_task_events_before(
+    pool: &PgPool,
+    before: chrono::NaiveDateTime,
+) -> Result<u64> {
+    let _timer = QueryTimer::start("delete_task_events_before");
+
+    let rows_affected = sqlx::query("DELETE FROM task_events WHERE date_created < $1")
+        .bind(before)
+        .execute(&mut *acquire(pool).await?)
         .await?
         .rows_affected();
 
-    if rows_affected == 0 {
-        return Err(AppError::TaskNotFound);
-    }
-
-    Ok(())
+    Ok(rows_affected)
 }
 
 /// Retrieves a single user from the database by its ID.
 ///
 /// This method returns a [`Result`] with the [`User`] if the user is found.
 /// Otherwise it returns an error.
-#[instrument]
+#[instrument(skip(pool, id), fields(user_id = id))]
 pub async fn get_user_by_id(pool: &PgPool, id: i32) -> Result<User> {
+    let _timer = QueryTimer::start("get_user_by_id");
+
     let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = $1")
         .bind(id)
-        .fetch_one(pool)
+        .fetch_one(&mut *acquire(pool).await?)
         .await
         .map_err(|_| AppError::UserNotFound)?;
 
@@ -184,30 +793,3272 @@ pub async fn get_user_by_id(pool: &PgPool, id: i32) -> Result<User> {
 ///
 /// This method returns a [`Result`] with the [`User`] if the user is found.
 /// Otherwise it returns an error.
-#[instrument(skip(api_key))]
+#[instrument(skip(pool, api_key), fields(user_id))]
 pub async fn get_user_by_key(pool: &PgPool, api_key: &str) -> Result<User> {
+    let _timer = QueryTimer::start("get_user_by_key");
+
     let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE api_key = $1")
         .bind(api_key)
-        .fetch_one(pool)
+        .fetch_one(&mut *acquire(pool).await?)
         .await
         .map_err(|_| AppError::UserNotFound)?;
 
+    Span::current().record("user_id", user.id);
+
+    Ok(user)
+}
+
+/// Retrieves a single user from the database by its email address.
+///
+/// Only users that have set a password are returned, because this method is used to log in with a password.
+/// This method returns a [`Result`] with the [`User`] if the user is found.
+/// Otherwise it returns an error.
+#[instrument(skip(pool, email_address), fields(email_address = %mask_email(email_address), user_id))]
+pub async fn get_user_by_email(pool: &PgPool, email_address: &str) -> Result<User> {
+    let _timer = QueryTimer::start("get_user_by_email");
+
+    let user = sqlx::query_as::<_, User>(
+        "SELECT * FROM users WHERE lower(email_address) = lower($1) AND password_hash IS NOT NULL",
+    )
+    .bind(email_address)
+    .fetch_one(&mut *acquire(pool).await?)
+    .await
+    .map_err(|_| AppError::UserNotFound)?;
+
+    Span::current().record("user_id", user.id);
+
+    Ok(user)
+}
+
+/// Retrieves a single user from the database by the email address in their client certificate.
+///
+/// Unlike [`get_user_by_email`], this also returns users without a password, because the certificate authority
+/// already vouched for the identity of the client.
+#[instrument(skip(pool, email_address), fields(email_address = %mask_email(email_address), user_id))]
+pub async fn get_user_by_certificate_email(pool: &PgPool, email_address: &str) -> Result<User> {
+    let _timer = QueryTimer::start("get_user_by_certificate_email");
+
+    let user =
+        sqlx::query_as::<_, User>("SELECT * FROM users WHERE lower(email_address) = lower($1)")
+            .bind(email_address)
+            .fetch_one(&mut *acquire(pool).await?)
+            .await
+            .map_err(|_| AppError::UserNotFound)?;
+
+    Span::current().record("user_id", user.id);
+
     Ok(user)
 }
 
 /// Inserts a new user profile in the database
 ///
+/// The password hash is optional, users that don't set a password can only log in with their API key.
+/// New users start out with an unverified email address. Email addresses are unique regardless of their casing,
+/// so we return [`AppError::EmailAddressTaken`] when the email address is already registered.
 /// This method returns the ID of the newly inserted user.
-#[instrument(skip(api_key))]
-pub async fn insert_user(pool: &PgPool, email_address: String, api_key: String) -> Result<i32> {
+#[instrument(skip(pool, email_address, api_key, password_hash), fields(email_address = %mask_email(&email_address), user_id))]
+pub async fn insert_user(
+    pool: &PgPool,
+    email_address: String,
+    api_key: String,
+    password_hash: Option<String>,
+) -> Result<i32> {
+    let _timer = QueryTimer::start("insert_user");
+
     let id: i32 = sqlx::query_scalar(
-        "INSERT INTO users (email_address, api_key, date_created) VALUES ($1, $2, $3) RETURNING id",
+        "INSERT INTO users (email_address, api_key, password_hash, date_created) VALUES ($1, $2, $3, $4) RETURNING id",
     )
     .bind(email_address)
     .bind(api_key)
+    .bind(password_hash)
     .bind(chrono::Utc::now())
-    .fetch_one(pool)
-    .await?;
+    .fetch_one(&mut *acquire(pool).await?)
+    .await
+    .map_err(|err| match err.as_database_error() {
+        Some(db_err) if db_err.is_unique_violation() => AppError::EmailAddressTaken,
+        _ => AppError::DbError(err),
+    })?;
+
+    Span::current().record("user_id", id);
 
     Ok(id)
 }
+
+/// Marks the email address of a user as verified.
+///
+/// The email address must still match the address the verification token was issued for. This way a token can't be
+/// used to verify an email address the user changed to after the token was sent.
+#[instrument(skip(pool, email_address), fields(email_address = %mask_email(email_address)))]
+pub async fn mark_email_verified(pool: &PgPool, user_id: i32, email_address: &str) -> Result<()> {
+    let _timer = QueryTimer::start("mark_email_verified");
+
+    let rows_affected = sqlx::query(
+        "UPDATE users SET email_verified = true, date_modified = $1 WHERE id = $2 AND lower(email_address) = lower($3)",
+    )
+    .bind(chrono::Utc::now())
+    .bind(user_id)
+    .bind(email_address)
+    .execute(&mut *acquire(pool).await?)
+    .await?
+    .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(AppError::UserNotFound);
+    }
+
+    Ok(())
+}
+
+/// Saves changes to the profile of a user in a single transaction, so either all of them are saved or none.
+///
+/// A new email address needs to be verified again, so we mark it as unverified. Email addresses and handles are unique
+/// regardless of their casing, so we return [`AppError::EmailAddressTaken`] or [`AppError::HandleTaken`] when another
+/// user already has them. Callers validate the changes and check the current password of the user before saving them.
+#[instrument(
+    skip(pool, changes),
+    fields(email_address = ?changes.email_address.as_deref().map(mask_email))
+)]
+pub async fn update_user(pool: &PgPool, user_id: i32, changes: &UserChanges) -> Result<()> {
+    let _timer = QueryTimer::start("update_user");
+
+    let mut connection = acquire(pool).await?;
+    let mut transaction = connection.begin().await?;
+
+    if changes.email_address.is_some() || changes.password_hash.is_some() {
+        set_user_credentials(
+            &mut transaction,
+            user_id,
+            changes.email_address.as_deref(),
+            changes.password_hash.as_deref(),
+        )
+        .await?;
+    }
+
+    if let Some(handle) = &changes.handle {
+        set_user_handle(&mut transaction, user_id, handle.as_deref()).await?;
+    }
+
+    if let Some((time_zone, quiet_hours)) = &changes.schedule {
+        set_user_schedule(&mut transaction, user_id, time_zone, *quiet_hours).await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+/// Changes the email address and/or the password of a user in a single statement.
+async fn set_user_credentials(
+    connection: &mut PgConnection,
+    user_id: i32,
+    email_address: Option<&str>,
+    password_hash: Option<&str>,
+) -> Result<()> {
+    let rows_affected = sqlx::query(
+        "UPDATE users SET email_address = COALESCE($1, email_address), \
+         email_verified = email_verified AND $1 IS NULL, \
+         password_hash = COALESCE($2, password_hash), date_modified = $3 WHERE id = $4",
+    )
+    .bind(email_address)
+    .bind(password_hash)
+    .bind(chrono::Utc::now())
+    .bind(user_id)
+    .execute(&mut *connection)
+    .await
+    .map_err(|err| match err.as_database_error() {
+        Some(db_err) if db_err.is_unique_violation() => AppError::EmailAddressTaken,
+        _ => AppError::DbError(err),
+    })?
+    .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(AppError::UserNotFound);
+    }
+
+    Ok(())
+}
+
+/// Changes or removes the handle other users mention a user with.
+///
+/// Handles are unique regardless of their casing, so we return [`AppError::HandleTaken`] when another user already
+/// has the handle.
+#[instrument(skip(pool))]
+pub async fn update_user_handle(pool: &PgPool, user_id: i32, handle: Option<String>) -> Result<()> {
+    let _timer = QueryTimer::start("update_user_handle");
+
+    set_user_handle(&mut *acquire(pool).await?, user_id, handle.as_deref()).await
+}
+
+async fn set_user_handle(
+    connection: &mut PgConnection,
+    user_id: i32,
+    handle: Option<&str>,
+) -> Result<()> {
+    let rows_affected =
+        sqlx::query("UPDATE users SET handle = $1, date_modified = $2 WHERE id = $3")
+            .bind(handle)
+            .bind(chrono::Utc::now())
+            .bind(user_id)
+            .execute(&mut *connection)
+            .await
+            .map_err(|err| match err.as_database_error() {
+                Some(db_err) if db_err.is_unique_violation() => AppError::HandleTaken,
+                _ => AppError::DbError(err),
+            })?
+            .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(AppError::UserNotFound);
+    }
+
+    Ok(())
+}
+
+/// Changes the time zone and quiet hours of a user.
+///
+/// Reminders at a time of day depend on the time zone, so the reminders of the user that weren't sent yet are
+/// scheduled again in the same transaction. Quiet hours are checked when a reminder fires, so they don't affect the
+/// schedule.
+#[instrument(skip(pool))]
+pub async fn update_user_schedule(
+    pool: &PgPool,
+    user_id: i32,
+    time_zone: &str,
+    quiet_hours: Option<(chrono::NaiveTime, chrono::NaiveTime)>,
+) -> Result<()> {
+    let _timer = QueryTimer::start("update_user_schedule");
+
+    let mut connection = acquire(pool).await?;
+    let mut transaction = connection.begin().await?;
+
+    set_user_schedule(&mut transaction, user_id, time_zone, quiet_hours).await?;
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+async fn set_user_schedule(
+    connection: &mut PgConnection,
+    user_id: i32,
+    time_zone: &str,
+    quiet_hours: Option<(chrono::NaiveTime, chrono::NaiveTime)>,
+) -> Result<()> {
+    let rows_affected = sqlx::query(
+        "UPDATE users SET time_zone = $1, quiet_hours_start = $2, quiet_hours_end = $3, date_modified = $4 WHERE id = $5",
+    )
+    .bind(time_zone)
+    .bind(quiet_hours.map(|(start, _)| start))
+    .bind(quiet_hours.map(|(_, end)| end))
+    .bind(chrono::Utc::now())
+    .bind(user_id)
+    .execute(&mut *connection)
+    .await?
+    .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(AppError::UserNotFound);
+    }
+
+    schedule_reminders(
+        connection,
+        "r.user_id = $1 AND r.date_sent IS NULL",
+        user_id,
+    )
+    .await
+}
+
+/// Deletes a user and all of their tasks and task events from the database.
+///
+/// We use a transaction so that we never end up with a user without tasks or tasks without a user when one of the
+/// statements fails. The transaction is rolled back automatically when it's dropped without calling `commit`.
+/// The webhooks of the user and their deliveries are removed by the foreign keys on the `webhooks` table.
+///
+/// Workspaces that only have the user as their member are removed along with the user. A workspace that has other
+/// members keeps needing an owner, so when the user is its last owner we return an error with the
+/// [`AppError::LastWorkspaceOwner`] variant and leave the user in place.
+#[instrument(skip(pool))]
+pub async fn delete_user(pool: &PgPool, user_id: i32) -> Result<()> {
+    let _timer = QueryTimer::start("delete_user");
+
+    let mut connection = acquire(pool).await?;
+    let mut transaction = connection.begin().await?;
+
+    let owned_workspaces = sqlx::query_scalar::<_, i32>(
+        "SELECT workspace_id FROM workspace_members WHERE user_id = $1 AND role = 'owner' ORDER BY workspace_id",
+    )
+    .bind(user_id)
+    .fetch_all(&mut *transaction)
+    .await?;
+
+    for workspace_id in owned_workspaces {
+        let members = sqlx::query_as::<_, (i32, WorkspaceRole)>(
+            "SELECT user_id, role FROM workspace_members WHERE workspace_id = $1 FOR UPDATE",
+        )
+        .bind(workspace_id)
+        .fetch_all(&mut *transaction)
+        .await?;
+
+        if members.iter().all(|(member_id, _)| *member_id == user_id) {
+            sqlx::query("DELETE FROM workspaces WHERE id = $1")
+                .bind(workspace_id)
+                .execute(&mut *transaction)
+                .await?;
+        } else if !members
+            .iter()
+            .any(|(member_id, role)| *member_id != user_id && *role == WorkspaceRole::Owner)
+        {
+            return Err(AppError::LastWorkspaceOwner);
+        }
+    }
+
+    sqlx::query("DELETE FROM tasks WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut *transaction)
+        .await?;
+
+    sqlx::query("DELETE FROM task_events WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut *transaction)
+        .await?;
+
+    let rows_affected = sqlx::query("DELETE FROM users WHERE id = $1")
+        .bind(user_id)
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(AppError::UserNotFound);
+    }
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+/// Retrieves the ID of the user with the given verified email address, creating the user when it doesn't exist yet.
+///
+/// This is used to log in users with the OpenID Connect identity provider. Users created this way receive a random
+/// API key that is never shown, so they can only authenticate with bearer tokens until they request a new key.
+///
+/// Because the identity provider verified the email address, we mark the email address of the user as verified.
+/// We use an upsert so that two simultaneous first logins can't create the same user twice.
+///
+/// Anyone can register an email address they don't own, so an existing user that never verified their email address
+/// may have been registered by someone else. When the owner of the email address claims such a user, we remove the
+/// password, replace the API key and record the moment in `credentials_reset_at`, so the refresh tokens that were
+/// issued before stop working. All of this happens in the same statement as the upsert.
+#[instrument(skip(pool, email_address), fields(email_address = %mask_email(email_address), user_id))]
+pub async fn get_or_create_user_by_email(pool: &PgPool, email_address: &str) -> Result<i32> {
+    let _timer = QueryTimer::start("get_or_create_user_by_email");
+
+    let now = chrono::Utc::now();
+
+    let id: i32 = sqlx::query_scalar(
+        "INSERT INTO users (email_address, api_key, email_verified, date_created) VALUES ($1, $2, true, $3)
+         ON CONFLICT ((lower(email_address))) DO UPDATE SET
+         password_hash = CASE WHEN users.email_verified THEN users.password_hash END,
+         api_key = CASE WHEN users.email_verified THEN users.api_key ELSE EXCLUDED.api_key END,
+         credentials_reset_at = CASE WHEN users.email_verified THEN users.credentials_reset_at ELSE $3 END,
+         email_verified = true, date_modified = $3
+         RETURNING id",
+    )
+    .bind(email_address)
+    .bind(ApiKey::new().hash)
+    .bind(now)
+    .fetch_one(&mut *acquire(pool).await?)
+    .await?;
+
+    Span::current().record("user_id", id);
+
+    Ok(id)
+}
+
+/// The number of seconds a user has to complete a login with the OpenID Connect identity provider.
+const OIDC_LOGIN_MAX_AGE: i64 = 600;
+
+/// Stores a pending login with the OpenID Connect identity provider.
+///
+/// Anyone can start a login, and most logins that are abandoned never reach the callback. So we remove the expired
+/// login requests in the same statement, which keeps the table from growing without bounds.
+#[instrument(skip(pool, login_request))]
+pub async fn insert_oidc_login_request(
+    pool: &PgPool,
+    login_request: &OidcLoginRequest,
+) -> Result<()> {
+    let _timer = QueryTimer::start("insert_oidc_login_request");
+
+    sqlx::query(
+        "WITH expired AS (DELETE FROM oidc_login_requests WHERE date_created < $5)
+         INSERT INTO oidc_login_requests (state, code_verifier, nonce, date_created) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(&login_request.state)
+    .bind(&login_request.code_verifier)
+    .bind(&login_request.nonce)
+    .bind(login_request.date_created)
+    .bind(oldest_oidc_login_date())
+    .execute(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(())
+}
+
+/// Removes a pending login with the OpenID Connect identity provider and returns it.
+///
+/// A login request can only be used once, so we delete it in the same statement that retrieves it. Expired login
+/// requests are ignored, and removed in the same statement as well.
+#[instrument(skip(pool, state))]
+pub async fn take_oidc_login_request(
+    pool: &PgPool,
+    state: &str,
+) -> Result<Option<OidcLoginRequest>> {
+    let _timer = QueryTimer::start("take_oidc_login_request");
+
+    let oldest_date_created = oldest_oidc_login_date();
+
+    let login_requests = sqlx::query_as::<_, OidcLoginRequest>(
+        "DELETE FROM oidc_login_requests WHERE state = $1 OR date_created < $2 RETURNING *",
+    )
+    .bind(state)
+    .bind(oldest_date_created)
+    .fetch_all(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(login_requests
+        .into_iter()
+        .find(|request| request.state == state && request.date_created >= oldest_date_created))
+}
+
+/// Returns the creation date of the oldest login request that hasn't expired.
+fn oldest_oidc_login_date() -> chrono::NaiveDateTime {
+    chrono::Utc::now().naive_utc() - chrono::Duration::seconds(OIDC_LOGIN_MAX_AGE)
+}
+
+/// Lists the webhooks of a user.
+#[instrument(skip(pool))]
+pub async fn list_webhooks(pool: &PgPool, user_id: i32) -> Result<Vec<Webhook>> {
+    let _timer = QueryTimer::start("list_webhooks");
+
+    let webhooks =
+        sqlx::query_as::<_, Webhook>("SELECT * FROM webhooks WHERE user_id = $1 ORDER BY id")
+            .bind(user_id)
+            .fetch_all(&mut *acquire(pool).await?)
+            .await?;
+
+    Ok(webhooks)
+}
+
+/// Finds a single webhook of a user by its ID.
+///
+/// We return [`AppError::WebhookNotFound`] when the webhook doesn't exist or belongs to another user.
+#[instrument(skip(pool))]
+pub async fn find_webhook(pool: &PgPool, user_id: i32, webhook_id: i32) -> Result<Webhook> {
+    let _timer = QueryTimer::start("find_webhook");
+
+    sqlx::query_as::<_, Webhook>("SELECT * FROM webhooks WHERE user_id = $1 AND id = $2")
+        .bind(user_id)
+        .bind(webhook_id)
+        .fetch_optional(&mut *acquire(pool).await?)
+        .await?
+        .ok_or(AppError::WebhookNotFound)
+}
+
+/// Inserts a new webhook for a user and returns it.
+#[instrument(skip(pool, url, secret), fields(webhook_id))]
+pub async fn insert_webhook(
+    pool: &PgPool,
+    user_id: i32,
+    url: String,
+    secret: String,
+    events: &[TaskEventType],
+) -> Result<Webhook> {
+    let _timer = QueryTimer::start("insert_webhook");
+
+    let webhook = sqlx::query_as::<_, Webhook>(
+        "INSERT INTO webhooks (user_id, url, secret, events, date_created) VALUES ($1, $2, $3, $4, $5) RETURNING *",
+    )
+    .bind(user_id)
+    .bind(url)
+    .bind(secret)
+    .bind(events)
+    .bind(chrono::Utc::now())
+    .fetch_one(&mut *acquire(pool).await?)
+    .await?;
+
+    Span::current().record("webhook_id", webhook.id);
+
+    Ok(webhook)
+}
+
+/// Updates the URL, events and state of a webhook and returns the updated webhook.
+#[instrument(skip(pool, url))]
+pub async fn update_webhook(
+    pool: &PgPool,
+    user_id: i32,
+    webhook_id: i32,
+    url: String,
+    events: &[TaskEventType],
+    active: bool,
+) -> Result<Webhook> {
+    let _timer = QueryTimer::start("update_webhook");
+
+    sqlx::query_as::<_, Webhook>(
+        "UPDATE webhooks SET url = $1, events = $2, active = $3, date_modified = $4 WHERE user_id = $5 AND id = $6 RETURNING *",
+    )
+    .bind(url)
+    .bind(events)
+    .bind(active)
+    .bind(chrono::Utc::now())
+    .bind(user_id)
+    .bind(webhook_id)
+    .fetch_optional(&mut *acquire(pool).await?)
+    .await?
+    .ok_or(AppError::WebhookNotFound)
+}
+
+/// Deletes a webhook together with its deliveries.
+#[instrument(skip(pool))]
+pub async fn delete_webhook(pool: &PgPool, user_id: i32, webhook_id: i32) -> Result<()> {
+    let _timer = QueryTimer::start("delete_webhook");
+
+    let rows_affected = sqlx::query("DELETE FROM webhooks WHERE user_id = $1 AND id = $2")
+        .bind(user_id)
+        .bind(webhook_id)
+        .execute(&mut *acquire(pool).await?)
+        .await?
+        .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(AppError::WebhookNotFound);
+    }
+
+    Ok(())
+}
+
+/// Queues a delivery of an event for every active webhook of the user that subscribed to the event.
+///
+/// This returns the number of deliveries that were queued, which is 0 when the user has no matching webhooks. Webhooks
+/// that already have a delivery of the event are skipped, so queueing the same event twice is harmless.
+#[instrument(skip(pool, payload))]
+pub async fn insert_webhook_deliveries(
+    pool: &PgPool,
+    user_id: i32,
+    event_id: &str,
+    event: TaskEventType,
+    payload: &serde_json::Value,
+) -> Result<u64> {
+    let _timer = QueryTimer::start("insert_webhook_deliveries");
+
+    let rows_affected = sqlx::query(
+        "INSERT INTO webhook_deliveries (webhook_id, event_id, event_type, payload, next_attempt_at, date_created)
+         SELECT id, $1, $2, $3, $4, $4 FROM webhooks
+         WHERE user_id = $5 AND active AND $2 = ANY(events) AND NOT EXISTS (
+             SELECT 1 FROM webhook_deliveries WHERE webhook_id = webhooks.id AND event_id = $1
+         )",
+    )
+    .bind(event_id)
+    .bind(event)
+    .bind(payload)
+    .bind(chrono::Utc::now())
+    .bind(user_id)
+    .execute(&mut *acquire(pool).await?)
+    .await?
+    .rows_affected();
+
+    Ok(rows_affected)
+}
+
+/// Lists the deliveries of a webhook, newest first.
+///
+/// Make sure the webhook belongs to the user with [`find_webhook`] first, this function doesn't check it.
+#[instrument(skip(pool))]
+pub async fn list_webhook_deliveries(
+    pool: &PgPool,
+    webhook_id: i32,
+    page_index: i32,
+    page_size: i32,
+) -> Result<PagedResult<WebhookDelivery>> {
+    let _timer = QueryTimer::start("list_webhook_deliveries");
+
+    let items = sqlx::query_as::<_, WebhookDelivery>(
+        "SELECT * FROM webhook_deliveries WHERE webhook_id = $1 ORDER BY id DESC LIMIT $2 OFFSET $3",
+    )
+    .bind(webhook_id)
+    .bind(page_size)
+    .bind(page_index * page_size)
+    .fetch_all(&mut *acquire(pool).await?)
+    .await?;
+
+    let total_count: i64 = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM webhook_deliveries WHERE webhook_id = $1",
+    )
+    .bind(webhook_id)
+    .fetch_one(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(PagedResult {
+        items,
+        page_index,
+        page_size,
+        total_count,
+    })
+}
+
+/// Queues a new delivery of the same event as an existing delivery and returns it.
+///
+/// The new delivery keeps the event ID, so receivers can recognize an event they already processed. Make sure the
+/// webhook belongs to the user with [`find_webhook`] first, this function doesn't check it.
+#[instrument(skip(pool))]
+pub async fn redeliver_webhook_delivery(
+    pool: &PgPool,
+    webhook_id: i32,
+    delivery_id: i64,
+) -> Result<WebhookDelivery> {
+    let _timer = QueryTimer::start("redeliver_webhook_delivery");
+
+    sqlx::query_as::<_, WebhookDelivery>(
+        "INSERT INTO webhook_deliveries (webhook_id, event_id, event_type, payload, redelivery_of, next_attempt_at, date_created)
+         SELECT webhook_id, event_id, event_type, payload, id, $1, $1 FROM webhook_deliveries WHERE webhook_id = $2 AND id = $3
+         RETURNING *",
+    )
+    .bind(chrono::Utc::now())
+    .bind(webhook_id)
+    .bind(delivery_id)
+    .fetch_optional(&mut *acquire(pool).await?)
+    .await?
+    .ok_or(AppError::WebhookDeliveryNotFound)
+}
+
+/// Claims a batch of deliveries that are due, so they can be sent.
+///
+/// Claiming counts as an attempt and moves the next attempt to `lease_until`. When the instance that claimed a
+/// delivery dies before it records the outcome, another instance picks the delivery up again after the lease expires.
+/// We use `FOR UPDATE SKIP LOCKED` so instances that claim at the same time never get the same delivery.
+#[instrument(skip(pool))]
+pub async fn claim_webhook_deliveries(
+    pool: &PgPool,
+    batch_size: i64,
+    lease_until: chrono::NaiveDateTime,
+) -> Result<Vec<PendingWebhookDelivery>> {
+    let _timer = QueryTimer::start("claim_webhook_deliveries");
+
+    let deliveries = sqlx::query_as::<_, PendingWebhookDelivery>(
+        "UPDATE webhook_deliveries AS delivery SET attempts = delivery.attempts + 1, next_attempt_at = $1
+         FROM webhooks AS webhook
+         WHERE webhook.id = delivery.webhook_id AND delivery.id IN (
+             SELECT pending.id FROM webhook_deliveries AS pending
+             JOIN webhooks ON webhooks.id = pending.webhook_id
+             WHERE pending.status = 'pending' AND pending.next_attempt_at <= $2 AND webhooks.active
+             ORDER BY pending.next_attempt_at
+             LIMIT $3
+             FOR UPDATE OF pending SKIP LOCKED
+         )
+         RETURNING delivery.id, delivery.event_id, delivery.payload, delivery.attempts, webhook.url, webhook.secret",
+    )
+    .bind(lease_until)
+    .bind(chrono::Utc::now().naive_utc())
+    .bind(batch_size)
+    .fetch_all(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(deliveries)
+}
+
+/// Records that the receiver accepted a delivery.
+#[instrument(skip(pool))]
+pub async fn mark_webhook_delivered(
+    pool: &PgPool,
+    delivery_id: i64,
+    response_status: i32,
+) -> Result<()> {
+    let _timer = QueryTimer::start("mark_webhook_delivered");
+
+    sqlx::query(
+        "UPDATE webhook_deliveries SET status = 'delivered', response_status = $1, error = NULL, date_delivered = $2 WHERE id = $3",
+    )
+    .bind(response_status)
+    .bind(chrono::Utc::now())
+    .bind(delivery_id)
+    .execute(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(())
+}
+
+/// Records a failed attempt of a delivery.
+///
+/// The delivery is tried again at `retry_at`. Without a time to retry at, the delivery is marked as failed.
+#[instrument(skip(pool, error))]
+pub async fn mark_webhook_attempt_failed(
+    pool: &PgPool,
+    delivery_id: i64,
+    response_status: Option<i32>,
+    error: &str,
+    retry_at: Option<chrono::NaiveDateTime>,
+) -> Result<()> {
+    let _timer = QueryTimer::start("mark_webhook_attempt_failed");
+
+    sqlx::query(
+        "UPDATE webhook_deliveries
+         SET status = CASE WHEN $1::timestamp IS NULL THEN 'failed' ELSE 'pending' END,
+             next_attempt_at = COALESCE($1, next_attempt_at), response_status = $2, error = $3
+         WHERE id = $4",
+    )
+    .bind(retry_at)
+    .bind(response_status)
+    .bind(error)
+    .bind(delivery_id)
+    .execute(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(())
+}
+
+/// Adds a background job to the outbox and returns its ID.
+///
+/// Call this in the same transaction as the change the job belongs to, so the job is only queued when the change is
+/// committed. The job is due right away.
+pub async fn insert_job<J: JobPayload>(connection: &mut PgConnection, job: &J) -> Result<i64> {
+    let payload = serde_json::to_value(job).expect("Job payloads can always be serialized.");
+    let date_created = chrono::Utc::now();
+
+    let job_id: i64 = sqlx::query_scalar(
+        "INSERT INTO jobs (kind, payload, run_at, date_created) VALUES ($1, $2, $3, $3) RETURNING id",
+    )
+    .bind(J::KIND)
+    .bind(payload)
+    .bind(date_created)
+    .fetch_one(&mut *connection)
+    .await?;
+
+    Ok(job_id)
+}
+
+/// Claims the job that is due first, so no other worker runs it until the lease expires.
+///
+/// Running jobs whose lease expired are claimed as well, because the worker that claimed them stopped before it could
+/// record the outcome. The attempts are counted when the job is claimed, so those interrupted runs count too.
+#[instrument(skip(pool))]
+pub async fn claim_job(pool: &PgPool, lease_until: chrono::NaiveDateTime) -> Result<Option<Job>> {
+    let _timer = QueryTimer::start("claim_job");
+
+    let now = chrono::Utc::now().naive_utc();
+
+    let job = sqlx::query_as::<_, Job>(
+        "UPDATE jobs SET status = 'running', attempts = attempts + 1, run_at = $1, date_modified = $2
+         WHERE id = (
+             SELECT id FROM jobs WHERE status IN ('pending', 'running') AND run_at <= $2
+             ORDER BY run_at, id
+             LIMIT 1
+             FOR UPDATE SKIP LOCKED
+         )
+         RETURNING *",
+    )
+    .bind(lease_until)
+    .bind(now)
+    .fetch_optional(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(job)
+}
+
+/// Removes a job that completed.
+#[instrument(skip(pool))]
+pub async fn delete_completed_job(pool: &PgPool, job_id: i64) -> Result<()> {
+    let _timer = QueryTimer::start("delete_completed_job");
+
+    sqlx::query("DELETE FROM jobs WHERE id = $1")
+        .bind(job_id)
+        .execute(&mut *acquire(pool).await?)
+        .await?;
+
+    Ok(())
+}
+
+/// Records a failed attempt of a job.
+///
+/// When `retry_at` is set, the job is pending again until then. Otherwise it's moved to the dead letter queue.
+#[instrument(skip(pool, error))]
+pub async fn mark_job_failed(
+    pool: &PgPool,
+    job_id: i64,
+    error: &str,
+    retry_at: Option<chrono::NaiveDateTime>,
+) -> Result<()> {
+    let _timer = QueryTimer::start("mark_job_failed");
+
+    sqlx::query(
+        "UPDATE jobs
+         SET status = CASE WHEN $1::timestamp IS NULL THEN 'dead' ELSE 'pending' END,
+             run_at = COALESCE($1, run_at), error = $2, date_modified = $3
+         WHERE id = $4",
+    )
+    .bind(retry_at)
+    .bind(error)
+    .bind(chrono::Utc::now())
+    .bind(job_id)
+    .execute(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(())
+}
+
+/// Lists the background jobs, optionally only the ones with the given status, oldest first.
+#[instrument(skip(pool))]
+pub async fn list_jobs(
+    pool: &PgPool,
+    status: Option<JobStatus>,
+    page_index: i32,
+    page_size: i32,
+) -> Result<PagedResult<Job>> {
+    let _timer = QueryTimer::start("list_jobs");
+
+    let items = sqlx::query_as::<_, Job>(
+        "SELECT * FROM jobs WHERE $1::varchar IS NULL OR status = $1 ORDER BY id LIMIT $2 OFFSET $3",
+    )
+    .bind(status)
+    .bind(page_size)
+    .bind(page_index * page_size)
+    .fetch_all(&mut *acquire(pool).await?)
+    .await?;
+
+    let total_count: i64 = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM jobs WHERE $1::varchar IS NULL OR status = $1",
+    )
+    .bind(status)
+    .fetch_one(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(PagedResult {
+        items,
+        page_index,
+        page_size,
+        total_count,
+    })
+}
+
+/// Counts the background jobs in each state.
+#[instrument(skip(pool))]
+pub async fn count_jobs_by_status(pool: &PgPool) -> Result<JobCounts> {
+    let _timer = QueryTimer::start("count_jobs_by_status");
+
+    let rows =
+        sqlx::query_as::<_, (JobStatus, i64)>("SELECT status, COUNT(*) FROM jobs GROUP BY status")
+            .fetch_all(&mut *acquire(pool).await?)
+            .await?;
+
+    let mut counts = JobCounts::default();
+
+    for (status, count) in rows {
+        match status {
+            JobStatus::Pending => counts.pending = count,
+            JobStatus::Running => counts.running = count,
+            JobStatus::Dead => counts.dead = count,
+        }
+    }
+
+    Ok(counts)
+}
+
+/// Moves a job from the dead letter queue back into the queue, with a fresh set of attempts.
+///
+/// When there's no dead job with the ID, we return an error with the [`AppError::JobNotFound`] variant.
+#[instrument(skip(pool))]
+pub async fn retry_dead_job(pool: &PgPool, job_id: i64) -> Result<Job> {
+    let _timer = QueryTimer::start("retry_dead_job");
+
+    let now = chrono::Utc::now().naive_utc();
+
+    sqlx::query_as::<_, Job>(
+        "UPDATE jobs SET status = 'pending', attempts = 0, run_at = $1, date_modified = $1
+         WHERE id = $2 AND status = 'dead'
+         RETURNING *",
+    )
+    .bind(now)
+    .bind(job_id)
+    .fetch_optional(&mut *acquire(pool).await?)
+    .await?
+    .ok_or(AppError::JobNotFound)
+}
+
+/// Removes a job from the dead letter queue without running it.
+///
+/// When there's no dead job with the ID, we return an error with the [`AppError::JobNotFound`] variant.
+#[instrument(skip(pool))]
+pub async fn delete_dead_job(pool: &PgPool, job_id: i64) -> Result<()> {
+    let _timer = QueryTimer::start("delete_dead_job");
+
+    let rows_affected = sqlx::query("DELETE FROM jobs WHERE id = $1 AND status = 'dead'")
+        .bind(job_id)
+        .execute(&mut *acquire(pool).await?)
+        .await?
+        .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(AppError::JobNotFound);
+    }
+
+    Ok(())
+}
+
+/// Computes when reminders fire in UTC, from the deadline of their task and the time zone of their user.
+///
+/// The condition selects the reminders to schedule, with `r` as the alias of the reminders table and `$1` as its only
+/// parameter. Reminders of tasks without a deadline don't fire, so their `remind_at` becomes `NULL`. PostgreSQL knows
+/// the same IANA time zones as [`chrono_tz`], and takes care of daylight saving time.
+async fn schedule_reminders(
+    connection: &mut PgConnection,
+    condition: &'static str,
+    id: i32,
+) -> Result<()> {
+    let statement = format!(
+        "UPDATE reminders AS r SET remind_at = CASE
+             WHEN r.minutes_before IS NOT NULL THEN t.due_at - make_interval(mins => r.minutes_before)
+             ELSE (((t.due_at AT TIME ZONE 'UTC') AT TIME ZONE u.time_zone)::date + r.time_of_day)
+                 AT TIME ZONE u.time_zone AT TIME ZONE 'UTC'
+         END
+         FROM tasks AS t, users AS u
+         WHERE t.id = r.task_id AND u.id = r.user_id AND {}",
+        condition
+    );
+
+    sqlx::query(&statement)
+        .bind(id)
+        .execute(&mut *connection)
+        .await?;
+
+    Ok(())
+}
+
+/// Lists the reminders of a task, given the owner of the task.
+///
+/// Check the access of the user to the task with [`crate::access::task_access`] first, and pass the owner it returns.
+/// When the task doesn't belong to the owner, we return an error with the [`AppError::TaskNotFound`] variant.
+#[instrument(skip(pool))]
+pub async fn list_reminders(pool: &PgPool, user_id: i32, task_id: i32) -> Result<Vec<Reminder>> {
+    let _timer = QueryTimer::start("list_reminders");
+
+    let mut connection = acquire(pool).await?;
+
+    sqlx::query_scalar::<_, i32>("SELECT id FROM tasks WHERE user_id = $1 AND id = $2")
+        .bind(user_id)
+        .bind(task_id)
+        .fetch_optional(&mut *connection)
+        .await?
+        .ok_or(AppError::TaskNotFound)?;
+
+    let reminders = sqlx::query_as::<_, Reminder>(
+        "SELECT id, task_id, minutes_before, time_of_day, channels, remind_at, date_sent, date_created
+         FROM reminders WHERE task_id = $1 ORDER BY id",
+    )
+    .bind(task_id)
+    .fetch_all(&mut *connection)
+    .await?;
+
+    Ok(reminders)
+}
+
+/// Adds a reminder for the owner of a task and schedules it.
+///
+/// Check the access of the user to the task with [`crate::access::task_access`] first, and pass the owner it returns.
+/// Set either the number of minutes before the deadline or the time of day, not both. When the task doesn't belong to
+/// the owner, we return an error with the [`AppError::TaskNotFound`] variant.
+#[instrument(skip(pool))]
+pub async fn insert_reminder(
+    pool: &PgPool,
+    user_id: i32,
+    task_id: i32,
+    minutes_before: Option<i32>,
+    time_of_day: Option<chrono::NaiveTime>,
+    channels: &[ReminderChannel],
+) -> Result<Reminder> {
+    let _timer = QueryTimer::start("insert_reminder");
+
+    let mut connection = acquire(pool).await?;
+    let mut transaction = connection.begin().await?;
+
+    let reminder_id: i32 = sqlx::query_scalar(
+        "INSERT INTO reminders (task_id, user_id, minutes_before, time_of_day, channels, date_created)
+         SELECT id, user_id, $1, $2, $3, $4 FROM tasks WHERE user_id = $5 AND id = $6
+         RETURNING id",
+    )
+    .bind(minutes_before)
+    .bind(time_of_day)
+    .bind(channels)
+    .bind(chrono::Utc::now())
+    .bind(user_id)
+    .bind(task_id)
+    .fetch_optional(&mut *transaction)
+    .await?
+    .ok_or(AppError::TaskNotFound)?;
+
+    schedule_reminders(&mut transaction, "r.id = $1", reminder_id).await?;
+
+    let reminder = sqlx::query_as::<_, Reminder>(
+        "SELECT id, task_id, minutes_before, time_of_day, channels, remind_at, date_sent, date_created
+         FROM reminders WHERE id = $1",
+    )
+    .bind(reminder_id)
+    .fetch_one(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(reminder)
+}
+
+/// Removes a reminder from a task, given the owner of the task.
+///
+/// Check the access of the user to the task with [`crate::access::task_access`] first, and pass the owner it returns.
+/// When the reminder doesn't exist, we return an error with the [`AppError::ReminderNotFound`] variant.
+#[instrument(skip(pool))]
+pub async fn delete_reminder(
+    pool: &PgPool,
+    user_id: i32,
+    task_id: i32,
+    reminder_id: i32,
+) -> Result<()> {
+    let _timer = QueryTimer::start("delete_reminder");
+
+    let rows_affected =
+        sqlx::query("DELETE FROM reminders WHERE user_id = $1 AND task_id = $2 AND id = $3")
+            .bind(user_id)
+            .bind(task_id)
+            .bind(reminder_id)
+            .execute(&mut *acquire(pool).await?)
+            .await?
+            .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(AppError::ReminderNotFound);
+    }
+
+    Ok(())
+}
+
+/// Lists all reminders of a user without pagination.
+///
+/// This is used to export all data we store about a user, so it's not meant for regular listing of reminders.
+#[instrument(skip(pool))]
+pub async fn list_all_reminders(pool: &PgPool, user_id: i32) -> Result<Vec<Reminder>> {
+    let _timer = QueryTimer::start("list_all_reminders");
+
+    let reminders = sqlx::query_as::<_, Reminder>(
+        "SELECT id, task_id, minutes_before, time_of_day, channels, remind_at, date_sent, date_created
+         FROM reminders WHERE user_id = $1 ORDER BY id",
+    )
+    .bind(user_id)
+    .fetch_all(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(reminders)
+}
+
+/// Lists the reminders that are due, oldest first, together with what the scheduler needs to send them.
+///
+/// Reminders of completed tasks are left alone. They fire when the task is opened again.
+#[instrument(skip(pool))]
+pub async fn list_due_reminders(
+    pool: &PgPool,
+    now: chrono::NaiveDateTime,
+    limit: i64,
+) -> Result<Vec<DueReminder>> {
+    let _timer = QueryTimer::start("list_due_reminders");
+
+    let reminders = sqlx::query_as::<_, DueReminder>(
+        "SELECT r.id, r.user_id, r.task_id, t.title, t.due_at, r.channels, r.remind_at, u.time_zone,
+             u.quiet_hours_start, u.quiet_hours_end
+         FROM reminders AS r
+         JOIN tasks AS t ON t.id = r.task_id
+         JOIN users AS u ON u.id = r.user_id
+         WHERE r.date_sent IS NULL AND r.remind_at <= $1 AND NOT t.completed AND t.due_at IS NOT NULL
+         ORDER BY r.remind_at, r.id
+         LIMIT $2",
+    )
+    .bind(now)
+    .bind(limit)
+    .fetch_all(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(reminders)
+}
+
+/// Moves a due reminder to a later time, for example to the end of the quiet hours of the user.
+///
+/// Nothing changes when the reminder was sent or scheduled again in the meantime.
+#[instrument(skip(pool, reminder), fields(reminder_id = reminder.id))]
+pub async fn postpone_reminder(
+    pool: &PgPool,
+    reminder: &DueReminder,
+    until: chrono::NaiveDateTime,
+) -> Result<()> {
+    let _timer = QueryTimer::start("postpone_reminder");
+
+    sqlx::query(
+        "UPDATE reminders SET remind_at = $1 WHERE id = $2 AND remind_at = $3 AND date_sent IS NULL",
+    )
+    .bind(until)
+    .bind(reminder.id)
+    .bind(reminder.remind_at)
+    .execute(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(())
+}
+
+/// Marks a due reminder as sent and queues the jobs that deliver it, in one transaction.
+///
+/// This returns `false` without queueing anything when another instance sent the reminder first, or when it was
+/// scheduled again in the meantime. The update waits for the row lock of a concurrent update and checks the condition
+/// again afterwards, so only one instance ever gets `true` for the same reminder.
+#[instrument(skip(pool, reminder, jobs), fields(reminder_id = reminder.id))]
+pub async fn mark_reminder_sent<J: JobPayload>(
+    pool: &PgPool,
+    reminder: &DueReminder,
+    jobs: &[J],
+) -> Result<bool> {
+    let _timer = QueryTimer::start("mark_reminder_sent");
+
+    let mut connection = acquire(pool).await?;
+    let mut transaction = connection.begin().await?;
+
+    let rows_affected = sqlx::query(
+        "UPDATE reminders SET date_sent = $1 WHERE id = $2 AND remind_at = $3 AND date_sent IS NULL",
+    )
+    .bind(chrono::Utc::now())
+    .bind(reminder.id)
+    .bind(reminder.remind_at)
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+
+    if rows_affected == 0 {
+        return Ok(false);
+    }
+
+    for job in jobs {
+        insert_job(&mut transaction, job).await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(true)
+}
+
+/// Adds a notification to the inbox of a user.
+///
+/// This returns `false` when the user already has a notification with the deduplication key, so delivering the same
+/// notification twice is harmless.
+#[instrument(skip(pool, title, body))]
+pub async fn insert_notification(
+    pool: &PgPool,
+    user_id: i32,
+    task_id: Option<i32>,
+    kind: NotificationKind,
+    title: &str,
+    body: &str,
+    dedup_key: &str,
+) -> Result<bool> {
+    let _timer = QueryTimer::start("insert_notification");
+
+    let rows_affected = sqlx::query(
+        "INSERT INTO notifications (user_id, task_id, kind, title, body, dedup_key, date_created)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)
+         ON CONFLICT (user_id, dedup_key) DO NOTHING",
+    )
+    .bind(user_id)
+    .bind(task_id)
+    .bind(kind)
+    .bind(title)
+    .bind(body)
+    .bind(dedup_key)
+    .bind(chrono::Utc::now())
+    .execute(&mut *acquire(pool).await?)
+    .await?
+    .rows_affected();
+
+    Ok(rows_affected > 0)
+}
+
+/// Lists the notifications of a user, newest first, optionally only the unread ones.
+#[instrument(skip(pool))]
+pub async fn list_notifications(
+    pool: &PgPool,
+    user_id: i32,
+    unread_only: bool,
+    page_index: i32,
+    page_size: i32,
+) -> Result<PagedResult<Notification>> {
+    let _timer = QueryTimer::start("list_notifications");
+
+    let items = sqlx::query_as::<_, Notification>(
+        "SELECT id, task_id, kind, title, body, date_read, date_created FROM notifications
+         WHERE user_id = $1 AND (NOT $2 OR date_read IS NULL)
+         ORDER BY id DESC LIMIT $3 OFFSET $4",
+    )
+    .bind(user_id)
+    .bind(unread_only)
+    .bind(page_size)
+    .bind(page_index * page_size)
+    .fetch_all(&mut *acquire(pool).await?)
+    .await?;
+
+    let total_count: i64 = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM notifications WHERE user_id = $1 AND (NOT $2 OR date_read IS NULL)",
+    )
+    .bind(user_id)
+    .bind(unread_only)
+    .fetch_one(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(PagedResult {
+        items,
+        page_index,
+        page_size,
+        total_count,
+    })
+}
+
+/// Lists all notifications of a user without pagination, oldest first.
+///
+/// This is used to export all data we store about a user, so it's not meant for the inbox.
+#[instrument(skip(pool))]
+pub async fn list_all_notifications(pool: &PgPool, user_id: i32) -> Result<Vec<Notification>> {
+    let _timer = QueryTimer::start("list_all_notifications");
+
+    let notifications = sqlx::query_as::<_, Notification>(
+        "SELECT id, task_id, kind, title, body, date_read, date_created FROM notifications
+         WHERE user_id = $1 ORDER BY id",
+    )
+    .bind(user_id)
+    .fetch_all(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(notifications)
+}
+
+/// Marks a notification of a user as read or unread.
+///
+/// Marking a notification as read twice keeps the date it was first read. When the notification doesn't exist, we
+/// return an error with the [`AppError::NotificationNotFound`] variant.
+#[instrument(skip(pool))]
+pub async fn mark_notification_read(
+    pool: &PgPool,
+    user_id: i32,
+    notification_id: i64,
+    read: bool,
+) -> Result<Notification> {
+    let _timer = QueryTimer::start("mark_notification_read");
+
+    sqlx::query_as::<_, Notification>(
+        "UPDATE notifications SET date_read = CASE WHEN $1 THEN COALESCE(date_read, $2) END
+         WHERE user_id = $3 AND id = $4
+         RETURNING id, task_id, kind, title, body, date_read, date_created",
+    )
+    .bind(read)
+    .bind(chrono::Utc::now())
+    .bind(user_id)
+    .bind(notification_id)
+    .fetch_optional(&mut *acquire(pool).await?)
+    .await?
+    .ok_or(AppError::NotificationNotFound)
+}
+
+/// Marks all unread notifications of a user as read, returning how many there were.
+#[instrument(skip(pool))]
+pub async fn mark_all_notifications_read(pool: &PgPool, user_id: i32) -> Result<u64> {
+    let _timer = QueryTimer::start("mark_all_notifications_read");
+
+    let rows_affected = sqlx::query(
+        "UPDATE notifications SET date_read = $1 WHERE user_id = $2 AND date_read IS NULL",
+    )
+    .bind(chrono::Utc::now())
+    .bind(user_id)
+    .execute(&mut *acquire(pool).await?)
+    .await?
+    .rows_affected();
+
+    Ok(rows_affected)
+}
+
+/// Finds the owner and workspace of a task and the highest role a user has for it, or `None` when the user has no
+/// access at all.
+///
+/// Use [`crate::access::task_access`] to check the access of a user, it turns the result into the right error.
+#[instrument(skip(pool))]
+pub async fn find_task_access(
+    pool: &PgPool,
+    user_id: i32,
+    task_id: i32,
+) -> Result<Option<(i32, Option<i32>, Role)>> {
+    let _timer = QueryTimer::start("find_task_access");
+
+    let access = sqlx::query_as::<_, (i32, Option<i32>, Role)>(
+        "SELECT tasks.user_id, tasks.workspace_id, task_access.role FROM task_access
+         JOIN tasks ON tasks.id = task_access.task_id
+         WHERE task_access.user_id = $1 AND task_access.task_id = $2
+         ORDER BY CASE task_access.role WHEN 'owner' THEN 3 WHEN 'editor' THEN 2 ELSE 1 END DESC
+         LIMIT 1",
+    )
+    .bind(user_id)
+    .bind(task_id)
+    .fetch_optional(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(access)
+}
+
+/// Finds the owner and workspace of a project and the highest role a user has for it, or `None` when the user has no
+/// access at all.
+///
+/// Use [`crate::access::project_access`] to check the access of a user, it turns the result into the right error.
+#[instrument(skip(pool))]
+pub async fn find_project_access(
+    pool: &PgPool,
+    user_id: i32,
+    project_id: i32,
+) -> Result<Option<(i32, Option<i32>, Role)>> {
+    let _timer = QueryTimer::start("find_project_access");
+
+    let access = sqlx::query_as::<_, (i32, Option<i32>, Role)>(
+        "SELECT projects.user_id, projects.workspace_id, project_access.role FROM project_access
+         JOIN projects ON projects.id = project_access.project_id
+         WHERE project_access.user_id = $1 AND project_access.project_id = $2
+         ORDER BY CASE project_access.role WHEN 'owner' THEN 3 WHEN 'editor' THEN 2 ELSE 1 END DESC
+         LIMIT 1",
+    )
+    .bind(user_id)
+    .bind(project_id)
+    .fetch_optional(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(access)
+}
+
+/// Lists the projects of a user and the projects others shared with them, together with the highest role of the user.
+///
+/// When a workspace is given, only the projects in that workspace are listed.
+#[instrument(skip(pool))]
+pub async fn list_projects(
+    pool: &PgPool,
+    user_id: i32,
+    workspace_id: Option<i32>,
+) -> Result<Vec<Project>> {
+    let _timer = QueryTimer::start("list_projects");
+
+    let projects = sqlx::query_as::<_, Project>(
+        "SELECT DISTINCT ON (projects.id) projects.id, projects.name, project_access.role, projects.workspace_id,
+             projects.date_created, projects.date_modified
+         FROM project_access JOIN projects ON projects.id = project_access.project_id
+         WHERE project_access.user_id = $1 AND ($2::integer IS NULL OR projects.workspace_id = $2)
+         ORDER BY projects.id, CASE project_access.role WHEN 'owner' THEN 3 WHEN 'editor' THEN 2 ELSE 1 END DESC",
+    )
+    .bind(user_id)
+    .bind(workspace_id)
+    .fetch_all(&mut *acquire_as(pool, user_id).await?)
+    .await?;
+
+    Ok(projects)
+}
+
+/// Finds a project the user has access to, together with the role of the user.
+///
+/// When the user has no access to the project, we return an error with the [`AppError::ProjectNotFound`] variant.
+#[instrument(skip(pool))]
+pub async fn find_project(pool: &PgPool, user_id: i32, project_id: i32) -> Result<Project> {
+    let _timer = QueryTimer::start("find_project");
+
+    sqlx::query_as::<_, Project>(
+        "SELECT projects.id, projects.name, project_access.role, projects.workspace_id, projects.date_created,
+             projects.date_modified
+         FROM project_access JOIN projects ON projects.id = project_access.project_id
+         WHERE project_access.user_id = $1 AND project_access.project_id = $2
+         ORDER BY CASE project_access.role WHEN 'owner' THEN 3 WHEN 'editor' THEN 2 ELSE 1 END DESC
+         LIMIT 1",
+    )
+    .bind(user_id)
+    .bind(project_id)
+    .fetch_optional(&mut *acquire_as(pool, user_id).await?)
+    .await?
+    .ok_or(AppError::ProjectNotFound)
+}
+
+/// Creates a project for a user, returning its ID. Projects without a workspace are personal projects of the user.
+#[instrument(skip(pool, name))]
+pub async fn insert_project(
+    pool: &PgPool,
+    user_id: i32,
+    workspace_id: Option<i32>,
+    name: String,
+) -> Result<i32> {
+    let _timer = QueryTimer::start("insert_project");
+
+    let project_id: i32 = sqlx::query_scalar(
+        "INSERT INTO projects (user_id, workspace_id, name, date_created) VALUES ($1, $2, $3, $4) RETURNING id",
+    )
+    .bind(user_id)
+    .bind(workspace_id)
+    .bind(name)
+    .bind(chrono::Utc::now())
+    .fetch_one(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(project_id)
+}
+
+/// Renames a project of a user.
+///
+/// When the project doesn't exist, we return an error with the [`AppError::ProjectNotFound`] variant.
+#[instrument(skip(pool, name))]
+pub async fn update_project(
+    pool: &PgPool,
+    user_id: i32,
+    project_id: i32,
+    name: String,
+) -> Result<()> {
+    let _timer = QueryTimer::start("update_project");
+
+    let rows_affected = sqlx::query(
+        "UPDATE projects SET name = $1, date_modified = $2 WHERE user_id = $3 AND id = $4",
+    )
+    .bind(name)
+    .bind(chrono::Utc::now())
+    .bind(user_id)
+    .bind(project_id)
+    .execute(&mut *acquire(pool).await?)
+    .await?
+    .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(AppError::ProjectNotFound);
+    }
+
+    Ok(())
+}
+
+/// Removes a project of a user. The tasks in the project stay, they no longer belong to a project.
+///
+/// When the project doesn't exist, we return an error with the [`AppError::ProjectNotFound`] variant.
+#[instrument(skip(pool))]
+pub async fn delete_project(pool: &PgPool, user_id: i32, project_id: i32) -> Result<()> {
+    let _timer = QueryTimer::start("delete_project");
+
+    let rows_affected = sqlx::query("DELETE FROM projects WHERE user_id = $1 AND id = $2")
+        .bind(user_id)
+        .bind(project_id)
+        .execute(&mut *acquire(pool).await?)
+        .await?
+        .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(AppError::ProjectNotFound);
+    }
+
+    Ok(())
+}
+
+/// Lists the tasks in a project.
+///
+/// Check the access of the user to the project with [`crate::access::project_access`] first, this function doesn't
+/// check it.
+#[instrument(skip(pool))]
+pub async fn list_project_tasks(
+    pool: &PgPool,
+    project_id: i32,
+    page_index: i32,
+    page_size: i32,
+) -> Result<PagedResult<Task>> {
+    let _timer = QueryTimer::start("list_project_tasks");
+
+    let items = sqlx::query_as::<_, Task>(
+        "SELECT id, title, description, completed, due_at, project_id, workspace_id, date_created, date_modified,
+         (SELECT COUNT(*) FROM comments WHERE comments.task_id = tasks.id) AS comment_count,
+         EXISTS (SELECT 1 FROM task_dependencies WHERE task_dependencies.task_id = tasks.id AND NOT task_dependencies.blocker_completed) AS blocked FROM tasks
+         WHERE project_id = $1 ORDER BY id LIMIT $2 OFFSET $3",
+    )
+    .bind(project_id)
+    .bind(page_size)
+    .bind(page_index * page_size)
+    .fetch_all(&mut *acquire(pool).await?)
+    .await?;
+
+    let total_count: i64 =
+        sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM tasks WHERE project_id = $1")
+            .bind(project_id)
+            .fetch_one(&mut *acquire(pool).await?)
+            .await?;
+
+    Ok(PagedResult {
+        items,
+        page_index,
+        page_size,
+        total_count,
+    })
+}
+
+/// Moves a task of a user into a project of the same user, or out of its project when the project is `None`.
+///
+/// When the task doesn't exist, we return an error with the [`AppError::TaskNotFound`] variant.
+#[instrument(skip(pool))]
+pub async fn set_task_project(
+    pool: &PgPool,
+    user_id: i32,
+    task_id: i32,
+    project_id: Option<i32>,
+) -> Result<()> {
+    let _timer = QueryTimer::start("set_task_project");
+
+    let rows_affected = sqlx::query(
+        "UPDATE tasks SET project_id = $1, date_modified = $2 WHERE user_id = $3 AND id = $4",
+    )
+    .bind(project_id)
+    .bind(chrono::Utc::now())
+    .bind(user_id)
+    .bind(task_id)
+    .execute(&mut *acquire(pool).await?)
+    .await?
+    .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(AppError::TaskNotFound);
+    }
+
+    Ok(())
+}
+
+/// Returns the column of the `shares` table that refers to the item, and the ID of the item.
+fn share_column(item: SharedItem) -> (&'static str, i32) {
+    match item {
+        SharedItem::Task(task_id) => ("task_id", task_id),
+        SharedItem::Project(project_id) => ("project_id", project_id),
+    }
+}
+
+/// Lists the users a task or project is shared with.
+///
+/// Check that the user owns the item with [`crate::access`] first, this function doesn't check it.
+#[instrument(skip(pool))]
+pub async fn list_shares(pool: &PgPool, item: SharedItem) -> Result<Vec<Share>> {
+    let _timer = QueryTimer::start("list_shares");
+
+    let (column, item_id) = share_column(item);
+
+    let statement = format!(
+        "SELECT shares.id, shares.task_id, shares.project_id, shares.user_id, users.email_address, shares.role,
+             shares.date_created, shares.date_modified
+         FROM shares JOIN users ON users.id = shares.user_id
+         WHERE shares.{} = $1
+         ORDER BY shares.id",
+        column
+    );
+
+    let shares = sqlx::query_as::<_, Share>(&statement)
+        .bind(item_id)
+        .fetch_all(&mut *acquire(pool).await?)
+        .await?;
+
+    Ok(shares)
+}
+
+/// Lists all shares of a user without pagination: the shares of the tasks and projects they own, and the items that
+/// are shared with them.
+///
+/// This is used to export all data we store about a user, so it's not meant for regular listing of shares.
+#[instrument(skip(pool))]
+pub async fn list_all_shares(pool: &PgPool, user_id: i32) -> Result<Vec<Share>> {
+    let _timer = QueryTimer::start("list_all_shares");
+
+    let shares = sqlx::query_as::<_, Share>(
+        "SELECT shares.id, shares.task_id, shares.project_id, shares.user_id, users.email_address, shares.role,
+             shares.date_created, shares.date_modified
+         FROM shares JOIN users ON users.id = shares.user_id
+         WHERE shares.user_id = $1
+             OR shares.task_id IN (SELECT id FROM tasks WHERE user_id = $1)
+             OR shares.project_id IN (SELECT id FROM projects WHERE user_id = $1)
+         ORDER BY shares.id",
+    )
+    .bind(user_id)
+    .fetch_all(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(shares)
+}
+
+/// Shares a task or project with the user with the given email address. Sharing it again changes the role.
+///
+/// When no user has the email address, we return an error with the [`AppError::UserNotFound`] variant. Owners can't
+/// share their items with themselves, in that case we return an error with the [`AppError::InvalidShare`] variant.
+#[instrument(skip(pool, email_address), fields(email_address = %mask_email(email_address)))]
+pub async fn insert_share(
+    pool: &PgPool,
+    owner_id: i32,
+    item: SharedItem,
+    email_address: &str,
+    role: Role,
+) -> Result<Share> {
+    let _timer = QueryTimer::start("insert_share");
+
+    let (column, item_id) = share_column(item);
+
+    let mut connection = acquire(pool).await?;
+
+    let user_id =
+        sqlx::query_scalar::<_, i32>("SELECT id FROM users WHERE lower(email_address) = lower($1)")
+            .bind(email_address)
+            .fetch_optional(&mut *connection)
+            .await?
+            .ok_or(AppError::UserNotFound)?;
+
+    if user_id == owner_id {
+        return Err(AppError::InvalidShare);
+    }
+
+    let statement = format!(
+        "WITH share AS (
+             INSERT INTO shares ({column}, user_id, role, date_created) VALUES ($1, $2, $3, $4)
+             ON CONFLICT ({column}, user_id) WHERE {column} IS NOT NULL
+             DO UPDATE SET role = EXCLUDED.role, date_modified = EXCLUDED.date_created
+             RETURNING *
+         )
+         SELECT share.id, share.task_id, share.project_id, share.user_id, users.email_address, share.role,
+             share.date_created, share.date_modified
+         FROM share JOIN users ON users.id = share.user_id",
+        column = column
+    );
+
+    let share = sqlx::query_as::<_, Share>(&statement)
+        .bind(item_id)
+        .bind(user_id)
+        .bind(role)
+        .bind(chrono::Utc::now())
+        .fetch_one(&mut *connection)
+        .await?;
+
+    Ok(share)
+}
+
+/// Revokes the access a share gives to a task or project.
+///
+/// When the item has no share with the ID, we return an error with the [`AppError::ShareNotFound`] variant.
+#[instrument(skip(pool))]
+pub async fn delete_share(pool: &PgPool, item: SharedItem, share_id: i32) -> Result<()> {
+    let _timer = QueryTimer::start("delete_share");
+
+    let (column, item_id) = share_column(item);
+    let statement = format!("DELETE FROM shares WHERE {} = $1 AND id = $2", column);
+
+    let rows_affected = sqlx::query(&statement)
+        .bind(item_id)
+        .bind(share_id)
+        .execute(&mut *acquire(pool).await?)
+        .await?
+        .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(AppError::ShareNotFound);
+    }
+
+    Ok(())
+}
+
+/// Finds the role of a user in a workspace, or `None` when the user isn't a member.
+///
+/// Use [`crate::access::workspace_access`] to check the access of a user, it turns the result into the right error.
+#[instrument(skip(pool))]
+pub async fn find_workspace_role(
+    pool: &PgPool,
+    user_id: i32,
+    workspace_id: i32,
+) -> Result<Option<WorkspaceRole>> {
+    let _timer = QueryTimer::start("find_workspace_role");
+
+    let role = sqlx::query_scalar::<_, WorkspaceRole>(
+        "SELECT role FROM workspace_members WHERE user_id = $1 AND workspace_id = $2",
+    )
+    .bind(user_id)
+    .bind(workspace_id)
+    .fetch_optional(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(role)
+}
+
+/// Lists the workspaces a user is a member of, together with their role.
+#[instrument(skip(pool))]
+pub async fn list_workspaces(pool: &PgPool, user_id: i32) -> Result<Vec<Workspace>> {
+    let _timer = QueryTimer::start("list_workspaces");
+
+    let workspaces = sqlx::query_as::<_, Workspace>(
+        "SELECT workspaces.id, workspaces.name, workspace_members.role, workspaces.date_created,
+             workspaces.date_modified
+         FROM workspaces JOIN workspace_members ON workspace_members.workspace_id = workspaces.id
+         WHERE workspace_members.user_id = $1
+         ORDER BY workspaces.id",
+    )
+    .bind(user_id)
+    .fetch_all(&mut *acquire_as(pool, user_id).await?)
+    .await?;
+
+    Ok(workspaces)
+}
+
+/// Finds a workspace the user is a member of, together with their role.
+///
+/// When the user isn't a member, we return an error with the [`AppError::WorkspaceNotFound`] variant.
+#[instrument(skip(pool))]
+pub async fn find_workspace(pool: &PgPool, user_id: i32, workspace_id: i32) -> Result<Workspace> {
+    let _timer = QueryTimer::start("find_workspace");
+
+    sqlx::query_as::<_, Workspace>(
+        "SELECT workspaces.id, workspaces.name, workspace_members.role, workspaces.date_created,
+             workspaces.date_modified
+         FROM workspaces JOIN workspace_members ON workspace_members.workspace_id = workspaces.id
+         WHERE workspace_members.user_id = $1 AND workspaces.id = $2",
+    )
+    .bind(user_id)
+    .bind(workspace_id)
+    .fetch_optional(&mut *acquire_as(pool, user_id).await?)
+    .await?
+    .ok_or(AppError::WorkspaceNotFound)
+}
+
+/// Creates a workspace with the user as its owner, returning its ID.
+#[instrument(skip(pool, name))]
+pub async fn insert_workspace(pool: &PgPool, user_id: i32, name: String) -> Result<i32> {
+    let _timer = QueryTimer::start("insert_workspace");
+
+    let date_created = chrono::Utc::now();
+
+    let mut connection = acquire(pool).await?;
+    let mut transaction = connection.begin().await?;
+
+    let workspace_id: i32 = sqlx::query_scalar(
+        "INSERT INTO workspaces (name, date_created) VALUES ($1, $2) RETURNING id",
+    )
+    .bind(name)
+    .bind(date_created)
+    .fetch_one(&mut *transaction)
+    .await?;
+
+    sqlx::query(
+        "INSERT INTO workspace_members (workspace_id, user_id, role, date_created) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(workspace_id)
+    .bind(user_id)
+    .bind(WorkspaceRole::Owner)
+    .bind(date_created)
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(workspace_id)
+}
+
+/// Renames a workspace.
+///
+/// Check the role of the user with [`crate::access::workspace_access`] first, this function doesn't check it.
+#[instrument(skip(pool, name))]
+pub async fn update_workspace(pool: &PgPool, workspace_id: i32, name: String) -> Result<()> {
+    let _timer = QueryTimer::start("update_workspace");
+
+    let rows_affected =
+        sqlx::query("UPDATE workspaces SET name = $1, date_modified = $2 WHERE id = $3")
+            .bind(name)
+            .bind(chrono::Utc::now())
+            .bind(workspace_id)
+            .execute(&mut *acquire(pool).await?)
+            .await?
+            .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(AppError::WorkspaceNotFound);
+    }
+
+    Ok(())
+}
+
+/// Removes a workspace, together with its tasks, projects, members, invitations and API keys.
+///
+/// Check the role of the user with [`crate::access::workspace_access`] first, this function doesn't check it.
+#[instrument(skip(pool))]
+pub async fn delete_workspace(pool: &PgPool, workspace_id: i32) -> Result<()> {
+    let _timer = QueryTimer::start("delete_workspace");
+
+    let rows_affected = sqlx::query("DELETE FROM workspaces WHERE id = $1")
+        .bind(workspace_id)
+        .execute(&mut *acquire(pool).await?)
+        .await?
+        .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(AppError::WorkspaceNotFound);
+    }
+
+    Ok(())
+}
+
+/// Lists the members of a workspace.
+#[instrument(skip(pool))]
+pub async fn list_workspace_members(
+    pool: &PgPool,
+    workspace_id: i32,
+) -> Result<Vec<WorkspaceMember>> {
+    let _timer = QueryTimer::start("list_workspace_members");
+
+    let members = sqlx::query_as::<_, WorkspaceMember>(
+        "SELECT workspace_members.user_id, users.email_address, workspace_members.role, workspace_members.date_created
+         FROM workspace_members JOIN users ON users.id = workspace_members.user_id
+         WHERE workspace_members.workspace_id = $1
+         ORDER BY workspace_members.date_created, workspace_members.user_id",
+    )
+    .bind(workspace_id)
+    .fetch_all(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(members)
+}
+
+/// Locks the owners of a workspace and checks that one remains when the member stops being an owner.
+///
+/// Call this in the transaction that changes the role of the member or removes them. When they're the last owner, we
+/// return an error with the [`AppError::LastWorkspaceOwner`] variant.
+async fn ensure_other_owner(
+    connection: &mut PgConnection,
+    workspace_id: i32,
+    user_id: i32,
+) -> Result<()> {
+    let owners = sqlx::query_scalar::<_, i32>(
+        "SELECT user_id FROM workspace_members WHERE workspace_id = $1 AND role = 'owner' FOR UPDATE",
+    )
+    .bind(workspace_id)
+    .fetch_all(&mut *connection)
+    .await?;
+
+    if owners == [user_id] {
+        return Err(AppError::LastWorkspaceOwner);
+    }
+
+    Ok(())
+}
+
+/// Changes the role of a member of a workspace.
+///
+/// When the user isn't a member, we return an error with the [`AppError::MemberNotFound`] variant. The last owner
+/// can't become an admin or member, in that case we return an error with the [`AppError::LastWorkspaceOwner`] variant.
+#[instrument(skip(pool))]
+pub async fn update_workspace_member(
+    pool: &PgPool,
+    workspace_id: i32,
+    user_id: i32,
+    role: WorkspaceRole,
+) -> Result<()> {
+    let _timer = QueryTimer::start("update_workspace_member");
+
+    let mut connection = acquire(pool).await?;
+    let mut transaction = connection.begin().await?;
+
+    if role != WorkspaceRole::Owner {
+        ensure_other_owner(&mut transaction, workspace_id, user_id).await?;
+    }
+
+    let rows_affected = sqlx::query(
+        "UPDATE workspace_members SET role = $1 WHERE workspace_id = $2 AND user_id = $3",
+    )
+    .bind(role)
+    .bind(workspace_id)
+    .bind(user_id)
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(AppError::MemberNotFound);
+    }
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+/// Removes a member from a workspace, together with their API keys for the workspace. The tasks and projects they
+/// created stay in the workspace.
+///
+/// When the user isn't a member, we return an error with the [`AppError::MemberNotFound`] variant. The last owner
+/// can't leave, in that case we return an error with the [`AppError::LastWorkspaceOwner`] variant.
+#[instrument(skip(pool))]
+pub async fn delete_workspace_member(pool: &PgPool, workspace_id: i32, user_id: i32) -> Result<()> {
+    let _timer = QueryTimer::start("delete_workspace_member");
+
+    let mut connection = acquire(pool).await?;
+    let mut transaction = connection.begin().await?;
+
+    ensure_other_owner(&mut transaction, workspace_id, user_id).await?;
+
+    let rows_affected =
+        sqlx::query("DELETE FROM workspace_members WHERE workspace_id = $1 AND user_id = $2")
+            .bind(workspace_id)
+            .bind(user_id)
+            .execute(&mut *transaction)
+            .await?
+            .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(AppError::MemberNotFound);
+    }
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+/// The columns of an invitation, including the name of its workspace.
+const INVITATION_COLUMNS: &str = "workspace_invitations.id, workspace_invitations.workspace_id,
+    workspaces.name AS workspace_name, workspace_invitations.email_address, workspace_invitations.role,
+    workspace_invitations.date_created";
+
+/// Lists the open invitations of a workspace.
+#[instrument(skip(pool))]
+pub async fn list_workspace_invitations(
+    pool: &PgPool,
+    workspace_id: i32,
+) -> Result<Vec<WorkspaceInvitation>> {
+    let _timer = QueryTimer::start("list_workspace_invitations");
+
+    let statement = format!(
+        "SELECT {} FROM workspace_invitations JOIN workspaces ON workspaces.id = workspace_invitations.workspace_id
+         WHERE workspace_invitations.workspace_id = $1
+         ORDER BY workspace_invitations.id",
+        INVITATION_COLUMNS
+    );
+
+    let invitations = sqlx::query_as::<_, WorkspaceInvitation>(&statement)
+        .bind(workspace_id)
+        .fetch_all(&mut *acquire(pool).await?)
+        .await?;
+
+    Ok(invitations)
+}
+
+/// Invites an email address to a workspace. Inviting the same address again changes the role of the invitation.
+///
+/// When a member already has the email address, we return an error with the [`AppError::AlreadyMember`] variant.
+#[instrument(skip(pool, email_address), fields(email_address = %mask_email(email_address)))]
+pub async fn insert_workspace_invitation(
+    pool: &PgPool,
+    workspace_id: i32,
+    invited_by: i32,
+    email_address: &str,
+    role: WorkspaceRole,
+) -> Result<WorkspaceInvitation> {
+    let _timer = QueryTimer::start("insert_workspace_invitation");
+
+    let mut connection = acquire(pool).await?;
+
+    let is_member: bool = sqlx::query_scalar(
+        "SELECT EXISTS (
+             SELECT 1 FROM workspace_members JOIN users ON users.id = workspace_members.user_id
+             WHERE workspace_members.workspace_id = $1 AND lower(users.email_address) = lower($2)
+         )",
+    )
+    .bind(workspace_id)
+    .bind(email_address)
+    .fetch_one(&mut *connection)
+    .await?;
+
+    if is_member {
+        return Err(AppError::AlreadyMember);
+    }
+
+    let statement = format!(
+        "WITH workspace_invitations AS (
+             INSERT INTO workspace_invitations (workspace_id, email_address, role, invited_by, date_created)
+             VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT (workspace_id, lower(email_address))
+             DO UPDATE SET role = EXCLUDED.role, invited_by = EXCLUDED.invited_by
+             RETURNING *
+         )
+         SELECT {} FROM workspace_invitations JOIN workspaces ON workspaces.id = workspace_invitations.workspace_id",
+        INVITATION_COLUMNS
+    );
+
+    let invitation = sqlx::query_as::<_, WorkspaceInvitation>(&statement)
+        .bind(workspace_id)
+        .bind(email_address)
+        .bind(role)
+        .bind(invited_by)
+        .bind(chrono::Utc::now())
+        .fetch_one(&mut *connection)
+        .await?;
+
+    Ok(invitation)
+}
+
+/// Withdraws an invitation to a workspace.
+///
+/// When the workspace has no invitation with the ID, we return an error with the [`AppError::InvitationNotFound`]
+/// variant.
+#[instrument(skip(pool))]
+pub async fn delete_workspace_invitation(
+    pool: &PgPool,
+    workspace_id: i32,
+    invitation_id: i32,
+) -> Result<()> {
+    let _timer = QueryTimer::start("delete_workspace_invitation");
+
+    let rows_affected =
+        sqlx::query("DELETE FROM workspace_invitations WHERE workspace_id = $1 AND id = $2")
+            .bind(workspace_id)
+            .bind(invitation_id)
+            .execute(&mut *acquire(pool).await?)
+            .await?
+            .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(AppError::InvitationNotFound);
+    }
+
+    Ok(())
+}
+
+/// Lists the invitations to the email address of a user.
+#[instrument(skip(pool))]
+pub async fn list_user_invitations(
+    pool: &PgPool,
+    user_id: i32,
+) -> Result<Vec<WorkspaceInvitation>> {
+    let _timer = QueryTimer::start("list_user_invitations");
+
+    let statement = format!(
+        "SELECT {} FROM workspace_invitations
+         JOIN workspaces ON workspaces.id = workspace_invitations.workspace_id
+         JOIN users ON lower(users.email_address) = lower(workspace_invitations.email_address)
+         WHERE users.id = $1
+         ORDER BY workspace_invitations.id",
+        INVITATION_COLUMNS
+    );
+
+    let invitations = sqlx::query_as::<_, WorkspaceInvitation>(&statement)
+        .bind(user_id)
+        .fetch_all(&mut *acquire(pool).await?)
+        .await?;
+
+    Ok(invitations)
+}
+
+/// Accepts an invitation to the email address of a user, which makes them a member of the workspace with the role of
+/// the invitation. This returns the ID of the workspace.
+///
+/// When the user has no invitation with the ID, we return an error with the [`AppError::InvitationNotFound`] variant.
+#[instrument(skip(pool))]
+pub async fn accept_workspace_invitation(
+    pool: &PgPool,
+    user_id: i32,
+    invitation_id: i32,
+) -> Result<i32> {
+    let _timer = QueryTimer::start("accept_workspace_invitation");
+
+    let mut connection = acquire(pool).await?;
+    let mut transaction = connection.begin().await?;
+
+    let (workspace_id, role) = sqlx::query_as::<_, (i32, WorkspaceRole)>(
+        "DELETE FROM workspace_invitations USING users
+         WHERE workspace_invitations.id = $1 AND users.id = $2
+             AND lower(users.email_address) = lower(workspace_invitations.email_address)
+         RETURNING workspace_invitations.workspace_id, workspace_invitations.role",
+    )
+    .bind(invitation_id)
+    .bind(user_id)
+    .fetch_optional(&mut *transaction)
+    .await?
+    .ok_or(AppError::InvitationNotFound)?;
+
+    sqlx::query(
+        "INSERT INTO workspace_members (workspace_id, user_id, role, date_created) VALUES ($1, $2, $3, $4)
+         ON CONFLICT (workspace_id, user_id) DO NOTHING",
+    )
+    .bind(workspace_id)
+    .bind(user_id)
+    .bind(role)
+    .bind(chrono::Utc::now())
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(workspace_id)
+}
+
+/// Declines an invitation to the email address of a user.
+///
+/// When the user has no invitation with the ID, we return an error with the [`AppError::InvitationNotFound`] variant.
+#[instrument(skip(pool))]
+pub async fn decline_workspace_invitation(
+    pool: &PgPool,
+    user_id: i32,
+    invitation_id: i32,
+) -> Result<()> {
+    let _timer = QueryTimer::start("decline_workspace_invitation");
+
+    let rows_affected = sqlx::query(
+        "DELETE FROM workspace_invitations USING users
+         WHERE workspace_invitations.id = $1 AND users.id = $2
+             AND lower(users.email_address) = lower(workspace_invitations.email_address)",
+    )
+    .bind(invitation_id)
+    .bind(user_id)
+    .execute(&mut *acquire(pool).await?)
+    .await?
+    .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(AppError::InvitationNotFound);
+    }
+
+    Ok(())
+}
+
+/// Lists the API keys a member created for a workspace.
+#[instrument(skip(pool))]
+pub async fn list_workspace_keys(
+    pool: &PgPool,
+    workspace_id: i32,
+    user_id: i32,
+) -> Result<Vec<WorkspaceKey>> {
+    let _timer = QueryTimer::start("list_workspace_keys");
+
+    let keys = sqlx::query_as::<_, WorkspaceKey>(
+        "SELECT id, workspace_id, user_id, name, date_created FROM workspace_keys
+         WHERE workspace_id = $1 AND user_id = $2
+         ORDER BY id",
+    )
+    .bind(workspace_id)
+    .bind(user_id)
+    .fetch_all(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(keys)
+}
+
+/// Lists the API keys a member created for all of their workspaces without pagination.
+///
+/// This is used to export all data we store about a user, so it's not meant for regular listing of API keys.
+#[instrument(skip(pool))]
+pub async fn list_all_workspace_keys(pool: &PgPool, user_id: i32) -> Result<Vec<WorkspaceKey>> {
+    let _timer = QueryTimer::start("list_all_workspace_keys");
+
+    let keys = sqlx::query_as::<_, WorkspaceKey>(
+        "SELECT id, workspace_id, user_id, name, date_created FROM workspace_keys WHERE user_id = $1 ORDER BY id",
+    )
+    .bind(user_id)
+    .fetch_all(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(keys)
+}
+
+/// Stores the hash of a new API key that acts on behalf of a member, but only within the workspace.
+#[instrument(skip(pool, name, api_key))]
+pub async fn insert_workspace_key(
+    pool: &PgPool,
+    workspace_id: i32,
+    user_id: i32,
+    name: String,
+    api_key: String,
+) -> Result<WorkspaceKey> {
+    let _timer = QueryTimer::start("insert_workspace_key");
+
+    let key = sqlx::query_as::<_, WorkspaceKey>(
+        "INSERT INTO workspace_keys (workspace_id, user_id, name, api_key, date_created) VALUES ($1, $2, $3, $4, $5)
+         RETURNING id, workspace_id, user_id, name, date_created",
+    )
+    .bind(workspace_id)
+    .bind(user_id)
+    .bind(name)
+    .bind(api_key)
+    .bind(chrono::Utc::now())
+    .fetch_one(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(key)
+}
+
+/// Revokes an API key a member created for a workspace.
+///
+/// When the member has no key with the ID, we return an error with the [`AppError::WorkspaceKeyNotFound`] variant.
+#[instrument(skip(pool))]
+pub async fn delete_workspace_key(
+    pool: &PgPool,
+    workspace_id: i32,
+    user_id: i32,
+    key_id: i32,
+) -> Result<()> {
+    let _timer = QueryTimer::start("delete_workspace_key");
+
+    let rows_affected = sqlx::query(
+        "DELETE FROM workspace_keys WHERE workspace_id = $1 AND user_id = $2 AND id = $3",
+    )
+    .bind(workspace_id)
+    .bind(user_id)
+    .bind(key_id)
+    .execute(&mut *acquire(pool).await?)
+    .await?
+    .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(AppError::WorkspaceKeyNotFound);
+    }
+
+    Ok(())
+}
+
+/// Retrieves an API key of a workspace by its hash.
+///
+/// When no workspace has the key, we return an error with the [`AppError::WorkspaceKeyNotFound`] variant.
+#[instrument(skip(pool, api_key))]
+pub async fn get_workspace_key(pool: &PgPool, api_key: &str) -> Result<WorkspaceKey> {
+    let _timer = QueryTimer::start("get_workspace_key");
+
+    sqlx::query_as::<_, WorkspaceKey>(
+        "SELECT id, workspace_id, user_id, name, date_created FROM workspace_keys WHERE api_key = $1",
+    )
+    .bind(api_key)
+    .fetch_optional(&mut *acquire(pool).await?)
+    .await?
+    .ok_or(AppError::WorkspaceKeyNotFound)
+}
+
+/// Records the users mentioned in the description of a task, and notifies the users who are newly mentioned.
+///
+/// Call this in the same transaction as the change of the description. Only users who can access the task count as
+/// mentioned. Users who are no longer mentioned are forgotten, so mentioning them again notifies them again.
+async fn update_task_mentions(connection: &mut PgConnection, task: &Task) -> Result<()> {
+    let mentioned = find_mentioned_users(connection, task.id, &task.description).await?;
+    let date_created = chrono::Utc::now();
+
+    sqlx::query("DELETE FROM task_mentions WHERE task_id = $1 AND NOT (user_id = ANY($2))")
+        .bind(task.id)
+        .bind(&mentioned)
+        .execute(&mut *connection)
+        .await?;
+
+    let newly_mentioned: Vec<i32> = sqlx::query_scalar(
+        "INSERT INTO task_mentions (task_id, user_id, date_created) SELECT $1, unnest($2::integer[]), $3
+         ON CONFLICT DO NOTHING RETURNING user_id",
+    )
+    .bind(task.id)
+    .bind(&mentioned)
+    .bind(date_created)
+    .fetch_all(&mut *connection)
+    .await?;
+
+    for user_id in newly_mentioned {
+        let notification = SendNotification {
+            user_id,
+            task_id: Some(task.id),
+            kind: NotificationKind::TaskMentioned,
+            title: "You were mentioned in a task".to_string(),
+            body: format!("You were mentioned in \"{}\".", task.title),
+            dedup_key: format!("mention_{}_{}", task.id, date_created.timestamp_micros()),
+        };
+
+        insert_job(&mut *connection, &notification).await?;
+    }
+
+    Ok(())
+}
+
+/// Finds the users mentioned in a text about a task, see [`parse_mentions`].
+///
+/// Only users who can access the task count as mentioned, so a mention never reveals the task to anyone else.
+async fn find_mentioned_users(
+    connection: &mut PgConnection,
+    task_id: i32,
+    text: &str,
+) -> Result<Vec<i32>> {
+    let mentions = parse_mentions(text);
+
+    if mentions.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let user_ids = sqlx::query_scalar(
+        "SELECT DISTINCT users.id FROM users JOIN task_access ON task_access.user_id = users.id
+         WHERE task_access.task_id = $1
+         AND (lower(users.handle) = ANY($2) OR lower(users.email_address) = ANY($3))",
+    )
+    .bind(task_id)
+    .bind(&mentions.handles)
+    .bind(&mentions.email_addresses)
+    .fetch_all(&mut *connection)
+    .await?;
+
+    Ok(user_ids)
+}
+
+/// Lists the users assigned to or watching a task, in the order they were added.
+async fn list_task_users(pool: &PgPool, table: &str, task_id: i32) -> Result<Vec<TaskUser>> {
+    let users = sqlx::query_as::<_, TaskUser>(&format!(
+        "SELECT users.id AS user_id, users.email_address, users.handle, {table}.date_created
+         FROM {table} JOIN users ON users.id = {table}.user_id
+         WHERE {table}.task_id = $1 ORDER BY {table}.date_created, users.id"
+    ))
+    .bind(task_id)
+    .fetch_all(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(users)
+}
+
+/// Lists the tasks a user is assigned to, watches or is mentioned in, in the order they were added.
+async fn list_user_tasks(pool: &PgPool, table: &str, user_id: i32) -> Result<Vec<UserTask>> {
+    let tasks = sqlx::query_as::<_, UserTask>(&format!(
+        "SELECT task_id, date_created FROM {table} WHERE user_id = $1 ORDER BY date_created, task_id"
+    ))
+    .bind(user_id)
+    .fetch_all(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(tasks)
+}
+
+/// Lists all tasks a user is assigned to without pagination.
+///
+/// This is used to export all data we store about a user, so it's not meant for regular listing of assignments.
+#[instrument(skip(pool))]
+pub async fn list_all_assignments(pool: &PgPool, user_id: i32) -> Result<Vec<UserTask>> {
+    let _timer = QueryTimer::start("list_all_assignments");
+
+    list_user_tasks(pool, "task_assignees", user_id).await
+}
+
+/// Lists all tasks a user watches without pagination.
+///
+/// This is used to export all data we store about a user, so it's not meant for regular listing of watched tasks.
+#[instrument(skip(pool))]
+pub async fn list_all_watches(pool: &PgPool, user_id: i32) -> Result<Vec<UserTask>> {
+    let _timer = QueryTimer::start("list_all_watches");
+
+    list_user_tasks(pool, "task_watchers", user_id).await
+}
+
+/// Lists all tasks a user is mentioned in without pagination.
+///
+/// This is used to export all data we store about a user, so it's not meant for regular listing of mentions.
+#[instrument(skip(pool))]
+pub async fn list_all_mentions(pool: &PgPool, user_id: i32) -> Result<Vec<UserTask>> {
+    let _timer = QueryTimer::start("list_all_mentions");
+
+    list_user_tasks(pool, "task_mentions", user_id).await
+}
+
+/// Checks that a user can access a task, before they're assigned to it or watch it.
+///
+/// When the user can't access the task, we return an error with the [`AppError::TaskNotShared`] variant.
+async fn ensure_task_access(
+    connection: &mut PgConnection,
+    task_id: i32,
+    user_id: i32,
+) -> Result<()> {
+    let has_access: bool = sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM task_access WHERE task_id = $1 AND user_id = $2)",
+    )
+    .bind(task_id)
+    .bind(user_id)
+    .fetch_one(&mut *connection)
+    .await?;
+
+    if has_access {
+        Ok(())
+    } else {
+        Err(AppError::TaskNotShared)
+    }
+}
+
+/// Lists the users a task is assigned to.
+#[instrument(skip(pool))]
+pub async fn list_task_assignees(pool: &PgPool, task_id: i32) -> Result<Vec<TaskUser>> {
+    let _timer = QueryTimer::start("list_task_assignees");
+
+    list_task_users(pool, "task_assignees", task_id).await
+}
+
+/// Assigns a user to a task, on behalf of another user.
+///
+/// The user must be able to access the task. Assigning a user twice is harmless. A new assignment notifies the
+/// assigned user and the watchers of the task in the same transaction, except for the user who made the change.
+#[instrument(skip(pool))]
+pub async fn insert_task_assignee(
+    pool: &PgPool,
+    assigned_by: i32,
+    task_id: i32,
+    user_id: i32,
+) -> Result<()> {
+    let _timer = QueryTimer::start("insert_task_assignee");
+
+    let mut connection = acquire(pool).await?;
+    let mut transaction = connection.begin().await?;
+
+    ensure_task_access(&mut transaction, task_id, user_id).await?;
+
+    let rows_affected = sqlx::query(
+        "INSERT INTO task_assignees (task_id, user_id, assigned_by, date_created) VALUES ($1, $2, $3, $4)
+         ON CONFLICT DO NOTHING",
+    )
+    .bind(task_id)
+    .bind(user_id)
+    .bind(assigned_by)
+    .bind(chrono::Utc::now())
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+
+    if rows_affected > 0 {
+        notify_assignment(&mut transaction, assigned_by, task_id, user_id, true).await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+/// Unassigns a user from a task, on behalf of another user.
+///
+/// This notifies the unassigned user and the watchers of the task, except for the user who made the change. When the
+/// user isn't assigned to the task, we return an error with the [`AppError::AssigneeNotFound`] variant.
+#[instrument(skip(pool))]
+pub async fn delete_task_assignee(
+    pool: &PgPool,
+    unassigned_by: i32,
+    task_id: i32,
+    user_id: i32,
+) -> Result<()> {
+    let _timer = QueryTimer::start("delete_task_assignee");
+
+    let mut connection = acquire(pool).await?;
+    let mut transaction = connection.begin().await?;
+
+    let rows_affected =
+        sqlx::query("DELETE FROM task_assignees WHERE task_id = $1 AND user_id = $2")
+            .bind(task_id)
+            .bind(user_id)
+            .execute(&mut *transaction)
+            .await?
+            .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(AppError::AssigneeNotFound);
+    }
+
+    notify_assignment(&mut transaction, unassigned_by, task_id, user_id, false).await?;
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+/// Queues the notifications about a user who was assigned to or unassigned from a task.
+///
+/// The user and the watchers of the task are notified, except for the user who made the change. Users are named by
+/// their handle when they have one, and by their email address otherwise.
+async fn notify_assignment(
+    connection: &mut PgConnection,
+    actor_id: i32,
+    task_id: i32,
+    user_id: i32,
+    assigned: bool,
+) -> Result<()> {
+    let (title, name): (String, String) = sqlx::query_as(
+        "SELECT tasks.title, COALESCE('@' || users.handle, users.email_address) FROM tasks, users
+         WHERE tasks.id = $1 AND users.id = $2",
+    )
+    .bind(task_id)
+    .bind(user_id)
+    .fetch_one(&mut *connection)
+    .await?;
+
+    let watchers: Vec<i32> = sqlx::query_scalar(
+        "SELECT user_id FROM task_watchers WHERE task_id = $1 AND user_id <> $2 AND user_id <> $3",
+    )
+    .bind(task_id)
+    .bind(actor_id)
+    .bind(user_id)
+    .fetch_all(&mut *connection)
+    .await?;
+
+    let (kind, change) = if assigned {
+        (NotificationKind::TaskAssigned, "assigned to")
+    } else {
+        (NotificationKind::TaskUnassigned, "unassigned from")
+    };
+
+    let dedup_key = format!(
+        "{}_{}_{}_{}",
+        if assigned { "assigned" } else { "unassigned" },
+        task_id,
+        user_id,
+        chrono::Utc::now().timestamp_micros()
+    );
+
+    let mut notifications = Vec::new();
+
+    if user_id != actor_id {
+        notifications.push(SendNotification {
+            user_id,
+            task_id: Some(task_id),
+            kind,
+            title: format!("You were {} a task", change),
+            body: format!("You were {} \"{}\".", change, title),
+            dedup_key: dedup_key.clone(),
+        });
+    }
+
+    for watcher_id in watchers {
+        notifications.push(SendNotification {
+            user_id: watcher_id,
+            task_id: Some(task_id),
+            kind,
+            title: format!("{} was {} a task", name, change),
+            body: format!("{} was {} \"{}\".", name, change, title),
+            dedup_key: dedup_key.clone(),
+        });
+    }
+
+    for notification in notifications {
+        insert_job(&mut *connection, &notification).await?;
+    }
+
+    Ok(())
+}
+
+/// Lists the users watching a task.
+#[instrument(skip(pool))]
+pub async fn list_task_watchers(pool: &PgPool, task_id: i32) -> Result<Vec<TaskUser>> {
+    let _timer = QueryTimer::start("list_task_watchers");
+
+    list_task_users(pool, "task_watchers", task_id).await
+}
+
+/// Adds a user to the watchers of a task. The user must be able to access the task, and watching it twice is harmless.
+#[instrument(skip(pool))]
+pub async fn insert_task_watcher(pool: &PgPool, task_id: i32, user_id: i32) -> Result<()> {
+    let _timer = QueryTimer::start("insert_task_watcher");
+
+    let mut connection = acquire(pool).await?;
+
+    ensure_task_access(&mut connection, task_id, user_id).await?;
+
+    sqlx::query(
+        "INSERT INTO task_watchers (task_id, user_id, date_created) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+    )
+    .bind(task_id)
+    .bind(user_id)
+    .bind(chrono::Utc::now())
+    .execute(&mut *connection)
+    .await?;
+
+    Ok(())
+}
+
+/// Removes a user from the watchers of a task.
+///
+/// When the user doesn't watch the task, we return an error with the [`AppError::WatcherNotFound`] variant.
+#[instrument(skip(pool))]
+pub async fn delete_task_watcher(pool: &PgPool, task_id: i32, user_id: i32) -> Result<()> {
+    let _timer = QueryTimer::start("delete_task_watcher");
+
+    let rows_affected =
+        sqlx::query("DELETE FROM task_watchers WHERE task_id = $1 AND user_id = $2")
+            .bind(task_id)
+            .bind(user_id)
+            .execute(&mut *acquire(pool).await?)
+            .await?
+            .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(AppError::WatcherNotFound);
+    }
+
+    Ok(())
+}
+
+/// Lists the comments on a task, oldest first, so they read like a conversation.
+#[instrument(skip(pool))]
+pub async fn list_comments(
+    pool: &PgPool,
+    task_id: i32,
+    page_index: i32,
+    page_size: i32,
+) -> Result<PagedResult<Comment>> {
+    let _timer = QueryTimer::start("list_comments");
+
+    let items = sqlx::query_as::<_, Comment>(
+        "SELECT comments.id, comments.task_id, comments.user_id, users.email_address, users.handle, comments.body,
+         comments.body_html, comments.date_created, comments.date_modified
+         FROM comments JOIN users ON users.id = comments.user_id
+         WHERE comments.task_id = $1 ORDER BY comments.id LIMIT $2 OFFSET $3",
+    )
+    .bind(task_id)
+    .bind(page_size)
+    .bind(page_index * page_size)
+    .fetch_all(&mut *acquire(pool).await?)
+    .await?;
+
+    let total_count: i64 =
+        sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM comments WHERE task_id = $1")
+            .bind(task_id)
+            .fetch_one(&mut *acquire(pool).await?)
+            .await?;
+
+    Ok(PagedResult {
+        items,
+        page_index,
+        page_size,
+        total_count,
+    })
+}
+
+/// Retrieves a comment on a task.
+///
+/// When the comment doesn't exist, or is on another task, we return an error with the [`AppError::CommentNotFound`]
+/// variant.
+#[instrument(skip(pool))]
+pub async fn find_comment(pool: &PgPool, task_id: i32, comment_id: i32) -> Result<Comment> {
+    let _timer = QueryTimer::start("find_comment");
+
+    get_comment(&mut *acquire(pool).await?, task_id, comment_id).await
+}
+
+/// Adds a comment to a task, and notifies the users mentioned in it.
+///
+/// The markdown is rendered to sanitised HTML once, when the comment is saved. The notifications are queued in the
+/// same transaction as the comment.
+#[instrument(skip(pool, body))]
+pub async fn insert_comment(
+    pool: &PgPool,
+    task_id: i32,
+    user_id: i32,
+    body: String,
+) -> Result<Comment> {
+    let _timer = QueryTimer::start("insert_comment");
+
+    let mut connection = acquire(pool).await?;
+    let mut transaction = connection.begin().await?;
+
+    let comment_id: i32 = sqlx::query_scalar(
+        "INSERT INTO comments (task_id, user_id, body, body_html, date_created) VALUES ($1, $2, $3, $4, $5)
+         RETURNING id",
+    )
+    .bind(task_id)
+    .bind(user_id)
+    .bind(&body)
+    .bind(render_markdown(&body))
+    .bind(chrono::Utc::now())
+    .fetch_one(&mut *transaction)
+    .await?;
+
+    let mentioned = find_mentioned_users(&mut transaction, task_id, &body).await?;
+    notify_comment_mentions(&mut transaction, comment_id, task_id, user_id, mentioned).await?;
+
+    let comment = get_comment(&mut transaction, task_id, comment_id).await?;
+
+    transaction.commit().await?;
+
+    Ok(comment)
+}
+
+/// Edits a comment on a task. Only the author of the comment can edit it.
+///
+/// The body the comment had before is kept as a revision, see [`list_comment_revisions`]. Users who are mentioned for
+/// the first time in the comment are notified. When the user isn't the author, we return an error with the
+/// [`AppError::NotCommentAuthor`] variant.
+#[instrument(skip(pool, body))]
+pub async fn update_comment(
+    pool: &PgPool,
+    task_id: i32,
+    comment_id: i32,
+    user_id: i32,
+    body: String,
+) -> Result<Comment> {
+    let _timer = QueryTimer::start("update_comment");
+
+    let mut connection = acquire(pool).await?;
+    let mut transaction = connection.begin().await?;
+
+    let previous_body = lock_own_comment(&mut transaction, task_id, comment_id, user_id).await?;
+
+    if previous_body != body {
+        let date_modified = chrono::Utc::now();
+
+        sqlx::query(
+            "INSERT INTO comment_revisions (comment_id, body, date_created) VALUES ($1, $2, $3)",
+        )
+        .bind(comment_id)
+        .bind(&previous_body)
+        .bind(date_modified)
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query(
+            "UPDATE comments SET body = $1, body_html = $2, date_modified = $3 WHERE id = $4",
+        )
+        .bind(&body)
+        .bind(render_markdown(&body))
+        .bind(date_modified)
+        .bind(comment_id)
+        .execute(&mut *transaction)
+        .await?;
+
+        let previously_mentioned =
+            find_mentioned_users(&mut transaction, task_id, &previous_body).await?;
+        let newly_mentioned = find_mentioned_users(&mut transaction, task_id, &body)
+            .await?
+            .into_iter()
+            .filter(|user_id| !previously_mentioned.contains(user_id))
+            .collect();
+
+        notify_comment_mentions(
+            &mut transaction,
+            comment_id,
+            task_id,
+            user_id,
+            newly_mentioned,
+        )
+        .await?;
+    }
+
+    let comment = get_comment(&mut transaction, task_id, comment_id).await?;
+
+    transaction.commit().await?;
+
+    Ok(comment)
+}
+
+/// Removes a comment from a task, together with its revisions. Only the author of the comment can remove it.
+///
+/// When the user isn't the author, we return an error with the [`AppError::NotCommentAuthor`] variant.
+#[instrument(skip(pool))]
+pub async fn delete_comment(
+    pool: &PgPool,
+    task_id: i32,
+    comment_id: i32,
+    user_id: i32,
+) -> Result<()> {
+    let _timer = QueryTimer::start("delete_comment");
+
+    let mut connection = acquire(pool).await?;
+    let mut transaction = connection.begin().await?;
+
+    lock_own_comment(&mut transaction, task_id, comment_id, user_id).await?;
+
+    sqlx::query("DELETE FROM comments WHERE id = $1")
+        .bind(comment_id)
+        .execute(&mut *transaction)
+        .await?;
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+/// Lists the earlier versions of a comment, newest first.
+///
+/// When the comment doesn't exist, or is on another task, we return an error with the [`AppError::CommentNotFound`]
+/// variant.
+#[instrument(skip(pool))]
+pub async fn list_comment_revisions(
+    pool: &PgPool,
+    task_id: i32,
+    comment_id: i32,
+) -> Result<Vec<CommentRevision>> {
+    let _timer = QueryTimer::start("list_comment_revisions");
+
+    let mut connection = acquire(pool).await?;
+
+    get_comment(&mut connection, task_id, comment_id).await?;
+
+    let revisions = sqlx::query_as::<_, CommentRevision>(
+        "SELECT id, comment_id, body, date_created FROM comment_revisions WHERE comment_id = $1 ORDER BY id DESC",
+    )
+    .bind(comment_id)
+    .fetch_all(&mut *connection)
+    .await?;
+
+    Ok(revisions)
+}
+
+/// Lists all comments a user wrote without pagination.
+///
+/// This is used to export all data we store about a user, so it's not meant for regular listing of comments.
+#[instrument(skip(pool))]
+pub async fn list_all_comments(pool: &PgPool, user_id: i32) -> Result<Vec<Comment>> {
+    let _timer = QueryTimer::start("list_all_comments");
+
+    let comments = sqlx::query_as::<_, Comment>(
+        "SELECT comments.id, comments.task_id, comments.user_id, users.email_address, users.handle, comments.body,
+         comments.body_html, comments.date_created, comments.date_modified
+         FROM comments JOIN users ON users.id = comments.user_id
+         WHERE comments.user_id = $1 ORDER BY comments.id",
+    )
+    .bind(user_id)
+    .fetch_all(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(comments)
+}
+
+/// Lists the earlier versions of all comments a user wrote without pagination.
+///
+/// This is used to export all data we store about a user, so it's not meant for regular listing of revisions.
+#[instrument(skip(pool))]
+pub async fn list_all_comment_revisions(
+    pool: &PgPool,
+    user_id: i32,
+) -> Result<Vec<CommentRevision>> {
+    let _timer = QueryTimer::start("list_all_comment_revisions");
+
+    let revisions = sqlx::query_as::<_, CommentRevision>(
+        "SELECT comment_revisions.id, comment_revisions.comment_id, comment_revisions.body,
+         comment_revisions.date_created
+         FROM comment_revisions JOIN comments ON comments.id = comment_revisions.comment_id
+         WHERE comments.user_id = $1 ORDER BY comment_revisions.id",
+    )
+    .bind(user_id)
+    .fetch_all(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(revisions)
+}
+
+/// Retrieves a comment together with its author.
+async fn get_comment(
+    connection: &mut PgConnection,
+    task_id: i32,
+    comment_id: i32,
+) -> Result<Comment> {
+    sqlx::query_as::<_, Comment>(
+        "SELECT comments.id, comments.task_id, comments.user_id, users.email_address, users.handle, comments.body,
+         comments.body_html, comments.date_created, comments.date_modified
+         FROM comments JOIN users ON users.id = comments.user_id
+         WHERE comments.task_id = $1 AND comments.id = $2",
+    )
+    .bind(task_id)
+    .bind(comment_id)
+    .fetch_optional(&mut *connection)
+    .await?
+    .ok_or(AppError::CommentNotFound)
+}
+
+/// Locks a comment for an edit or removal by a user, and returns its current body.
+///
+/// When the comment doesn't exist, we return an error with the [`AppError::CommentNotFound`] variant. When the user
+/// didn't write the comment, we return an error with the [`AppError::NotCommentAuthor`] variant.
+async fn lock_own_comment(
+    connection: &mut PgConnection,
+    task_id: i32,
+    comment_id: i32,
+    user_id: i32,
+) -> Result<String> {
+    let (author_id, body): (i32, String) = sqlx::query_as(
+        "SELECT user_id, body FROM comments WHERE task_id = $1 AND id = $2 FOR UPDATE",
+    )
+    .bind(task_id)
+    .bind(comment_id)
+    .fetch_optional(&mut *connection)
+    .await?
+    .ok_or(AppError::CommentNotFound)?;
+
+    if author_id != user_id {
+        return Err(AppError::NotCommentAuthor);
+    }
+
+    Ok(body)
+}
+
+/// Notifies the users mentioned in a comment. Authors aren't notified when they mention themselves.
+async fn notify_comment_mentions(
+    connection: &mut PgConnection,
+    comment_id: i32,
+    task_id: i32,
+    author_id: i32,
+    mentioned: Vec<i32>,
+) -> Result<()> {
+    let mentioned: Vec<i32> = mentioned
+        .into_iter()
+        .filter(|user_id| *user_id != author_id)
+        .collect();
+
+    if mentioned.is_empty() {
+        return Ok(());
+    }
+
+    let (title, author): (String, String) = sqlx::query_as(
+        "SELECT tasks.title, COALESCE('@' || users.handle, users.email_address) FROM tasks, users
+         WHERE tasks.id = $1 AND users.id = $2",
+    )
+    .bind(task_id)
+    .bind(author_id)
+    .fetch_one(&mut *connection)
+    .await?;
+
+    let date_created = chrono::Utc::now();
+
+    for user_id in mentioned {
+        let notification = SendNotification {
+            user_id,
+            task_id: Some(task_id),
+            kind: NotificationKind::TaskMentioned,
+            title: "You were mentioned in a comment".to_string(),
+            body: format!("{} mentioned you in a comment on \"{}\".", author, title),
+            dedup_key: format!(
+                "comment_mention_{}_{}",
+                comment_id,
+                date_created.timestamp_micros()
+            ),
+        };
+
+        insert_job(&mut *connection, &notification).await?;
+    }
+
+    Ok(())
+}
+
+/// Lists the files attached to a task, in the order they were uploaded.
+#[instrument(skip(pool))]
+pub async fn list_attachments(pool: &PgPool, task_id: i32) -> Result<Vec<Attachment>> {
+    let _timer = QueryTimer::start("list_attachments");
+
+    let attachments = sqlx::query_as::<_, Attachment>(
+        "SELECT id, task_id, user_id, file_name, content_type, size, storage_key, date_created FROM attachments
+         WHERE task_id = $1 ORDER BY id",
+    )
+    .bind(task_id)
+    .fetch_all(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(attachments)
+}
+
+/// Lists all files a user uploaded without pagination.
+///
+/// This is used to export all data we store about a user, so it's not meant for regular listing of attachments.
+#[instrument(skip(pool))]
+pub async fn list_all_attachments(pool: &PgPool, user_id: i32) -> Result<Vec<Attachment>> {
+    let _timer = QueryTimer::start("list_all_attachments");
+
+    let attachments = sqlx::query_as::<_, Attachment>(
+        "SELECT id, task_id, user_id, file_name, content_type, size, storage_key, date_created FROM attachments
+         WHERE user_id = $1 ORDER BY id",
+    )
+    .bind(user_id)
+    .fetch_all(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(attachments)
+}
+
+/// Records a file that was attached to a task. The contents must be in the blob store already.
+#[instrument(skip(pool))]
+pub async fn insert_attachment(
+    pool: &PgPool,
+    task_id: i32,
+    user_id: i32,
+    file_name: &str,
+    content_type: &str,
+    size: i64,
+    storage_key: &str,
+) -> Result<Attachment> {
+    let _timer = QueryTimer::start("insert_attachment");
+
+    let attachment = sqlx::query_as::<_, Attachment>(
+        "INSERT INTO attachments (task_id, user_id, file_name, content_type, size, storage_key, date_created)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)
+         RETURNING id, task_id, user_id, file_name, content_type, size, storage_key, date_created",
+    )
+    .bind(task_id)
+    .bind(user_id)
+    .bind(file_name)
+    .bind(content_type)
+    .bind(size)
+    .bind(storage_key)
+    .bind(chrono::Utc::now())
+    .fetch_one(&mut *acquire(pool).await?)
+    .await?;
+
+    Ok(attachment)
+}
+
+/// Queues the removal of contents from the blob store that no attachment refers to, like the file of an upload that
+/// couldn't be recorded.
+#[instrument(skip(pool))]
+pub async fn insert_blob_deletion(pool: &PgPool, storage_key: &str) -> Result<()> {
+    let _timer = QueryTimer::start("insert_blob_deletion");
+
+    insert_job(
+        &mut *acquire(pool).await?,
+        &DeleteBlob {
+            storage_key: storage_key.to_string(),
+        },
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Retrieves a file attached to a task.
+///
+/// When the attachment doesn't exist, or belongs to another task, we return an error with the
+/// [`AppError::AttachmentNotFound`] variant.
+#[instrument(skip(pool))]
+pub async fn find_attachment(
+    pool: &PgPool,
+    task_id: i32,
+    attachment_id: i32,
+) -> Result<Attachment> {
+    let _timer = QueryTimer::start("find_attachment");
+
+    sqlx::query_as::<_, Attachment>(
+        "SELECT id, task_id, user_id, file_name, content_type, size, storage_key, date_created FROM attachments
+         WHERE task_id = $1 AND id = $2",
+    )
+    .bind(task_id)
+    .bind(attachment_id)
+    .fetch_optional(&mut *acquire(pool).await?)
+    .await?
+    .ok_or(AppError::AttachmentNotFound)
+}
+
+/// Retrieves an attachment by its ID alone.
+///
+/// This is only meant for the download endpoint, where the signature of the URL grants the access. When the
+/// attachment doesn't exist, we return an error with the [`AppError::AttachmentNotFound`] variant.
+#[instrument(skip(pool))]
+pub async fn get_attachment(pool: &PgPool, attachment_id: i32) -> Result<Attachment> {
+    let _timer = QueryTimer::start("get_attachment");
+
+    sqlx::query_as::<_, Attachment>(
+        "SELECT id, task_id, user_id, file_name, content_type, size, storage_key, date_created FROM attachments
+         WHERE id = $1",
+    )
+    .bind(attachment_id)
+    .fetch_optional(&mut *acquire(pool).await?)
+    .await?
+    .ok_or(AppError::AttachmentNotFound)
+}
+
+/// Removes a file from a task.
+///
+/// The trigger on the `attachments` table queues the removal of the contents from the blob store. When the attachment
+/// doesn't exist, we return an error with the [`AppError::AttachmentNotFound`] variant.
+#[instrument(skip(pool))]
+pub async fn delete_attachment(pool: &PgPool, task_id: i32, attachment_id: i32) -> Result<()> {
+    let _timer = QueryTimer::start("delete_attachment");
+
+    let rows_affected = sqlx::query("DELETE FROM attachments WHERE task_id = $1 AND id = $2")
+        .bind(task_id)
+        .bind(attachment_id)
+        .execute(&mut *acquire(pool).await?)
+        .await?
+        .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(AppError::AttachmentNotFound);
+    }
+
+    Ok(())
+}
+
+/// The key of the advisory lock that is held while a dependency between tasks is added.
+///
+/// Two links that are added at the same time can close a cycle together, while neither of them does on its own. The
+/// lock makes sure each link is checked against the links that were added before it.
+const TASK_DEPENDENCIES_LOCK: i64 = 0x7461_736b_5f64_6570;
+
+/// Makes a task wait for another task.
+///
+/// We walk the tasks the other task waits for, directly or through other tasks. When the task is one of them, the new
+/// link would close a cycle, so we return an error with the [`AppError::DependencyCycle`] variant. Adding a link that
+/// already exists does nothing.
+///
+/// The walk follows every link, including the links through tasks the user who adds the link can't see. Skipping
+/// those would let a hidden task close a cycle, and the tasks in it could never be completed. The error doesn't say
+/// which tasks are in the cycle, so it doesn't reveal more than that the tasks are linked somehow.
+#[instrument(skip(pool))]
+pub async fn insert_task_dependency(pool: &PgPool, task_id: i32, blocked_by_id: i32) -> Result<()> {
+    let _timer = QueryTimer::start("insert_task_dependency");
+
+    if task_id == blocked_by_id {
+        return Err(AppError::DependencyCycle);
+    }
+
+    let mut connection = acquire(pool).await?;
+    let mut transaction = connection.begin().await?;
+
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(TASK_DEPENDENCIES_LOCK)
+        .execute(&mut *transaction)
+        .await?;
+
+    // Lock the task, so it can't be completed while we add a blocker that isn't completed.
+    sqlx::query("SELECT id FROM tasks WHERE id = $1 FOR UPDATE")
+        .bind(task_id)
+        .fetch_optional(&mut *transaction)
+        .await?
+        .ok_or(AppError::TaskNotFound)?;
+
+    let creates_cycle = sqlx::query_scalar::<_, bool>(
+        "WITH RECURSIVE blockers (id) AS (
+             SELECT blocked_by_id FROM task_dependencies WHERE task_id = $1
+             UNION
+             SELECT task_dependencies.blocked_by_id FROM task_dependencies
+             JOIN blockers ON blockers.id = task_dependencies.task_id
+         )
+         SELECT EXISTS (SELECT 1 FROM blockers WHERE id = $2)",
+    )
+    .bind(blocked_by_id)
+    .bind(task_id)
+    .fetch_one(&mut *transaction)
+    .await?;
+
+    if creates_cycle {
+        return Err(AppError::DependencyCycle);
+    }
+
+    sqlx::query(
+        "INSERT INTO task_dependencies (task_id, blocked_by_id, blocker_completed, date_created)
+         SELECT $1, id, completed, $3 FROM tasks WHERE id = $2
+         ON CONFLICT DO NOTHING",
+    )
+    .bind(task_id)
+    .bind(blocked_by_id)
+    .bind(chrono::Utc::now())
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+/// Removes the link that makes a task wait for another task.
+///
+/// When the task isn't blocked by the other task, we return an error with the [`AppError::DependencyNotFound`]
+/// variant.
+#[instrument(skip(pool))]
+pub async fn delete_task_dependency(pool: &PgPool, task_id: i32, blocked_by_id: i32) -> Result<()> {
+    let _timer = QueryTimer::start("delete_task_dependency");
+
+    let rows_affected =
+        sqlx::query("DELETE FROM task_dependencies WHERE task_id = $1 AND blocked_by_id = $2")
+            .bind(task_id)
+            .bind(blocked_by_id)
+            .execute(&mut *acquire(pool).await?)
+            .await?
+            .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(AppError::DependencyNotFound);
+    }
+
+    Ok(())
+}
+
+/// Retrieves the dependency graph of a task: the tasks it waits for and the tasks that wait for it, directly or through
+/// other tasks.
+///
+/// We only walk through the tasks the user can access, within the workspace their credentials are bound to, so the
+/// graph doesn't reveal tasks the user can't see. Both directions are walked breadth first with a recursive query, and
+/// the links between the tasks we found are read in a second query.
+///
+/// The graph holds at most `max_nodes` tasks. When there are more, the tasks nearest to the task are kept, so the graph
+/// stays connected, and the graph is marked as truncated. The walks stop once they found enough tasks, so a large graph
+/// doesn't make the query more expensive.
+#[instrument(skip(pool))]
+pub async fn find_task_graph(
+    pool: &PgPool,
+    user_id: i32,
+    scope: Option<i32>,
+    task_id: i32,
+    max_nodes: usize,
+) -> Result<TaskGraph> {
+    let _timer = QueryTimer::start("find_task_graph");
+
+    let mut connection = acquire(pool).await?;
+
+    // We ask for one more task than we keep, so we know whether the graph is larger than the limit. A task we reach
+    // through several paths shows up once for every distance we reach it at, so each walk reads a few times more rows
+    // than we keep. Postgres only evaluates as many rows of a recursive query as it's asked for, so the walk stops
+    // there. When a walk reaches its limit, there may be tasks we didn't see, so the graph is truncated as well.
+    let walk_limit = 4 * (max_nodes as i64 + 1);
+
+    let rows = sqlx::query_as::<_, (i32, bool)>(
+        "WITH RECURSIVE blockers (id, depth) AS (
+             SELECT $1, 0
+             UNION
+             SELECT task_dependencies.blocked_by_id, blockers.depth + 1 FROM task_dependencies
+             JOIN blockers ON blockers.id = task_dependencies.task_id
+             WHERE blockers.depth < $4 AND EXISTS (
+                 SELECT 1 FROM tasks JOIN task_access ON task_access.task_id = tasks.id
+                 WHERE tasks.id = task_dependencies.blocked_by_id AND task_access.user_id = $2
+                 AND ($3::integer IS NULL OR tasks.workspace_id = $3)
+             )
+         ),
+         dependents (id, depth) AS (
+             SELECT $1, 0
+             UNION
+             SELECT task_dependencies.task_id, dependents.depth + 1 FROM task_dependencies
+             JOIN dependents ON dependents.id = task_dependencies.blocked_by_id
+             WHERE dependents.depth < $4 AND EXISTS (
+                 SELECT 1 FROM tasks JOIN task_access ON task_access.task_id = tasks.id
+                 WHERE tasks.id = task_dependencies.task_id AND task_access.user_id = $2
+                 AND ($3::integer IS NULL OR tasks.workspace_id = $3)
+             )
+         ),
+         walked (id, depth, upstream) AS (
+             (SELECT id, depth, TRUE FROM blockers LIMIT $5)
+             UNION ALL
+             (SELECT id, depth, FALSE FROM dependents LIMIT $5)
+         )
+         SELECT id,
+         (SELECT COUNT(*) FROM walked WHERE upstream) >= $5 OR (SELECT COUNT(*) FROM walked WHERE NOT upstream) >= $5
+         FROM walked GROUP BY id ORDER BY MIN(depth), id LIMIT $4",
+    )
+    .bind(task_id)
+    .bind(user_id)
+    .bind(scope)
+    .bind(max_nodes as i64 + 1)
+    .bind(walk_limit)
+    .fetch_all(&mut *connection)
+    .await?;
+
+    let walk_stopped = rows.iter().any(|(_, stopped)| *stopped);
+    let mut ids: Vec<i32> = rows.into_iter().map(|(id, _)| id).collect();
+
+    let truncated = walk_stopped || ids.len() > max_nodes;
+    ids.truncate(max_nodes);
+
+    let nodes = sqlx::query_as::<_, TaskNode>(
+        "SELECT id, title, completed,
+         EXISTS (SELECT 1 FROM task_dependencies WHERE task_dependencies.task_id = tasks.id AND NOT task_dependencies.blocker_completed) AS blocked
+         FROM tasks WHERE id = ANY($1) ORDER BY id",
+    )
+    .bind(&ids)
+    .fetch_all(&mut *connection)
+    .await?;
+
+    let edges = sqlx::query_as::<_, TaskDependency>(
+        "SELECT task_id, blocked_by_id FROM task_dependencies
+         WHERE task_id = ANY($1) AND blocked_by_id = ANY($1) ORDER BY task_id, blocked_by_id",
+    )
+    .bind(&ids)
+    .fetch_all(&mut *connection)
+    .await?;
+
+    Ok(TaskGraph {
+        task_id,
+        nodes,
+        edges,
+        truncated,
+    })
+}
@@ -4,13 +4,18 @@
 //! of the web interface of the application.
 //!
 //! Note that not all fields are serialized by the API. For example, the generated API key for a user is not serialized.
+//!
+//! The entities that appear in responses derive [`ToSchema`], so they're described in the OpenAPI document. The
+//! document follows the [`serde`] attributes, so fields that aren't serialized don't show up in it either.
 
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::{PgHasArrayType, PgTypeInfo};
 use sqlx::FromRow;
+use utoipa::ToSchema;
 
 /// Defines the structure of a paged resultset
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct PagedResult<T> {
     /// Items retrieved from the database
     pub items: Vec<T>,
@@ -26,9 +31,9 @@ pub struct PagedResult<T> {
 }
 
 /// Defines the data structure for a task.
-#[derive(FromRow, Serialize)]
+#[derive(FromRow, Serialize, ToSchema)]
 pub struct Task {
-    // Automatically generated ID.
+    /// Automatically generated ID.
     pub id: i32,
 
     /// Title to display in the task list.
@@ -40,15 +45,361 @@ pub struct Task {
     /// Whether the task is completed or not.
     pub completed: bool,
 
+    /// Whether the task is blocked by a task that isn't completed yet. A blocked task can't be completed.
+    pub blocked: bool,
+
+    /// The deadline of the task in UTC, if it has one. Reminders are scheduled relative to this date.
+    pub due_at: Option<chrono::NaiveDateTime>,
+
+    /// The project the task belongs to, if any.
+    pub project_id: Option<i32>,
+
+    /// The workspace the task belongs to, or `None` for a personal task.
+    pub workspace_id: Option<i32>,
+
     /// The date the task was created.
     pub date_created: chrono::NaiveDateTime,
 
     /// The date the task was last modified.
     pub date_modified: Option<chrono::NaiveDateTime>,
+
+    /// The number of comments on the task. Only task lists include it.
+    #[sqlx(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment_count: Option<i64>,
+}
+
+/// Defines the data structure for a project that groups tasks.
+#[derive(FromRow, Serialize, ToSchema)]
+pub struct Project {
+    /// Automatically generated ID.
+    pub id: i32,
+
+    /// The name of the project.
+    pub name: String,
+
+    /// The role of the current user in the project, `owner` for their own projects.
+    pub role: Role,
+
+    /// The workspace the project belongs to, or `None` for a personal project.
+    pub workspace_id: Option<i32>,
+
+    /// The date the project was created.
+    pub date_created: chrono::NaiveDateTime,
+
+    /// The date the project was last modified.
+    pub date_modified: Option<chrono::NaiveDateTime>,
+}
+
+/// The roles a user can have for a task or project. Every role can do what the roles before it can.
+#[derive(
+    Deserialize, Serialize, ToSchema, sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+pub enum Role {
+    /// Can read the item.
+    Viewer,
+
+    /// Can change the item as well.
+    Editor,
+
+    /// Created the item. Only the owner can remove it and share it with others.
+    Owner,
+}
+
+/// Defines the data structure for a task or project that is shared with another user.
+#[derive(FromRow, Serialize, ToSchema)]
+pub struct Share {
+    /// Automatically generated ID.
+    pub id: i32,
+
+    /// The task that is shared, if a task is shared.
+    pub task_id: Option<i32>,
+
+    /// The project that is shared, if a project is shared.
+    pub project_id: Option<i32>,
+
+    /// The user the item is shared with.
+    pub user_id: i32,
+
+    /// The email address of the user the item is shared with.
+    pub email_address: String,
+
+    /// What the user can do with the item.
+    pub role: Role,
+
+    /// The date the item was shared.
+    pub date_created: chrono::NaiveDateTime,
+
+    /// The date the role was last changed.
+    pub date_modified: Option<chrono::NaiveDateTime>,
+}
+
+/// Defines the data structure for a user that is assigned to or watches a task.
+#[derive(FromRow, Serialize, ToSchema)]
+pub struct TaskUser {
+    /// The ID of the user.
+    pub user_id: i32,
+
+    /// The email address of the user.
+    pub email_address: String,
+
+    /// The handle of the user, if they picked one.
+    pub handle: Option<String>,
+
+    /// The date the user was assigned to or started watching the task.
+    pub date_created: chrono::NaiveDateTime,
+}
+
+/// Defines the data structure for a task a user is assigned to, watches or is mentioned in.
+#[derive(FromRow, Serialize, ToSchema)]
+pub struct UserTask {
+    /// The ID of the task.
+    pub task_id: i32,
+
+    /// The date the user was assigned to, started watching or was mentioned in the task.
+    pub date_created: chrono::NaiveDateTime,
+}
+
+/// Defines the data structure for a comment on a task.
+#[derive(FromRow, Serialize, ToSchema)]
+pub struct Comment {
+    /// Automatically generated ID.
+    pub id: i32,
+
+    /// The task the comment is on.
+    pub task_id: i32,
+
+    /// The ID of the author of the comment.
+    pub user_id: i32,
+
+    /// The email address of the author.
+    pub email_address: String,
+
+    /// The handle of the author, if they picked one.
+    pub handle: Option<String>,
+
+    /// The comment as markdown, like the author wrote it.
+    pub body: String,
+
+    /// The comment rendered to HTML. Only safe tags and attributes are left, so clients can show it as is.
+    pub body_html: String,
+
+    /// The date the comment was created.
+    pub date_created: chrono::NaiveDateTime,
+
+    /// The date the comment was last edited.
+    pub date_modified: Option<chrono::NaiveDateTime>,
+}
+
+/// Defines the data structure for an earlier version of a comment.
+#[derive(FromRow, Serialize, ToSchema)]
+pub struct CommentRevision {
+    /// Automatically generated ID.
+    pub id: i32,
+
+    /// The comment this is an earlier version of.
+    pub comment_id: i32,
+
+    /// The comment as markdown, before it was edited.
+    pub body: String,
+
+    /// The date the comment was edited, which replaced this version.
+    pub date_created: chrono::NaiveDateTime,
+}
+
+/// Defines the data structure for a file attached to a task.
+#[derive(FromRow, Serialize, ToSchema)]
+pub struct Attachment {
+    /// Automatically generated ID.
+    pub id: i32,
+
+    /// The task the file is attached to.
+    pub task_id: i32,
+
+    /// The ID of the user who uploaded the file, or `None` when they were removed.
+    pub user_id: Option<i32>,
+
+    /// The name of the file.
+    pub file_name: String,
+
+    /// The content type of the file, like `image/png`.
+    pub content_type: String,
+
+    /// The size of the file in bytes.
+    pub size: i64,
+
+    /// The key of the contents in the blob store. We don't share this with the user.
+    #[serde(skip)]
+    pub storage_key: String,
+
+    /// The date the file was uploaded.
+    pub date_created: chrono::NaiveDateTime,
+}
+
+/// Defines the data structure for a short-lived URL to download an attachment.
+#[derive(Serialize, ToSchema)]
+pub struct AttachmentUrl {
+    /// The URL to download the file from. URLs to the application itself are relative to the API.
+    pub url: String,
+
+    /// The date the URL stops working.
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Defines the data structure for a task in the dependencies of another task.
+#[derive(FromRow, Serialize, ToSchema)]
+pub struct TaskNode {
+    /// The ID of the task.
+    pub id: i32,
+
+    /// The title of the task.
+    pub title: String,
+
+    /// Whether the task is completed or not.
+    pub completed: bool,
+
+    /// Whether the task is blocked by a task that isn't completed yet.
+    pub blocked: bool,
+}
+
+/// Defines the data structure for a link between two tasks.
+#[derive(FromRow, Serialize, ToSchema)]
+pub struct TaskDependency {
+    /// The task that waits.
+    pub task_id: i32,
+
+    /// The task that must be completed first.
+    pub blocked_by_id: i32,
+}
+
+/// Defines the data structure for the dependency graph of a task.
+///
+/// The graph holds every task the task waits for, directly or through other tasks, and every task that waits for it.
+/// Tasks the user can't see are left out, along with their links.
+#[derive(Serialize, ToSchema)]
+pub struct TaskGraph {
+    /// The task the graph is for.
+    pub task_id: i32,
+
+    /// The tasks in the graph, including the task itself.
+    pub nodes: Vec<TaskNode>,
+
+    /// The links between the tasks in the graph.
+    pub edges: Vec<TaskDependency>,
+
+    /// Whether tasks were left out, because the graph is larger than the API returns at once.
+    pub truncated: bool,
+}
+
+/// A task or project that can be shared with other users.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SharedItem {
+    /// A single task.
+    Task(i32),
+
+    /// A project, together with all of its tasks.
+    Project(i32),
+}
+
+/// The roles of the members of a workspace. Every role can do what the roles before it can.
+#[derive(
+    Deserialize, Serialize, ToSchema, sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+pub enum WorkspaceRole {
+    /// Can work on the tasks and projects in the workspace.
+    Member,
+
+    /// Can invite, change and remove members as well.
+    Admin,
+
+    /// Can rename and remove the workspace.
+    Owner,
+}
+
+/// Defines the data structure for a workspace that a team shares.
+#[derive(FromRow, Serialize, ToSchema)]
+pub struct Workspace {
+    /// Automatically generated ID.
+    pub id: i32,
+
+    /// The name of the workspace.
+    pub name: String,
+
+    /// The role of the current user in the workspace.
+    pub role: WorkspaceRole,
+
+    /// The date the workspace was created.
+    pub date_created: chrono::NaiveDateTime,
+
+    /// The date the workspace was last modified.
+    pub date_modified: Option<chrono::NaiveDateTime>,
+}
+
+/// Defines the data structure for a member of a workspace.
+#[derive(FromRow, Serialize, ToSchema)]
+pub struct WorkspaceMember {
+    /// The ID of the user.
+    pub user_id: i32,
+
+    /// The email address of the user.
+    pub email_address: String,
+
+    /// The role of the user in the workspace.
+    pub role: WorkspaceRole,
+
+    /// The date the user joined the workspace.
+    pub date_created: chrono::NaiveDateTime,
+}
+
+/// Defines the data structure for an invitation to join a workspace.
+#[derive(FromRow, Serialize, ToSchema)]
+pub struct WorkspaceInvitation {
+    /// Automatically generated ID.
+    pub id: i32,
+
+    /// The workspace the invitation is for.
+    pub workspace_id: i32,
+
+    /// The name of the workspace the invitation is for.
+    pub workspace_name: String,
+
+    /// The email address of the invited user.
+    pub email_address: String,
+
+    /// The role the user gets when they accept the invitation.
+    pub role: WorkspaceRole,
+
+    /// The date the user was invited.
+    pub date_created: chrono::NaiveDateTime,
+}
+
+/// Defines the data structure for an API key that only gives access to one workspace.
+///
+/// The key itself is only shown once, when it's created. We only store its hash.
+#[derive(FromRow, Serialize, ToSchema)]
+pub struct WorkspaceKey {
+    /// Automatically generated ID.
+    pub id: i32,
+
+    /// The workspace the key gives access to.
+    pub workspace_id: i32,
+
+    /// The member the key acts on behalf of.
+    pub user_id: i32,
+
+    /// A name to recognize the key by.
+    pub name: String,
+
+    /// The date the key was created.
+    pub date_created: chrono::NaiveDateTime,
 }
 
 /// Defines the data structure for a user.
-#[derive(FromRow, Serialize)]
+#[derive(FromRow, Serialize, ToSchema)]
 pub struct User {
     /// Automatically generated ID.
     pub id: i32,
@@ -56,10 +407,34 @@ pub struct User {
     /// The email address associated with the user.
     pub email_address: String,
 
+    /// The handle other users mention the user with, like `jane` for `@jane`.
+    pub handle: Option<String>,
+
+    /// Whether the user has proven they own the email address.
+    pub email_verified: bool,
+
     /// The API key associated with the user.
     #[serde(skip_serializing)]
     pub api_key: String,
 
+    /// The hashed password of the user, if the user has set a password.
+    #[serde(skip_serializing)]
+    pub password_hash: Option<String>,
+
+    /// The IANA time zone of the user, like `Europe/Amsterdam`. Reminders at a time of day use this time zone.
+    pub time_zone: String,
+
+    /// The local time from which no reminders are sent, if the user set quiet hours.
+    pub quiet_hours_start: Option<chrono::NaiveTime>,
+
+    /// The local time until which no reminders are sent, if the user set quiet hours.
+    pub quiet_hours_end: Option<chrono::NaiveTime>,
+
+    /// The date the credentials of the user were taken away from whoever held them. Refresh tokens issued before this
+    /// date are no longer accepted.
+    #[serde(skip_serializing)]
+    pub credentials_reset_at: Option<chrono::NaiveDateTime>,
+
     /// The date the user information was created.
     pub date_created: chrono::NaiveDateTime,
 
@@ -67,6 +442,469 @@ pub struct User {
     pub date_modified: Option<chrono::NaiveDateTime>,
 }
 
+/// Defines the changes to the profile of a user, which are saved together. Fields that are `None` stay as they are.
+#[derive(Default)]
+pub struct UserChanges {
+    /// The new email address, which needs to be verified again.
+    pub email_address: Option<String>,
+
+    /// The hash of the new password.
+    pub password_hash: Option<String>,
+
+    /// The new handle, or `Some(None)` to remove the handle.
+    pub handle: Option<Option<String>>,
+
+    /// The new time zone and quiet hours.
+    pub schedule: Option<(String, Option<(chrono::NaiveTime, chrono::NaiveTime)>)>,
+}
+
+/// Defines the data structure for a pending login with the OpenID Connect identity provider.
+///
+/// We store the login request until the user returns from the identity provider, so we can verify the state and
+/// send the code verifier when exchanging the authorization code.
+#[derive(FromRow)]
+pub struct OidcLoginRequest {
+    /// The random state that identifies the login request.
+    pub state: String,
+
+    /// The PKCE code verifier for the login request.
+    pub code_verifier: String,
+
+    /// The nonce that must be included in the ID token.
+    pub nonce: String,
+
+    /// The date the login request was created.
+    pub date_created: chrono::NaiveDateTime,
+}
+
+/// The changes to tasks that clients can follow with webhooks and the event stream.
+///
+/// The events are stored in the database with the same names as they have in the API, like `task.created`.
+#[derive(Deserialize, Serialize, ToSchema, sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[sqlx(type_name = "text")]
+pub enum TaskEventType {
+    /// A task was created.
+    #[serde(rename = "task.created")]
+    #[sqlx(rename = "task.created")]
+    TaskCreated,
+
+    /// A task was changed, including when it's completed.
+    #[serde(rename = "task.updated")]
+    #[sqlx(rename = "task.updated")]
+    TaskUpdated,
+
+    /// A task that was open is now completed.
+    #[serde(rename = "task.completed")]
+    #[sqlx(rename = "task.completed")]
+    TaskCompleted,
+
+    /// A task was removed.
+    #[serde(rename = "task.deleted")]
+    #[sqlx(rename = "task.deleted")]
+    TaskDeleted,
+
+    /// A reminder of a task fired. Only webhooks receive this event, it isn't part of the event stream.
+    #[serde(rename = "task.reminder")]
+    #[sqlx(rename = "task.reminder")]
+    TaskReminder,
+}
+
+impl TaskEventType {
+    /// Returns the name of the event, like `task.created`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaskEventType::TaskCreated => "task.created",
+            TaskEventType::TaskUpdated => "task.updated",
+            TaskEventType::TaskCompleted => "task.completed",
+            TaskEventType::TaskDeleted => "task.deleted",
+            TaskEventType::TaskReminder => "task.reminder",
+        }
+    }
+}
+
+impl PgHasArrayType for TaskEventType {
+    fn array_type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("_text")
+    }
+}
+
+/// Defines the data structure for a change to a task, as it's sent on the event stream.
+#[derive(FromRow, Serialize, ToSchema, Debug, Clone)]
+pub struct TaskEvent {
+    /// Automatically generated ID, which increases with every event. Clients send the ID of the last event they
+    /// received to resume the stream.
+    pub id: i64,
+
+    /// The user the task belongs to.
+    #[serde(skip_serializing)]
+    pub user_id: i32,
+
+    /// The task that changed.
+    pub task_id: i32,
+
+    /// The workspace the task was in when it changed, if any.
+    #[serde(skip_serializing)]
+    pub workspace_id: Option<i32>,
+
+    /// What happened to the task.
+    #[serde(rename = "type")]
+    pub event_type: TaskEventType,
+
+    /// The task after the change, or only its ID when it was removed.
+    #[serde(rename = "data")]
+    pub payload: serde_json::Value,
+
+    /// The date the change happened.
+    pub date_created: chrono::NaiveDateTime,
+}
+
+/// Defines the data structure for a webhook a user subscribed to.
+#[derive(FromRow, Serialize, ToSchema)]
+pub struct Webhook {
+    /// Automatically generated ID.
+    pub id: i32,
+
+    /// The URL the events are posted to.
+    pub url: String,
+
+    /// The secret the deliveries are signed with. It's only shown once, when the webhook is created.
+    #[serde(skip_serializing)]
+    pub secret: String,
+
+    /// The events that are posted to the URL.
+    pub events: Vec<TaskEventType>,
+
+    /// Whether events are posted to the URL. Events that happen while the webhook is inactive are not delivered.
+    pub active: bool,
+
+    /// The date the webhook was created.
+    pub date_created: chrono::NaiveDateTime,
+
+    /// The date the webhook was last modified.
+    pub date_modified: Option<chrono::NaiveDateTime>,
+}
+
+/// The states a webhook delivery goes through.
+#[derive(Serialize, ToSchema, sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    /// The delivery waits for its next attempt.
+    Pending,
+
+    /// The receiver accepted the delivery with a 2xx response.
+    Delivered,
+
+    /// All attempts failed, the delivery is no longer retried.
+    Failed,
+}
+
+/// Defines the data structure for a single delivery of an event to a webhook.
+///
+/// Deliveries double as the delivery log, so users can find out why their receiver didn't get an event.
+#[derive(FromRow, Serialize, ToSchema)]
+pub struct WebhookDelivery {
+    /// Automatically generated ID.
+    pub id: i64,
+
+    /// The webhook the event is delivered to.
+    pub webhook_id: i32,
+
+    /// The ID of the event, sent in the `webhook-id` header. Redeliveries keep the ID, so receivers can ignore events
+    /// they already processed.
+    pub event_id: String,
+
+    /// The type of the event.
+    pub event_type: TaskEventType,
+
+    /// The body that is posted to the webhook.
+    pub payload: serde_json::Value,
+
+    /// Whether the event was delivered.
+    pub status: DeliveryStatus,
+
+    /// The number of attempts made so far.
+    pub attempts: i32,
+
+    /// When the next attempt is made, if the delivery is still pending.
+    pub next_attempt_at: chrono::NaiveDateTime,
+
+    /// The status code of the last response of the receiver.
+    pub response_status: Option<i32>,
+
+    /// Why the last attempt failed.
+    pub error: Option<String>,
+
+    /// The ID of the delivery this is a manual redelivery of.
+    pub redelivery_of: Option<i64>,
+
+    /// The date the event happened.
+    pub date_created: chrono::NaiveDateTime,
+
+    /// The date the receiver accepted the delivery.
+    pub date_delivered: Option<chrono::NaiveDateTime>,
+}
+
+/// Defines the data structure for a delivery that was claimed for an attempt, together with where to send it.
+#[derive(FromRow)]
+pub struct PendingWebhookDelivery {
+    /// The ID of the delivery.
+    pub id: i64,
+
+    /// The ID of the event.
+    pub event_id: String,
+
+    /// The body to post to the webhook.
+    pub payload: serde_json::Value,
+
+    /// The number of attempts, including the one that is about to be made.
+    pub attempts: i32,
+
+    /// The URL to post the event to.
+    pub url: String,
+
+    /// The secret to sign the delivery with.
+    pub secret: String,
+}
+
+/// The kinds of background jobs. Every kind has its own payload, see [`crate::jobs`].
+#[derive(Deserialize, Serialize, ToSchema, sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[sqlx(type_name = "text")]
+pub enum JobKind {
+    /// Queues the deliveries of a task event for the webhooks of the user.
+    #[serde(rename = "webhooks.publish")]
+    #[sqlx(rename = "webhooks.publish")]
+    PublishWebhooks,
+
+    /// Delivers a reminder of a task through one of its channels.
+    #[serde(rename = "reminders.send")]
+    #[sqlx(rename = "reminders.send")]
+    SendReminder,
+
+    /// Adds a notification to the in-app inbox of a user.
+    #[serde(rename = "notifications.send")]
+    #[sqlx(rename = "notifications.send")]
+    SendNotification,
+
+    /// Removes the contents of an attachment from the blob store.
+    #[serde(rename = "attachments.delete")]
+    #[sqlx(rename = "attachments.delete")]
+    DeleteBlob,
+}
+
+impl JobKind {
+    /// Returns the name of the kind, like `webhooks.publish`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobKind::PublishWebhooks => "webhooks.publish",
+            JobKind::SendReminder => "reminders.send",
+            JobKind::SendNotification => "notifications.send",
+            JobKind::DeleteBlob => "attachments.delete",
+        }
+    }
+}
+
+/// The states a background job goes through. Jobs that completed are removed.
+#[derive(Deserialize, Serialize, ToSchema, sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+pub enum JobStatus {
+    /// The job waits until it's due.
+    Pending,
+
+    /// A worker is running the job.
+    Running,
+
+    /// All attempts failed. The job stays in the dead letter queue until an administrator retries or removes it.
+    Dead,
+}
+
+/// Defines the data structure for a background job in the outbox.
+#[derive(FromRow, Serialize, ToSchema)]
+pub struct Job {
+    /// Automatically generated ID.
+    pub id: i64,
+
+    /// What the job does.
+    pub kind: JobKind,
+
+    /// The input of the job, which depends on the kind.
+    pub payload: serde_json::Value,
+
+    /// Where the job is in its lifecycle.
+    pub status: JobStatus,
+
+    /// The number of attempts made so far, including the one that is running.
+    pub attempts: i32,
+
+    /// When the job is due. For a running job this is when its lease expires and another worker may pick it up.
+    pub run_at: chrono::NaiveDateTime,
+
+    /// Why the last attempt failed.
+    pub error: Option<String>,
+
+    /// The date the job was queued.
+    pub date_created: chrono::NaiveDateTime,
+
+    /// The date the job last changed state.
+    pub date_modified: Option<chrono::NaiveDateTime>,
+}
+
+/// Defines the number of background jobs in each state.
+#[derive(Serialize, ToSchema, Debug, Default, PartialEq, Eq)]
+pub struct JobCounts {
+    pub pending: i64,
+    pub running: i64,
+    pub dead: i64,
+}
+
+/// The ways a reminder reaches the user.
+#[derive(Deserialize, Serialize, ToSchema, sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum ReminderChannel {
+    /// An email to the verified email address of the user.
+    Email,
+
+    /// A `task.reminder` event for the webhooks of the user.
+    Webhook,
+
+    /// A notification in the inbox of the user.
+    InApp,
+}
+
+impl ReminderChannel {
+    /// Returns the name of the channel, like `in_app`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReminderChannel::Email => "email",
+            ReminderChannel::Webhook => "webhook",
+            ReminderChannel::InApp => "in_app",
+        }
+    }
+}
+
+impl PgHasArrayType for ReminderChannel {
+    fn array_type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("_text")
+    }
+}
+
+/// Defines the data structure for a reminder of a task.
+///
+/// A reminder fires either a number of minutes before the task is due, or at a time of day on the date the task is
+/// due, in the time zone of the user.
+#[derive(FromRow, Serialize, ToSchema)]
+pub struct Reminder {
+    /// Automatically generated ID.
+    pub id: i32,
+
+    /// The task the reminder belongs to.
+    pub task_id: i32,
+
+    /// The number of minutes before the task is due that the reminder fires.
+    pub minutes_before: Option<i32>,
+
+    /// The local time on the date the task is due that the reminder fires.
+    pub time_of_day: Option<chrono::NaiveTime>,
+
+    /// The ways the reminder reaches the user.
+    pub channels: Vec<ReminderChannel>,
+
+    /// When the reminder fires in UTC, or `None` when the task has no deadline.
+    pub remind_at: Option<chrono::NaiveDateTime>,
+
+    /// The date the reminder was sent.
+    pub date_sent: Option<chrono::NaiveDateTime>,
+
+    /// The date the reminder was created.
+    pub date_created: chrono::NaiveDateTime,
+}
+
+/// Defines the data structure for a reminder that is due, together with what the scheduler needs to send it.
+#[derive(FromRow)]
+pub struct DueReminder {
+    /// The ID of the reminder.
+    pub id: i32,
+
+    /// The user to remind.
+    pub user_id: i32,
+
+    /// The task to remind the user of.
+    pub task_id: i32,
+
+    /// The title of the task.
+    pub title: String,
+
+    /// The deadline of the task in UTC.
+    pub due_at: chrono::NaiveDateTime,
+
+    /// The ways the reminder reaches the user.
+    pub channels: Vec<ReminderChannel>,
+
+    /// When the reminder was due to fire in UTC.
+    pub remind_at: chrono::NaiveDateTime,
+
+    /// The time zone of the user.
+    pub time_zone: String,
+
+    /// The start of the quiet hours of the user.
+    pub quiet_hours_start: Option<chrono::NaiveTime>,
+
+    /// The end of the quiet hours of the user.
+    pub quiet_hours_end: Option<chrono::NaiveTime>,
+}
+
+/// The kinds of notifications in the inbox.
+#[derive(Deserialize, Serialize, ToSchema, sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[sqlx(type_name = "text")]
+pub enum NotificationKind {
+    /// A reminder of a task fired.
+    #[serde(rename = "task.reminder")]
+    #[sqlx(rename = "task.reminder")]
+    TaskReminder,
+
+    /// A user was assigned to a task.
+    #[serde(rename = "task.assigned")]
+    #[sqlx(rename = "task.assigned")]
+    TaskAssigned,
+
+    /// A user was unassigned from a task.
+    #[serde(rename = "task.unassigned")]
+    #[sqlx(rename = "task.unassigned")]
+    TaskUnassigned,
+
+    /// The user was mentioned in a task.
+    #[serde(rename = "task.mentioned")]
+    #[sqlx(rename = "task.mentioned")]
+    TaskMentioned,
+}
+
+/// Defines the data structure for a notification in the in-app inbox of a user.
+#[derive(FromRow, Serialize, ToSchema)]
+pub struct Notification {
+    /// Automatically generated ID.
+    pub id: i64,
+
+    /// The task the notification is about, if any.
+    pub task_id: Option<i32>,
+
+    /// What the notification is about.
+    pub kind: NotificationKind,
+
+    /// A short summary to show in the inbox.
+    pub title: String,
+
+    /// The full text of the notification.
+    pub body: String,
+
+    /// The date the user marked the notification as read, or `None` while it's unread.
+    pub date_read: Option<chrono::NaiveDateTime>,
+
+    /// The date the notification was created.
+    pub date_created: chrono::NaiveDateTime,
+}
+
 /// Represents an API key in its original form and its hashed form.
 /// The original key is only available when the user submits the key or when the key is created.
 /// The hashed key is used to compare the key with the one stored in the database.
@@ -90,16 +928,22 @@ impl ApiKey {
     }
 
     /// Create an API key from a string.
-    pub fn from_string(key: &String) -> Self {
-        let hash = sha256::digest(key.clone());
+    pub fn from_string(key: &str) -> Self {
+        let hash = sha256::digest(key);
 
         Self {
-            key: key.clone(),
+            key: key.to_string(),
             hash,
         }
     }
 }
 
+impl Default for ApiKey {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
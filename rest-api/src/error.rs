@@ -18,6 +18,14 @@ use std::fmt;
 
 use axum::{http::StatusCode, response::IntoResponse, Json};
 use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    auth::{oidc::OidcError, token::TokenError},
+    blobs::BlobError,
+    mail::MailError,
+    request_id,
+};
 
 /// This alias is used to simplify the return type of functions that can return a [`crate::error::AppError`].
 ///
@@ -39,6 +47,10 @@ pub enum AppError {
     /// wrong. Most commonly this error is thrown when the `config.toml` file is missing keys or has the wrong format.
     ConfigError(config::ConfigError),
 
+    /// When the configuration can be loaded, but contains missing or invalid values, you'll get this error. It lists
+    /// every problem we found, so you can fix them all at once instead of restarting the application for each one.
+    InvalidConfig(Vec<String>),
+
     /// When the application can't communicate with the database, this error is returned. The details explain exactly
     /// what went wrong. The most common error is a connection error so make sure to check if your database server
     /// is running.
@@ -51,13 +63,249 @@ pub enum AppError {
     /// When a user can't be found, this error is returned. This error isn't fixable by the user and is used to
     /// indicate that the requested user doesn't exist. The error is automatically translated to a 404.
     UserNotFound,
+
+    /// When a user tries to obtain a token with credentials that don't match a user, this error is returned.
+    /// We intentionally don't tell the user which part of the credentials was wrong. The error is automatically
+    /// translated to a 401.
+    InvalidCredentials,
+
+    /// When a user changes their password or email address without the correct current password, this error is
+    /// returned. The error is automatically translated to a 403.
+    IncorrectPassword,
+
+    /// When a user without a password, like a user who signs in with the identity provider, changes their password or
+    /// email address, this error is returned. The error is automatically translated to a 403.
+    PasswordNotSet,
+
+    /// When the application can't issue a token, this error is returned. The most common cause is a missing signing
+    /// key in the authentication configuration.
+    TokenError(TokenError),
+
+    /// When login with the OpenID Connect identity provider is requested, but no identity provider is configured,
+    /// this error is returned. The error is automatically translated to a 404.
+    OidcNotConfigured,
+
+    /// When the user returns from the identity provider with a state that we don't know or that has expired,
+    /// this error is returned. The user needs to start the login again. The error is automatically translated to a 400.
+    InvalidLoginState,
+
+    /// When the login with the OpenID Connect identity provider fails, this error is returned. Depending on the cause,
+    /// the error is translated to a 401 or a 502 when the identity provider can't be reached.
+    OidcError(OidcError),
+
+    /// When a user registers with an email address that is already registered, this error is returned. Email
+    /// addresses are compared without taking casing into account. The error is automatically translated to a 409.
+    EmailAddressTaken,
+
+    /// When a user registers with something that isn't an email address, this error is returned.
+    /// The error is automatically translated to a 400.
+    InvalidEmailAddress,
+
+    /// When a user submits a verification token that is invalid or expired, this error is returned.
+    /// The error is automatically translated to a 400.
+    InvalidVerificationToken,
+
+    /// When the application can't send an email, this error is returned. The details explain exactly what went wrong.
+    /// The most common error is a misconfigured SMTP server.
+    MailError(MailError),
+
+    /// When the application can't hash a password, this error is returned. The details explain exactly what went wrong.
+    PasswordHashError(argon2::password_hash::Error),
+
+    /// When a user tries to store more data than their quota allows, this error is returned.
+    /// The error is automatically translated to a 403.
+    QuotaExceeded,
+
+    /// When the body of a request is larger than the configured limit, this error is returned.
+    /// The error is automatically translated to a 413.
+    PayloadTooLarge,
+
+    /// When a handler takes longer than the configured timeout to produce a response, this error is returned.
+    /// The server was too slow rather than the client, so the error is automatically translated to a 503.
+    ResponseTimeout,
+
+    /// When a webhook can't be found, this error is returned. Webhooks of other users can't be found either.
+    /// The error is automatically translated to a 404.
+    WebhookNotFound,
+
+    /// When a delivery of a webhook can't be found, this error is returned.
+    /// The error is automatically translated to a 404.
+    WebhookDeliveryNotFound,
+
+    /// When a user subscribes a URL that isn't an HTTP(S) URL or that points to the internal network, this error is
+    /// returned. The error is automatically translated to a 400.
+    InvalidWebhookUrl,
+
+    /// When a user subscribes a webhook to no events at all, this error is returned.
+    /// The error is automatically translated to a 400.
+    MissingWebhookEvents,
+
+    /// When a background job can't be found, this error is returned. Only dead jobs can be retried or removed, so
+    /// jobs in another state can't be found either. The error is automatically translated to a 404.
+    JobNotFound,
+
+    /// When the payload of a background job doesn't match its kind, this error is returned.
+    /// The job is retried like any other failed job, so it ends up in the dead letter queue.
+    InvalidJobPayload(serde_json::Error),
+
+    /// When a background job takes longer than the configured timeout, this error is returned.
+    JobTimeout,
+
+    /// When a reminder can't be found, this error is returned. Reminders of other users can't be found either.
+    /// The error is automatically translated to a 404.
+    ReminderNotFound,
+
+    /// When a reminder doesn't say when it fires, says it twice, or has no channels, this error is returned.
+    /// The error is automatically translated to a 400.
+    InvalidReminder,
+
+    /// When a notification can't be found, this error is returned. Notifications of other users can't be found either.
+    /// The error is automatically translated to a 404.
+    NotificationNotFound,
+
+    /// When a user picks a time zone that isn't in the IANA time zone database, this error is returned.
+    /// The error is automatically translated to a 400.
+    InvalidTimeZone,
+
+    /// When the quiet hours of a user start and end at the same time, this error is returned.
+    /// The error is automatically translated to a 400.
+    InvalidQuietHours,
+
+    /// When a project can't be found, this error is returned. Projects the user has no access to can't be found
+    /// either. The error is automatically translated to a 404.
+    ProjectNotFound,
+
+    /// When a share can't be found, this error is returned.
+    /// The error is automatically translated to a 404.
+    ShareNotFound,
+
+    /// When a user shares an item with themselves, this error is returned.
+    /// The error is automatically translated to a 400.
+    InvalidShare,
+
+    /// When a user can see a task or project, but their role doesn't allow what they tried, this error is returned.
+    /// The error is automatically translated to a 403.
+    AccessDenied,
+
+    /// When a workspace can't be found, or the user isn't a member of it, this error is returned.
+    /// The error is automatically translated to a 404.
+    WorkspaceNotFound,
+
+    /// When a member of a workspace can't be found, this error is returned.
+    /// The error is automatically translated to a 404.
+    MemberNotFound,
+
+    /// When an invitation to a workspace can't be found, this error is returned.
+    /// The error is automatically translated to a 404.
+    InvitationNotFound,
+
+    /// When a user is invited to a workspace they're already a member of, this error is returned.
+    /// The error is automatically translated to a 409.
+    AlreadyMember,
+
+    /// When a change would leave a workspace without an owner, this error is returned.
+    /// The error is automatically translated to a 409.
+    LastWorkspaceOwner,
+
+    /// When a task is moved into a project of another workspace, this error is returned.
+    /// The error is automatically translated to a 400.
+    WorkspaceMismatch,
+
+    /// When an API key of a workspace can't be found, this error is returned.
+    /// The error is automatically translated to a 404.
+    WorkspaceKeyNotFound,
+
+    /// When a user is assigned to or watches a task they can't access, this error is returned.
+    /// The error is automatically translated to a 400.
+    TaskNotShared,
+
+    /// When a user isn't assigned to the task, this error is returned.
+    /// The error is automatically translated to a 404.
+    AssigneeNotFound,
+
+    /// When a user doesn't watch the task, this error is returned.
+    /// The error is automatically translated to a 404.
+    WatcherNotFound,
+
+    /// When a user picks a handle that isn't 3 to 30 letters, digits or underscores, this error is returned.
+    /// The error is automatically translated to a 400.
+    InvalidHandle,
+
+    /// When a user picks a handle that another user already has, this error is returned. Handles are compared without
+    /// taking casing into account. The error is automatically translated to a 409.
+    HandleTaken,
+
+    /// When a comment can't be found, this error is returned.
+    /// The error is automatically translated to a 404.
+    CommentNotFound,
+
+    /// When a user changes a comment someone else wrote, this error is returned.
+    /// The error is automatically translated to a 403.
+    NotCommentAuthor,
+
+    /// When a comment is empty or too long, this error is returned.
+    /// The error is automatically translated to a 400.
+    InvalidComment,
+
+    /// When an attachment can't be found, this error is returned.
+    /// The error is automatically translated to a 404.
+    AttachmentNotFound,
+
+    /// When an upload doesn't contain a file with a name, this error is returned.
+    /// The error is automatically translated to a 400.
+    InvalidAttachment,
+
+    /// When an upload has a content type that isn't allowed, this error is returned.
+    /// The error is automatically translated to a 415.
+    UnsupportedContentType,
+
+    /// When a download URL has expired or its signature is wrong, this error is returned.
+    /// The error is automatically translated to a 403.
+    InvalidDownloadUrl,
+
+    /// When the blob store fails to store or retrieve a file, this error is returned.
+    /// The error is automatically translated to a 500.
+    BlobError(BlobError),
+
+    /// When a task isn't blocked by the other task, this error is returned.
+    /// The error is automatically translated to a 404.
+    DependencyNotFound,
+
+    /// When a link between tasks would make a task wait for itself, directly or through other tasks, this error is
+    /// returned. The error is automatically translated to a 409.
+    DependencyCycle,
+
+    /// When a task is completed while it's blocked by tasks that aren't completed, this error is returned.
+    /// The error is automatically translated to a 409.
+    TaskBlocked,
+
+    /// When credentials that are bound to a workspace are used outside of the workspace, like for the account of the
+    /// user, this error is returned. The error is automatically translated to a 403.
+    ScopedCredentials,
 }
 
 /// The details of an error that are shown to the application user.
-#[derive(Serialize)]
-struct ErrorDetails {
+///
+/// Use [`ErrorDetails::new`] to create the details, so the ID of the current request is included. Users can send us
+/// the request ID when they report a problem, so we can find the logs of the failed request.
+#[derive(Serialize, ToSchema)]
+pub struct ErrorDetails {
     /// The error message shown to the application user.
     message: String,
+
+    /// The ID of the request that failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+impl ErrorDetails {
+    /// Creates the details for an error with the given message.
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            request_id: request_id::current_request_id(),
+        }
+    }
 }
 
 impl fmt::Display for AppError {
@@ -67,11 +315,92 @@ impl fmt::Display for AppError {
                 f,
                 "Configuration error. Please check your configuration file."
             ),
+            AppError::InvalidConfig(problems) => {
+                write!(f, "The configuration is invalid: {}", problems.join(" "))
+            }
             AppError::DbError(_) => {
                 write!(f, "An error occurred while interacting with the database.")
             }
             AppError::TaskNotFound => write!(f, "The requested task was not found."),
             AppError::UserNotFound => write!(f, "The requested user was not found."),
+            AppError::InvalidCredentials => write!(f, "The provided credentials are invalid."),
+            AppError::IncorrectPassword => {
+                write!(f, "The current password is missing or incorrect.")
+            }
+            AppError::PasswordNotSet => write!(f, "The user has no password."),
+            AppError::TokenError(err) => write!(f, "Failed to issue a token: {}", err),
+            AppError::PasswordHashError(_) => write!(f, "Failed to hash the password."),
+            AppError::QuotaExceeded => write!(f, "The quota for this user has been exceeded."),
+            AppError::PayloadTooLarge => write!(f, "The request body is too large."),
+            AppError::ResponseTimeout => write!(f, "The request took too long to complete."),
+            AppError::WebhookNotFound => write!(f, "The requested webhook was not found."),
+            AppError::WebhookDeliveryNotFound => {
+                write!(f, "The requested webhook delivery was not found.")
+            }
+            AppError::InvalidWebhookUrl => write!(f, "The webhook URL is invalid."),
+            AppError::MissingWebhookEvents => {
+                write!(f, "The webhook must subscribe to at least one event.")
+            }
+            AppError::JobNotFound => write!(f, "The requested job was not found."),
+            AppError::InvalidJobPayload(err) => write!(f, "The job payload is invalid: {}", err),
+            AppError::JobTimeout => write!(f, "The job took too long to complete."),
+            AppError::ReminderNotFound => write!(f, "The requested reminder was not found."),
+            AppError::InvalidReminder => write!(f, "The reminder is invalid."),
+            AppError::NotificationNotFound => {
+                write!(f, "The requested notification was not found.")
+            }
+            AppError::InvalidTimeZone => write!(f, "The time zone is unknown."),
+            AppError::InvalidQuietHours => write!(f, "The quiet hours are invalid."),
+            AppError::ProjectNotFound => write!(f, "The requested project was not found."),
+            AppError::ShareNotFound => write!(f, "The requested share was not found."),
+            AppError::InvalidShare => write!(f, "The item can't be shared with its owner."),
+            AppError::AccessDenied => write!(f, "The role of the user doesn't allow this."),
+            AppError::WorkspaceNotFound => write!(f, "The requested workspace was not found."),
+            AppError::MemberNotFound => write!(f, "The requested member was not found."),
+            AppError::InvitationNotFound => write!(f, "The requested invitation was not found."),
+            AppError::AlreadyMember => write!(f, "The user is already a member of the workspace."),
+            AppError::LastWorkspaceOwner => write!(f, "The workspace would have no owner left."),
+            AppError::WorkspaceMismatch => {
+                write!(
+                    f,
+                    "The task and the project belong to different workspaces."
+                )
+            }
+            AppError::WorkspaceKeyNotFound => write!(f, "The requested API key was not found."),
+            AppError::TaskNotShared => write!(f, "The task isn't shared with the user."),
+            AppError::AssigneeNotFound => write!(f, "The user isn't assigned to the task."),
+            AppError::WatcherNotFound => write!(f, "The user doesn't watch the task."),
+            AppError::InvalidHandle => write!(f, "The handle is invalid."),
+            AppError::HandleTaken => write!(f, "The handle is already taken."),
+            AppError::CommentNotFound => write!(f, "The requested comment was not found."),
+            AppError::NotCommentAuthor => write!(f, "Only the author can change the comment."),
+            AppError::InvalidComment => write!(f, "The comment is empty or too long."),
+            AppError::AttachmentNotFound => write!(f, "The requested attachment was not found."),
+            AppError::InvalidAttachment => write!(f, "The upload doesn't contain a file."),
+            AppError::UnsupportedContentType => {
+                write!(f, "Files of this content type can't be uploaded.")
+            }
+            AppError::InvalidDownloadUrl => write!(f, "The download URL is invalid or expired."),
+            AppError::BlobError(err) => write!(f, "Blob storage error: {}", err),
+            AppError::DependencyNotFound => write!(f, "The task isn't blocked by the other task."),
+            AppError::DependencyCycle => write!(f, "The dependency would create a cycle."),
+            AppError::TaskBlocked => {
+                write!(f, "The task is blocked by tasks that aren't completed.")
+            }
+            AppError::ScopedCredentials => {
+                write!(f, "The credentials are bound to a workspace.")
+            }
+            AppError::EmailAddressTaken => write!(f, "The email address is already registered."),
+            AppError::InvalidEmailAddress => write!(f, "The email address is invalid."),
+            AppError::InvalidVerificationToken => {
+                write!(f, "The verification token is invalid or expired.")
+            }
+            AppError::MailError(err) => write!(f, "Failed to send an email: {}", err),
+            AppError::OidcNotConfigured => write!(f, "No identity provider is configured."),
+            AppError::InvalidLoginState => write!(f, "The login request is unknown or expired."),
+            AppError::OidcError(err) => {
+                write!(f, "Login with the identity provider failed: {}", err)
+            }
         }
     }
 }
@@ -88,6 +417,36 @@ impl From<sqlx::Error> for AppError {
     }
 }
 
+impl From<TokenError> for AppError {
+    fn from(value: TokenError) -> Self {
+        AppError::TokenError(value)
+    }
+}
+
+impl From<OidcError> for AppError {
+    fn from(value: OidcError) -> Self {
+        AppError::OidcError(value)
+    }
+}
+
+impl From<MailError> for AppError {
+    fn from(value: MailError) -> Self {
+        AppError::MailError(value)
+    }
+}
+
+impl From<BlobError> for AppError {
+    fn from(value: BlobError) -> Self {
+        AppError::BlobError(value)
+    }
+}
+
+impl From<argon2::password_hash::Error> for AppError {
+    fn from(value: argon2::password_hash::Error) -> Self {
+        AppError::PasswordHashError(value)
+    }
+}
+
 impl IntoResponse for AppError {
     /// Converts an application error into a corresponding HTTP response.
     ///
@@ -99,26 +458,316 @@ impl IntoResponse for AppError {
     fn into_response(self) -> axum::response::Response {
         let response_data = match self {
             AppError::TaskNotFound => {
-                let error_details = ErrorDetails {
-                    message: "The requested task was not found.".to_string(),
-                };
+                let error_details = ErrorDetails::new("The requested task was not found.");
 
                 (StatusCode::NOT_FOUND, Json(error_details))
             }
-            AppError::ConfigError(_) | AppError::DbError(_) => {
-                let error_details = ErrorDetails {
-                    message: "Internal server error".to_string(),
-                };
+            AppError::ConfigError(_)
+            | AppError::InvalidConfig(_)
+            | AppError::DbError(_)
+            | AppError::TokenError(_)
+            | AppError::PasswordHashError(_)
+            | AppError::MailError(_)
+            | AppError::BlobError(_)
+            | AppError::InvalidJobPayload(_)
+            | AppError::JobTimeout => {
+                let error_details = ErrorDetails::new("Internal server error");
 
                 (StatusCode::INTERNAL_SERVER_ERROR, Json(error_details))
             }
             AppError::UserNotFound => {
-                let error_details = ErrorDetails {
-                    message: "The requested user was not found.".to_string(),
-                };
+                let error_details = ErrorDetails::new("The requested user was not found.");
 
                 (StatusCode::NOT_FOUND, Json(error_details))
             }
+            AppError::InvalidCredentials => {
+                let error_details = ErrorDetails::new("The provided credentials are invalid.");
+
+                (StatusCode::UNAUTHORIZED, Json(error_details))
+            }
+            AppError::IncorrectPassword => {
+                let error_details = ErrorDetails::new(
+                    "Send your current password in `current_password` to change your password or email address.",
+                );
+
+                (StatusCode::FORBIDDEN, Json(error_details))
+            }
+            AppError::PasswordNotSet => {
+                let error_details = ErrorDetails::new(
+                    "The account has no password to confirm the change with. Manage your email address with your identity provider instead.",
+                );
+
+                (StatusCode::FORBIDDEN, Json(error_details))
+            }
+            AppError::EmailAddressTaken => {
+                let error_details = ErrorDetails::new("The email address is already registered.");
+
+                (StatusCode::CONFLICT, Json(error_details))
+            }
+            AppError::InvalidEmailAddress => {
+                let error_details = ErrorDetails::new("The email address is invalid.");
+
+                (StatusCode::BAD_REQUEST, Json(error_details))
+            }
+            AppError::InvalidVerificationToken => {
+                let error_details =
+                    ErrorDetails::new("The verification token is invalid or expired.");
+
+                (StatusCode::BAD_REQUEST, Json(error_details))
+            }
+            AppError::QuotaExceeded => {
+                let error_details =
+                    ErrorDetails::new("You have reached the maximum number of tasks.");
+
+                (StatusCode::FORBIDDEN, Json(error_details))
+            }
+            AppError::PayloadTooLarge => {
+                let error_details = ErrorDetails::new("The request body is too large.");
+
+                (StatusCode::PAYLOAD_TOO_LARGE, Json(error_details))
+            }
+            AppError::ResponseTimeout => {
+                let error_details = ErrorDetails::new(
+                    "The request took too long to complete. Please try again later.",
+                );
+
+                (StatusCode::SERVICE_UNAVAILABLE, Json(error_details))
+            }
+            AppError::WebhookNotFound => {
+                let error_details = ErrorDetails::new("The requested webhook was not found.");
+
+                (StatusCode::NOT_FOUND, Json(error_details))
+            }
+            AppError::WebhookDeliveryNotFound => {
+                let error_details =
+                    ErrorDetails::new("The requested webhook delivery was not found.");
+
+                (StatusCode::NOT_FOUND, Json(error_details))
+            }
+            AppError::InvalidWebhookUrl => {
+                let error_details =
+                    ErrorDetails::new("The webhook URL must be a public HTTP or HTTPS URL.");
+
+                (StatusCode::BAD_REQUEST, Json(error_details))
+            }
+            AppError::MissingWebhookEvents => {
+                let error_details =
+                    ErrorDetails::new("The webhook must subscribe to at least one event.");
+
+                (StatusCode::BAD_REQUEST, Json(error_details))
+            }
+            AppError::JobNotFound => {
+                let error_details = ErrorDetails::new("The requested job was not found.");
+
+                (StatusCode::NOT_FOUND, Json(error_details))
+            }
+            AppError::ReminderNotFound => {
+                let error_details = ErrorDetails::new("The requested reminder was not found.");
+
+                (StatusCode::NOT_FOUND, Json(error_details))
+            }
+            AppError::InvalidReminder => {
+                let error_details = ErrorDetails::new(
+                    "A reminder needs either minutes_before or time_of_day, and at least one channel.",
+                );
+
+                (StatusCode::BAD_REQUEST, Json(error_details))
+            }
+            AppError::NotificationNotFound => {
+                let error_details = ErrorDetails::new("The requested notification was not found.");
+
+                (StatusCode::NOT_FOUND, Json(error_details))
+            }
+            AppError::InvalidTimeZone => {
+                let error_details = ErrorDetails::new(
+                    "The time zone must be an IANA time zone, like Europe/Amsterdam.",
+                );
+
+                (StatusCode::BAD_REQUEST, Json(error_details))
+            }
+            AppError::InvalidQuietHours => {
+                let error_details =
+                    ErrorDetails::new("The quiet hours must start and end at a different time.");
+
+                (StatusCode::BAD_REQUEST, Json(error_details))
+            }
+            AppError::ProjectNotFound => {
+                let error_details = ErrorDetails::new("The requested project was not found.");
+
+                (StatusCode::NOT_FOUND, Json(error_details))
+            }
+            AppError::ShareNotFound => {
+                let error_details = ErrorDetails::new("The requested share was not found.");
+
+                (StatusCode::NOT_FOUND, Json(error_details))
+            }
+            AppError::InvalidShare => {
+                let error_details = ErrorDetails::new("You can't share an item with yourself.");
+
+                (StatusCode::BAD_REQUEST, Json(error_details))
+            }
+            AppError::AccessDenied => {
+                let error_details =
+                    ErrorDetails::new("You don't have the permission to do this with the item.");
+
+                (StatusCode::FORBIDDEN, Json(error_details))
+            }
+            AppError::WorkspaceNotFound => {
+                let error_details = ErrorDetails::new("The requested workspace was not found.");
+
+                (StatusCode::NOT_FOUND, Json(error_details))
+            }
+            AppError::MemberNotFound => {
+                let error_details = ErrorDetails::new("The requested member was not found.");
+
+                (StatusCode::NOT_FOUND, Json(error_details))
+            }
+            AppError::InvitationNotFound => {
+                let error_details = ErrorDetails::new("The requested invitation was not found.");
+
+                (StatusCode::NOT_FOUND, Json(error_details))
+            }
+            AppError::AlreadyMember => {
+                let error_details =
+                    ErrorDetails::new("The user is already a member of the workspace.");
+
+                (StatusCode::CONFLICT, Json(error_details))
+            }
+            AppError::LastWorkspaceOwner => {
+                let error_details = ErrorDetails::new(
+                    "A workspace needs at least one owner. Make another member an owner first.",
+                );
+
+                (StatusCode::CONFLICT, Json(error_details))
+            }
+            AppError::WorkspaceMismatch => {
+                let error_details =
+                    ErrorDetails::new("The task and the project belong to different workspaces.");
+
+                (StatusCode::BAD_REQUEST, Json(error_details))
+            }
+            AppError::WorkspaceKeyNotFound => {
+                let error_details = ErrorDetails::new("The requested API key was not found.");
+
+                (StatusCode::NOT_FOUND, Json(error_details))
+            }
+            AppError::TaskNotShared => {
+                let error_details = ErrorDetails::new("The task isn't shared with the user.");
+
+                (StatusCode::BAD_REQUEST, Json(error_details))
+            }
+            AppError::AssigneeNotFound => {
+                let error_details = ErrorDetails::new("The user isn't assigned to the task.");
+
+                (StatusCode::NOT_FOUND, Json(error_details))
+            }
+            AppError::WatcherNotFound => {
+                let error_details = ErrorDetails::new("The user doesn't watch the task.");
+
+                (StatusCode::NOT_FOUND, Json(error_details))
+            }
+            AppError::InvalidHandle => {
+                let error_details =
+                    ErrorDetails::new("The handle must be 3 to 30 letters, digits or underscores.");
+
+                (StatusCode::BAD_REQUEST, Json(error_details))
+            }
+            AppError::HandleTaken => {
+                let error_details = ErrorDetails::new("The handle is already taken.");
+
+                (StatusCode::CONFLICT, Json(error_details))
+            }
+            AppError::CommentNotFound => {
+                let error_details = ErrorDetails::new("The requested comment was not found.");
+
+                (StatusCode::NOT_FOUND, Json(error_details))
+            }
+            AppError::NotCommentAuthor => {
+                let error_details = ErrorDetails::new("Only the author can change the comment.");
+
+                (StatusCode::FORBIDDEN, Json(error_details))
+            }
+            AppError::InvalidComment => {
+                let error_details = ErrorDetails::new(
+                    "The comment must have at least one and at most 10000 characters.",
+                );
+
+                (StatusCode::BAD_REQUEST, Json(error_details))
+            }
+            AppError::AttachmentNotFound => {
+                let error_details = ErrorDetails::new("The requested attachment was not found.");
+
+                (StatusCode::NOT_FOUND, Json(error_details))
+            }
+            AppError::InvalidAttachment => {
+                let error_details =
+                    ErrorDetails::new("Upload the file in a `file` field with a file name.");
+
+                (StatusCode::BAD_REQUEST, Json(error_details))
+            }
+            AppError::UnsupportedContentType => {
+                let error_details =
+                    ErrorDetails::new("Files of this content type can't be uploaded.");
+
+                (StatusCode::UNSUPPORTED_MEDIA_TYPE, Json(error_details))
+            }
+            AppError::InvalidDownloadUrl => {
+                let error_details = ErrorDetails::new(
+                    "The download URL is invalid or expired. Please request a new one.",
+                );
+
+                (StatusCode::FORBIDDEN, Json(error_details))
+            }
+            AppError::DependencyNotFound => {
+                let error_details = ErrorDetails::new("The task isn't blocked by the other task.");
+
+                (StatusCode::NOT_FOUND, Json(error_details))
+            }
+            AppError::DependencyCycle => {
+                let error_details = ErrorDetails::new(
+                    "The task can't wait for a task that waits for it, directly or through other tasks.",
+                );
+
+                (StatusCode::CONFLICT, Json(error_details))
+            }
+            AppError::TaskBlocked => {
+                let error_details = ErrorDetails::new(
+                    "The task is blocked by tasks that aren't completed. Complete them first, or use `force=true`.",
+                );
+
+                (StatusCode::CONFLICT, Json(error_details))
+            }
+            AppError::ScopedCredentials => {
+                let error_details = ErrorDetails::new(
+                    "API keys of a workspace only give access to the workspace. Use your personal credentials instead.",
+                );
+
+                (StatusCode::FORBIDDEN, Json(error_details))
+            }
+            AppError::OidcNotConfigured => {
+                let error_details =
+                    ErrorDetails::new("Login with an identity provider is not available.");
+
+                (StatusCode::NOT_FOUND, Json(error_details))
+            }
+            AppError::InvalidLoginState => {
+                let error_details = ErrorDetails::new(
+                    "The login request is unknown or expired. Please try to log in again.",
+                );
+
+                (StatusCode::BAD_REQUEST, Json(error_details))
+            }
+            AppError::OidcError(OidcError::Http(_))
+            | AppError::OidcError(OidcError::InvalidUrl(_)) => {
+                let error_details = ErrorDetails::new("The identity provider is not available.");
+
+                (StatusCode::BAD_GATEWAY, Json(error_details))
+            }
+            AppError::OidcError(_) => {
+                let error_details = ErrorDetails::new("The identity provider did not return a valid identity with a verified email address.");
+
+                (StatusCode::UNAUTHORIZED, Json(error_details))
+            }
         };
 
         response_data.into_response()
@@ -134,9 +783,6 @@ mod tests {
         let err = config::ConfigError::NotFound("test".to_string());
         let app_err = AppError::from(err);
 
-        match app_err {
-            AppError::ConfigError(_) => assert!(true),
-            _ => assert!(false),
-        }
+        assert!(matches!(app_err, AppError::ConfigError(_)));
     }
 }
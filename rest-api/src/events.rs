@@ -0,0 +1,329 @@
+//! This module streams the changes to tasks to the clients that follow them live.
+//!
+//! ## Fan-out across instances
+//! The write paths in [`crate::db`] record every change in the `task_events` table, in the same transaction as the
+//! change itself, and notify the [`db::TASK_EVENTS_CHANNEL`] channel with the ID of the event. Every instance runs
+//! [`EventHub::listen`], which listens on that channel with a dedicated connection, loads the event and hands it to
+//! the clients connected to the instance. This way it doesn't matter which instance a client is connected to, or
+//! which instance handled the change.
+//!
+//! ## Resuming
+//! Events have increasing IDs. A client that reconnects sends the ID of the last event it received, and its
+//! [`Subscription`] first replays the events it missed from the table before it continues with live events. The same
+//! happens when a client reads so slowly that it falls out of the buffer of the instance. Events are kept for the
+//! number of seconds in [`EventsConfig::retention`], so clients can only resume within that window.
+//!
+//! ## Audience
+//! Clients receive the events of every task they can access, like the tasks that are shared with them and the tasks
+//! in their workspaces. The instance looks up who can see an event once, when it loads the event, so it doesn't need
+//! to ask the database for every client. Clients with credentials that are bound to a workspace only receive the
+//! events of that workspace.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use sqlx::postgres::{PgListener, PgNotification};
+use sqlx::PgPool;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    access, config::EventsConfig, db, entity::TaskEvent, error::Result, shutdown::Shutdown,
+};
+
+/// The maximum number of events we load from the database at once when catching up.
+const CATCH_UP_LIMIT: i64 = 500;
+
+/// How long we wait before connecting again after the listener lost its connection to the database.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// How often we remove the events that are older than the retention period.
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// A task event, together with the users that could see its task when it was loaded.
+#[derive(Debug)]
+struct Delivery {
+    event: Arc<TaskEvent>,
+    audience: Vec<i32>,
+}
+
+/// Distributes the task events of all users to the clients connected to this instance.
+#[derive(Debug)]
+pub struct EventHub {
+    sender: broadcast::Sender<Arc<Delivery>>,
+    config: EventsConfig,
+}
+
+impl EventHub {
+    /// Creates a new event hub. Events only reach subscribers once [`EventHub::listen`] is running.
+    pub fn new(config: EventsConfig) -> Self {
+        let (sender, _) = broadcast::channel(config.buffer.max(1));
+        Self { sender, config }
+    }
+
+    /// Returns the configuration of the event stream.
+    pub fn config(&self) -> &EventsConfig {
+        &self.config
+    }
+
+    /// Starts following the events of the tasks a user can access.
+    ///
+    /// The scope is the workspace the credentials of the user are bound to, see [`crate::access`]. When
+    /// `last_event_id` is given, the subscription starts with the events for the user that came after it.
+    pub fn subscribe(
+        &self,
+        pool: PgPool,
+        user_id: i32,
+        scope: Option<i32>,
+        last_event_id: Option<i64>,
+    ) -> Subscription {
+        Subscription {
+            pool,
+            user_id,
+            scope,
+            receiver: self.sender.subscribe(),
+            backlog: VecDeque::new(),
+            last_event_id: last_event_id.unwrap_or(0),
+            replayed_until: 0,
+            catching_up: last_event_id.is_some(),
+        }
+    }
+
+    /// Listens for the notifications of all instances and passes the events on to the subscribers, until the
+    /// application shuts down.
+    ///
+    /// This also removes the events that are older than the retention period from time to time.
+    pub async fn listen(&self, pool: &PgPool, shutdown: &Shutdown) {
+        let mut cleanup = tokio::time::interval(CLEANUP_INTERVAL);
+        let mut last_event_id = None;
+
+        info!("Listening for task events");
+
+        loop {
+            match PgListener::connect_with(pool).await {
+                Ok(mut listener) => {
+                    if let Err(err) = listener.listen(db::TASK_EVENTS_CHANNEL).await {
+                        error!("Failed to listen for task events: {}", err);
+                    } else {
+                        // We only start catching up after we're listening again, so there is no gap in between.
+                        if let Some(after_id) = last_event_id {
+                            last_event_id = Some(self.catch_up(pool, after_id).await);
+                        }
+
+                        loop {
+                            tokio::select! {
+                                _ = shutdown.started() => {
+                                    info!("Stopped listening for task events");
+                                    return;
+                                }
+                                _ = cleanup.tick() => self.remove_expired(pool).await,
+                                notification = listener.try_recv() => match notification {
+                                    Ok(Some(notification)) => {
+                                        self.forward(pool, &notification, &mut last_event_id).await
+                                    }
+                                    Ok(None) => {
+                                        warn!("Lost the connection to listen for task events");
+                                        break;
+                                    }
+                                    Err(err) => {
+                                        error!("Failed to receive task events: {}", err);
+                                        break;
+                                    }
+                                },
+                            }
+                        }
+                    }
+                }
+                Err(err) => error!("Failed to connect to listen for task events: {}", err),
+            }
+
+            tokio::select! {
+                _ = shutdown.started() => {
+                    info!("Stopped listening for task events");
+                    return;
+                }
+                _ = tokio::time::sleep(RECONNECT_DELAY) => {}
+            }
+        }
+    }
+
+    /// Loads the event a notification refers to and passes it on to the subscribers.
+    async fn forward(
+        &self,
+        pool: &PgPool,
+        notification: &PgNotification,
+        last_event_id: &mut Option<i64>,
+    ) {
+        let Ok(event_id) = notification.payload().parse::<i64>() else {
+            warn!(
+                "Ignoring task event notification: {}",
+                notification.payload()
+            );
+            return;
+        };
+
+        *last_event_id = Some(last_event_id.map_or(event_id, |last| last.max(event_id)));
+
+        // Without subscribers there is nobody to load the event for.
+        if self.sender.receiver_count() == 0 {
+            return;
+        }
+
+        match db::get_task_event(pool, event_id).await {
+            Ok(Some(event)) => {
+                if let Err(err) = self.send(pool, event).await {
+                    error!(
+                        "Failed to load who can see task event {}: {}",
+                        event_id, err
+                    );
+                }
+            }
+            Ok(None) => debug!("Task event {} no longer exists", event_id),
+            Err(err) => error!("Failed to load task event {}: {}", event_id, err),
+        }
+    }
+
+    /// Looks up who can see the event and passes it on to the subscribers.
+    async fn send(&self, pool: &PgPool, event: TaskEvent) -> Result<()> {
+        let audience = db::list_task_event_audience(pool, &event).await?;
+
+        let _ = self.sender.send(Arc::new(Delivery {
+            event: Arc::new(event),
+            audience,
+        }));
+
+        Ok(())
+    }
+
+    /// Passes on the events that happened after the given event, and returns the ID of the last one.
+    ///
+    /// Notifications that are sent while we're not listening are lost, so we look them up in the table instead.
+    async fn catch_up(&self, pool: &PgPool, mut after_id: i64) -> i64 {
+        loop {
+            let events = match db::list_all_task_events(pool, after_id, CATCH_UP_LIMIT).await {
+                Ok(events) => events,
+                Err(err) => {
+                    error!("Failed to catch up on task events: {}", err);
+                    return after_id;
+                }
+            };
+
+            let count = events.len() as i64;
+
+            for event in events {
+                let event_id = event.id;
+
+                if let Err(err) = self.send(pool, event).await {
+                    error!("Failed to catch up on task events: {}", err);
+                    return after_id;
+                }
+
+                after_id = event_id;
+            }
+
+            if count < CATCH_UP_LIMIT {
+                return after_id;
+            }
+        }
+    }
+
+    /// Removes the events that are older than the retention period.
+    async fn remove_expired(&self, pool: &PgPool) {
+        let before = chrono::Utc::now().naive_utc()
+            - chrono::Duration::seconds(self.config.retention as i64);
+
+        match db::delete_task_events_before(pool, before).await {
+            Ok(removed) => debug!("Removed {} expired task events", removed),
+            Err(err) => error!("Failed to remove expired task events: {}", err),
+        }
+    }
+}
+
+/// Follows the task events for a single user.
+///
+/// Events arrive in the order of their IDs while the subscription is catching up from the database, and in the order
+/// the transactions were committed after that.
+#[derive(Debug)]
+pub struct Subscription {
+    pool: PgPool,
+    user_id: i32,
+
+    /// The workspace the credentials of the user are bound to, if any.
+    scope: Option<i32>,
+    receiver: broadcast::Receiver<Arc<Delivery>>,
+
+    /// The events loaded from the database that weren't returned yet.
+    backlog: VecDeque<Arc<TaskEvent>>,
+
+    /// The ID of the last event that was returned, to resume from when catching up.
+    last_event_id: i64,
+
+    /// The highest ID loaded from the database. Live events up to this ID were already part of the backlog.
+    replayed_until: i64,
+
+    /// Whether there may be events in the database that weren't loaded into the backlog yet.
+    catching_up: bool,
+}
+
+impl Subscription {
+    /// Waits for the next event for the user.
+    ///
+    /// This returns `None` when the event hub is gone, and an error when missed events can't be loaded from the
+    /// database. It's safe to cancel, for example when it's used in a [`tokio::select!`].
+    pub async fn next(&mut self) -> Result<Option<Arc<TaskEvent>>> {
+        loop {
+            if let Some(event) = self.backlog.pop_front() {
+                self.last_event_id = event.id;
+                return Ok(Some(event));
+            }
+
+            if self.catching_up {
+                self.load_missed().await?;
+                continue;
+            }
+
+            match self.receiver.recv().await {
+                Ok(delivery) if self.receives(&delivery) => {
+                    self.last_event_id = delivery.event.id;
+                    return Ok(Some(delivery.event.clone()));
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(skipped)) => {
+                    debug!("Subscriber skipped {} events, catching up", skipped);
+                    self.catching_up = true;
+                }
+                Err(RecvError::Closed) => return Ok(None),
+            }
+        }
+    }
+
+    /// Returns whether a live event is for the user, and wasn't already loaded into the backlog.
+    fn receives(&self, delivery: &Delivery) -> bool {
+        delivery.event.id > self.replayed_until
+            && delivery.audience.contains(&self.user_id)
+            && access::in_scope(self.scope, delivery.event.workspace_id)
+    }
+
+    /// Loads the next page of events the subscriber missed into the backlog.
+    async fn load_missed(&mut self) -> Result<()> {
+        let events = db::list_task_events(
+            &self.pool,
+            self.user_id,
+            self.scope,
+            self.last_event_id,
+            CATCH_UP_LIMIT,
+        )
+        .await?;
+
+        self.catching_up = events.len() as i64 == CATCH_UP_LIMIT;
+
+        if let Some(last) = events.last() {
+            self.replayed_until = self.replayed_until.max(last.id);
+        }
+
+        self.backlog.extend(events.into_iter().map(Arc::new));
+
+        Ok(())
+    }
+}
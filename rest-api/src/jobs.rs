@@ -0,0 +1,211 @@
+//! This module runs the side effects of changes in the background, like queueing webhook deliveries.
+//!
+//! ## The outbox
+//! A side effect can't be part of the database transaction that makes the change. When we run it right after the
+//! commit instead, it's lost whenever the application stops in between. So the write paths in [`crate::db`] add a job
+//! to the `jobs` table in the same transaction as the change, with [`db::insert_job`]. The job exists if and only if
+//! the change was committed, and the [`JobRunner`] runs it afterwards.
+//!
+//! Every kind of job has a payload type that implements [`JobPayload`]. To add a new kind, add a variant to
+//! [`JobKind`], implement [`JobPayload`] for its payload and add it to [`execute`]. Jobs get the services they need,
+//! like the database and the mailer, from the [`JobContext`].
+//!
+//! ## Running jobs
+//! Every instance runs a pool of workers. A worker claims one due job at a time with `FOR UPDATE SKIP LOCKED`, so a
+//! job is only picked up by one worker across all instances. The claim is a lease of [`JobsConfig::timeout`] seconds:
+//! when an instance stops in the middle of a job, another worker runs it again once the lease expired. Jobs can run
+//! more than once, so they must be safe to repeat.
+//!
+//! A job that fails is retried with an exponential backoff. After the configured number of attempts it's moved to the
+//! dead letter queue, where it stays until an administrator retries or removes it with the admin endpoints.
+//!
+//! On shutdown the workers finish the job they're running, but don't claim new ones.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{de::DeserializeOwned, Serialize};
+use sqlx::PgPool;
+use tracing::{error, info, instrument, warn};
+
+use crate::{
+    attachments::DeleteBlob,
+    blobs::BlobStore,
+    config::JobsConfig,
+    db,
+    entity::{Job, JobKind},
+    error::{AppError, Result},
+    mail::Mailer,
+    monitoring,
+    notifications::SendNotification,
+    reminders::SendReminder,
+    shutdown::Shutdown,
+    webhooks::PublishWebhooks,
+};
+
+/// The input of a kind of background job, and what the job does with it.
+pub trait JobPayload: Serialize + DeserializeOwned + Send {
+    /// The kind of job the payload belongs to.
+    const KIND: JobKind;
+
+    /// Runs the job. The job may have run before, so it must be safe to repeat.
+    fn perform(self, context: &JobContext) -> impl Future<Output = Result<()>> + Send;
+}
+
+/// The services a job can use while it runs.
+#[derive(Debug, Clone)]
+pub struct JobContext {
+    /// The database connection pool.
+    pub pool: PgPool,
+
+    /// The mailer to send emails to users with.
+    pub mailer: Arc<dyn Mailer>,
+
+    /// The store that keeps the contents of attachments.
+    pub blobs: Arc<dyn BlobStore>,
+}
+
+impl JobContext {
+    /// Creates a new job context from the services of the application.
+    pub fn new(pool: PgPool, mailer: Arc<dyn Mailer>, blobs: Arc<dyn BlobStore>) -> Self {
+        Self {
+            pool,
+            mailer,
+            blobs,
+        }
+    }
+}
+
+/// Runs the background jobs from the outbox.
+#[derive(Debug)]
+pub struct JobRunner {
+    config: JobsConfig,
+}
+
+impl JobRunner {
+    /// Creates a new job runner with the given configuration.
+    pub fn new(config: JobsConfig) -> Self {
+        Self { config }
+    }
+
+    /// Runs jobs with the configured number of workers until the application shuts down.
+    ///
+    /// This only returns when every worker finished the job it was running.
+    pub async fn run(&self, context: &JobContext, shutdown: &Shutdown) {
+        info!(
+            "Running background jobs with {} workers",
+            self.config.workers
+        );
+
+        // The workers spend most of their time waiting for the database, so they can share a task.
+        let workers = (0..self.config.workers).map(|_| self.work(context, shutdown));
+        futures_util::future::join_all(workers).await;
+
+        info!("Stopped running background jobs");
+    }
+
+    /// Runs one job after the other, and waits for new jobs when there are none.
+    async fn work(&self, context: &JobContext, shutdown: &Shutdown) {
+        let interval = Duration::from_secs(self.config.interval);
+
+        loop {
+            let delay = match self.run_next(context).await {
+                Ok(Some(_)) => Duration::ZERO,
+                Ok(None) => interval,
+                Err(err) => {
+                    error!("Failed to run background jobs: {}", err);
+                    interval
+                }
+            };
+
+            tokio::select! {
+                biased;
+                _ = shutdown.started() => break,
+                _ = tokio::time::sleep(delay) => {}
+            }
+        }
+    }
+
+    /// Claims the next job that is due and runs it.
+    ///
+    /// This returns the ID of the job that ran, or `None` when no job was due. Failed jobs are scheduled for another
+    /// attempt or moved to the dead letter queue, and only result in an error when that can't be recorded.
+    pub async fn run_next(&self, context: &JobContext) -> Result<Option<i64>> {
+        let pool = &context.pool;
+        let timeout = Duration::from_secs(self.config.timeout);
+        let lease_until = chrono::Utc::now().naive_utc() + to_chrono(timeout);
+
+        let Some(job) = db::claim_job(pool, lease_until).await? else {
+            return Ok(None);
+        };
+
+        let outcome = match tokio::time::timeout(timeout, execute(context, &job)).await {
+            Ok(outcome) => outcome,
+            Err(_) => Err(AppError::JobTimeout),
+        };
+
+        self.record_outcome(pool, &job, outcome).await?;
+
+        Ok(Some(job.id))
+    }
+
+    /// Removes a job that completed, or schedules the next attempt of a job that failed.
+    async fn record_outcome(&self, pool: &PgPool, job: &Job, outcome: Result<()>) -> Result<()> {
+        let kind = job.kind.as_str();
+
+        let err = match outcome {
+            Ok(()) => {
+                monitoring::record_job(kind, "completed");
+                return db::delete_completed_job(pool, job.id).await;
+            }
+            Err(err) => err,
+        };
+
+        let attempt = job.attempts.max(1) as u32;
+
+        if attempt >= self.config.attempts {
+            error!(
+                job_id = job.id,
+                kind, "Job failed after {} attempts: {}", attempt, err
+            );
+            monitoring::record_job(kind, "dead");
+
+            return db::mark_job_failed(pool, job.id, &err.to_string(), None).await;
+        }
+
+        let retry_at = chrono::Utc::now().naive_utc() + to_chrono(self.config.backoff(attempt));
+
+        warn!(
+            job_id = job.id,
+            kind, "Job failed, retrying at {}: {}", retry_at, err
+        );
+        monitoring::record_job(kind, "retried");
+
+        db::mark_job_failed(pool, job.id, &err.to_string(), Some(retry_at)).await
+    }
+}
+
+/// Runs a job with the payload type of its kind.
+#[instrument(skip(context, job), fields(job_id = job.id, kind = job.kind.as_str()))]
+async fn execute(context: &JobContext, job: &Job) -> Result<()> {
+    match job.kind {
+        JobKind::PublishWebhooks => perform::<PublishWebhooks>(context, job).await,
+        JobKind::SendReminder => perform::<SendReminder>(context, job).await,
+        JobKind::SendNotification => perform::<SendNotification>(context, job).await,
+        JobKind::DeleteBlob => perform::<DeleteBlob>(context, job).await,
+    }
+}
+
+/// Reads the payload of a job and runs it.
+async fn perform<J: JobPayload>(context: &JobContext, job: &Job) -> Result<()> {
+    let payload: J =
+        serde_json::from_value(job.payload.clone()).map_err(AppError::InvalidJobPayload)?;
+
+    payload.perform(context).await
+}
+
+/// Converts a duration from the configuration into a duration we can add to a date.
+fn to_chrono(duration: Duration) -> chrono::Duration {
+    chrono::Duration::from_std(duration).expect("The duration fits in a chrono duration.")
+}
@@ -0,0 +1,205 @@
+//! This module contains the HTTP middleware that applies to every request.
+//!
+//! The middleware is configured with [`crate::config::HttpConfig`], so each deployment can decide which browser
+//! origins can call the API, how large a request can be and how long a handler can take. From the outside in, the
+//! stack looks like this:
+//!
+//! 1. The security headers are added to every response, unless the handler already set them.
+//! 2. CORS preflight requests are answered and the CORS headers are added to the responses.
+//! 3. Responses are compressed with zstd, brotli or gzip, depending on the `Accept-Encoding` header.
+//! 4. The handler must produce a response within the timeout, or the client gets a `503 Service Unavailable` response.
+//! 5. Requests with a `Content-Length` larger than the limit are rejected with a `413 Payload Too Large` response.
+//!    Multipart uploads are left to the limit of their route, which can be larger, like the upload of attachments.
+//! 6. Compressed request bodies are decompressed. The limit also applies to the decompressed body, so a small
+//!    compressed body can't expand into gigabytes of JSON.
+//!
+//! The timeout only covers producing the response headers. A response that is streamed to the client, like a file
+//! download, can take longer than that.
+//!
+//! Please check out the [`tower_http`] crate for more information about the layers we use here.
+
+use std::time::Duration;
+
+use axum::{
+    extract::{DefaultBodyLimit, Request, State},
+    http::{header, HeaderName, HeaderValue, Method},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Router,
+};
+use tower_http::{
+    compression::{
+        predicate::{NotForContentType, Predicate, SizeAbove},
+        CompressionLayer,
+    },
+    cors::{AllowOrigin, CorsLayer},
+    decompression::RequestDecompressionLayer,
+    set_header::SetResponseHeaderLayer,
+};
+use tracing::warn;
+
+use crate::{
+    config::{CorsConfig, HttpConfig, SecurityHeadersConfig},
+    error::AppError,
+    request_id::REQUEST_ID_HEADER,
+};
+
+/// The response headers that a frontend on another origin can read.
+const EXPOSED_HEADERS: [HeaderName; 5] = [
+    REQUEST_ID_HEADER,
+    HeaderName::from_static("ratelimit-limit"),
+    HeaderName::from_static("ratelimit-remaining"),
+    HeaderName::from_static("ratelimit-reset"),
+    header::RETRY_AFTER,
+];
+
+/// The content security policy for the API. The API only returns JSON, so browsers don't need to load anything.
+const CONTENT_SECURITY_POLICY: &str = "default-src 'none'; frame-ancestors 'none'";
+
+/// Wraps the router with the middleware stack from the configuration.
+///
+/// The layers must be applied inside the request ID and tracing layers, so rejected requests are still logged.
+pub fn apply(router: Router, config: &HttpConfig) -> Router {
+    let mut router = router
+        .layer(DefaultBodyLimit::max(config.limit))
+        .layer(RequestDecompressionLayer::new())
+        .layer(middleware::from_fn_with_state(config.limit, limit_body));
+
+    if config.timeout > 0 {
+        router = router.layer(middleware::from_fn_with_state(
+            Duration::from_secs(config.timeout),
+            limit_duration,
+        ));
+    }
+
+    if config.compression.enabled {
+        let predicate = SizeAbove::new(config.compression.min)
+            .and(NotForContentType::GRPC)
+            .and(NotForContentType::IMAGES)
+            .and(NotForContentType::SSE);
+
+        router = router.layer(CompressionLayer::new().compress_when(predicate));
+    }
+
+    if let Some(cors) = create_cors_layer(&config.cors) {
+        router = router.layer(cors);
+    }
+
+    if config.headers.enabled {
+        router = add_security_headers(router, &config.headers);
+    }
+
+    router
+}
+
+/// Rejects requests that announce a body larger than the limit, before we start reading the body.
+///
+/// Bodies without a `Content-Length` header, like compressed or chunked bodies, are limited while they're read by
+/// [`DefaultBodyLimit`] instead. The same goes for multipart uploads, whose routes set their own [`DefaultBodyLimit`].
+async fn limit_body(State(limit): State<usize>, request: Request, next: Next) -> Response {
+    let is_multipart = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("multipart/form-data"));
+
+    if is_multipart {
+        return next.run(request).await;
+    }
+
+    let content_length = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+
+    match content_length {
+        Some(length) if length > limit => AppError::PayloadTooLarge.into_response(),
+        _ => next.run(request).await,
+    }
+}
+
+/// Cancels the handler when it takes longer than the timeout to produce a response.
+async fn limit_duration(State(timeout): State<Duration>, request: Request, next: Next) -> Response {
+    let path = request.uri().path().to_string();
+
+    match tokio::time::timeout(timeout, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => {
+            warn!("The request to {} timed out after {:?}", path, timeout);
+            AppError::ResponseTimeout.into_response()
+        }
+    }
+}
+
+/// Creates the CORS layer, or returns `None` when no origins are allowed.
+///
+/// The configuration is validated when it's loaded, so we can skip values that don't parse here.
+fn create_cors_layer(config: &CorsConfig) -> Option<CorsLayer> {
+    if config.origins.is_empty() {
+        return None;
+    }
+
+    let allow_origin = if config.origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(
+            config
+                .origins
+                .iter()
+                .filter_map(|origin| HeaderValue::from_str(origin).ok()),
+        )
+    };
+
+    let methods: Vec<_> = config
+        .methods
+        .iter()
+        .filter_map(|method| Method::from_bytes(method.as_bytes()).ok())
+        .collect();
+
+    let headers: Vec<_> = config
+        .headers
+        .iter()
+        .filter_map(|header| HeaderName::from_bytes(header.as_bytes()).ok())
+        .collect();
+
+    let cors = CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods(methods)
+        .allow_headers(headers)
+        .allow_credentials(config.credentials)
+        .expose_headers(EXPOSED_HEADERS)
+        .max_age(Duration::from_secs(config.age));
+
+    Some(cors)
+}
+
+/// Adds the security headers to every response that doesn't set them itself.
+fn add_security_headers(router: Router, config: &SecurityHeadersConfig) -> Router {
+    let router = router
+        .layer(SetResponseHeaderLayer::if_not_present(
+            header::X_CONTENT_TYPE_OPTIONS,
+            HeaderValue::from_static("nosniff"),
+        ))
+        .layer(SetResponseHeaderLayer::if_not_present(
+            header::X_FRAME_OPTIONS,
+            HeaderValue::from_static("DENY"),
+        ))
+        .layer(SetResponseHeaderLayer::if_not_present(
+            header::REFERRER_POLICY,
+            HeaderValue::from_static("no-referrer"),
+        ))
+        .layer(SetResponseHeaderLayer::if_not_present(
+            header::CONTENT_SECURITY_POLICY,
+            HeaderValue::from_static(CONTENT_SECURITY_POLICY),
+        ));
+
+    match config.hsts {
+        Some(max_age) => router.layer(SetResponseHeaderLayer::if_not_present(
+            header::STRICT_TRANSPORT_SECURITY,
+            HeaderValue::from_str(&format!("max-age={}; includeSubDomains", max_age))
+                .expect("The HSTS header is always valid."),
+        )),
+        None => router,
+    }
+}
@@ -1,7 +1,25 @@
+pub mod access;
+pub mod attachments;
 pub mod auth;
+pub mod blobs;
+pub mod comments;
 pub mod config;
 pub mod db;
 pub mod entity;
 pub mod error;
+pub mod events;
+pub mod jobs;
+pub mod layers;
+pub mod mail;
+pub mod monitoring;
+pub mod notifications;
+pub mod ratelimit;
+pub mod redact;
+pub mod reminders;
+pub mod request_id;
+pub mod shutdown;
 pub mod state;
+pub mod telemetry;
+pub mod tls;
 pub mod web;
+pub mod webhooks;
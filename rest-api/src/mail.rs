@@ -0,0 +1,261 @@
+//! This module contains the logic to send emails to users.
+//!
+//! Emails are sent through the [`Mailer`] trait, so we can swap out the way emails are delivered without touching the
+//! code that sends them. We have the following implementations:
+//!
+//! * [`SmtpMailer`] delivers emails through an SMTP server. Use this one in production.
+//! * [`FileMailer`] writes emails to a directory. This is useful for local development and testing.
+//! * [`LogMailer`] writes that an email was sent to the application log. This is the default, so you don't need a
+//!   mail server to run the application. The log only shows the masked recipient and the subject, because the body
+//!   contains tokens, like the ones to verify an email address. Use the [`FileMailer`] to read the emails themselves.
+//!
+//! The mailer is created from the [`crate::config::MailConfig`] with the [`create_mailer`] function and stored in the
+//! application state as a trait object.
+
+use std::fmt;
+use std::path::PathBuf;
+
+use axum::async_trait;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use tracing::info;
+
+use crate::config::{MailConfig, MailTransport};
+use crate::redact::mask_email;
+
+/// An email message to send to a single recipient.
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// The different types of errors that can occur when sending an email.
+#[derive(Debug)]
+pub enum MailError {
+    /// The sender or recipient isn't a valid email address.
+    InvalidAddress(lettre::address::AddressError),
+
+    /// The email couldn't be composed.
+    InvalidMessage(lettre::error::Error),
+
+    /// The SMTP server couldn't be reached or refused the email.
+    Smtp(lettre::transport::smtp::Error),
+
+    /// The email couldn't be written to the mail directory.
+    Io(std::io::Error),
+
+    /// The mailer is misconfigured, for example because the SMTP host is missing.
+    Misconfigured(String),
+}
+
+impl fmt::Display for MailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MailError::InvalidAddress(err) => write!(f, "Invalid email address: {}", err),
+            MailError::InvalidMessage(err) => write!(f, "Invalid email message: {}", err),
+            MailError::Smtp(err) => write!(f, "Failed to send the email: {}", err),
+            MailError::Io(err) => write!(f, "Failed to write the email: {}", err),
+            MailError::Misconfigured(reason) => {
+                write!(f, "The mailer is misconfigured: {}", reason)
+            }
+        }
+    }
+}
+
+impl From<lettre::address::AddressError> for MailError {
+    fn from(value: lettre::address::AddressError) -> Self {
+        MailError::InvalidAddress(value)
+    }
+}
+
+impl From<lettre::error::Error> for MailError {
+    fn from(value: lettre::error::Error) -> Self {
+        MailError::InvalidMessage(value)
+    }
+}
+
+impl From<lettre::transport::smtp::Error> for MailError {
+    fn from(value: lettre::transport::smtp::Error) -> Self {
+        MailError::Smtp(value)
+    }
+}
+
+impl From<std::io::Error> for MailError {
+    fn from(value: std::io::Error) -> Self {
+        MailError::Io(value)
+    }
+}
+
+/// Delivers emails to users.
+///
+/// We use the [`async_trait`] macro, because we store the mailer as a trait object in the application state.
+#[async_trait]
+pub trait Mailer: Send + Sync + fmt::Debug {
+    /// Sends an email.
+    async fn send(&self, email: &Email) -> Result<(), MailError>;
+}
+
+/// Writes the recipient and subject of emails to the application log.
+///
+/// The body is left out, because it contains tokens that give access to the account of the recipient.
+#[derive(Debug)]
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, email: &Email) -> Result<(), MailError> {
+        info!(
+            to = %mask_email(&email.to),
+            subject = %email.subject,
+            "Sending email"
+        );
+
+        Ok(())
+    }
+}
+
+/// Writes emails as `.eml` files to a directory.
+#[derive(Debug)]
+pub struct FileMailer {
+    sender: Mailbox,
+    directory: PathBuf,
+}
+
+impl FileMailer {
+    /// Creates a new mailer that writes emails to the given directory.
+    pub fn new(sender: &str, directory: impl Into<PathBuf>) -> Result<Self, MailError> {
+        Ok(Self {
+            sender: sender.parse()?,
+            directory: directory.into(),
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: &Email) -> Result<(), MailError> {
+        let message = build_message(&self.sender, email)?;
+
+        let file_name = format!(
+            "{}-{}.eml",
+            chrono::Utc::now().format("%Y%m%d%H%M%S%.f"),
+            email.to.replace(['@', '/', '\\'], "_")
+        );
+
+        tokio::fs::create_dir_all(&self.directory).await?;
+        tokio::fs::write(self.directory.join(file_name), message.formatted()).await?;
+
+        Ok(())
+    }
+}
+
+/// Delivers emails through an SMTP server using STARTTLS.
+pub struct SmtpMailer {
+    sender: Mailbox,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl fmt::Debug for SmtpMailer {
+    /// Formats the mailer without exposing the SMTP credentials.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SmtpMailer")
+            .field("sender", &self.sender)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: &Email) -> Result<(), MailError> {
+        let message = build_message(&self.sender, email)?;
+        self.transport.send(message).await?;
+
+        Ok(())
+    }
+}
+
+/// Creates the mailer that is selected in the configuration.
+pub fn create_mailer(config: &MailConfig) -> Result<Box<dyn Mailer>, MailError> {
+    match config.transport {
+        MailTransport::Log => Ok(Box::new(LogMailer)),
+        MailTransport::File => {
+            let directory = config.directory.as_deref().unwrap_or("mail");
+            Ok(Box::new(FileMailer::new(&config.sender, directory)?))
+        }
+        MailTransport::Smtp => {
+            let smtp_config = config.smtp.as_ref().ok_or_else(|| {
+                MailError::Misconfigured("The SMTP server is not configured.".to_string())
+            })?;
+
+            let mut builder =
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&smtp_config.host)?
+                    .port(smtp_config.port);
+
+            if let (Some(username), Some(password)) = (&smtp_config.username, &smtp_config.password)
+            {
+                builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+            }
+
+            Ok(Box::new(SmtpMailer {
+                sender: config.sender.parse()?,
+                transport: builder.build(),
+            }))
+        }
+    }
+}
+
+fn build_message(sender: &Mailbox, email: &Email) -> Result<lettre::Message, MailError> {
+    let message = lettre::Message::builder()
+        .from(sender.clone())
+        .to(email.to.parse()?)
+        .subject(&email.subject)
+        .body(email.body.clone())?;
+
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn file_mailer_writes_email_to_directory() {
+        let directory = std::env::temp_dir().join(format!("todo-api-mail-{}", std::process::id()));
+        let mailer = FileMailer::new("todo-api@example.org", &directory).unwrap();
+
+        mailer
+            .send(&Email {
+                to: "jane@example.org".to_string(),
+                subject: "Hello".to_string(),
+                body: "Hello Jane".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let mut entries = std::fs::read_dir(&directory).unwrap();
+        let contents = std::fs::read_to_string(entries.next().unwrap().unwrap().path()).unwrap();
+
+        assert!(contents.contains("To: jane@example.org"));
+        assert!(contents.contains("Hello Jane"));
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn file_mailer_rejects_invalid_recipient() {
+        let mailer = FileMailer::new("todo-api@example.org", std::env::temp_dir()).unwrap();
+
+        let result = mailer
+            .send(&Email {
+                to: "not an email address".to_string(),
+                subject: "Hello".to_string(),
+                body: "Hello".to_string(),
+            })
+            .await;
+
+        assert!(matches!(result, Err(MailError::InvalidAddress(_))));
+    }
+}
@@ -1,66 +1,423 @@
+use clap::{Parser, Subcommand};
+use opentelemetry_sdk::trace::TracerProvider;
 use std::env;
-use todo_api::{config::AppConfig, db, state::AppState, web};
-use tokio::{net::TcpListener, signal};
-use tracing::info;
+use std::future::IntoFuture;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+use todo_api::{
+    config::{AppConfig, LogFormat, OtlpConfig},
+    db,
+    error::AppError,
+    monitoring,
+    state::AppState,
+    telemetry, tls, web,
+};
+use tokio::{net::TcpListener, signal, sync::oneshot, time::Instant};
+use tracing::{error, info, warn};
+
+/// The exit code when requests or background jobs didn't finish before the shutdown timeout expired.
+const EXIT_DRAIN_TIMEOUT: u8 = 3;
+
+/// How long we wait for the database connections to close after the requests and background jobs have finished.
+const POOL_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long we wait for the remaining spans to be exported when the application stops.
+const TRACER_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The command line arguments of the application.
+///
+/// Without a command, the application starts the server.
+#[derive(Parser)]
+#[command(version, about = "A REST API to manage your tasks.")]
+struct Cli {
+    /// The TOML or YAML configuration file to load. Environment variables override its values.
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Work with the configuration of the application.
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Validate the configuration and print the effective configuration with secrets masked.
+    Check,
+}
 
 /// The main function of the application.
 ///
-/// This function is the entry point of the application. It loads the application configuration, connects to the database,
-/// creates the application state, and starts the server.
+/// This function is the entry point of the application. It parses the command line arguments, loads the application
+/// configuration, and runs the requested command. When the configuration is invalid, we print all the problems and
+/// exit with a non-zero exit code instead of panicking.
 ///
 /// Notice that we use the `#[tokio::main]` macro to mark this function as the main function of the application
 /// instead of writing a regular entrypoint. This is because the logic in the API is asynchronous and wouldn't work
 /// if we didn't instrument the main method with the [`tokio::main`] macro.
 #[tokio::main]
-async fn main() {
-    init_tracing();
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
 
-    let app_config = AppConfig::load().expect("Failed to load application configuration.");
+    let app_config = match AppConfig::load_from(cli.config.as_deref()) {
+        Ok(app_config) => app_config,
+        Err(err) => {
+            print_config_error(&err);
+            return ExitCode::from(2);
+        }
+    };
 
-    let connection_pool = db::connect_db(&app_config.database)
-        .await
-        .expect("Failed to connect to the database.");
+    match cli.command {
+        Some(Command::Config {
+            command: ConfigCommand::Check,
+        }) => {
+            println!("{}", app_config.to_masked_toml());
+            eprintln!("The configuration is valid.");
+
+            ExitCode::SUCCESS
+        }
+        None => serve(app_config).await,
+    }
+}
+
+/// Prints the problems with the configuration to the terminal.
+fn print_config_error(err: &AppError) {
+    match err {
+        AppError::InvalidConfig(problems) => {
+            eprintln!("The configuration is invalid:");
+
+            for problem in problems {
+                eprintln!("  - {}", problem);
+            }
+        }
+        err => eprintln!("Failed to load the configuration: {}", err),
+    }
+}
 
-    let app_state = AppState::new(connection_pool);
-    let router = web::create_router(app_state);
+/// Connects to the database and serves the API until the application receives a shutdown signal.
+async fn serve(app_config: AppConfig) -> ExitCode {
+    let tracer_provider = init_tracing(app_config.log.format, app_config.otlp.as_ref());
+
+    // Install the metrics recorder before anything else, so we don't lose metrics recorded during startup.
+    monitoring::prometheus_handle();
+
+    let connection_pool = match db::connect_db(&app_config.database).await {
+        Ok(connection_pool) => connection_pool,
+        Err(err) => {
+            error!("Failed to connect to the database: {:?}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let app_state = match AppState::new(connection_pool, &app_config) {
+        Ok(app_state) => app_state,
+        Err(err) => {
+            error!("Failed to create the application state: {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // The metrics and admin endpoints can be exposed on a separate port, so they aren't reachable through the public
+    // ingress.
+    let router = match app_config.metrics.port {
+        Some(port) => {
+            let admin_listener =
+                match TcpListener::bind(format!("{}:{}", app_config.server.host, port)).await {
+                    Ok(admin_listener) => admin_listener,
+                    Err(err) => {
+                        error!("Failed to bind to the admin address: {}", err);
+                        return ExitCode::FAILURE;
+                    }
+                };
+            let admin_router = web::create_admin_router(app_state.clone());
+            let admin_state = app_state.clone();
+
+            info!(
+                "Serving metrics and admin endpoints on {}:{}",
+                app_config.server.host, port
+            );
+
+            app_state.shutdown.spawn(async move {
+                let result = axum::serve(admin_listener, admin_router)
+                    .with_graceful_shutdown(async move { admin_state.shutdown.started().await })
+                    .await;
+
+                if let Err(err) = result {
+                    error!("The admin listener failed: {:?}", err);
+                }
+            });
+
+            web::create_router(app_state.clone())
+        }
+        None => web::create_router_with_admin(app_state.clone()),
+    };
+
+    let tls = match &app_config.server.tls {
+        Some(tls_config) => match tls::ReloadableTlsConfig::load(tls_config) {
+            Ok(tls) => Some(Arc::new(tls)),
+            Err(err) => {
+                error!("Failed to load the TLS configuration: {}", err);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
 
     let listener = TcpListener::bind(app_config.server.to_address())
         .await
         .expect("Failed to bind to address.");
 
     info!(
-        "Listening on {}:{}",
-        app_config.server.host, app_config.server.port
+        "Listening on {}:{}{}",
+        app_config.server.host,
+        app_config.server.port,
+        if tls.is_some() { " with TLS" } else { "" }
     );
 
-    // Make sure to use graceful shutdown on Ctrl+C otherwise the server will panic on shutdown.
-    axum::serve(listener, router)
-        .with_graceful_shutdown(async move {
-            signal::ctrl_c()
+    if let Some(tls) = &tls {
+        let tls = tls.clone();
+        let watch_state = app_state.clone();
+
+        app_state
+            .shutdown
+            .spawn(async move { tls.watch(&watch_state.shutdown).await });
+    }
+
+    if let Some(redirect_port) = app_config.server.tls.as_ref().and_then(|tls| tls.redirect) {
+        let redirect_address = format!("{}:{}", app_config.server.host, redirect_port);
+        let redirect_listener = match TcpListener::bind(&redirect_address).await {
+            Ok(redirect_listener) => redirect_listener,
+            Err(err) => {
+                error!("Failed to bind to the redirect address: {}", err);
+                return ExitCode::FAILURE;
+            }
+        };
+        let redirect_router = tls::create_redirect_router(app_config.server.port);
+        let redirect_state = app_state.clone();
+
+        info!(
+            "Redirecting HTTP on {}:{} to HTTPS",
+            app_config.server.host, redirect_port
+        );
+
+        app_state.shutdown.spawn(async move {
+            let result = axum::serve(redirect_listener, redirect_router)
+                .with_graceful_shutdown(async move { redirect_state.shutdown.started().await })
+                .await;
+
+            if let Err(err) = result {
+                error!("The redirect listener failed: {:?}", err);
+            }
+        });
+    }
+
+    if app_config.webhooks.enabled {
+        let webhook_state = app_state.clone();
+
+        app_state.shutdown.spawn(async move {
+            webhook_state
+                .webhooks
+                .run(&webhook_state.connection_pool, &webhook_state.shutdown)
                 .await
-                .expect("Failed to catch the SIGINT signal");
-        })
+        });
+    }
+
+    if app_config.jobs.enabled {
+        let jobs_state = app_state.clone();
+
+        app_state.shutdown.spawn(async move {
+            jobs_state
+                .jobs
+                .run(&jobs_state.job_context(), &jobs_state.shutdown)
+                .await
+        });
+    }
+
+    if app_config.reminders.enabled {
+        let reminders_state = app_state.clone();
+
+        app_state.shutdown.spawn(async move {
+            reminders_state
+                .reminders
+                .run(&reminders_state.connection_pool, &reminders_state.shutdown)
+                .await
+        });
+    }
+
+    let events_state = app_state.clone();
+
+    app_state.shutdown.spawn(async move {
+        events_state
+            .events
+            .listen(&events_state.connection_pool, &events_state.shutdown)
+            .await
+    });
+
+    let shutdown_delay = Duration::from_secs(app_config.server.shutdown.delay);
+    let drain_timeout = Duration::from_secs(app_config.server.shutdown.timeout);
+    let (drain_sender, drain_receiver) = oneshot::channel();
+    let shutdown_state = app_state.clone();
+
+    let shutdown = async move {
+        shutdown_signal().await;
+
+        // Let the readiness check fail for a while, so the load balancer stops sending us new requests
+        // before we stop accepting connections.
+        info!("Shutting down in {} seconds", shutdown_delay.as_secs());
+        shutdown_state.begin_shutdown();
+        tokio::time::sleep(shutdown_delay).await;
+
+        info!(
+            "Waiting up to {} seconds for in-flight requests and background jobs",
+            drain_timeout.as_secs()
+        );
+
+        let _ = drain_sender.send(Instant::now() + drain_timeout);
+    };
+
+    // We include the address of the client with each request, so the rate limiter can identify anonymous clients.
+    let mut server = match tls {
+        Some(tls) => tokio::spawn(tls::serve(listener, router, tls, shutdown)),
+        None => tokio::spawn(
+            axum::serve(
+                listener,
+                router.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(shutdown)
+            .into_future(),
+        ),
+    };
+
+    // The server runs until the shutdown starts. When it stops before that, it failed and dropped the sender.
+    let Ok(drain_deadline) = drain_receiver.await else {
+        error!("The server stopped unexpectedly: {:?}", server.await);
+        return ExitCode::FAILURE;
+    };
+
+    let mut exit_code = ExitCode::SUCCESS;
+
+    // The server has stopped accepting connections. We give the requests in flight and the background jobs until the
+    // deadline to finish. When they take longer, they're cancelled.
+    match tokio::time::timeout_at(drain_deadline, &mut server).await {
+        Ok(Ok(Ok(()))) => info!("All requests have finished"),
+        Ok(result) => {
+            error!("The server failed while shutting down: {:?}", result);
+            exit_code = ExitCode::FAILURE;
+        }
+        Err(_) => {
+            warn!("Requests were still running when the shutdown timeout expired");
+            server.abort();
+            exit_code = ExitCode::from(EXIT_DRAIN_TIMEOUT);
+        }
+    }
+
+    if tokio::time::timeout_at(drain_deadline, app_state.shutdown.jobs_finished())
+        .await
+        .is_err()
+    {
+        warn!("Background jobs were still running when the shutdown timeout expired");
+        exit_code = ExitCode::from(EXIT_DRAIN_TIMEOUT);
+    }
+
+    // Closing the pool waits for the connections that are in use, so we limit how long that takes.
+    if tokio::time::timeout(POOL_CLOSE_TIMEOUT, app_state.connection_pool.close())
         .await
-        .unwrap();
+        .is_err()
+    {
+        warn!("Failed to close all database connections in time");
+    }
+
+    // Flush the spans that haven't been exported yet. This blocks, so we move it off the async runtime. A collector
+    // that is down or slow shouldn't keep the application from stopping, so we limit how long that takes.
+    if let Some(tracer_provider) = tracer_provider {
+        let shutdown = tokio::task::spawn_blocking(move || tracer_provider.shutdown());
+
+        match tokio::time::timeout(TRACER_SHUTDOWN_TIMEOUT, shutdown).await {
+            Ok(Ok(Ok(()))) => {}
+            Ok(Ok(Err(err))) => warn!("Failed to export the remaining spans: {}", err),
+            Ok(Err(err)) => warn!("Failed to shut down the tracer provider: {}", err),
+            Err(_) => warn!("Failed to export the remaining spans in time"),
+        }
+    }
+
+    info!("Shutdown complete");
+    exit_code
+}
+
+/// Waits until the application receives a SIGINT or SIGTERM signal.
+///
+/// You send SIGINT by pressing Ctrl+C in the terminal. Container orchestrators like Kubernetes and Docker send
+/// SIGTERM when they stop a container. SIGTERM doesn't exist on Windows, so there we only wait for Ctrl+C.
+async fn shutdown_signal() {
+    let interrupt = async {
+        signal::ctrl_c()
+            .await
+            .expect("Failed to catch the SIGINT signal");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("Failed to catch the SIGTERM signal")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => info!("Received SIGINT"),
+        _ = terminate => info!("Received SIGTERM"),
+    }
 }
 
 /// This function initializes tracing so we can see logs from the application.
 ///
-/// The logs are currently set up to show in the terminal. In a production scenario
-/// you should consider setting up opentelemetry instead. There's a great package called
-/// `tracing-opentelemetry` that can be used to send logs to a OTEL collector.
-fn init_tracing() {
+/// The logs are always written to the terminal, either as plain text or as one JSON object per line. When an
+/// OpenTelemetry collector is configured, the spans are also exported to the collector using the
+/// `tracing-opentelemetry` layer. We return the tracer provider, so we can flush the remaining spans when the
+/// application stops.
+fn init_tracing(log_format: LogFormat, otlp_config: Option<&OtlpConfig>) -> Option<TracerProvider> {
+    use opentelemetry::trace::TracerProvider as _;
     use tracing_subscriber::{filter::LevelFilter, fmt, prelude::*, EnvFilter};
 
     let rust_log = env::var(EnvFilter::DEFAULT_ENV)
         .unwrap_or_else(|_| "sqlx=info,tower_http=debug,info".to_string());
 
+    let tracer_provider = otlp_config.map(|config| {
+        telemetry::create_tracer_provider(config).expect("Failed to create the trace exporter.")
+    });
+
+    let otel_layer = tracer_provider.as_ref().map(|tracer_provider| {
+        tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer(telemetry::SERVICE_NAME))
+    });
+
+    // The JSON lines include the fields of all the spans the event happened in, like the request ID.
+    let fmt_layer = match log_format {
+        LogFormat::Text => fmt::layer().boxed(),
+        LogFormat::Json => fmt::layer().json().with_span_list(true).boxed(),
+    };
+
     tracing_subscriber::registry()
-        .with(fmt::layer())
+        .with(otel_layer)
+        .with(fmt_layer)
         .with(
             EnvFilter::builder()
                 .with_default_directive(LevelFilter::INFO.into())
                 .parse_lossy(&rust_log),
         )
         .init();
+
+    tracer_provider
 }
@@ -0,0 +1,228 @@
+//! This module contains the metrics we collect about the application.
+//!
+//! We use the [`metrics`] crate to record metrics and the [`metrics_exporter_prometheus`] crate to expose them in the
+//! Prometheus text format on the `/metrics` endpoint. The [`metrics`] crate works with a global recorder, so you can
+//! record a metric from anywhere in the application without passing a registry around. When no recorder is installed,
+//! for example in the integration tests, recording a metric does nothing.
+//!
+//! We collect the following metrics:
+//!
+//! * `http_requests_total` and `http_request_duration_seconds` for each route, labelled by the path template.
+//! * `db_query_duration_seconds` for each function in [`crate::db`].
+//! * `db_pool_connections`, `db_pool_max_connections`, `db_pool_waiting` and `db_pool_acquire_duration_seconds` for
+//!   the database connection pool.
+//! * `auth_failures_total` for each variant of [`crate::auth::AuthError`].
+//! * `tasks_created_total` and `tasks` for the number of tasks that are created, open and completed.
+//! * `webhook_deliveries_total` for each outcome of an attempt to deliver a webhook.
+//! * `jobs_total` for each kind and outcome of a background job, and `jobs` for the number of jobs in each state.
+//! * `reminders_total` for each outcome of a reminder that is due.
+
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+};
+use metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use sqlx::PgPool;
+
+/// The histogram buckets for durations in seconds, ranging from 1 millisecond to 10 seconds.
+const DURATION_BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+static PROMETHEUS_HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+/// Returns the handle to render the metrics in the Prometheus text format.
+///
+/// The first call installs the Prometheus recorder as the global recorder. Metrics recorded before that are lost.
+pub fn prometheus_handle() -> &'static PrometheusHandle {
+    PROMETHEUS_HANDLE.get_or_init(|| {
+        PrometheusBuilder::new()
+            .set_buckets_for_metric(
+                Matcher::Suffix("duration_seconds".to_string()),
+                DURATION_BUCKETS,
+            )
+            .expect("The duration buckets must not be empty.")
+            .install_recorder()
+            .expect("Failed to install the Prometheus recorder.")
+    })
+}
+
+/// Records the number of requests and their duration for each route.
+///
+/// We label the metrics with the path template of the route, like `/v1/todos/:id`, instead of the actual path.
+/// Otherwise every task would get its own time series. Requests that don't match a route share a single label.
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let path = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|matched_path| matched_path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let method = request.method().to_string();
+    let started_at = Instant::now();
+
+    let response = next.run(request).await;
+
+    let labels = [
+        ("method", method),
+        ("path", path),
+        ("status", response.status().as_u16().to_string()),
+    ];
+
+    counter!("http_requests_total", &labels).increment(1);
+    histogram!("http_request_duration_seconds", &labels).record(started_at.elapsed());
+
+    response
+}
+
+/// Records the duration of a database query when it's dropped.
+///
+/// Create the timer at the start of a function in [`crate::db`], so the duration is recorded on every return path,
+/// including errors.
+pub struct QueryTimer {
+    query: &'static str,
+    started_at: Instant,
+}
+
+impl QueryTimer {
+    /// Starts timing the query with the given name.
+    pub fn start(query: &'static str) -> Self {
+        Self {
+            query,
+            started_at: Instant::now(),
+        }
+    }
+}
+
+impl Drop for QueryTimer {
+    fn drop(&mut self) {
+        histogram!("db_query_duration_seconds", "query" => self.query)
+            .record(self.started_at.elapsed());
+    }
+}
+
+/// Keeps track of a request that waits for a connection from the pool.
+///
+/// The `db_pool_waiting` gauge is increased when the guard is created and decreased when it's dropped. Call
+/// [`PoolWaitGuard::acquired`] once the connection is available to record the time we waited for it.
+pub struct PoolWaitGuard {
+    started_at: Instant,
+}
+
+impl PoolWaitGuard {
+    /// Starts waiting for a connection.
+    pub fn start() -> Self {
+        gauge!("db_pool_waiting").increment(1.0);
+
+        Self {
+            started_at: Instant::now(),
+        }
+    }
+
+    /// Records the time we waited for the connection.
+    pub fn acquired(self) {
+        histogram!("db_pool_acquire_duration_seconds").record(self.started_at.elapsed());
+    }
+}
+
+impl Drop for PoolWaitGuard {
+    fn drop(&mut self) {
+        gauge!("db_pool_waiting").decrement(1.0);
+    }
+}
+
+/// Records the current size of the connection pool.
+pub fn record_pool_status(pool: &PgPool) {
+    let size = f64::from(pool.size());
+    let idle = pool.num_idle() as f64;
+
+    gauge!("db_pool_connections", "state" => "idle").set(idle);
+    gauge!("db_pool_connections", "state" => "in_use").set((size - idle).max(0.0));
+    gauge!("db_pool_max_connections").set(f64::from(pool.options().get_max_connections()));
+}
+
+/// Records a request that was rejected because of missing or invalid credentials.
+pub fn record_auth_failure(reason: &'static str) {
+    counter!("auth_failures_total", "reason" => reason).increment(1);
+}
+
+/// Records a task that was created.
+pub fn record_task_created() {
+    counter!("tasks_created_total").increment(1);
+}
+
+/// Records the number of open and completed tasks.
+pub fn record_task_counts(open: i64, completed: i64) {
+    gauge!("tasks", "state" => "open").set(open as f64);
+    gauge!("tasks", "state" => "completed").set(completed as f64);
+}
+
+/// Records the outcome of an attempt to deliver a webhook: `delivered`, `retried` or `failed`.
+pub fn record_webhook_delivery(outcome: &'static str) {
+    counter!("webhook_deliveries_total", "outcome" => outcome).increment(1);
+}
+
+/// Records the outcome of a background job: `completed`, `retried` or `dead`.
+pub fn record_job(kind: &'static str, outcome: &'static str) {
+    counter!("jobs_total", "kind" => kind, "outcome" => outcome).increment(1);
+}
+
+/// Records the number of pending, running and dead background jobs.
+pub fn record_job_counts(pending: i64, running: i64, dead: i64) {
+    gauge!("jobs", "state" => "pending").set(pending as f64);
+    gauge!("jobs", "state" => "running").set(running as f64);
+    gauge!("jobs", "state" => "dead").set(dead as f64);
+}
+
+/// Records what happened to a reminder that is due: `sent` or `postponed` because of the quiet hours of the user.
+pub fn record_reminder(outcome: &'static str) {
+    counter!("reminders_total", "outcome" => outcome).increment(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn requests_are_labelled_with_path_template() {
+        let handle = prometheus_handle();
+
+        let router = Router::new()
+            .route("/metrics-test/:id", get(|| async { "ok" }))
+            .layer(middleware::from_fn(track_requests));
+
+        router
+            .oneshot(
+                Request::builder()
+                    .uri("/metrics-test/42")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let rendered = handle.render();
+
+        assert!(rendered.contains(r#"path="/metrics-test/:id""#));
+        assert!(!rendered.contains("/metrics-test/42"));
+    }
+
+    #[test]
+    fn query_timer_records_duration_on_drop() {
+        let handle = prometheus_handle();
+
+        drop(QueryTimer::start("query_timer_test"));
+
+        assert!(handle
+            .render()
+            .contains(r#"db_query_duration_seconds_count{query="query_timer_test"} 1"#));
+    }
+}
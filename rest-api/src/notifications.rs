@@ -0,0 +1,173 @@
+//! This module notifies users about the tasks they're involved in.
+//!
+//! ## Assignments and mentions
+//! Users are involved in a task when they're assigned to it, watch it, or are mentioned in its description. Assigning
+//! or unassigning a user notifies that user and the watchers of the task. Mentioning a user notifies them once, when
+//! they're first mentioned in the description.
+//!
+//! A mention is an `@` followed by the handle of a user, like `@jane`, or by their email address, like
+//! `@jane@example.org`. Only users who can access the task are notified, so a mention never reveals a task to someone
+//! it isn't shared with.
+//!
+//! ## Delivery
+//! The notifications end up in the in-app inbox of the user. Like the other side effects, they're queued as a
+//! [`SendNotification`] job in the same transaction as the change, see [`crate::jobs`]. Every notification carries a
+//! deduplication key, so running the job twice adds it to the inbox once.
+
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+    db,
+    entity::{JobKind, NotificationKind},
+    error::Result,
+    jobs::{JobContext, JobPayload},
+};
+
+/// The length of the shortest handle.
+const MIN_HANDLE_LENGTH: usize = 3;
+
+/// The length of the longest handle.
+const MAX_HANDLE_LENGTH: usize = 30;
+
+/// Adds a notification to the in-app inbox of a user.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SendNotification {
+    /// The user to notify.
+    pub user_id: i32,
+
+    /// The task the notification is about.
+    pub task_id: Option<i32>,
+
+    /// What the notification is about.
+    pub kind: NotificationKind,
+
+    /// A short summary to show in the inbox.
+    pub title: String,
+
+    /// The full text of the notification.
+    pub body: String,
+
+    /// A key that identifies the notification, so it's added to the inbox once.
+    pub dedup_key: String,
+}
+
+impl JobPayload for SendNotification {
+    const KIND: JobKind = JobKind::SendNotification;
+
+    #[instrument(skip_all, fields(user_id = self.user_id, task_id = self.task_id))]
+    async fn perform(self, context: &JobContext) -> Result<()> {
+        db::insert_notification(
+            &context.pool,
+            self.user_id,
+            self.task_id,
+            self.kind,
+            &self.title,
+            &self.body,
+            &self.dedup_key,
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// The users mentioned in a text, by handle and by email address. Both are lowercase and without duplicates.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Mentions {
+    /// The handles that were mentioned, like `jane` for `@jane`.
+    pub handles: Vec<String>,
+
+    /// The email addresses that were mentioned, like `jane@example.org` for `@jane@example.org`.
+    pub email_addresses: Vec<String>,
+}
+
+impl Mentions {
+    /// Returns whether nobody was mentioned.
+    pub fn is_empty(&self) -> bool {
+        self.handles.is_empty() && self.email_addresses.is_empty()
+    }
+}
+
+/// Finds the mentions in a text.
+///
+/// A mention starts with an `@` that doesn't follow a word, so the domain of an email address in the text isn't taken
+/// for a handle. Punctuation at the end of a mention, like the period that ends a sentence, isn't part of it. Words
+/// that aren't a valid handle or email address are ignored.
+pub fn parse_mentions(text: &str) -> Mentions {
+    let mut mentions = Mentions::default();
+    let mut previous = None;
+
+    for (index, character) in text.char_indices() {
+        if character == '@' && !previous.is_some_and(is_mention_character) {
+            let rest = &text[index + 1..];
+            let end = rest
+                .find(|c: char| !is_mention_character(c))
+                .unwrap_or(rest.len());
+            let mention = rest[..end]
+                .trim_end_matches(['.', '-', '+', '@'])
+                .to_lowercase();
+
+            let found = if mention.contains('@') {
+                mention
+                    .parse::<lettre::Address>()
+                    .is_ok()
+                    .then_some(&mut mentions.email_addresses)
+            } else {
+                is_valid_handle(&mention).then_some(&mut mentions.handles)
+            };
+
+            if let Some(found) = found {
+                if !found.contains(&mention) {
+                    found.push(mention);
+                }
+            }
+        }
+
+        previous = Some(character);
+    }
+
+    mentions
+}
+
+/// Returns whether a handle is valid: 3 to 30 letters, digits or underscores.
+pub fn is_valid_handle(handle: &str) -> bool {
+    (MIN_HANDLE_LENGTH..=MAX_HANDLE_LENGTH).contains(&handle.len())
+        && handle
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Returns whether a character can be part of a mention.
+fn is_mention_character(character: char) -> bool {
+    character.is_alphanumeric() || matches!(character, '.' | '_' | '-' | '+' | '@')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handles_and_email_addresses_are_found() {
+        let mentions =
+            parse_mentions("@Jane, can you ask @bob@Example.org? Thanks @jane and @Carl_2.");
+
+        assert_eq!(mentions.handles, vec!["jane", "carl_2"]);
+        assert_eq!(mentions.email_addresses, vec!["bob@example.org"]);
+    }
+
+    #[test]
+    fn email_addresses_in_the_text_are_not_mentions() {
+        let mentions = parse_mentions("Mail jane@example.org or @x about it");
+
+        assert!(mentions.is_empty());
+    }
+
+    #[test]
+    fn handles_are_validated() {
+        assert!(is_valid_handle("jane_doe"));
+        assert!(!is_valid_handle("jo"));
+        assert!(!is_valid_handle("jane.doe"));
+        assert!(!is_valid_handle(&"a".repeat(31)));
+    }
+}
@@ -0,0 +1,485 @@
+//! This module contains the rate limiting logic for the API.
+//!
+//! We use the token bucket algorithm to limit the number of requests a client can make. Every client gets a bucket
+//! that holds a number of tokens. Each request takes a token from the bucket, and the bucket is refilled at a steady
+//! rate. When the bucket is empty, the request is rejected with a `429 Too Many Requests` response. The size of the
+//! bucket determines how many requests a client can make in a short burst.
+//!
+//! Clients are identified by their API key, the subject of their bearer token or their client certificate. Requests
+//! without credentials, like the ones to `/v1/users/register`, are identified by the IP address of the client. So are
+//! requests with an API key we haven't seen authenticate a request recently or a bearer token that isn't valid,
+//! otherwise a client could get a fresh bucket for every request by making up a new key each time. We don't look API
+//! keys up in the database here, so a client sending made up keys can't exhaust the connection pool either.
+//!
+//! Each route group has its own limits, which you can configure with [`crate::config::RateLimitConfig`]. The buckets
+//! are kept in memory, so each instance of the API enforces the limits on its own.
+//!
+//! The [`rate_limit`] function is an Axum middleware. Please check out the [`axum::middleware`] module for more
+//! information about writing middleware.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, Extensions, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use crate::{
+    auth::token::TokenType,
+    config::{RateLimitConfig, RateLimitRule},
+    entity::ApiKey,
+    error::ErrorDetails,
+    state::AppState,
+    tls::ClientCertificate,
+};
+
+/// The maximum number of buckets we keep. When there are more, we remove the buckets that are full again, and then
+/// the buckets of the clients we haven't seen for the longest time until half of them are left.
+const MAX_BUCKETS: usize = 10_000;
+
+/// How long we keep identifying a client by its API key after the key was last used to authenticate a request.
+const KNOWN_KEY_TTL: Duration = Duration::from_secs(600);
+
+/// The groups of routes that have their own rate limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteGroup {
+    /// The routes to manage tasks and webhooks.
+    Tasks,
+
+    /// The routes to register and manage users.
+    Users,
+
+    /// The routes to obtain tokens.
+    Auth,
+}
+
+/// A bucket with tokens for a single client.
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// The outcome of taking a token from a bucket.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimitDecision {
+    /// Whether the request is allowed.
+    pub allowed: bool,
+
+    /// The size of the bucket.
+    pub limit: u32,
+
+    /// The number of requests the client can still make right now.
+    pub remaining: u32,
+
+    /// The number of seconds until the bucket is full again.
+    pub reset: u64,
+
+    /// The number of seconds until the client can make another request, when the request isn't allowed.
+    pub retry_after: u64,
+}
+
+impl TokenBucket {
+    fn full(rule: &RateLimitRule, now: Instant) -> Self {
+        Self {
+            tokens: f64::from(rule.burst),
+            last_refill: now,
+        }
+    }
+
+    /// Refills the bucket for the time that passed since the last refill and tries to take a token from it.
+    fn take(&mut self, rule: &RateLimitRule, now: Instant) -> RateLimitDecision {
+        let capacity = f64::from(rule.burst);
+        let refill_per_second = f64::from(rule.rate) / 60.0;
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * refill_per_second).min(capacity);
+        self.last_refill = now;
+
+        let allowed = self.tokens >= 1.0;
+
+        if allowed {
+            self.tokens -= 1.0;
+        }
+
+        let seconds_until = |tokens: f64| -> u64 {
+            if tokens <= 0.0 {
+                0
+            } else if refill_per_second <= 0.0 {
+                u64::MAX
+            } else {
+                (tokens / refill_per_second).ceil() as u64
+            }
+        };
+
+        RateLimitDecision {
+            allowed,
+            limit: rule.burst,
+            remaining: self.tokens.floor() as u32,
+            reset: seconds_until(capacity - self.tokens),
+            retry_after: if allowed {
+                0
+            } else {
+                seconds_until(1.0 - self.tokens)
+            },
+        }
+    }
+
+    fn is_full(&self, rule: &RateLimitRule, now: Instant) -> bool {
+        let refill_per_second = f64::from(rule.rate) / 60.0;
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+
+        self.tokens + elapsed * refill_per_second >= f64::from(rule.burst)
+    }
+}
+
+/// Keeps track of the token buckets for all clients.
+#[derive(Debug)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<HashMap<(RouteGroup, String), TokenBucket>>,
+
+    /// The hashes of the API keys that authenticated a request recently, with the time they were last used.
+    known_keys: Mutex<HashMap<String, Instant>>,
+}
+
+impl RateLimiter {
+    /// Creates a new rate limiter with the limits from the configuration.
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            buckets: Mutex::new(HashMap::new()),
+            known_keys: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the limits for a route group.
+    fn rule(&self, group: RouteGroup) -> &RateLimitRule {
+        match group {
+            RouteGroup::Tasks => &self.config.tasks,
+            RouteGroup::Users => &self.config.users,
+            RouteGroup::Auth => &self.config.auth,
+        }
+    }
+
+    /// Takes a token from the bucket of a client for a route group.
+    pub fn check(&self, group: RouteGroup, client_key: &str) -> RateLimitDecision {
+        self.check_at(group, client_key, Instant::now())
+    }
+
+    fn check_at(&self, group: RouteGroup, client_key: &str, now: Instant) -> RateLimitDecision {
+        let rule = self.rule(group);
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() >= MAX_BUCKETS {
+            // Buckets that are full again behave the same as new buckets, so we can safely remove them.
+            buckets.retain(|(group, _), bucket| !bucket.is_full(self.rule(*group), now));
+
+            // Clients that keep their buckets empty would make us keep every bucket, so we also forget the clients we
+            // haven't seen for the longest time. We leave room for many new clients, so we only get here once in a
+            // while, instead of on every request.
+            forget_least_recent(&mut buckets, |bucket| bucket.last_refill);
+        }
+
+        buckets
+            .entry((group, client_key.to_string()))
+            .or_insert_with(|| TokenBucket::full(rule, now))
+            .take(rule, now)
+    }
+
+    /// Remembers that an API key authenticated a request, so the next requests with the key get a bucket of their own.
+    ///
+    /// The authentication extractor calls this after it found the key in the database, so we don't need to look the
+    /// key up again for every request.
+    pub fn remember_key(&self, api_key_hash: &str) {
+        if self.config.enabled {
+            self.remember_key_at(api_key_hash, Instant::now());
+        }
+    }
+
+    fn remember_key_at(&self, api_key_hash: &str, now: Instant) {
+        let mut known_keys = self.known_keys.lock().unwrap();
+
+        if known_keys.len() >= MAX_BUCKETS {
+            known_keys
+                .retain(|_, last_used| now.saturating_duration_since(*last_used) < KNOWN_KEY_TTL);
+            forget_least_recent(&mut known_keys, |last_used| *last_used);
+        }
+
+        known_keys.insert(api_key_hash.to_string(), now);
+    }
+
+    /// Returns whether an API key authenticated a request recently.
+    fn is_known_key_at(&self, api_key_hash: &str, now: Instant) -> bool {
+        self.known_keys
+            .lock()
+            .unwrap()
+            .get(api_key_hash)
+            .is_some_and(|last_used| now.saturating_duration_since(*last_used) < KNOWN_KEY_TTL)
+    }
+}
+
+/// Forgets the entries we haven't seen for the longest time until half of the maximum number of entries is left.
+fn forget_least_recent<K, V>(entries: &mut HashMap<K, V>, last_seen: impl Fn(&V) -> Instant) {
+    if entries.len() > MAX_BUCKETS / 2 {
+        let mut times: Vec<Instant> = entries.values().map(&last_seen).collect();
+        let newest = times.len() - MAX_BUCKETS / 2;
+        let cutoff = *times.select_nth_unstable(newest).1;
+
+        entries.retain(|_, value| last_seen(value) >= cutoff);
+    }
+}
+
+/// Limits the number of requests a client can make to a route group.
+///
+/// This middleware adds the `RateLimit-Limit`, `RateLimit-Remaining` and `RateLimit-Reset` headers to every response.
+/// When the client made too many requests, we return a `429 Too Many Requests` response with a `Retry-After` header
+/// without calling the handler.
+pub async fn rate_limit(
+    State((app_state, group)): State<(Arc<AppState>, RouteGroup)>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !app_state.rate_limiter.config.enabled {
+        return next.run(request).await;
+    }
+
+    let client_key = client_key(&app_state, request.headers(), request.extensions());
+    let decision = app_state.rate_limiter.check(group, &client_key);
+
+    let mut response = if decision.allowed {
+        next.run(request).await
+    } else {
+        let error_details = ErrorDetails::new("Too many requests. Please try again later.");
+
+        let mut response = (StatusCode::TOO_MANY_REQUESTS, Json(error_details)).into_response();
+        insert_header(
+            response.headers_mut(),
+            header::RETRY_AFTER,
+            decision.retry_after,
+        );
+
+        response
+    };
+
+    let headers = response.headers_mut();
+    insert_header(
+        headers,
+        HeaderName::from_static("ratelimit-limit"),
+        decision.limit,
+    );
+    insert_header(
+        headers,
+        HeaderName::from_static("ratelimit-remaining"),
+        decision.remaining,
+    );
+    insert_header(
+        headers,
+        HeaderName::from_static("ratelimit-reset"),
+        decision.reset,
+    );
+
+    response
+}
+
+/// Determines the key that identifies the client that sent the request.
+///
+/// We prefer the credentials of the client, so all requests with the same API key or bearer token subject share a
+/// bucket regardless of where they come from. We only trust credentials we checked: API keys need to have
+/// authenticated a request recently, and bearer tokens need a valid signature. We never store the API key itself,
+/// only its hash.
+fn client_key(app_state: &AppState, headers: &HeaderMap, extensions: &Extensions) -> String {
+    if let Some(api_key) = headers
+        .get("X-Api-Key")
+        .and_then(|value| value.to_str().ok())
+    {
+        let api_key = ApiKey::from_string(api_key);
+
+        if app_state
+            .rate_limiter
+            .is_known_key_at(&api_key.hash, Instant::now())
+        {
+            return format!("key:{}", api_key.hash);
+        }
+    }
+
+    let bearer_token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    if let Some(token) = bearer_token {
+        if let Ok(claims) = app_state.token_issuer.validate(token, TokenType::Access) {
+            return format!("user:{}", claims.sub);
+        }
+    }
+
+    if let Some(client_certificate) = extensions.get::<ClientCertificate>() {
+        return format!("cert:{}", client_certificate.email.to_lowercase());
+    }
+
+    let forwarded = app_state.rate_limiter.config.forwarded;
+    format!("ip:{}", client_ip(forwarded, headers, extensions))
+}
+
+/// Determines the IP address of the client.
+///
+/// When the API runs behind a reverse proxy, all requests come from the proxy. In that case you can configure the
+/// rate limiter to use the `X-Forwarded-For` header. Proxies append the address they received the request from to
+/// that header, so only the last address comes from our own proxy. The addresses before it are sent by the client,
+/// which could make up a new one for every request to get a fresh bucket.
+fn client_ip(forwarded: bool, headers: &HeaderMap, extensions: &Extensions) -> String {
+    if forwarded {
+        let forwarded_for = headers
+            .get("X-Forwarded-For")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit(',').next())
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty());
+
+        if let Some(ip) = forwarded_for {
+            return ip;
+        }
+    }
+
+    extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(address)| address.ip().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn insert_header(headers: &mut HeaderMap, name: HeaderName, value: impl ToString) {
+    if let Ok(value) = HeaderValue::from_str(&value.to_string()) {
+        headers.insert(name, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn create_limiter(rate: u32, burst: u32) -> RateLimiter {
+        let rule = RateLimitRule { rate, burst };
+
+        RateLimiter::new(RateLimitConfig {
+            enabled: true,
+            forwarded: false,
+            tasks: rule.clone(),
+            users: rule.clone(),
+            auth: rule,
+        })
+    }
+
+    #[test]
+    fn requests_within_burst_are_allowed() {
+        let limiter = create_limiter(60, 3);
+        let now = Instant::now();
+
+        for expected_remaining in [2, 1, 0] {
+            let decision = limiter.check_at(RouteGroup::Tasks, "client", now);
+
+            assert!(decision.allowed);
+            assert_eq!(decision.remaining, expected_remaining);
+        }
+    }
+
+    #[test]
+    fn request_over_burst_is_rejected_with_retry_after() {
+        let limiter = create_limiter(60, 2);
+        let now = Instant::now();
+
+        limiter.check_at(RouteGroup::Tasks, "client", now);
+        limiter.check_at(RouteGroup::Tasks, "client", now);
+        let decision = limiter.check_at(RouteGroup::Tasks, "client", now);
+
+        assert!(!decision.allowed);
+        assert_eq!(decision.retry_after, 1);
+    }
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let limiter = create_limiter(60, 1);
+        let now = Instant::now();
+
+        assert!(limiter.check_at(RouteGroup::Tasks, "client", now).allowed);
+        assert!(!limiter.check_at(RouteGroup::Tasks, "client", now).allowed);
+        assert!(
+            limiter
+                .check_at(RouteGroup::Tasks, "client", now + Duration::from_secs(1))
+                .allowed
+        );
+    }
+
+    #[test]
+    fn least_recently_seen_clients_are_forgotten_when_buckets_run_out() {
+        let limiter = create_limiter(60, 1);
+        let now = Instant::now();
+
+        for client in 0..MAX_BUCKETS {
+            let seen_at = now + Duration::from_millis(client as u64);
+            limiter.check_at(RouteGroup::Tasks, &client.to_string(), seen_at);
+        }
+
+        let later = now + Duration::from_millis(MAX_BUCKETS as u64);
+        limiter.check_at(RouteGroup::Tasks, "new", later);
+
+        let buckets = limiter.buckets.lock().unwrap();
+        assert!(buckets.len() <= MAX_BUCKETS / 2 + 1);
+        assert!(!buckets.contains_key(&(RouteGroup::Tasks, "0".to_string())));
+        assert!(buckets.contains_key(&(RouteGroup::Tasks, (MAX_BUCKETS - 1).to_string())));
+    }
+
+    #[test]
+    fn clients_and_groups_have_separate_buckets() {
+        let limiter = create_limiter(60, 1);
+        let now = Instant::now();
+
+        assert!(limiter.check_at(RouteGroup::Tasks, "client", now).allowed);
+        assert!(limiter.check_at(RouteGroup::Tasks, "other", now).allowed);
+        assert!(limiter.check_at(RouteGroup::Users, "client", now).allowed);
+    }
+
+    #[test]
+    fn spoofed_forwarded_addresses_share_the_bucket_of_the_proxy_address() {
+        let limiter = create_limiter(60, 1);
+        let now = Instant::now();
+
+        for spoofed in ["192.0.2.1", "192.0.2.2"] {
+            let mut headers = HeaderMap::new();
+            let value = format!("{}, 203.0.113.7", spoofed);
+            headers.insert("X-Forwarded-For", HeaderValue::from_str(&value).unwrap());
+
+            let ip = client_ip(true, &headers, &Extensions::new());
+            assert_eq!(ip, "203.0.113.7");
+
+            let decision = limiter.check_at(RouteGroup::Users, &format!("ip:{}", ip), now);
+            assert_eq!(decision.allowed, spoofed == "192.0.2.1");
+        }
+    }
+
+    #[test]
+    fn api_keys_are_known_until_they_expire() {
+        let limiter = create_limiter(60, 1);
+        let now = Instant::now();
+
+        assert!(!limiter.is_known_key_at("hash", now));
+
+        limiter.remember_key_at("hash", now);
+
+        assert!(limiter.is_known_key_at("hash", now + Duration::from_secs(1)));
+        assert!(!limiter.is_known_key_at("hash", now + KNOWN_KEY_TTL));
+        assert!(!limiter.is_known_key_at("other", now));
+    }
+}
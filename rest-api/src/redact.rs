@@ -0,0 +1,39 @@
+//! This module contains the policy for keeping sensitive data out of the logs and traces.
+//!
+//! * Secrets like API keys, passwords and tokens are never logged. Skip them in `#[instrument]` attributes and leave
+//!   them out of [`std::fmt::Debug`] implementations.
+//! * Email addresses are masked with [`mask_email`], so you can still tell users apart without exposing the address.
+//! * Content that users write, like task descriptions, is replaced with [`REDACTED`].
+//!
+//! Prefer skipping a field over redacting it. A field that isn't logged can't leak.
+
+/// The placeholder for values that are left out of the logs.
+pub const REDACTED: &str = "[redacted]";
+
+/// Masks an email address, keeping the first character of the local part and the domain.
+///
+/// For example `jane.doe@example.org` becomes `j***@example.org`.
+pub fn mask_email(email_address: &str) -> String {
+    match email_address.split_once('@') {
+        Some((local_part, domain)) => match local_part.chars().next() {
+            Some(first) => format!("{}***@{}", first, domain),
+            None => format!("***@{}", domain),
+        },
+        None => REDACTED.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mask_email_keeps_first_character_and_domain() {
+        assert_eq!(mask_email("jane.doe@example.org"), "j***@example.org");
+    }
+
+    #[test]
+    fn mask_email_redacts_invalid_address() {
+        assert_eq!(mask_email("not an email address"), REDACTED);
+    }
+}
@@ -0,0 +1,369 @@
+//! This module sends the reminders users set on their tasks.
+//!
+//! ## Scheduling
+//! A reminder fires either a number of minutes before the task is due, or at a time of day on the date the task is
+//! due, in the time zone of the user. The database computes when each reminder fires whenever the deadline, the
+//! reminder or the time zone changes, see [`db::insert_reminder`]. That leaves the [`ReminderScheduler`] with a
+//! simple question: which reminders are due now?
+//!
+//! Reminders that become due during the quiet hours of the user are postponed until the quiet hours end. The quiet
+//! hours are checked when the reminder fires, so changing them takes effect right away.
+//!
+//! ## Delivery
+//! A reminder reaches the user through one or more channels: an email, a `task.reminder` event for their webhooks, or
+//! a notification in their in-app inbox. The scheduler doesn't deliver the reminder itself. It marks the reminder as
+//! sent and queues a [`SendReminder`] job for every channel in the same transaction, so the jobs run once the
+//! reminder is marked and each channel is retried on its own when it fails.
+//!
+//! Every instance can run the scheduler. Marking a reminder as sent only succeeds for the first instance, see
+//! [`db::mark_reminder_sent`], and the jobs are safe to repeat: the in-app notification and the webhook event carry a
+//! key derived from the reminder and the deadline, so a reminder is delivered once per deadline.
+
+use std::time::Duration;
+
+use chrono::{NaiveDateTime, NaiveTime, TimeZone};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tracing::{error, info, instrument};
+
+use crate::{
+    config::RemindersConfig,
+    db,
+    entity::{DueReminder, JobKind, NotificationKind, ReminderChannel, TaskEventType},
+    error::{AppError, Result},
+    jobs::{JobContext, JobPayload},
+    mail::Email,
+    monitoring,
+    shutdown::Shutdown,
+    webhooks::PublishWebhooks,
+};
+
+/// Queues the reminders that are due.
+#[derive(Debug)]
+pub struct ReminderScheduler {
+    config: RemindersConfig,
+}
+
+impl ReminderScheduler {
+    /// Creates a new scheduler with the given configuration.
+    pub fn new(config: RemindersConfig) -> Self {
+        Self { config }
+    }
+
+    /// Checks for reminders that are due at the configured interval until the application shuts down.
+    pub async fn run(&self, pool: &PgPool, shutdown: &Shutdown) {
+        info!(
+            "Scheduling reminders every {} seconds",
+            self.config.interval
+        );
+
+        let interval = Duration::from_secs(self.config.interval);
+
+        loop {
+            if let Err(err) = self.send_due(pool).await {
+                error!("Failed to send reminders: {}", err);
+            }
+
+            tokio::select! {
+                biased;
+                _ = shutdown.started() => break,
+                _ = tokio::time::sleep(interval) => {}
+            }
+        }
+
+        info!("Stopped scheduling reminders");
+    }
+
+    /// Queues the deliveries of the reminders that are due now, or postpones them when the user has quiet hours.
+    ///
+    /// This returns the number of reminders this instance sent. Reminders that another instance sent first aren't
+    /// counted.
+    pub async fn send_due(&self, pool: &PgPool) -> Result<usize> {
+        let now = chrono::Utc::now().naive_utc();
+        let reminders = db::list_due_reminders(pool, now, self.config.batch.into()).await?;
+
+        let mut sent = 0;
+
+        for reminder in reminders {
+            if let Some(until) = quiet_until(&reminder, now) {
+                db::postpone_reminder(pool, &reminder, until).await?;
+                monitoring::record_reminder("postponed");
+                continue;
+            }
+
+            let jobs: Vec<SendReminder> = reminder
+                .channels
+                .iter()
+                .map(|&channel| SendReminder::new(&reminder, channel))
+                .collect();
+
+            if db::mark_reminder_sent(pool, &reminder, &jobs).await? {
+                monitoring::record_reminder("sent");
+                sent += 1;
+            }
+        }
+
+        Ok(sent)
+    }
+}
+
+/// Returns when the quiet hours of the user of a reminder end, or `None` when the user isn't in their quiet hours.
+fn quiet_until(reminder: &DueReminder, now: NaiveDateTime) -> Option<NaiveDateTime> {
+    let (start, end) = reminder.quiet_hours_start.zip(reminder.quiet_hours_end)?;
+    let time_zone = parse_time_zone(&reminder.time_zone).unwrap_or(Tz::UTC);
+
+    end_of_quiet_hours(now, time_zone, start, end)
+}
+
+/// Returns the end of the quiet hours in UTC when the given moment falls inside them, or `None` otherwise.
+///
+/// The quiet hours are local times in the given time zone. When the start is after the end, the quiet hours wrap
+/// around midnight, like from 22:00 until 07:00.
+pub fn end_of_quiet_hours(
+    now: NaiveDateTime,
+    time_zone: Tz,
+    start: NaiveTime,
+    end: NaiveTime,
+) -> Option<NaiveDateTime> {
+    let local = time_zone.from_utc_datetime(&now).naive_local();
+    let time = local.time();
+
+    let ends_today = if start < end {
+        if time < start || time >= end {
+            return None;
+        }
+
+        true
+    } else {
+        if time < start && time >= end {
+            return None;
+        }
+
+        time < end
+    };
+
+    let date = if ends_today {
+        local.date()
+    } else {
+        local.date().succ_opt()?
+    };
+
+    // The end may fall in the gap when the clocks move forward, in which case the quiet hours end an hour later.
+    let local_end = date.and_time(end);
+
+    time_zone
+        .from_local_datetime(&local_end)
+        .earliest()
+        .or_else(|| {
+            time_zone
+                .from_local_datetime(&(local_end + chrono::Duration::hours(1)))
+                .earliest()
+        })
+        .map(|end| end.naive_utc())
+}
+
+/// Parses the name of an IANA time zone, like `Europe/Amsterdam`.
+pub fn parse_time_zone(name: &str) -> Result<Tz> {
+    name.parse::<Tz>().map_err(|_| AppError::InvalidTimeZone)
+}
+
+/// The payload of the background job that delivers a reminder through one of its channels.
+#[derive(Serialize, Deserialize)]
+pub struct SendReminder {
+    /// The reminder that fired.
+    pub reminder_id: i32,
+
+    /// The user to remind.
+    pub user_id: i32,
+
+    /// The task to remind the user of.
+    pub task_id: i32,
+
+    /// The title of the task.
+    pub title: String,
+
+    /// The deadline of the task in UTC.
+    pub due_at: NaiveDateTime,
+
+    /// The time zone of the user, to show the deadline in.
+    pub time_zone: String,
+
+    /// The channel to deliver the reminder through.
+    pub channel: ReminderChannel,
+}
+
+impl SendReminder {
+    /// Prepares the delivery of a reminder that is due through one of its channels.
+    pub fn new(reminder: &DueReminder, channel: ReminderChannel) -> Self {
+        Self {
+            reminder_id: reminder.id,
+            user_id: reminder.user_id,
+            task_id: reminder.task_id,
+            title: reminder.title.clone(),
+            due_at: reminder.due_at,
+            time_zone: reminder.time_zone.clone(),
+            channel,
+        }
+    }
+
+    /// Returns a key that is the same every time this reminder fires for the same deadline.
+    fn dedup_key(&self) -> String {
+        format!(
+            "reminder_{}_{}",
+            self.reminder_id,
+            self.due_at.and_utc().timestamp()
+        )
+    }
+
+    /// Returns the deadline in the time zone of the user, like `2024-06-01 09:00 (Europe/Amsterdam)`.
+    fn local_due_at(&self) -> String {
+        let time_zone = parse_time_zone(&self.time_zone).unwrap_or(Tz::UTC);
+        let due_at = time_zone.from_utc_datetime(&self.due_at);
+
+        format!("{} ({})", due_at.format("%Y-%m-%d %H:%M"), time_zone.name())
+    }
+
+    /// Sends the reminder to the verified email address of the user. Users without one don't get an email.
+    async fn send_email(&self, context: &JobContext) -> Result<()> {
+        let user = db::get_user_by_id(&context.pool, self.user_id).await?;
+
+        if !user.email_verified {
+            info!(
+                user_id = self.user_id,
+                "Skipping the reminder email, the email address isn't verified"
+            );
+            return Ok(());
+        }
+
+        let email = Email {
+            to: user.email_address,
+            subject: format!("Reminder: {}", self.title),
+            body: format!(
+                "This is a reminder that your task \"{}\" is due at {}.",
+                self.title,
+                self.local_due_at()
+            ),
+        };
+
+        context.mailer.send(&email).await?;
+
+        Ok(())
+    }
+
+    /// Queues a `task.reminder` event for the webhooks of the user.
+    async fn publish_event(&self, context: &JobContext) -> Result<()> {
+        let data = serde_json::json!({
+            "reminder_id": self.reminder_id,
+            "task_id": self.task_id,
+            "title": self.title,
+            "due_at": self.due_at,
+        });
+
+        PublishWebhooks::with_event_id(
+            format!("evt_{}", self.dedup_key()),
+            self.user_id,
+            TaskEventType::TaskReminder,
+            &data,
+        )
+        .queue_deliveries(&context.pool)
+        .await
+    }
+
+    /// Adds a notification to the in-app inbox of the user.
+    async fn notify(&self, context: &JobContext) -> Result<()> {
+        db::insert_notification(
+            &context.pool,
+            self.user_id,
+            Some(self.task_id),
+            NotificationKind::TaskReminder,
+            &format!("Reminder: {}", self.title),
+            &format!("Your task is due at {}.", self.local_due_at()),
+            &self.dedup_key(),
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+impl JobPayload for SendReminder {
+    const KIND: JobKind = JobKind::SendReminder;
+
+    #[instrument(skip_all, fields(reminder_id = self.reminder_id, channel = self.channel.as_str()))]
+    async fn perform(self, context: &JobContext) -> Result<()> {
+        match self.channel {
+            ReminderChannel::Email => self.send_email(context).await,
+            ReminderChannel::Webhook => self.publish_event(context).await,
+            ReminderChannel::InApp => self.notify(context).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn utc(hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, 15)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    fn time(hour: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn outside_quiet_hours_is_not_postponed() {
+        assert_eq!(
+            end_of_quiet_hours(utc(12, 0), Tz::UTC, time(22), time(7)),
+            None
+        );
+        assert_eq!(
+            end_of_quiet_hours(utc(7, 0), Tz::UTC, time(22), time(7)),
+            None
+        );
+        assert_eq!(
+            end_of_quiet_hours(utc(18, 0), Tz::UTC, time(12), time(14)),
+            None
+        );
+    }
+
+    #[test]
+    fn quiet_hours_wrap_around_midnight() {
+        assert_eq!(
+            end_of_quiet_hours(utc(23, 30), Tz::UTC, time(22), time(7)),
+            Some(utc(7, 0) + chrono::Duration::days(1))
+        );
+        assert_eq!(
+            end_of_quiet_hours(utc(3, 0), Tz::UTC, time(22), time(7)),
+            Some(utc(7, 0))
+        );
+    }
+
+    #[test]
+    fn quiet_hours_are_local_to_the_user() {
+        // 22:30 UTC is 23:30 in Amsterdam in winter, so the quiet hours end at 07:00 local, 06:00 UTC.
+        assert_eq!(
+            end_of_quiet_hours(utc(22, 30), Tz::Europe__Amsterdam, time(23), time(7)),
+            Some(utc(6, 0) + chrono::Duration::days(1))
+        );
+        assert_eq!(
+            end_of_quiet_hours(utc(22, 30), Tz::UTC, time(23), time(7)),
+            None
+        );
+    }
+
+    #[test]
+    fn unknown_time_zone_is_rejected() {
+        assert!(parse_time_zone("Europe/Amsterdam").is_ok());
+        assert!(matches!(
+            parse_time_zone("Mars/Olympus_Mons"),
+            Err(AppError::InvalidTimeZone)
+        ));
+    }
+}
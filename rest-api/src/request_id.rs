@@ -0,0 +1,128 @@
+//! This module contains the middleware that gives every request an ID.
+//!
+//! Clients and proxies can send their own ID in the `X-Request-Id` header, for example to correlate the logs of
+//! multiple services. When there's no valid ID in the request, we generate one. The ID is returned in the
+//! `X-Request-Id` header of the response, is included in the request span so it's on every log line, and is included in
+//! the body of error responses. When a user reports an error, the ID leads you straight to the relevant logs.
+//!
+//! The ID is stored in a task-local variable while the request is handled, so code that doesn't have access to the
+//! request, like the [`axum::response::IntoResponse`] implementation of [`crate::error::AppError`], can still read it.
+
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+
+/// The name of the header that carries the request ID.
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// The maximum length of a request ID we accept from a client.
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Assigns an ID to the request and adds it to the response.
+///
+/// This middleware must be the outermost layer of the router, so the ID is available to all other middleware.
+pub async fn propagate_request_id(mut request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| is_valid_request_id(value))
+        .map(str::to_string)
+        .unwrap_or_else(generate_request_id);
+
+    // The ID is always valid as a header value, because it's either validated or generated by us.
+    let header_value = HeaderValue::from_str(&request_id).expect("Invalid request ID.");
+    request
+        .headers_mut()
+        .insert(REQUEST_ID_HEADER, header_value.clone());
+
+    let mut response = REQUEST_ID.scope(request_id, next.run(request)).await;
+    response
+        .headers_mut()
+        .insert(REQUEST_ID_HEADER, header_value);
+
+    response
+}
+
+/// Returns the ID of the request that is currently being handled.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|request_id| request_id.clone()).ok()
+}
+
+/// Checks that the ID from the client is short and only contains safe characters, so it can't be used to inject
+/// content into the logs.
+fn is_valid_request_id(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= MAX_REQUEST_ID_LENGTH
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+fn generate_request_id() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn create_router() -> Router {
+        Router::new()
+            .route(
+                "/",
+                get(|| async { current_request_id().unwrap_or_default() }),
+            )
+            .layer(middleware::from_fn(propagate_request_id))
+    }
+
+    #[tokio::test]
+    async fn request_id_from_client_is_propagated() {
+        let response = create_router()
+            .oneshot(
+                Request::builder()
+                    .uri("/")
+                    .header("X-Request-Id", "abc-123")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.headers()[&REQUEST_ID_HEADER], "abc-123");
+    }
+
+    #[tokio::test]
+    async fn invalid_request_id_is_replaced() {
+        let response = create_router()
+            .oneshot(
+                Request::builder()
+                    .uri("/")
+                    .header("X-Request-Id", "abc 123; rm -rf")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let request_id = response.headers()[&REQUEST_ID_HEADER].to_str().unwrap();
+
+        assert_eq!(request_id.len(), 32);
+        assert_ne!(request_id, "abc 123; rm -rf");
+    }
+
+    #[test]
+    fn request_id_is_missing_outside_of_request() {
+        assert!(current_request_id().is_none());
+    }
+}
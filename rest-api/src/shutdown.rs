@@ -0,0 +1,103 @@
+//! This module coordinates the graceful shutdown of the application.
+//!
+//! When the application receives a shutdown signal, we don't want to drop the work that is still in progress. The
+//! server stops accepting new connections and finishes the requests it's handling, but background jobs need to know
+//! that they should stop as well. That's what the [`Shutdown`] type is for.
+//!
+//! Background jobs are started with [`Shutdown::spawn`], so we can wait for them before closing the database
+//! connection pool. A job should stop picking up new work once [`Shutdown::started`] resolves, for example by using
+//! it in a [`tokio::select!`] next to the work it's waiting for.
+
+use std::future::Future;
+
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+
+/// Keeps track of the shutdown of the application and the background jobs that must finish before it stops.
+#[derive(Debug, Default)]
+pub struct Shutdown {
+    token: CancellationToken,
+    jobs: TaskTracker,
+}
+
+impl Shutdown {
+    /// Creates a new shutdown coordinator.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts the shutdown. Background jobs are notified and the readiness check starts failing.
+    pub fn begin(&self) {
+        self.token.cancel();
+        self.jobs.close();
+    }
+
+    /// Returns whether the shutdown has started.
+    pub fn is_started(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// Waits until the shutdown has started.
+    pub async fn started(&self) {
+        self.token.cancelled().await
+    }
+
+    /// Starts a background job that we wait for when the application shuts down.
+    pub fn spawn<F>(&self, job: F) -> JoinHandle<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.jobs.spawn(job)
+    }
+
+    /// Waits until all background jobs have finished.
+    ///
+    /// This only completes after [`Shutdown::begin`] was called, because new jobs can be started until then.
+    pub async fn jobs_finished(&self) {
+        self.jobs.wait().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn background_jobs_stop_when_shutdown_begins() {
+        let shutdown = Arc::new(Shutdown::new());
+        let job_shutdown = shutdown.clone();
+
+        shutdown.spawn(async move {
+            job_shutdown.started().await;
+        });
+
+        assert!(!shutdown.is_started());
+        shutdown.begin();
+        assert!(shutdown.is_started());
+
+        tokio::time::timeout(Duration::from_secs(1), shutdown.jobs_finished())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn jobs_are_awaited_after_shutdown_begins() {
+        let shutdown = Shutdown::new();
+
+        shutdown.spawn(tokio::time::sleep(Duration::from_millis(50)));
+        shutdown.begin();
+
+        let still_running =
+            tokio::time::timeout(Duration::from_millis(1), shutdown.jobs_finished()).await;
+
+        assert!(still_running.is_err());
+
+        tokio::time::timeout(Duration::from_secs(1), shutdown.jobs_finished())
+            .await
+            .unwrap();
+    }
+}
@@ -3,16 +3,76 @@
 //! The application state is wrapped in a [`Arc`] object to allow it to be shared across multiple threads.
 //!
 //! The application state is created in the [`AppState::new`] function. This function takes the database connection pool
-//! as an argument and returns an [`Arc`] object containing the application state.
+//! and the application configuration as arguments and returns an [`Arc`] object containing the application state.
 use std::sync::Arc;
 
 use sqlx::PgPool;
 
+use crate::{
+    attachments::DownloadSigner,
+    auth::{oidc::OidcClient, token::TokenIssuer},
+    blobs::{self, BlobStore},
+    config::{AdminConfig, AppConfig, AttachmentsConfig, HttpConfig, QuotaConfig},
+    error::Result,
+    events::EventHub,
+    jobs::{JobContext, JobRunner},
+    mail::{self, Mailer},
+    ratelimit::RateLimiter,
+    reminders::ReminderScheduler,
+    shutdown::Shutdown,
+    webhooks::WebhookDispatcher,
+};
+
 /// Contains information that must be shared across multiple web request handlers.
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct AppState {
     /// The database connection pool to use for running database queries and updates.
     pub connection_pool: PgPool,
+
+    /// The token issuer to use for issuing and validating bearer tokens.
+    pub token_issuer: TokenIssuer,
+
+    /// The client for the OpenID Connect identity provider, if one is configured.
+    pub oidc_client: Option<OidcClient>,
+
+    /// The mailer to use for sending emails to users.
+    pub mailer: Arc<dyn Mailer>,
+
+    /// The store that keeps the contents of attachments.
+    pub blobs: Arc<dyn BlobStore>,
+
+    /// The limits on uploads and the lifetime of download URLs.
+    pub attachments: AttachmentsConfig,
+
+    /// Signs the URLs of the download endpoint for attachments.
+    pub download_signer: DownloadSigner,
+
+    /// The rate limiter that keeps track of the number of requests per client.
+    pub rate_limiter: RateLimiter,
+
+    /// The limits on the amount of data a single user can store.
+    pub quota: QuotaConfig,
+
+    /// The settings for the middleware that applies to every request, like CORS and the body size limit.
+    pub http: HttpConfig,
+
+    /// Sends the events to the webhooks users subscribed to.
+    pub webhooks: WebhookDispatcher,
+
+    /// Passes the changes to tasks on to the clients that follow them live.
+    pub events: EventHub,
+
+    /// Runs the background jobs from the outbox.
+    pub jobs: JobRunner,
+
+    /// Queues the reminders of tasks when they're due.
+    pub reminders: ReminderScheduler,
+
+    /// The settings for the admin endpoints.
+    pub admin: AdminConfig,
+
+    /// Coordinates the shutdown of the server and the background jobs.
+    pub shutdown: Shutdown,
 }
 
 impl AppState {
@@ -20,8 +80,47 @@ impl AppState {
     ///
     /// This method should only be called once per application. We assume that the object has a `'static` lifetime
     /// scope. This is because the application state is shared across multiple threads and needs to be `'static`.
-    pub fn new(connection_pool: PgPool) -> Arc<AppState> {
-        let app_state = AppState { connection_pool };
-        Arc::new(app_state)
+    ///
+    /// This method returns an error when one of the services in the state can't be created from the configuration.
+    pub fn new(connection_pool: PgPool, app_config: &AppConfig) -> Result<Arc<AppState>> {
+        let app_state = AppState {
+            connection_pool,
+            token_issuer: TokenIssuer::new(&app_config.auth),
+            oidc_client: app_config.oidc.clone().map(OidcClient::new),
+            mailer: Arc::from(mail::create_mailer(&app_config.mail)?),
+            blobs: Arc::from(blobs::create_blob_store(&app_config.attachments)?),
+            attachments: app_config.attachments.clone(),
+            download_signer: DownloadSigner::new(app_config.attachments.secret.as_deref()),
+            rate_limiter: RateLimiter::new(app_config.ratelimit.clone()),
+            quota: app_config.quota.clone(),
+            http: app_config.http.clone(),
+            webhooks: WebhookDispatcher::new(app_config.webhooks.clone()),
+            events: EventHub::new(app_config.events.clone()),
+            jobs: JobRunner::new(app_config.jobs.clone()),
+            reminders: ReminderScheduler::new(app_config.reminders.clone()),
+            admin: app_config.admin.clone(),
+            shutdown: Shutdown::new(),
+        };
+
+        Ok(Arc::new(app_state))
+    }
+
+    /// Returns the services the background jobs can use.
+    pub fn job_context(&self) -> JobContext {
+        JobContext::new(
+            self.connection_pool.clone(),
+            self.mailer.clone(),
+            self.blobs.clone(),
+        )
+    }
+
+    /// Marks the application as shutting down, so the readiness check starts failing and background jobs stop.
+    pub fn begin_shutdown(&self) {
+        self.shutdown.begin();
+    }
+
+    /// Returns whether the application is shutting down.
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutdown.is_started()
     }
 }
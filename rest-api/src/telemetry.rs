@@ -0,0 +1,83 @@
+//! This module contains the logic to export traces to an OpenTelemetry collector.
+//!
+//! The application uses the [`tracing`] crate for logging. The [`tracing_opentelemetry`] layer turns the spans we
+//! create with `#[instrument]` into OpenTelemetry spans, which are exported to a collector using the OTLP protocol.
+//! Log events are exported as events on the span they were written in.
+//!
+//! When a request comes in with a W3C `traceparent` header, we continue the trace of the caller. This way you can
+//! follow a request from the client through the API down to the database queries.
+
+use opentelemetry::{propagation::TextMapPropagator, trace::TraceError, KeyValue};
+use opentelemetry_http::HeaderExtractor;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator, runtime, trace::TracerProvider, Resource,
+};
+use tracing::{info_span, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::config::{OtlpConfig, OtlpProtocol};
+use crate::request_id::REQUEST_ID_HEADER;
+
+/// The name of the service in the exported traces.
+pub const SERVICE_NAME: &str = "todo-api";
+
+/// Creates a tracer provider that exports spans to the configured collector.
+///
+/// Spans are exported in batches in the background. Make sure to call [`TracerProvider::shutdown`] before the
+/// application exits, otherwise the last batch of spans is lost.
+pub fn create_tracer_provider(config: &OtlpConfig) -> Result<TracerProvider, TraceError> {
+    let endpoint = config.endpoint.trim_end_matches('/');
+
+    let exporter = match config.protocol {
+        OtlpProtocol::Grpc => SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(endpoint)
+            .build()?,
+        // The HTTP exporter expects the full URL of the traces endpoint, while the gRPC exporter only needs the base.
+        OtlpProtocol::Http => SpanExporter::builder()
+            .with_http()
+            .with_endpoint(format!("{}/v1/traces", endpoint))
+            .build()?,
+    };
+
+    let tracer_provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new(vec![KeyValue::new(
+            "service.name",
+            SERVICE_NAME,
+        )]))
+        .build();
+
+    Ok(tracer_provider)
+}
+
+/// Creates the span for an incoming request.
+///
+/// This is used with [`tower_http::trace::TraceLayer::make_span_with`]. When the request carries a `traceparent`
+/// header, the span becomes a child of the span of the caller. The span carries the request ID that is assigned by
+/// [`crate::request_id::propagate_request_id`], so it's included in every log line written while handling the request.
+///
+/// We only record the path of the request. The query string can contain secrets, like the authorization code the
+/// identity provider sends to the OpenID Connect callback.
+pub fn make_request_span<B>(request: &axum::http::Request<B>) -> Span {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    let span = info_span!(
+        "request",
+        method = %request.method(),
+        path = %request.uri().path(),
+        version = ?request.version(),
+        request_id = %request_id,
+    );
+
+    // Incoming requests carry the trace context of the caller in the W3C `traceparent` header.
+    let parent_context = TraceContextPropagator::new().extract(&HeaderExtractor(request.headers()));
+    span.set_parent(parent_context);
+
+    span
+}
@@ -0,0 +1,453 @@
+//! This module contains the logic to serve the API over HTTPS.
+//!
+//! Usually the API runs behind an ingress that terminates TLS, but on-premises installations often don't have one.
+//! For those installations the server can terminate TLS itself using [`rustls`]. We negotiate HTTP/2 with clients that
+//! support it through ALPN and fall back to HTTP/1.1 for the others.
+//!
+//! Certificates are short-lived these days, so we don't want to restart the server every time one is renewed. The
+//! [`ReloadableTlsConfig`] keeps the current TLS configuration and replaces it when the certificate files change. New
+//! connections use the new certificate, while existing connections keep using the old one until they're closed.
+//!
+//! When a certificate authority for clients is configured, clients can present a certificate to authenticate. This is
+//! optional, so clients without a certificate can still use an API key or bearer token. We identify the user by the
+//! email address in the certificate. Please check out [`crate::auth`] for more information.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::future::Future;
+use std::io::{self, BufReader};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, uri::Authority, StatusCode, Uri},
+    response::{IntoResponse, Redirect, Response},
+    Router,
+};
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{VerifierBuilderError, WebPkiClientVerifier};
+use rustls::{RootCertStore, ServerConfig};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tower::Service;
+use tracing::{debug, info, warn};
+use x509_parser::extensions::GeneralName;
+
+use crate::config::TlsConfig;
+use crate::shutdown::Shutdown;
+
+/// How long a client can take to complete the TLS handshake before we close the connection.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The protocols we offer to clients through ALPN, in order of preference.
+const ALPN_PROTOCOLS: &[&[u8]] = &[b"h2", b"http/1.1"];
+
+/// The different types of errors that can occur while loading the TLS configuration.
+#[derive(Debug)]
+pub enum TlsError {
+    /// One of the files couldn't be read.
+    Io(String, io::Error),
+
+    /// The certificate file doesn't contain any certificates.
+    NoCertificates(String),
+
+    /// The key file doesn't contain a private key.
+    NoPrivateKey(String),
+
+    /// The certificates of the client certificate authorities can't be used to verify clients.
+    InvalidClientAuthority(VerifierBuilderError),
+
+    /// The certificate and key can't be used together, or one of them isn't supported.
+    Rustls(rustls::Error),
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TlsError::Io(path, err) => write!(f, "Failed to read `{}`: {}", path, err),
+            TlsError::NoCertificates(path) => {
+                write!(f, "`{}` doesn't contain a certificate.", path)
+            }
+            TlsError::NoPrivateKey(path) => write!(f, "`{}` doesn't contain a private key.", path),
+            TlsError::InvalidClientAuthority(err) => {
+                write!(f, "The client certificate authorities are invalid: {}", err)
+            }
+            TlsError::Rustls(err) => write!(f, "The certificate or key is invalid: {}", err),
+        }
+    }
+}
+
+impl From<VerifierBuilderError> for TlsError {
+    fn from(value: VerifierBuilderError) -> Self {
+        TlsError::InvalidClientAuthority(value)
+    }
+}
+
+impl From<rustls::Error> for TlsError {
+    fn from(value: rustls::Error) -> Self {
+        TlsError::Rustls(value)
+    }
+}
+
+/// The identity of a client that authenticated with a certificate.
+///
+/// This is added to the extensions of every request on a connection with a valid client certificate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCertificate {
+    /// The email address from the subject alternative name or the subject of the certificate.
+    pub email: String,
+}
+
+impl ClientCertificate {
+    /// Reads the identity of the client from a DER encoded certificate.
+    ///
+    /// We prefer the email address in the subject alternative name, which is where modern certificate authorities put
+    /// it. Older certificates have it in the subject instead. Certificates without an email address can't be used to
+    /// identify a user, so we ignore them.
+    pub fn from_der(der: &[u8]) -> Option<ClientCertificate> {
+        let (_, certificate) = x509_parser::parse_x509_certificate(der).ok()?;
+
+        let alternative_email = certificate
+            .subject_alternative_name()
+            .ok()
+            .flatten()
+            .and_then(|extension| {
+                extension
+                    .value
+                    .general_names
+                    .iter()
+                    .find_map(|name| match name {
+                        GeneralName::RFC822Name(email) => Some(email.to_string()),
+                        _ => None,
+                    })
+            });
+
+        let email = alternative_email.or_else(|| {
+            certificate
+                .subject()
+                .iter_email()
+                .find_map(|attribute| attribute.as_str().ok())
+                .map(str::to_string)
+        })?;
+
+        Some(ClientCertificate { email })
+    }
+}
+
+/// Keeps the current TLS configuration and replaces it when the certificate files change.
+#[derive(Debug)]
+pub struct ReloadableTlsConfig {
+    config: TlsConfig,
+    current: RwLock<Arc<ServerConfig>>,
+    modified: Mutex<Vec<Option<SystemTime>>>,
+}
+
+impl ReloadableTlsConfig {
+    /// Loads the certificate and key from the configured files.
+    pub fn load(config: &TlsConfig) -> Result<ReloadableTlsConfig, TlsError> {
+        let server_config = build_server_config(config)?;
+
+        Ok(ReloadableTlsConfig {
+            config: config.clone(),
+            current: RwLock::new(Arc::new(server_config)),
+            modified: Mutex::new(modification_times(config)),
+        })
+    }
+
+    /// Returns the TLS configuration for new connections.
+    pub fn current(&self) -> Arc<ServerConfig> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Loads the certificate and key again when one of the files changed since the last time we loaded them.
+    ///
+    /// Returns whether the configuration was replaced. When the new files are invalid, for example because we read
+    /// them while they were being written, we keep the current configuration and try again on the next check.
+    pub fn reload_if_changed(&self) -> Result<bool, TlsError> {
+        let modified = modification_times(&self.config);
+
+        if *self.modified.lock().unwrap() == modified {
+            return Ok(false);
+        }
+
+        let server_config = build_server_config(&self.config)?;
+
+        *self.current.write().unwrap() = Arc::new(server_config);
+        *self.modified.lock().unwrap() = modified;
+
+        Ok(true)
+    }
+
+    /// Checks the certificate files for changes until the application shuts down.
+    ///
+    /// Run this as a background job with [`Shutdown::spawn`].
+    pub async fn watch(&self, shutdown: &Shutdown) {
+        if self.config.reload == 0 {
+            return;
+        }
+
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.reload));
+
+        loop {
+            tokio::select! {
+                _ = shutdown.started() => break,
+                _ = interval.tick() => match self.reload_if_changed() {
+                    Ok(true) => info!("Reloaded the TLS certificate"),
+                    Ok(false) => {}
+                    Err(err) => warn!("Failed to reload the TLS certificate, keeping the current one: {}", err),
+                },
+            }
+        }
+    }
+}
+
+/// Builds the [`rustls`] configuration from the certificate files.
+fn build_server_config(config: &TlsConfig) -> Result<ServerConfig, TlsError> {
+    let certificates = read_certificates(&config.cert)?;
+    let private_key = read_private_key(&config.key)?;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+
+    let builder = match &config.ca {
+        Some(ca) => {
+            let mut roots = RootCertStore::empty();
+
+            for certificate in read_certificates(ca)? {
+                roots.add(certificate)?;
+            }
+
+            // Client certificates are optional, so clients can still authenticate with an API key or bearer token.
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .allow_unauthenticated()
+                .build()?;
+
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut server_config = builder.with_single_cert(certificates, private_key)?;
+    server_config.alpn_protocols = ALPN_PROTOCOLS
+        .iter()
+        .map(|protocol| protocol.to_vec())
+        .collect();
+
+    Ok(server_config)
+}
+
+fn read_certificates(path: &str) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    let file = File::open(path).map_err(|err| TlsError::Io(path.to_string(), err))?;
+
+    let certificates = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| TlsError::Io(path.to_string(), err))?;
+
+    if certificates.is_empty() {
+        return Err(TlsError::NoCertificates(path.to_string()));
+    }
+
+    Ok(certificates)
+}
+
+fn read_private_key(path: &str) -> Result<PrivateKeyDer<'static>, TlsError> {
+    let file = File::open(path).map_err(|err| TlsError::Io(path.to_string(), err))?;
+
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|err| TlsError::Io(path.to_string(), err))?
+        .ok_or_else(|| TlsError::NoPrivateKey(path.to_string()))
+}
+
+/// Returns the last modification times of the files in the TLS configuration.
+///
+/// Files that can't be read get `None`, so we notice when they appear again.
+fn modification_times(config: &TlsConfig) -> Vec<Option<SystemTime>> {
+    [Some(&config.cert), Some(&config.key), config.ca.as_ref()]
+        .into_iter()
+        .flatten()
+        .map(|path| {
+            fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        })
+        .collect()
+}
+
+/// Serves the router over TLS until the shutdown future completes.
+///
+/// This does the same as [`axum::serve`] with graceful shutdown, but performs a TLS handshake on every connection
+/// first. After the shutdown future completes, we stop accepting connections and wait for the open connections to
+/// finish their requests. Like [`axum::Router::into_make_service_with_connect_info`], we add the address of the client
+/// to every request, together with the client certificate when the client presented one.
+pub async fn serve<F>(
+    listener: TcpListener,
+    router: Router,
+    tls: Arc<ReloadableTlsConfig>,
+    shutdown: F,
+) -> io::Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let connections = TaskTracker::new();
+    let closing = CancellationToken::new();
+    let mut shutdown = std::pin::pin!(shutdown);
+
+    loop {
+        let (stream, remote_address) = tokio::select! {
+            result = listener.accept() => match result {
+                Ok(connection) => connection,
+                Err(err) => {
+                    // Errors like running out of file descriptors go away once other connections are closed.
+                    warn!("Failed to accept a connection: {}", err);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            },
+            _ = &mut shutdown => break,
+        };
+
+        let acceptor = TlsAcceptor::from(tls.current());
+        let router = router.clone();
+        let closing = closing.clone();
+
+        connections.spawn(async move {
+            let stream =
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => stream,
+                    Ok(Err(err)) => {
+                        debug!("TLS handshake with {} failed: {}", remote_address, err);
+                        return;
+                    }
+                    Err(_) => {
+                        debug!("TLS handshake with {} timed out", remote_address);
+                        return;
+                    }
+                };
+
+            let client_certificate = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|certificates| certificates.first())
+                .and_then(|certificate| ClientCertificate::from_der(certificate));
+
+            let service = hyper::service::service_fn(move |mut request: Request<Incoming>| {
+                request.extensions_mut().insert(ConnectInfo(remote_address));
+
+                if let Some(client_certificate) = &client_certificate {
+                    request.extensions_mut().insert(client_certificate.clone());
+                }
+
+                router.clone().call(request)
+            });
+
+            let builder = auto::Builder::new(TokioExecutor::new());
+            let connection = builder.serve_connection_with_upgrades(TokioIo::new(stream), service);
+            let mut connection = std::pin::pin!(connection);
+
+            // Once the server shuts down, we let the connection finish the current request and then close it.
+            let result = tokio::select! {
+                result = connection.as_mut() => result,
+                _ = closing.cancelled() => {
+                    connection.as_mut().graceful_shutdown();
+                    connection.await
+                }
+            };
+
+            if let Err(err) = result {
+                debug!("Connection with {} failed: {}", remote_address, err);
+            }
+        });
+    }
+
+    drop(listener);
+    closing.cancel();
+    connections.close();
+    connections.wait().await;
+
+    Ok(())
+}
+
+/// Creates the router for the plain HTTP listener that redirects every request to the HTTPS port.
+pub fn create_redirect_router(https_port: u16) -> Router {
+    Router::new()
+        .fallback(redirect_to_https)
+        .with_state(https_port)
+}
+
+/// Redirects a request to the same path on the HTTPS port.
+///
+/// We use a permanent redirect that keeps the method and body, so a `POST` is sent again as a `POST`.
+async fn redirect_to_https(State(https_port): State<u16>, request: Request) -> Response {
+    let host = request
+        .headers()
+        .get(header::HOST)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<Authority>().ok());
+
+    let Some(host) = host else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    let authority = match https_port {
+        443 => host.host().to_string(),
+        port => format!("{}:{}", host.host(), port),
+    };
+
+    let path = request
+        .uri()
+        .path_and_query()
+        .map(|path| path.as_str())
+        .unwrap_or("/");
+
+    match format!("https://{}{}", authority, path).parse::<Uri>() {
+        Ok(location) => Redirect::permanent(&location.to_string()).into_response(),
+        Err(_) => StatusCode::BAD_REQUEST.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    async fn redirect(https_port: u16, host: &str, uri: &str) -> Response {
+        create_redirect_router(https_port)
+            .oneshot(
+                Request::builder()
+                    .uri(uri)
+                    .header(header::HOST, host)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn redirect_keeps_path_and_query() {
+        let response = redirect(8443, "example.org:8080", "/v1/todos?page=2").await;
+
+        assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "https://example.org:8443/v1/todos?page=2"
+        );
+    }
+
+    #[tokio::test]
+    async fn redirect_to_default_port_leaves_out_port() {
+        let response = redirect(443, "example.org", "/").await;
+
+        assert_eq!(response.headers()[header::LOCATION], "https://example.org/");
+    }
+}
@@ -16,57 +16,327 @@
 //! To access the database, the handlers need access to the application state. The application state is a shared
 //! object that is obtained using the [`axum::extract::State`] extractor. The application state is a shared object
 //! that is created in the [`crate::state`] module. We use the [`Arc`] type to share the state across multiple threads.
+//!
+//! Operational endpoints that aren't part of the public API live in submodules, like the health checks in [`health`],
+//! the Prometheus metrics in [`metrics`] and the admin endpoints for the job queue in [`jobs`]. The endpoints to manage webhooks live in the `webhooks` submodule,
+//! the stream of task changes in the `events` submodule, the reminders of tasks in the `reminders` submodule and the
+//! in-app inbox in the `notifications` submodule. Projects live in the `projects` submodule, and sharing tasks and
+//! projects with other users in the `shares` submodule.
+//!
+//! Teams share their tasks and projects in workspaces, which are managed in the `workspaces` submodule. Who works on a
+//! task and who follows it is managed in the `assignments` submodule, the discussion about a task in the `comments`
+//! submodule and the files attached to it in the `attachments` submodule. The tasks a task waits for are managed in the
+//! `dependencies` submodule.
+//!
+//! Handlers for a single task or project check the access of the user with the [`crate::access`] module first, and
+//! then work on the item on behalf of its owner. That way the rules for sharing and workspaces live in one place.
+//!
+//! Every handler is annotated with [`utoipa::path`], which describes the route, its parameters and its responses. The
+//! forms and responses derive [`ToSchema`]. Together they make up the OpenAPI document in the [`openapi`] module, so
+//! when you add or change a route, you update its annotation right next to it.
+
+mod assignments;
+mod attachments;
+mod comments;
+mod dependencies;
+mod events;
+pub mod health;
+pub mod jobs;
+pub mod metrics;
+mod notifications;
+pub mod openapi;
+mod projects;
+mod reminders;
+mod shares;
+mod webhooks;
+mod workspaces;
 
+use std::fmt;
 use std::sync::Arc;
 
-use crate::entity::ApiKey;
+use crate::entity::{
+    ApiKey, Attachment, Comment, CommentRevision, Notification, OidcLoginRequest, PagedResult,
+    Project, Reminder, Role, Share, Task, User, UserChanges, UserTask, Webhook, Workspace,
+    WorkspaceInvitation, WorkspaceKey, WorkspaceRole,
+};
 use axum::{
     extract::{Path, Query, State},
-    http::StatusCode,
-    response::IntoResponse,
-    routing::{get, post},
+    http::{header, StatusCode},
+    middleware,
+    response::{IntoResponse, Redirect},
+    routing::{delete, get, patch, post, put},
     Json, Router,
 };
 use serde::{Deserialize, Serialize};
 use tower_http::trace::TraceLayer;
-use tracing::instrument;
+use tracing::{instrument, warn, Span};
+use utoipa::{IntoParams, ToSchema};
 
-use crate::{auth::AuthenticatedUser, db, error::AppError, state::AppState};
+use crate::{
+    access,
+    auth::{self, token::TokenType, AuthenticatedUser, VerifiedUser},
+    db,
+    error::{AppError, ErrorDetails},
+    layers,
+    mail::Email,
+    monitoring,
+    notifications::is_valid_handle,
+    ratelimit::{rate_limit, RouteGroup},
+    redact::REDACTED,
+    reminders::parse_time_zone,
+    request_id,
+    state::AppState,
+    telemetry,
+};
 
 /// Defines the querystring parameters for retrieving todos.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct Pagination {
+    /// The index of the page to retrieve.
     page: i32,
+
+    /// Only list the items in this workspace.
+    #[serde(default)]
+    workspace: Option<i32>,
+
+    /// Only list the tasks the user is assigned to.
+    #[serde(default)]
+    assigned_to_me: bool,
 }
 
 /// Defines the fields that can be used to create a new todo item.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, ToSchema)]
 struct CreateTodoForm {
     pub title: String,
     pub description: String,
+
+    /// The deadline of the task, if it has one.
+    #[serde(default)]
+    pub due_at: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// The workspace to create the task in. Without a workspace, the task is a personal task. Requests with an API
+    /// key of a workspace create their tasks in that workspace.
+    #[serde(default)]
+    pub workspace_id: Option<i32>,
+}
+
+impl fmt::Debug for CreateTodoForm {
+    /// Formats the form without the description, which may contain personal information.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CreateTodoForm")
+            .field("title", &self.title)
+            .field("description", &REDACTED)
+            .field("due_at", &self.due_at)
+            .field("workspace_id", &self.workspace_id)
+            .finish()
+    }
 }
 
 /// Defines the fields that can be updated in a todo item.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, ToSchema)]
 struct UpdateTodoForm {
     pub title: String,
     pub description: String,
     pub completed: bool,
+
+    /// The deadline of the task. Leaving it out removes the deadline.
+    #[serde(default)]
+    pub due_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl fmt::Debug for UpdateTodoForm {
+    /// Formats the form without the description, which may contain personal information.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UpdateTodoForm")
+            .field("title", &self.title)
+            .field("description", &REDACTED)
+            .field("completed", &self.completed)
+            .field("due_at", &self.due_at)
+            .finish()
+    }
 }
 
 /// Defines the fields that can be used to register a new user.
-#[derive(Deserialize, Debug)]
+///
+/// The password is optional. Users that set a password can use it to obtain a bearer token.
+#[derive(Deserialize, ToSchema)]
 struct RegisterUserForm {
     pub email_address: String,
+    pub password: Option<String>,
+}
+
+/// Defines the fields that can be used to verify the email address of a user.
+#[derive(Deserialize, ToSchema)]
+struct VerifyUserForm {
+    pub token: String,
+}
+
+/// Defines the fields of the user profile that can be changed.
+///
+/// All fields are optional, fields that are missing are left unchanged. Send `null` as the quiet hours to remove them.
+#[derive(Deserialize, ToSchema)]
+struct UpdateUserForm {
+    pub email_address: Option<String>,
+    pub password: Option<String>,
+
+    /// The current password of the user, which is required to change the email address or the password. Users that
+    /// log in with the identity provider and never set a password can leave it out.
+    pub current_password: Option<String>,
+
+    /// The handle other users mention the user with, like `jane` for `@jane`. Send `null` to remove it.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    #[schema(value_type = Option<String>, nullable)]
+    pub handle: Option<Option<String>>,
+
+    /// The IANA time zone reminders are scheduled in, like `Europe/Amsterdam`.
+    pub time_zone: Option<String>,
+
+    /// The local times between which no reminders are sent.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    #[schema(value_type = Option<QuietHours>, nullable)]
+    pub quiet_hours: Option<Option<QuietHours>>,
+}
+
+/// Defines the quiet hours of a user, as local times like `22:00` and `07:00`.
+///
+/// When the start is after the end, the quiet hours wrap around midnight.
+#[derive(Deserialize, ToSchema, Clone, Copy)]
+struct QuietHours {
+    #[schema(value_type = String, example = "22:00")]
+    pub start: chrono::NaiveTime,
+    #[schema(value_type = String, example = "07:00")]
+    pub end: chrono::NaiveTime,
+}
+
+/// Deserializes a field that is present into `Some`, so a field that is `null` can be told apart from a missing one.
+fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// Defines the fields that can be used to obtain a bearer token.
+///
+/// The `grant_type` field determines which credentials are expected in the request. We use the tagged enum
+/// representation of [`serde`] so that each grant type only accepts its own fields.
+#[derive(Deserialize, ToSchema)]
+#[serde(tag = "grant_type", rename_all = "snake_case")]
+enum TokenRequestForm {
+    /// Exchange an API key for a pair of tokens.
+    ApiKey { api_key: String },
+
+    /// Exchange an email address and password for a pair of tokens.
+    Password {
+        email_address: String,
+        password: String,
+    },
+
+    /// Exchange a refresh token for a new pair of tokens.
+    RefreshToken { refresh_token: String },
 }
 
 /// Defines the response structure for a user that has been created.
-#[derive(Serialize, Debug)]
+#[derive(Serialize, ToSchema)]
 struct UserCreatedResponse {
     /// The generated API key for the user.
     pub api_key: String,
 }
 
+/// Defines the structure of the export with all data we store about a user.
+#[derive(Serialize, ToSchema)]
+struct UserExportResponse {
+    /// The date and time the export was created.
+    pub exported_at: chrono::DateTime<chrono::Utc>,
+
+    /// The profile of the user.
+    pub user: User,
+
+    /// All tasks the user can access, including the tasks that are shared with them and the tasks in their
+    /// workspaces.
+    pub tasks: Vec<Task>,
+
+    /// All projects the user can access, with the role of the user.
+    pub projects: Vec<Project>,
+
+    /// The shares of the tasks and projects of the user, and the shares that give the user access to other items.
+    pub shares: Vec<Share>,
+
+    /// The workspaces the user is a member of, with their role.
+    pub workspaces: Vec<Workspace>,
+
+    /// The API keys the user created for their workspaces, without the keys themselves.
+    pub workspace_keys: Vec<WorkspaceKey>,
+
+    /// The open invitations to the email address of the user.
+    pub invitations: Vec<WorkspaceInvitation>,
+
+    /// The tasks the user is assigned to.
+    pub assignments: Vec<UserTask>,
+
+    /// The tasks the user watches.
+    pub watches: Vec<UserTask>,
+
+    /// The tasks that mention the user in their description.
+    pub mentions: Vec<UserTask>,
+
+    /// The comments the user wrote.
+    pub comments: Vec<Comment>,
+
+    /// The earlier versions of the comments the user wrote.
+    pub comment_revisions: Vec<CommentRevision>,
+
+    /// The files the user uploaded, without their contents.
+    pub attachments: Vec<Attachment>,
+
+    /// All webhooks of the user, without their secrets.
+    pub webhooks: Vec<Webhook>,
+
+    /// All reminders of the user.
+    pub reminders: Vec<Reminder>,
+
+    /// All notifications in the inbox of the user, read or not.
+    pub notifications: Vec<Notification>,
+}
+
+/// Defines the querystring parameters for updating a todo.
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct UpdateTodoQuery {
+    /// Complete the task even when it's blocked by tasks that aren't completed.
+    #[serde(default)]
+    force: bool,
+}
+
+/// Defines the querystring parameters the identity provider sends to the OpenID Connect callback.
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct OidcCallbackQuery {
+    /// The authorization code to exchange for an ID token.
+    pub code: String,
+
+    /// The state that identifies the login request.
+    pub state: String,
+}
+
+/// Defines the response structure for an issued pair of tokens.
+#[derive(Serialize, ToSchema)]
+struct TokenResponse {
+    /// The short-lived token to send in the `Authorization: Bearer <token>` header.
+    pub access_token: String,
+
+    /// The long-lived token that can be exchanged for a new pair of tokens.
+    pub refresh_token: String,
+
+    /// The type of the access token, this is always `Bearer`.
+    pub token_type: &'static str,
+
+    /// The number of seconds until the access token expires.
+    pub expires_in: i64,
+}
+
 /// Retrieves a list of todos from the database and renders them as a JSON response.
 ///
 /// The URL must include `?page=<number>` to specify which page to include. The page parameter is retrieved using the
@@ -78,13 +348,39 @@ struct UserCreatedResponse {
 ///
 /// In addition to the shared state, we also use the [`AuthenticatedUser`] extractor to obtain the user
 /// ID of the authenticated user. If this extractor fails, we automatically return a 401 Unauthorized response.
-#[instrument]
+#[utoipa::path(
+    get,
+    path = "/v1/todos",
+    tag = "tasks",
+    params(Pagination),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 200, description = "A page with tasks of the user and the tasks shared with them.", body = PagedResult<Task>),
+        (status = 401, description = "The credentials are invalid.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
 async fn list_tasks(
     State(app_state): State<Arc<AppState>>,
     Query(pagination): Query<Pagination>,
-    AuthenticatedUser { user_id }: AuthenticatedUser,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
 ) -> Result<impl IntoResponse, AppError> {
-    let result = db::list_tasks(&app_state.connection_pool, user_id, pagination.page, 10).await?;
+    let workspace_id = access::scope(workspace_id, pagination.workspace)?;
+
+    let result = db::list_tasks(
+        &app_state.connection_pool,
+        user_id,
+        workspace_id,
+        pagination.assigned_to_me,
+        pagination.page,
+        10,
+    )
+    .await?;
+
     Ok(Json(result))
 }
 
@@ -95,15 +391,39 @@ async fn list_tasks(
 ///
 /// This function uses the [`State`] extractor to obtain the shared application state. The application state contains the
 /// database connection pool that is used to retrieve the todo item.
-#[instrument]
+#[utoipa::path(
+    get,
+    path = "/v1/todos/{id}",
+    tag = "tasks",
+    params(("id" = i32, Path, description = "The ID of the task.")),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 200, description = "The task.", body = Task),
+        (status = 404, description = "The task doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state, id), fields(task_id = id))]
 async fn task_details(
     State(app_state): State<Arc<AppState>>,
     Path(id): Path<i32>,
-    AuthenticatedUser { user_id }: AuthenticatedUser,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
 ) -> Result<impl IntoResponse, AppError> {
-    let result = db::find_task(&app_state.connection_pool, user_id, id)
+    let access = access::task_access(
+        &app_state.connection_pool,
+        user_id,
+        workspace_id,
+        id,
+        Role::Viewer,
+    )
+    .await?;
+
+    let result = db::find_task(&app_state.connection_pool, access.owner_id, id)
         .await
-        .map(|todo| Json(todo))?;
+        .map(Json)?;
 
     Ok(result)
 }
@@ -113,22 +433,57 @@ async fn task_details(
 /// The request body must be a JSON object that can be deserialized to [`CreateTodoForm`].
 /// We're using [`serde`] to deserialize the JSON object into a [`CreateTodoForm`] struct.
 ///
+/// We use the [`VerifiedUser`] extractor here, so only users that have verified their email address can create todos.
+///
 /// This function uses the [`State`] extractor to obtain the shared application state. The application state contains the
 /// database connection pool that is used to retrieve the todo item.
-#[instrument]
+#[utoipa::path(
+    post,
+    path = "/v1/todos",
+    tag = "tasks",
+    request_body = CreateTodoForm,
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 201, description = "The task was created."),
+        (status = 403, description = "The email address isn't verified or the quota is exceeded.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state, form), fields(task_id))]
 async fn create_task(
     State(app_state): State<Arc<AppState>>,
-    AuthenticatedUser { user_id }: AuthenticatedUser,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
     Json(form): Json<CreateTodoForm>,
 ) -> Result<impl IntoResponse, AppError> {
-    db::insert_task(
+    let workspace_id = access::scope(workspace_id, form.workspace_id)?;
+
+    if let Some(workspace_id) = workspace_id {
+        access::workspace_access(
+            &app_state.connection_pool,
+            user_id,
+            None,
+            workspace_id,
+            WorkspaceRole::Member,
+        )
+        .await?;
+    }
+
+    let task_id = db::insert_task_within_quota(
         &app_state.connection_pool,
         user_id,
+        workspace_id,
         form.title.clone(),
         form.description.clone(),
+        form.due_at.map(|due_at| due_at.naive_utc()),
+        app_state.quota.tasks,
     )
     .await?;
 
+    Span::current().record("task_id", task_id);
+    monitoring::record_task_created();
+
     Ok((StatusCode::CREATED, ()))
 }
 
@@ -142,20 +497,50 @@ async fn create_task(
 ///
 /// This function uses the [`State`] extractor to obtain the shared application state. The application state contains the
 /// database connection pool that is used to retrieve the todo item.
-#[instrument]
+#[utoipa::path(
+    put,
+    path = "/v1/todos/{id}",
+    tag = "tasks",
+    params(("id" = i32, Path, description = "The ID of the task."), UpdateTodoQuery),
+    request_body = UpdateTodoForm,
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 202, description = "The task was updated."),
+        (status = 403, description = "The task is shared with the user as a viewer.", body = ErrorDetails),
+        (status = 404, description = "The task doesn't exist.", body = ErrorDetails),
+        (status = 409, description = "The task is blocked by tasks that aren't completed.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state, id, form), fields(task_id = id))]
 async fn update_task(
     State(app_state): State<Arc<AppState>>,
-    AuthenticatedUser { user_id }: AuthenticatedUser,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
     Path(id): Path<i32>,
+    Query(query): Query<UpdateTodoQuery>,
     Json(form): Json<UpdateTodoForm>,
 ) -> Result<impl IntoResponse, AppError> {
-    db::update_task(
+    let access = access::task_access(
         &app_state.connection_pool,
         user_id,
+        workspace_id,
+        id,
+        Role::Editor,
+    )
+    .await?;
+
+    db::update_task(
+        &app_state.connection_pool,
+        access.owner_id,
         id,
         form.title.clone(),
         form.description.clone(),
         form.completed,
+        form.due_at.map(|due_at| due_at.naive_utc()),
+        // A blocked task can only be completed on purpose, see the `dependencies` submodule.
+        query.force,
     )
     .await?;
 
@@ -169,32 +554,92 @@ async fn update_task(
 ///
 /// This function uses the [`State`] extractor to obtain the shared application state. The application state contains the
 /// database connection pool that is used to retrieve the todo item.
-#[instrument]
+#[utoipa::path(
+    delete,
+    path = "/v1/todos/{id}",
+    tag = "tasks",
+    params(("id" = i32, Path, description = "The ID of the task.")),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 204, description = "The task was removed."),
+        (status = 403, description = "Only the owner can remove the task.", body = ErrorDetails),
+        (status = 404, description = "The task doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state, id), fields(task_id = id))]
 async fn delete_todo(
     State(app_state): State<Arc<AppState>>,
-    AuthenticatedUser { user_id }: AuthenticatedUser,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
     Path(id): Path<i32>,
 ) -> Result<impl IntoResponse, AppError> {
-    db::delete_task(&app_state.connection_pool, user_id, id).await?;
+    let access = access::task_access(
+        &app_state.connection_pool,
+        user_id,
+        workspace_id,
+        id,
+        Role::Owner,
+    )
+    .await?;
+
+    db::delete_task(&app_state.connection_pool, access.owner_id, id).await?;
+
     Ok((StatusCode::NO_CONTENT, ()))
 }
 
 /// Register a new user with associated API key.
-#[instrument]
+///
+/// When the user provides a password, we store the hashed password so the user can obtain bearer tokens with it.
+///
+/// The new user starts out with an unverified email address. We send a verification token to the email address that
+/// the user can submit to the `/v1/users/verify` endpoint. Until then, the user can only read data.
+#[utoipa::path(
+    post,
+    path = "/v1/users/register",
+    tag = "users",
+    request_body = RegisterUserForm,
+    responses(
+        (status = 201, description = "The user was registered.", body = UserCreatedResponse),
+        (status = 400, description = "The email address is invalid.", body = ErrorDetails),
+        (status = 409, description = "The email address is already registered.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip_all, fields(user_id))]
 async fn register_user(
     State(app_state): State<Arc<AppState>>,
     Json(form): Json<RegisterUserForm>,
 ) -> Result<impl IntoResponse, AppError> {
+    if form.email_address.parse::<lettre::Address>().is_err() {
+        return Err(AppError::InvalidEmailAddress);
+    }
+
     // Generate a random hex string 30 characters long.
     let api_key = ApiKey::new();
 
-    db::insert_user(
+    let password_hash = form
+        .password
+        .as_deref()
+        .map(auth::hash_password)
+        .transpose()?;
+
+    let user_id = db::insert_user(
         &app_state.connection_pool,
         form.email_address.clone(),
         api_key.hash.clone(),
+        password_hash,
     )
     .await?;
 
+    Span::current().record("user_id", user_id);
+
+    // The user is already registered at this point, so we don't fail the request when the email can't be sent.
+    // The user can request a new verification email later.
+    if let Err(err) = send_verification_email(&app_state, user_id, &form.email_address).await {
+        warn!("Failed to send the verification email: {}", err);
+    }
+
     // Returns the API Key for the user. This is a sensitive piece of information and should be handled with care.
     // We're returning it here for the user to write it down. It will be gone afterwards.
     Ok((
@@ -205,20 +650,785 @@ async fn register_user(
     ))
 }
 
+/// Verifies the email address of a user with the token that was sent to the email address.
+///
+/// After verifying, users that authenticate with a bearer token need to refresh their token to get full access.
+#[utoipa::path(
+    post,
+    path = "/v1/users/verify",
+    tag = "users",
+    request_body = VerifyUserForm,
+    responses(
+        (status = 204, description = "The email address was verified."),
+        (status = 400, description = "The token is invalid or expired.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip_all, fields(user_id))]
+async fn verify_user(
+    State(app_state): State<Arc<AppState>>,
+    Json(form): Json<VerifyUserForm>,
+) -> Result<impl IntoResponse, AppError> {
+    let claims = app_state
+        .token_issuer
+        .validate(&form.token, TokenType::Verification)
+        .map_err(|_| AppError::InvalidVerificationToken)?;
+
+    let user_id = claims
+        .user_id()
+        .map_err(|_| AppError::InvalidVerificationToken)?;
+
+    Span::current().record("user_id", user_id);
+
+    let email_address = claims.email.ok_or(AppError::InvalidVerificationToken)?;
+
+    db::mark_email_verified(&app_state.connection_pool, user_id, &email_address)
+        .await
+        .map_err(|err| match err {
+            AppError::UserNotFound => AppError::InvalidVerificationToken,
+            err => err,
+        })?;
+
+    Ok((StatusCode::NO_CONTENT, ()))
+}
+
+/// Sends a new verification email to the authenticated user.
+///
+/// Nothing is sent when the user already verified their email address.
+#[utoipa::path(
+    post,
+    path = "/v1/users/verify/resend",
+    tag = "users",
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses((status = 202, description = "A new verification email is on its way."))
+)]
+#[instrument(skip(app_state))]
+async fn resend_verification(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+) -> Result<impl IntoResponse, AppError> {
+    let user = db::get_user_by_id(&app_state.connection_pool, user_id).await?;
+
+    if !user.email_verified {
+        send_verification_email(&app_state, user.id, &user.email_address).await?;
+    }
+
+    Ok((StatusCode::ACCEPTED, ()))
+}
+
+/// Sends an email with a verification token for the given email address.
+async fn send_verification_email(
+    app_state: &AppState,
+    user_id: i32,
+    email_address: &str,
+) -> Result<(), AppError> {
+    let token = app_state
+        .token_issuer
+        .issue_verification_token(user_id, email_address)?;
+
+    let email = Email {
+        to: email_address.to_string(),
+        subject: "Please verify your email address".to_string(),
+        body: format!(
+            "Welcome to the task management application!\n\n\
+             Please verify your email address by sending the following token to POST /v1/users/verify:\n\n{}\n",
+            token
+        ),
+    };
+
+    app_state.mailer.send(&email).await?;
+
+    Ok(())
+}
+
+/// Retrieves the profile of the authenticated user.
+#[utoipa::path(
+    get,
+    path = "/v1/users/me",
+    tag = "users",
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses((status = 200, description = "The profile of the user.", body = User))
+)]
+#[instrument(skip(app_state))]
+async fn current_user(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+) -> Result<impl IntoResponse, AppError> {
+    access::require_unscoped(workspace_id)?;
+
+    let user = db::get_user_by_id(&app_state.connection_pool, user_id).await?;
+    Ok(Json(user))
+}
+
+/// Updates the profile of the authenticated user.
+///
+/// When the user changes their email address, the new email address needs to be verified again. We send a new
+/// verification token to the new email address, and until the user submits it they can only read data.
+///
+/// Changing the email address or the password requires the current password, so that a leaked API key or token can't
+/// be used to take over the account. Users without a password, like users who sign in with the identity provider,
+/// can't change them here. All changes are checked before any of them is saved.
+#[utoipa::path(
+    patch,
+    path = "/v1/users/me",
+    tag = "users",
+    request_body = UpdateUserForm,
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 200, description = "The updated profile of the user.", body = User),
+        (status = 400, description = "The email address, handle, time zone or quiet hours are invalid.", body = ErrorDetails),
+        (status = 403, description = "The current password is missing or incorrect, or the user has no password.", body = ErrorDetails),
+        (status = 409, description = "The email address or handle is already taken.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state, form))]
+async fn update_current_user(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+    Json(form): Json<UpdateUserForm>,
+) -> Result<impl IntoResponse, AppError> {
+    access::require_unscoped(workspace_id)?;
+
+    let user = db::get_user_by_id(&app_state.connection_pool, user_id).await?;
+
+    // We check every field before we save anything, so a request with an invalid field doesn't change the profile.
+    if let Some(email_address) = form.email_address.as_deref() {
+        if email_address.parse::<lettre::Address>().is_err() {
+            return Err(AppError::InvalidEmailAddress);
+        }
+    }
+
+    let email_address = form
+        .email_address
+        .filter(|email_address| !user.email_address.eq_ignore_ascii_case(email_address));
+
+    if email_address.is_some() || form.password.is_some() {
+        // Without a password there's nothing to prove the request comes from the user rather than from a leaked key.
+        let password_hash = user
+            .password_hash
+            .as_deref()
+            .ok_or(AppError::PasswordNotSet)?;
+        let current_password = form.current_password.as_deref().unwrap_or_default();
+
+        if !auth::verify_password(current_password, password_hash) {
+            return Err(AppError::IncorrectPassword);
+        }
+    }
+
+    if form.handle.as_ref().is_some_and(|handle| {
+        handle
+            .as_deref()
+            .is_some_and(|handle| !is_valid_handle(handle))
+    }) {
+        return Err(AppError::InvalidHandle);
+    }
+
+    let schedule = if form.time_zone.is_some() || form.quiet_hours.is_some() {
+        let time_zone = form.time_zone.unwrap_or(user.time_zone);
+        parse_time_zone(&time_zone)?;
+
+        let quiet_hours = match form.quiet_hours {
+            Some(quiet_hours) => quiet_hours.map(|hours| (hours.start, hours.end)),
+            None => user.quiet_hours_start.zip(user.quiet_hours_end),
+        };
+
+        if matches!(quiet_hours, Some((start, end)) if start == end) {
+            return Err(AppError::InvalidQuietHours);
+        }
+
+        Some((time_zone, quiet_hours))
+    } else {
+        None
+    };
+
+    let changes = UserChanges {
+        email_address: email_address.clone(),
+        password_hash: form
+            .password
+            .as_deref()
+            .map(auth::hash_password)
+            .transpose()?,
+        handle: form.handle,
+        schedule,
+    };
+
+    db::update_user(&app_state.connection_pool, user_id, &changes).await?;
+
+    if let Some(email_address) = email_address {
+        if let Err(err) = send_verification_email(&app_state, user_id, &email_address).await {
+            warn!("Failed to send the verification email: {}", err);
+        }
+    }
+
+    let user = db::get_user_by_id(&app_state.connection_pool, user_id).await?;
+    Ok(Json(user))
+}
+
+/// Removes the authenticated user and all of their tasks from the database.
+///
+/// This can't be undone. Bearer tokens issued to the user remain valid until they expire, but they no longer give
+/// access to any data. Workspaces without other members are removed too, while workspaces with other members need
+/// another owner before the user can leave.
+#[utoipa::path(
+    delete,
+    path = "/v1/users/me",
+    tag = "users",
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 204, description = "The user and their tasks were removed."),
+        (status = 409, description = "The user is the last owner of a workspace with other members.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+async fn delete_current_user(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+) -> Result<impl IntoResponse, AppError> {
+    access::require_unscoped(workspace_id)?;
+
+    db::delete_user(&app_state.connection_pool, user_id).await?;
+    Ok((StatusCode::NO_CONTENT, ()))
+}
+
+/// Exports all data we store about the authenticated user as a JSON document.
+///
+/// The `Content-Disposition` header makes browsers download the export as a file.
+#[utoipa::path(
+    get,
+    path = "/v1/users/me/export",
+    tag = "users",
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses((status = 200, description = "All data we store about the user.", body = UserExportResponse))
+)]
+#[instrument(skip(app_state))]
+async fn export_current_user(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+) -> Result<impl IntoResponse, AppError> {
+    access::require_unscoped(workspace_id)?;
+
+    let user = db::get_user_by_id(&app_state.connection_pool, user_id).await?;
+    let tasks = db::list_all_tasks(&app_state.connection_pool, user_id).await?;
+    let projects = db::list_projects(&app_state.connection_pool, user_id, None).await?;
+    let shares = db::list_all_shares(&app_state.connection_pool, user_id).await?;
+    let workspaces = db::list_workspaces(&app_state.connection_pool, user_id).await?;
+    let workspace_keys = db::list_all_workspace_keys(&app_state.connection_pool, user_id).await?;
+    let invitations = db::list_user_invitations(&app_state.connection_pool, user_id).await?;
+    let assignments = db::list_all_assignments(&app_state.connection_pool, user_id).await?;
+    let watches = db::list_all_watches(&app_state.connection_pool, user_id).await?;
+    let mentions = db::list_all_mentions(&app_state.connection_pool, user_id).await?;
+    let comments = db::list_all_comments(&app_state.connection_pool, user_id).await?;
+    let comment_revisions =
+        db::list_all_comment_revisions(&app_state.connection_pool, user_id).await?;
+    let attachments = db::list_all_attachments(&app_state.connection_pool, user_id).await?;
+    let webhooks = db::list_webhooks(&app_state.connection_pool, user_id).await?;
+    let reminders = db::list_all_reminders(&app_state.connection_pool, user_id).await?;
+    let notifications = db::list_all_notifications(&app_state.connection_pool, user_id).await?;
+
+    let export = UserExportResponse {
+        exported_at: chrono::Utc::now(),
+        user,
+        tasks,
+        projects,
+        shares,
+        workspaces,
+        workspace_keys,
+        invitations,
+        assignments,
+        watches,
+        mentions,
+        comments,
+        comment_revisions,
+        attachments,
+        webhooks,
+        reminders,
+        notifications,
+    };
+
+    Ok((
+        [(
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"todo-api-export.json\"",
+        )],
+        Json(export),
+    ))
+}
+
+/// Issues a new pair of bearer tokens in exchange for an API key, email address and password, or refresh token.
+///
+/// The credentials are checked against the database, after which we issue a short-lived access token and a
+/// long-lived refresh token. Any problem with the credentials results in the same error, so we don't reveal
+/// whether an email address is registered.
+#[utoipa::path(
+    post,
+    path = "/v1/auth/token",
+    tag = "auth",
+    request_body = TokenRequestForm,
+    responses(
+        (status = 200, description = "A new pair of tokens.", body = TokenResponse),
+        (status = 401, description = "The credentials are invalid.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip_all, fields(user_id))]
+async fn issue_token(
+    State(app_state): State<Arc<AppState>>,
+    Json(form): Json<TokenRequestForm>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+
+    let (user, workspace_id) = match form {
+        TokenRequestForm::ApiKey { api_key } => {
+            let api_key = ApiKey::from_string(&api_key);
+
+            // Tokens for an API key of a workspace are limited to the workspace, just like the key.
+            match db::get_user_by_key(pool, &api_key.hash).await {
+                Ok(user) => (user, None),
+                Err(_) => {
+                    let key = db::get_workspace_key(pool, &api_key.hash)
+                        .await
+                        .map_err(|_| AppError::InvalidCredentials)?;
+
+                    let user = db::get_user_by_id(pool, key.user_id)
+                        .await
+                        .map_err(|_| AppError::InvalidCredentials)?;
+
+                    (user, Some(key.workspace_id))
+                }
+            }
+        }
+        TokenRequestForm::Password {
+            email_address,
+            password,
+        } => {
+            let user = db::get_user_by_email(pool, &email_address)
+                .await
+                .map_err(|_| AppError::InvalidCredentials)?;
+
+            let password_hash = user.password_hash.as_deref().unwrap_or_default();
+
+            if !auth::verify_password(&password, password_hash) {
+                return Err(AppError::InvalidCredentials);
+            }
+
+            (user, None)
+        }
+        TokenRequestForm::RefreshToken { refresh_token } => {
+            let claims = app_state
+                .token_issuer
+                .validate(&refresh_token, TokenType::Refresh)
+                .map_err(|_| AppError::InvalidCredentials)?;
+
+            let user_id = claims.user_id().map_err(|_| AppError::InvalidCredentials)?;
+
+            // Make sure the user still exists before we hand out a new pair of tokens.
+            // This also picks up changes to the user, for example when the user verified their email address.
+            let user = db::get_user_by_id(pool, user_id)
+                .await
+                .map_err(|_| AppError::InvalidCredentials)?;
+
+            // The credentials of the user were taken away from whoever held them after this token was issued. Tokens
+            // issued in the same second as the reset can't be told apart by their time, but the ones issued before the
+            // reset were issued while the email address wasn't verified yet.
+            if let Some(reset_at) = user.credentials_reset_at {
+                let reset_at = reset_at.and_utc().timestamp();
+
+                if claims.iat < reset_at || (claims.iat == reset_at && !claims.verified) {
+                    return Err(AppError::InvalidCredentials);
+                }
+            }
+
+            // A token for a workspace is only refreshed while the user is still a member.
+            if let Some(workspace_id) = claims.workspace {
+                db::find_workspace_role(pool, user_id, workspace_id)
+                    .await?
+                    .ok_or(AppError::InvalidCredentials)?;
+            }
+
+            (user, claims.workspace)
+        }
+    };
+
+    issue_token_response(&app_state, user.id, user.email_verified, workspace_id)
+}
+
+/// Starts a login with the OpenID Connect identity provider.
+///
+/// We store the state, nonce and PKCE code verifier of the login in the database, so any instance of the API can
+/// handle the callback. After that, the user is redirected to the identity provider.
+#[utoipa::path(
+    get,
+    path = "/v1/auth/oidc/login",
+    tag = "auth",
+    responses(
+        (status = 303, description = "Redirects to the identity provider."),
+        (status = 404, description = "No identity provider is configured.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip_all)]
+async fn oidc_login(State(app_state): State<Arc<AppState>>) -> Result<impl IntoResponse, AppError> {
+    let oidc_client = app_state
+        .oidc_client
+        .as_ref()
+        .ok_or(AppError::OidcNotConfigured)?;
+
+    let authorization_request = oidc_client.authorization_request().await?;
+
+    db::insert_oidc_login_request(
+        &app_state.connection_pool,
+        &OidcLoginRequest {
+            state: authorization_request.state,
+            code_verifier: authorization_request.code_verifier,
+            nonce: authorization_request.nonce,
+            date_created: chrono::Utc::now().naive_utc(),
+        },
+    )
+    .await?;
+
+    Ok(Redirect::to(&authorization_request.url))
+}
+
+/// Completes a login with the OpenID Connect identity provider.
+///
+/// The identity provider redirects the user to this endpoint with an authorization code. We exchange the code for an
+/// ID token and look up the user by the verified email address in the token. Users that log in for the first time
+/// are created automatically. The response contains the same tokens as the `/v1/auth/token` endpoint.
+#[utoipa::path(
+    get,
+    path = "/v1/auth/oidc/callback",
+    tag = "auth",
+    params(OidcCallbackQuery),
+    responses(
+        (status = 200, description = "A new pair of tokens.", body = TokenResponse),
+        (status = 400, description = "The login request is unknown or expired.", body = ErrorDetails),
+        (status = 401, description = "The identity provider didn't return a verified email address.", body = ErrorDetails),
+        (status = 502, description = "The identity provider isn't available.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip_all, fields(user_id))]
+async fn oidc_callback(
+    State(app_state): State<Arc<AppState>>,
+    Query(query): Query<OidcCallbackQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let oidc_client = app_state
+        .oidc_client
+        .as_ref()
+        .ok_or(AppError::OidcNotConfigured)?;
+
+    let login_request = db::take_oidc_login_request(&app_state.connection_pool, &query.state)
+        .await?
+        .ok_or(AppError::InvalidLoginState)?;
+
+    let identity = oidc_client
+        .exchange_code(
+            &query.code,
+            &login_request.code_verifier,
+            &login_request.nonce,
+        )
+        .await?;
+
+    let user_id =
+        db::get_or_create_user_by_email(&app_state.connection_pool, &identity.email_address)
+            .await?;
+
+    issue_token_response(&app_state, user_id, true, None)
+}
+
+/// Issues a new pair of tokens for a user and translates them into a response.
+fn issue_token_response(
+    app_state: &AppState,
+    user_id: i32,
+    verified: bool,
+    workspace_id: Option<i32>,
+) -> Result<Json<TokenResponse>, AppError> {
+    // The span belongs to the handler that calls this function, which declares the `user_id` field.
+    Span::current().record("user_id", user_id);
+
+    let tokens = app_state
+        .token_issuer
+        .issue(user_id, verified, workspace_id)?;
+
+    Ok(Json(TokenResponse {
+        access_token: tokens.access_token,
+        refresh_token: tokens.refresh_token,
+        token_type: "Bearer",
+        expires_in: tokens.expires_in,
+    }))
+}
+
 /// Creates the router for the web application.
 ///
 /// The router configures various routes to assiocated handler functions. Each handler function can use the
 /// application state thanks to the [`axum::Router::with_state`] method call that we've added. Although we use one
 /// piece of state information you're free to add more if needed.
-#[instrument]
+///
+/// The routes are split into groups that each have their own rate limit. The [`rate_limit`] middleware is added to
+/// each group with [`axum::Router::route_layer`], so requests to unknown routes don't use up the limit.
+///
+/// The router doesn't include the metrics and admin endpoints. Use [`create_router_with_admin`] to serve them on the
+/// same port, or [`create_admin_router`] to serve them on a port of their own.
+#[instrument(skip_all)]
 pub fn create_router(app_state: Arc<AppState>) -> Router {
+    apply_layers(create_api_routes(app_state.clone()), &app_state)
+}
+
+/// Creates the router for the web application, including the metrics and admin endpoints.
+///
+/// The metrics are reachable through the public ingress this way, so `/metrics` needs the admin token just like the
+/// admin endpoints.
+#[instrument(skip_all)]
+pub fn create_router_with_admin(app_state: Arc<AppState>) -> Router {
+    let router = create_api_routes(app_state.clone()).merge(
+        Router::new()
+            .route("/metrics", get(metrics::metrics))
+            .merge(create_admin_routes())
+            .with_state(app_state.clone()),
+    );
+
+    apply_layers(router, &app_state)
+}
+
+/// Creates the router for the Prometheus metrics and the admin endpoints on a separate admin port.
+///
+/// The admin port isn't reachable through the public ingress, so Prometheus can scrape `/metrics` without the admin
+/// token. The admin endpoints need the admin token either way.
+pub fn create_admin_router(app_state: Arc<AppState>) -> Router {
+    let router = Router::new()
+        .route("/metrics", get(metrics::internal_metrics))
+        .merge(create_admin_routes())
+        .with_state(app_state.clone());
+
+    apply_layers(router, &app_state)
+}
+
+/// Creates the routes for the admin endpoints, which need the admin token.
+fn create_admin_routes() -> Router<Arc<AppState>> {
     Router::new()
+        .route("/admin/jobs", get(jobs::list_jobs))
+        .route("/admin/jobs/counts", get(jobs::job_counts))
+        .route("/admin/jobs/:id", delete(jobs::delete_job))
+        .route("/admin/jobs/:id/retry", post(jobs::retry_job))
+}
+
+/// Adds the middleware that applies to every request, from request IDs and tracing to the limits of the HTTP
+/// configuration.
+fn apply_layers(router: Router, app_state: &AppState) -> Router {
+    layers::apply(router, &app_state.http)
+        .layer(middleware::from_fn(monitoring::track_requests))
+        .layer(TraceLayer::new_for_http().make_span_with(telemetry::make_request_span))
+        .layer(middleware::from_fn(request_id::propagate_request_id))
+}
+
+/// Creates the routes of the API, without the middleware that applies to every request.
+fn create_api_routes(app_state: Arc<AppState>) -> Router {
+    let task_routes = Router::new()
         .route(
             "/v1/todos/:id",
             get(task_details).put(update_task).delete(delete_todo),
         )
         .route("/v1/todos", get(list_tasks).post(create_task))
+        .route("/v1/todos/events", get(events::task_events))
+        .route(
+            "/v1/todos/:id/reminders",
+            get(reminders::list_reminders).post(reminders::create_reminder),
+        )
+        .route(
+            "/v1/todos/:id/reminders/:reminder_id",
+            delete(reminders::delete_reminder),
+        )
+        .route("/v1/todos/:id/assignees", get(assignments::list_assignees))
+        .route(
+            "/v1/todos/:id/assignees/:user_id",
+            put(assignments::assign_user).delete(assignments::unassign_user),
+        )
+        .route("/v1/todos/:id/watchers", get(assignments::list_watchers))
+        .route(
+            "/v1/todos/:id/watchers/:user_id",
+            put(assignments::watch_task).delete(assignments::unwatch_task),
+        )
+        .route(
+            "/v1/todos/:id/comments",
+            get(comments::list_comments).post(comments::create_comment),
+        )
+        .route(
+            "/v1/todos/:id/comments/:comment_id",
+            patch(comments::update_comment).delete(comments::delete_comment),
+        )
+        .route(
+            "/v1/todos/:id/comments/:comment_id/revisions",
+            get(comments::list_comment_revisions),
+        )
+        .route(
+            "/v1/todos/:id/blockers/:blocker_id",
+            put(dependencies::add_blocker).delete(dependencies::remove_blocker),
+        )
+        .route("/v1/todos/:id/graph", get(dependencies::task_graph))
+        .route(
+            "/v1/todos/:id/attachments",
+            get(attachments::list_attachments)
+                .post(attachments::upload_attachment)
+                .layer(attachments::upload_limit(&app_state.attachments)),
+        )
+        .route(
+            "/v1/todos/:id/attachments/:attachment_id",
+            delete(attachments::delete_attachment),
+        )
+        .route(
+            "/v1/todos/:id/attachments/:attachment_id/url",
+            get(attachments::attachment_url),
+        )
+        .route(
+            "/v1/attachments/:id/content",
+            get(attachments::download_attachment),
+        )
+        .route(
+            "/v1/todos/:id/shares",
+            get(shares::list_task_shares).post(shares::share_task),
+        )
+        .route(
+            "/v1/todos/:id/shares/:share_id",
+            delete(shares::revoke_task_share),
+        )
+        .route(
+            "/v1/projects",
+            get(projects::list_projects).post(projects::create_project),
+        )
+        .route(
+            "/v1/projects/:id",
+            get(projects::project_details)
+                .put(projects::update_project)
+                .delete(projects::delete_project),
+        )
+        .route("/v1/projects/:id/tasks", get(projects::list_project_tasks))
+        .route(
+            "/v1/projects/:id/tasks/:task_id",
+            put(projects::add_project_task).delete(projects::remove_project_task),
+        )
+        .route(
+            "/v1/projects/:id/shares",
+            get(shares::list_project_shares).post(shares::share_project),
+        )
+        .route(
+            "/v1/projects/:id/shares/:share_id",
+            delete(shares::revoke_project_share),
+        )
+        .route(
+            "/v1/workspaces",
+            get(workspaces::list_workspaces).post(workspaces::create_workspace),
+        )
+        .route(
+            "/v1/workspaces/:id",
+            get(workspaces::workspace_details)
+                .put(workspaces::update_workspace)
+                .delete(workspaces::delete_workspace),
+        )
+        .route("/v1/workspaces/:id/members", get(workspaces::list_members))
+        .route(
+            "/v1/workspaces/:id/members/:user_id",
+            put(workspaces::update_member).delete(workspaces::remove_member),
+        )
+        .route(
+            "/v1/workspaces/:id/invitations",
+            get(workspaces::list_invitations).post(workspaces::invite_member),
+        )
+        .route(
+            "/v1/workspaces/:id/invitations/:invitation_id",
+            delete(workspaces::withdraw_invitation),
+        )
+        .route(
+            "/v1/workspaces/:id/keys",
+            get(workspaces::list_keys).post(workspaces::create_key),
+        )
+        .route(
+            "/v1/workspaces/:id/keys/:key_id",
+            delete(workspaces::revoke_key),
+        )
+        .route("/v1/invitations", get(workspaces::list_my_invitations))
+        .route(
+            "/v1/invitations/:id",
+            delete(workspaces::decline_invitation),
+        )
+        .route(
+            "/v1/invitations/:id/accept",
+            post(workspaces::accept_invitation),
+        )
+        .route("/v1/notifications", get(notifications::list_notifications))
+        .route(
+            "/v1/notifications/:id",
+            patch(notifications::update_notification),
+        )
+        .route("/v1/notifications/read", post(notifications::mark_all_read))
+        .route(
+            "/v1/webhooks",
+            get(webhooks::list_webhooks).post(webhooks::create_webhook),
+        )
+        .route(
+            "/v1/webhooks/:id",
+            get(webhooks::webhook_details)
+                .patch(webhooks::update_webhook)
+                .delete(webhooks::delete_webhook),
+        )
+        .route(
+            "/v1/webhooks/:id/deliveries",
+            get(webhooks::list_deliveries),
+        )
+        .route(
+            "/v1/webhooks/:id/deliveries/:delivery_id/redeliver",
+            post(webhooks::redeliver),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            (app_state.clone(), RouteGroup::Tasks),
+            rate_limit,
+        ));
+
+    let user_routes = Router::new()
         .route("/v1/users/register", post(register_user))
+        .route("/v1/users/verify", post(verify_user))
+        .route("/v1/users/verify/resend", post(resend_verification))
+        .route(
+            "/v1/users/me",
+            get(current_user)
+                .patch(update_current_user)
+                .delete(delete_current_user),
+        )
+        .route("/v1/users/me/export", get(export_current_user))
+        .route_layer(middleware::from_fn_with_state(
+            (app_state.clone(), RouteGroup::Users),
+            rate_limit,
+        ));
+
+    let health_routes = Router::new()
+        .route("/healthz", get(health::liveness))
+        .route("/readyz", get(health::readiness));
+
+    let auth_routes = Router::new()
+        .route("/v1/auth/token", post(issue_token))
+        .route("/v1/auth/oidc/login", get(oidc_login))
+        .route("/v1/auth/oidc/callback", get(oidc_callback))
+        .route_layer(middleware::from_fn_with_state(
+            (app_state.clone(), RouteGroup::Auth),
+            rate_limit,
+        ));
+
+    Router::new()
+        .merge(task_routes)
+        .merge(user_routes)
+        .merge(auth_routes)
+        .merge(health_routes)
         .with_state(app_state)
-        .layer(TraceLayer::new_for_http())
+        .merge(openapi::create_docs_router())
 }
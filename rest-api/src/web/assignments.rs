@@ -0,0 +1,233 @@
+//! This module contains the endpoints to assign users to tasks and to watch tasks.
+//!
+//! A task can be assigned to one or more users who can access it, and editors of the task manage the assignees. Users
+//! who can see a task watch it to hear about its assignments, and editors can add other users as watchers as well.
+//! Users can always unassign themselves and stop watching a task.
+//!
+//! The users involved are notified of every change to the assignees, see [`crate::notifications`]. The tasks a user is
+//! assigned to are listed with `GET /v1/todos?assigned_to_me=true`.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use tracing::instrument;
+
+use crate::{
+    access,
+    auth::{AuthenticatedUser, VerifiedUser},
+    db,
+    entity::{Role, TaskUser},
+    error::{AppError, ErrorDetails},
+    state::AppState,
+};
+
+/// Returns the role a user needs to change the assignment or watch of another user.
+///
+/// Changing someone else takes an editor, while users can always unassign themselves and stop or start watching.
+fn required_role(user_id: i32, other_user_id: i32) -> Role {
+    if user_id == other_user_id {
+        Role::Viewer
+    } else {
+        Role::Editor
+    }
+}
+
+/// Lists the users a task is assigned to.
+#[utoipa::path(
+    get,
+    path = "/v1/todos/{id}/assignees",
+    tag = "assignments",
+    params(("id" = i32, Path, description = "The ID of the task.")),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 200, description = "The assignees of the task.", body = Vec<TaskUser>),
+        (status = 404, description = "The task doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn list_assignees(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    access::task_access(pool, user_id, workspace_id, id, Role::Viewer).await?;
+
+    let assignees = db::list_task_assignees(pool, id).await?;
+    Ok(Json(assignees))
+}
+
+/// Assigns a user who can access the task to it. Owners and editors of the task can assign users.
+#[utoipa::path(
+    put,
+    path = "/v1/todos/{id}/assignees/{user_id}",
+    tag = "assignments",
+    params(
+        ("id" = i32, Path, description = "The ID of the task."),
+        ("user_id" = i32, Path, description = "The ID of the user to assign.")
+    ),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 204, description = "The user is assigned to the task."),
+        (status = 400, description = "The user can't access the task.", body = ErrorDetails),
+        (status = 403, description = "The task is shared with the user as a viewer.", body = ErrorDetails),
+        (status = 404, description = "The task doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn assign_user(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path((id, assignee_id)): Path<(i32, i32)>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    access::task_access(pool, user_id, workspace_id, id, Role::Editor).await?;
+
+    db::insert_task_assignee(pool, user_id, id, assignee_id).await?;
+
+    Ok((StatusCode::NO_CONTENT, ()))
+}
+
+/// Unassigns a user from a task. Owners and editors of the task can unassign anyone, others only themselves.
+#[utoipa::path(
+    delete,
+    path = "/v1/todos/{id}/assignees/{user_id}",
+    tag = "assignments",
+    params(
+        ("id" = i32, Path, description = "The ID of the task."),
+        ("user_id" = i32, Path, description = "The ID of the user to unassign.")
+    ),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 204, description = "The user is no longer assigned to the task."),
+        (status = 403, description = "The task is shared with the user as a viewer.", body = ErrorDetails),
+        (status = 404, description = "The task doesn't exist, or the user isn't assigned to it.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn unassign_user(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path((id, assignee_id)): Path<(i32, i32)>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    let required = required_role(user_id, assignee_id);
+    access::task_access(pool, user_id, workspace_id, id, required).await?;
+
+    db::delete_task_assignee(pool, user_id, id, assignee_id).await?;
+
+    Ok((StatusCode::NO_CONTENT, ()))
+}
+
+/// Lists the users watching a task.
+#[utoipa::path(
+    get,
+    path = "/v1/todos/{id}/watchers",
+    tag = "assignments",
+    params(("id" = i32, Path, description = "The ID of the task.")),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 200, description = "The watchers of the task.", body = Vec<TaskUser>),
+        (status = 404, description = "The task doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn list_watchers(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    access::task_access(pool, user_id, workspace_id, id, Role::Viewer).await?;
+
+    let watchers = db::list_task_watchers(pool, id).await?;
+    Ok(Json(watchers))
+}
+
+/// Adds a user who can access the task to its watchers. Users can watch a task themselves, editors can add others.
+#[utoipa::path(
+    put,
+    path = "/v1/todos/{id}/watchers/{user_id}",
+    tag = "assignments",
+    params(
+        ("id" = i32, Path, description = "The ID of the task."),
+        ("user_id" = i32, Path, description = "The ID of the user to add.")
+    ),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 204, description = "The user watches the task."),
+        (status = 400, description = "The user can't access the task.", body = ErrorDetails),
+        (status = 403, description = "Only editors can add other users.", body = ErrorDetails),
+        (status = 404, description = "The task doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn watch_task(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path((id, watcher_id)): Path<(i32, i32)>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    let required = required_role(user_id, watcher_id);
+    access::task_access(pool, user_id, workspace_id, id, required).await?;
+
+    db::insert_task_watcher(pool, id, watcher_id).await?;
+
+    Ok((StatusCode::NO_CONTENT, ()))
+}
+
+/// Removes a user from the watchers of a task. Users can stop watching themselves, editors can remove others.
+#[utoipa::path(
+    delete,
+    path = "/v1/todos/{id}/watchers/{user_id}",
+    tag = "assignments",
+    params(
+        ("id" = i32, Path, description = "The ID of the task."),
+        ("user_id" = i32, Path, description = "The ID of the user to remove.")
+    ),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 204, description = "The user no longer watches the task."),
+        (status = 403, description = "Only editors can remove other users.", body = ErrorDetails),
+        (status = 404, description = "The task doesn't exist, or the user doesn't watch it.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn unwatch_task(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path((id, watcher_id)): Path<(i32, i32)>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    let required = required_role(user_id, watcher_id);
+    access::task_access(pool, user_id, workspace_id, id, required).await?;
+
+    db::delete_task_watcher(pool, id, watcher_id).await?;
+
+    Ok((StatusCode::NO_CONTENT, ()))
+}
@@ -0,0 +1,329 @@
+//! This module contains the endpoints to attach files to tasks and download them again.
+//!
+//! Editors of a task upload files with a `multipart/form-data` request that has the file in a `file` field. Everyone
+//! who can see the task lists its attachments and asks for a short-lived download URL. The rules for uploads and the
+//! signed URLs are described in [`crate::attachments`].
+//!
+//! The download endpoint doesn't need credentials: the signature in the URL proves that the user could see the task when
+//! they asked for the URL. That way a browser can follow the URL directly, like any other link.
+
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    extract::{multipart::MultipartError, DefaultBodyLimit, Multipart, Path, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+use tracing::{instrument, warn};
+use utoipa::IntoParams;
+
+use crate::{
+    access,
+    attachments::{clean_file_name, content_disposition, is_allowed_content_type, new_storage_key},
+    auth::{AuthenticatedUser, VerifiedUser},
+    blobs::{BlobError, Download},
+    config::AttachmentsConfig,
+    db,
+    entity::{Attachment, AttachmentUrl, Role},
+    error::{AppError, ErrorDetails},
+    state::AppState,
+};
+
+/// The room we leave for the boundaries and headers of a multipart request, on top of the size of the file.
+const MULTIPART_OVERHEAD: usize = 64 * 1024;
+
+/// The name of the multipart field that holds the file.
+const FILE_FIELD: &str = "file";
+
+/// Defines the querystring parameters of a signed download URL.
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DownloadQuery {
+    /// The UNIX timestamp at which the URL expires.
+    pub expires: i64,
+
+    /// The signature of the URL.
+    pub signature: String,
+}
+
+/// Returns the body limit for uploads, which replaces the regular limit of [`crate::config::HttpConfig`].
+pub fn upload_limit(config: &AttachmentsConfig) -> DefaultBodyLimit {
+    DefaultBodyLimit::max(config.limit + MULTIPART_OVERHEAD)
+}
+
+/// Lists the files attached to a task.
+#[utoipa::path(
+    get,
+    path = "/v1/todos/{id}/attachments",
+    tag = "attachments",
+    params(("id" = i32, Path, description = "The ID of the task.")),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 200, description = "The attachments of the task.", body = Vec<Attachment>),
+        (status = 404, description = "The task doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn list_attachments(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    access::task_access(pool, user_id, workspace_id, id, Role::Viewer).await?;
+
+    let attachments = db::list_attachments(pool, id).await?;
+    Ok(Json(attachments))
+}
+
+/// Attaches a file to a task. Owners and editors of the task can upload files.
+///
+/// The file goes in the `file` field of a `multipart/form-data` request, with its name and content type.
+#[utoipa::path(
+    post,
+    path = "/v1/todos/{id}/attachments",
+    tag = "attachments",
+    params(("id" = i32, Path, description = "The ID of the task.")),
+    request_body(content_type = "multipart/form-data", description = "The file in a `file` field."),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 201, description = "The file was attached.", body = Attachment),
+        (status = 400, description = "The request doesn't contain a file with a name.", body = ErrorDetails),
+        (status = 403, description = "The task is shared with the user as a viewer.", body = ErrorDetails),
+        (status = 404, description = "The task doesn't exist.", body = ErrorDetails),
+        (status = 413, description = "The file is too large.", body = ErrorDetails),
+        (status = 415, description = "Files of this content type can't be uploaded.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state, multipart))]
+pub async fn upload_attachment(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path(id): Path<i32>,
+    mut multipart: Multipart,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    access::task_access(pool, user_id, workspace_id, id, Role::Editor).await?;
+
+    let config = &app_state.attachments;
+
+    while let Some(mut field) = multipart.next_field().await.map_err(multipart_error)? {
+        if field.name() != Some(FILE_FIELD) {
+            continue;
+        }
+
+        let file_name = field
+            .file_name()
+            .and_then(clean_file_name)
+            .ok_or(AppError::InvalidAttachment)?;
+        let content_type = field
+            .content_type()
+            .unwrap_or(mime::APPLICATION_OCTET_STREAM.as_ref())
+            .to_string();
+
+        if !is_allowed_content_type(&config.types, &content_type) {
+            return Err(AppError::UnsupportedContentType);
+        }
+
+        let mut data = Vec::new();
+
+        while let Some(chunk) = field.chunk().await.map_err(multipart_error)? {
+            if data.len() + chunk.len() > config.limit {
+                return Err(AppError::PayloadTooLarge);
+            }
+
+            data.extend_from_slice(&chunk);
+        }
+
+        let size = data.len() as i64;
+        let storage_key = new_storage_key(id);
+
+        app_state
+            .blobs
+            .put(&storage_key, &content_type, data.into())
+            .await?;
+
+        let result = db::insert_attachment(
+            pool,
+            id,
+            user_id,
+            &file_name,
+            &content_type,
+            size,
+            &storage_key,
+        )
+        .await;
+
+        // Without a row, the trigger never queues the removal of the file, so we remove it right away. When the blob
+        // store fails as well, a job retries the removal later.
+        if result.is_err() {
+            if let Err(err) = app_state.blobs.delete(&storage_key).await {
+                warn!("Failed to remove the file of a failed upload: {}", err);
+
+                if let Err(err) = db::insert_blob_deletion(pool, &storage_key).await {
+                    warn!(
+                        "Failed to queue the removal of the file of a failed upload: {}",
+                        err
+                    );
+                }
+            }
+        }
+
+        return Ok((StatusCode::CREATED, Json(result?)));
+    }
+
+    Err(AppError::InvalidAttachment)
+}
+
+/// Removes a file from a task. Owners and editors of the task can remove files.
+#[utoipa::path(
+    delete,
+    path = "/v1/todos/{id}/attachments/{attachment_id}",
+    tag = "attachments",
+    params(
+        ("id" = i32, Path, description = "The ID of the task."),
+        ("attachment_id" = i32, Path, description = "The ID of the attachment.")
+    ),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 204, description = "The file was removed."),
+        (status = 403, description = "The task is shared with the user as a viewer.", body = ErrorDetails),
+        (status = 404, description = "The task or the attachment doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn delete_attachment(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path((id, attachment_id)): Path<(i32, i32)>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    access::task_access(pool, user_id, workspace_id, id, Role::Editor).await?;
+
+    db::delete_attachment(pool, id, attachment_id).await?;
+
+    Ok((StatusCode::NO_CONTENT, ()))
+}
+
+/// Returns a short-lived URL to download a file attached to a task.
+#[utoipa::path(
+    get,
+    path = "/v1/todos/{id}/attachments/{attachment_id}/url",
+    tag = "attachments",
+    params(
+        ("id" = i32, Path, description = "The ID of the task."),
+        ("attachment_id" = i32, Path, description = "The ID of the attachment.")
+    ),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 200, description = "The URL to download the file from.", body = AttachmentUrl),
+        (status = 404, description = "The task or the attachment doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn attachment_url(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+    Path((id, attachment_id)): Path<(i32, i32)>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    access::task_access(pool, user_id, workspace_id, id, Role::Viewer).await?;
+
+    let attachment = db::find_attachment(pool, id, attachment_id).await?;
+
+    let ttl = Duration::from_secs(app_state.attachments.ttl);
+    let expires_at = chrono::Utc::now() + ttl;
+    let disposition = content_disposition(&attachment.file_name);
+    let download = Download {
+        content_type: &attachment.content_type,
+        content_disposition: &disposition,
+    };
+
+    let url = match app_state
+        .blobs
+        .presigned_url(&attachment.storage_key, &download, ttl)
+    {
+        Some(url) => url.to_string(),
+        None => {
+            let expires = expires_at.timestamp();
+            let signature = app_state.download_signer.sign(attachment.id, expires);
+
+            format!(
+                "/v1/attachments/{}/content?expires={}&signature={}",
+                attachment.id, expires, signature
+            )
+        }
+    };
+
+    Ok(Json(AttachmentUrl { url, expires_at }))
+}
+
+/// Downloads a file through a signed URL, see [`attachment_url`].
+#[utoipa::path(
+    get,
+    path = "/v1/attachments/{id}/content",
+    tag = "attachments",
+    params(("id" = i32, Path, description = "The ID of the attachment."), DownloadQuery),
+    responses(
+        (status = 200, description = "The contents of the file."),
+        (status = 403, description = "The URL is invalid or expired.", body = ErrorDetails),
+        (status = 404, description = "The attachment doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state, query))]
+pub async fn download_attachment(
+    State(app_state): State<Arc<AppState>>,
+    Path(id): Path<i32>,
+    Query(query): Query<DownloadQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    app_state
+        .download_signer
+        .verify(id, query.expires, &query.signature)?;
+
+    let attachment = db::get_attachment(&app_state.connection_pool, id).await?;
+
+    let data = app_state
+        .blobs
+        .get(&attachment.storage_key)
+        .await
+        .map_err(|err| match err {
+            BlobError::NotFound => AppError::AttachmentNotFound,
+            err => AppError::BlobError(err),
+        })?;
+
+    let headers = [
+        (header::CONTENT_TYPE, attachment.content_type.clone()),
+        (
+            header::CONTENT_DISPOSITION,
+            content_disposition(&attachment.file_name),
+        ),
+        (header::CACHE_CONTROL, "private, no-store".to_string()),
+    ];
+
+    Ok((headers, data))
+}
+
+/// Translates a problem with reading a multipart request into an error, keeping track of bodies that are too large.
+fn multipart_error(err: MultipartError) -> AppError {
+    if err.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        AppError::PayloadTooLarge
+    } else {
+        AppError::InvalidAttachment
+    }
+}
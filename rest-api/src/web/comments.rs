@@ -0,0 +1,215 @@
+//! This module contains the endpoints to discuss a task in comments.
+//!
+//! Everyone who can see a task reads and writes comments on it, but only the author of a comment edits or removes it.
+//! Comments are markdown, which we render to sanitised HTML, see [`crate::comments`]. Every edit keeps the earlier
+//! version of the comment, so the history of a comment can be retrieved.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+use tracing::instrument;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    access,
+    auth::{AuthenticatedUser, VerifiedUser},
+    comments::is_valid_comment,
+    db,
+    entity::{Comment, CommentRevision, PagedResult, Role},
+    error::{AppError, ErrorDetails},
+    state::AppState,
+};
+
+/// The number of comments on a page.
+const COMMENTS_PAGE_SIZE: i32 = 20;
+
+/// Defines the querystring parameters for listing comments.
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CommentListQuery {
+    /// The index of the page to retrieve.
+    #[serde(default)]
+    pub page: i32,
+}
+
+/// Defines the fields to write or edit a comment.
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct CommentForm {
+    /// The comment as markdown, with at most 10000 characters.
+    pub body: String,
+}
+
+/// Lists the comments on a task, oldest first.
+#[utoipa::path(
+    get,
+    path = "/v1/todos/{id}/comments",
+    tag = "comments",
+    params(("id" = i32, Path, description = "The ID of the task."), CommentListQuery),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 200, description = "A page of comments on the task.", body = PagedResult<Comment>),
+        (status = 404, description = "The task doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn list_comments(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+    Path(id): Path<i32>,
+    Query(query): Query<CommentListQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    access::task_access(pool, user_id, workspace_id, id, Role::Viewer).await?;
+
+    let comments = db::list_comments(pool, id, query.page, COMMENTS_PAGE_SIZE).await?;
+    Ok(Json(comments))
+}
+
+/// Writes a comment on a task. Everyone who can see the task can comment on it.
+#[utoipa::path(
+    post,
+    path = "/v1/todos/{id}/comments",
+    tag = "comments",
+    params(("id" = i32, Path, description = "The ID of the task.")),
+    request_body = CommentForm,
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 201, description = "The comment was created.", body = Comment),
+        (status = 400, description = "The comment is empty or too long.", body = ErrorDetails),
+        (status = 404, description = "The task doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state, form))]
+pub async fn create_comment(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path(id): Path<i32>,
+    Json(form): Json<CommentForm>,
+) -> Result<impl IntoResponse, AppError> {
+    if !is_valid_comment(&form.body) {
+        return Err(AppError::InvalidComment);
+    }
+
+    let pool = &app_state.connection_pool;
+    access::task_access(pool, user_id, workspace_id, id, Role::Viewer).await?;
+
+    let comment = db::insert_comment(pool, id, user_id, form.body).await?;
+    Ok((StatusCode::CREATED, Json(comment)))
+}
+
+/// Edits a comment on a task. Only the author of the comment can edit it.
+#[utoipa::path(
+    patch,
+    path = "/v1/todos/{id}/comments/{comment_id}",
+    tag = "comments",
+    params(
+        ("id" = i32, Path, description = "The ID of the task."),
+        ("comment_id" = i32, Path, description = "The ID of the comment.")
+    ),
+    request_body = CommentForm,
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 200, description = "The edited comment.", body = Comment),
+        (status = 400, description = "The comment is empty or too long.", body = ErrorDetails),
+        (status = 403, description = "The user didn't write the comment.", body = ErrorDetails),
+        (status = 404, description = "The task or the comment doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state, form))]
+pub async fn update_comment(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path((id, comment_id)): Path<(i32, i32)>,
+    Json(form): Json<CommentForm>,
+) -> Result<impl IntoResponse, AppError> {
+    if !is_valid_comment(&form.body) {
+        return Err(AppError::InvalidComment);
+    }
+
+    let pool = &app_state.connection_pool;
+    access::task_access(pool, user_id, workspace_id, id, Role::Viewer).await?;
+
+    let comment = db::update_comment(pool, id, comment_id, user_id, form.body).await?;
+    Ok(Json(comment))
+}
+
+/// Removes a comment from a task. Only the author of the comment can remove it.
+#[utoipa::path(
+    delete,
+    path = "/v1/todos/{id}/comments/{comment_id}",
+    tag = "comments",
+    params(
+        ("id" = i32, Path, description = "The ID of the task."),
+        ("comment_id" = i32, Path, description = "The ID of the comment.")
+    ),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 204, description = "The comment was removed."),
+        (status = 403, description = "The user didn't write the comment.", body = ErrorDetails),
+        (status = 404, description = "The task or the comment doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn delete_comment(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path((id, comment_id)): Path<(i32, i32)>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    access::task_access(pool, user_id, workspace_id, id, Role::Viewer).await?;
+
+    db::delete_comment(pool, id, comment_id, user_id).await?;
+
+    Ok((StatusCode::NO_CONTENT, ()))
+}
+
+/// Lists the earlier versions of a comment, newest first.
+#[utoipa::path(
+    get,
+    path = "/v1/todos/{id}/comments/{comment_id}/revisions",
+    tag = "comments",
+    params(
+        ("id" = i32, Path, description = "The ID of the task."),
+        ("comment_id" = i32, Path, description = "The ID of the comment.")
+    ),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 200, description = "The earlier versions of the comment.", body = Vec<CommentRevision>),
+        (status = 404, description = "The task or the comment doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn list_comment_revisions(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+    Path((id, comment_id)): Path<(i32, i32)>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    access::task_access(pool, user_id, workspace_id, id, Role::Viewer).await?;
+
+    let revisions = db::list_comment_revisions(pool, id, comment_id).await?;
+    Ok(Json(revisions))
+}
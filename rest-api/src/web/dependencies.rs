@@ -0,0 +1,130 @@
+//! This module contains the endpoints to make tasks wait for other tasks.
+//!
+//! A task that is blocked by another task can't be completed until the other task is completed, unless the update is
+//! forced with `PUT /v1/todos/:id?force=true`. Editors of a task choose the tasks it waits for, out of the tasks they
+//! can see. Links that would make a task wait for itself, directly or through other tasks, are refused.
+//!
+//! Every task has a `blocked` flag that tells whether it waits for a task that isn't completed, and the whole graph of
+//! a task is returned by `GET /v1/todos/:id/graph`.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use tracing::instrument;
+
+use crate::{
+    access,
+    auth::{AuthenticatedUser, VerifiedUser},
+    db,
+    entity::{Role, TaskGraph},
+    error::{AppError, ErrorDetails},
+    state::AppState,
+};
+
+/// The number of tasks a dependency graph holds at most.
+const MAX_GRAPH_NODES: usize = 500;
+
+/// Makes a task wait for another task. Owners and editors of the task can add blockers, out of the tasks they can see.
+#[utoipa::path(
+    put,
+    path = "/v1/todos/{id}/blockers/{blocker_id}",
+    tag = "dependencies",
+    params(
+        ("id" = i32, Path, description = "The ID of the task."),
+        ("blocker_id" = i32, Path, description = "The ID of the task to wait for.")
+    ),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 204, description = "The task waits for the other task."),
+        (status = 403, description = "The task is shared with the user as a viewer.", body = ErrorDetails),
+        (status = 404, description = "One of the tasks doesn't exist.", body = ErrorDetails),
+        (status = 409, description = "The other task waits for the task, directly or through other tasks.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn add_blocker(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path((id, blocker_id)): Path<(i32, i32)>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    access::task_access(pool, user_id, workspace_id, id, Role::Editor).await?;
+    access::task_access(pool, user_id, workspace_id, blocker_id, Role::Viewer).await?;
+
+    db::insert_task_dependency(pool, id, blocker_id).await?;
+
+    Ok((StatusCode::NO_CONTENT, ()))
+}
+
+/// Stops a task from waiting for another task. Owners and editors of the task can remove blockers.
+#[utoipa::path(
+    delete,
+    path = "/v1/todos/{id}/blockers/{blocker_id}",
+    tag = "dependencies",
+    params(
+        ("id" = i32, Path, description = "The ID of the task."),
+        ("blocker_id" = i32, Path, description = "The ID of the task it waits for.")
+    ),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 204, description = "The task no longer waits for the other task."),
+        (status = 403, description = "The task is shared with the user as a viewer.", body = ErrorDetails),
+        (status = 404, description = "The task doesn't exist, or doesn't wait for the other task.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn remove_blocker(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path((id, blocker_id)): Path<(i32, i32)>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    access::task_access(pool, user_id, workspace_id, id, Role::Editor).await?;
+
+    db::delete_task_dependency(pool, id, blocker_id).await?;
+
+    Ok((StatusCode::NO_CONTENT, ()))
+}
+
+/// Returns the dependency graph of a task, with the tasks it waits for and the tasks that wait for it.
+///
+/// The graph only holds the tasks the user can see, and at most 500 of them. A larger graph keeps the tasks nearest to
+/// the task and is marked as truncated.
+#[utoipa::path(
+    get,
+    path = "/v1/todos/{id}/graph",
+    tag = "dependencies",
+    params(("id" = i32, Path, description = "The ID of the task.")),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 200, description = "The dependency graph of the task.", body = TaskGraph),
+        (status = 404, description = "The task doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn task_graph(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    access::task_access(pool, user_id, workspace_id, id, Role::Viewer).await?;
+
+    let graph = db::find_task_graph(pool, user_id, workspace_id, id, MAX_GRAPH_NODES).await?;
+    Ok(Json(graph))
+}
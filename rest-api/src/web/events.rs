@@ -0,0 +1,180 @@
+//! This module contains the endpoint that streams the changes to the tasks the authenticated user can access.
+//!
+//! Clients get the stream as [server-sent events](https://html.spec.whatwg.org/multipage/server-sent-events.html) by
+//! default. Clients that prefer a WebSocket connect to the same URL with an upgrade request, and receive every event
+//! as a JSON text message. The events themselves come from the [`crate::events`] module.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    extract::{
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    http::HeaderMap,
+    response::{
+        sse::{Event, KeepAlive},
+        IntoResponse, Response, Sse,
+    },
+};
+use serde::Deserialize;
+use tracing::{instrument, warn};
+use utoipa::IntoParams;
+
+use crate::{auth::AuthenticatedUser, entity::TaskEvent, events::Subscription, state::AppState};
+
+/// The header browsers send with the ID of the last event when they reconnect to a stream.
+const LAST_EVENT_ID_HEADER: &str = "last-event-id";
+
+/// Defines the querystring parameters for the event stream.
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EventStreamQuery {
+    /// The ID of the last event the client received. Clients that can't set the `Last-Event-ID` header, like
+    /// WebSocket clients in the browser, can use this instead.
+    pub last_event_id: Option<i64>,
+}
+
+/// Streams the changes to the tasks the authenticated user can access.
+///
+/// This includes the tasks that are shared with the user and the tasks in their workspaces. Credentials of a workspace
+/// only receive the changes in that workspace.
+///
+/// Every event has the ID of the change, its type, like `task.created`, and the same data as the webhook payload.
+/// Clients that reconnect with the `Last-Event-ID` header or the `last_event_id` parameter first receive the events
+/// they missed. Send a WebSocket upgrade request to receive the events as JSON messages instead.
+#[utoipa::path(
+    get,
+    path = "/v1/todos/events",
+    tag = "tasks",
+    params(
+        EventStreamQuery,
+        ("Last-Event-ID" = Option<i64>, Header, description = "The ID of the last event the client received.")
+    ),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 200, description = "A stream of task events.", content_type = "text/event-stream", body = TaskEvent),
+        (status = 101, description = "The connection was upgraded to a WebSocket.")
+    )
+)]
+#[instrument(skip(app_state, headers, upgrade))]
+pub async fn task_events(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+    Query(query): Query<EventStreamQuery>,
+    headers: HeaderMap,
+    upgrade: Option<WebSocketUpgrade>,
+) -> Response {
+    let last_event_id = headers
+        .get(LAST_EVENT_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .or(query.last_event_id);
+
+    let subscription = app_state.events.subscribe(
+        app_state.connection_pool.clone(),
+        user_id,
+        workspace_id,
+        last_event_id,
+    );
+
+    match upgrade {
+        Some(upgrade) => upgrade
+            .on_upgrade(move |socket| stream_websocket(socket, subscription, app_state))
+            .into_response(),
+        None => stream_sse(subscription, app_state).into_response(),
+    }
+}
+
+/// Sends the events as server-sent events until the client disconnects or the application shuts down.
+fn stream_sse(subscription: Subscription, app_state: Arc<AppState>) -> impl IntoResponse {
+    let keepalive = Duration::from_secs(app_state.events.config().keepalive);
+
+    let stream = futures_util::stream::unfold(
+        (subscription, app_state),
+        |(mut subscription, app_state)| async move {
+            let event = tokio::select! {
+                _ = app_state.shutdown.started() => return None,
+                event = subscription.next() => event,
+            };
+
+            match event {
+                Ok(Some(event)) => {
+                    let message = Event::default()
+                        .id(event.id.to_string())
+                        .event(event.event_type.as_str())
+                        .json_data(&*event);
+
+                    Some((message, (subscription, app_state)))
+                }
+                Ok(None) => None,
+                Err(err) => {
+                    warn!("Closing event stream: {}", err);
+                    None
+                }
+            }
+        },
+    );
+
+    Sse::new(stream).keep_alive(KeepAlive::new().interval(keepalive))
+}
+
+/// Sends the events as WebSocket text messages until the client disconnects or the application shuts down.
+///
+/// Messages from the client are ignored, apart from the close message.
+async fn stream_websocket(
+    mut socket: WebSocket,
+    mut subscription: Subscription,
+    app_state: Arc<AppState>,
+) {
+    let mut keepalive =
+        tokio::time::interval(Duration::from_secs(app_state.events.config().keepalive));
+
+    loop {
+        tokio::select! {
+            _ = app_state.shutdown.started() => {
+                close_websocket(&mut socket, close_code::AWAY, "The server is shutting down.").await;
+                break;
+            }
+            event = subscription.next() => match event {
+                Ok(Some(event)) => {
+                    let text = serde_json::to_string(&*event).expect("Task events can be serialized.");
+
+                    if socket.send(Message::Text(text)).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    warn!("Closing event stream: {}", err);
+                    close_websocket(&mut socket, close_code::ERROR, "The events can't be loaded.").await;
+                    break;
+                }
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+            _ = keepalive.tick() => {
+                if socket.send(Message::Ping(Vec::new())).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Tells the client why we close the WebSocket.
+async fn close_websocket(socket: &mut WebSocket, code: u16, reason: &'static str) {
+    let frame = CloseFrame {
+        code,
+        reason: reason.into(),
+    };
+
+    let _ = socket.send(Message::Close(Some(frame))).await;
+}
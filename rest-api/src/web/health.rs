@@ -0,0 +1,197 @@
+//! This module contains the health endpoints that the container platform uses to decide what to do with an instance.
+//!
+//! * `/healthz` tells whether the process is alive. It doesn't touch the database, so a database outage doesn't cause
+//!   the platform to restart healthy instances.
+//! * `/readyz` tells whether the instance can handle requests. It checks the database connection with a timeout,
+//!   reports how busy the connection pool is and lists migrations that aren't applied yet. It starts failing as soon as
+//!   the application is shutting down, so the platform stops sending new requests while in-flight requests drain.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::Serialize;
+use tracing::warn;
+use utoipa::ToSchema;
+
+use crate::{db, state::AppState};
+
+/// The maximum time the database check in the readiness endpoint may take.
+const DATABASE_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The status of a single check or of the instance as a whole.
+#[derive(Serialize, ToSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Failing,
+}
+
+/// The response of the liveness endpoint.
+#[derive(Serialize, ToSchema)]
+pub struct HealthResponse {
+    pub status: CheckStatus,
+}
+
+/// The response of the readiness endpoint.
+#[derive(Serialize, ToSchema)]
+pub struct ReadinessResponse {
+    pub status: CheckStatus,
+    pub shutting_down: bool,
+    pub database: DatabaseCheck,
+    pub pool: PoolStatus,
+    pub migrations: MigrationsCheck,
+}
+
+/// The outcome of the database connection check.
+#[derive(Serialize, ToSchema)]
+pub struct DatabaseCheck {
+    pub status: CheckStatus,
+
+    /// The time it took to run the check in milliseconds.
+    pub latency_ms: u128,
+
+    /// The reason the check failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// How busy the connection pool is.
+#[derive(Serialize, ToSchema)]
+pub struct PoolStatus {
+    /// The number of open connections.
+    pub size: u32,
+
+    /// The number of open connections that aren't in use.
+    pub idle: usize,
+
+    /// The maximum number of connections in the pool.
+    pub max_connections: u32,
+
+    /// The fraction of the maximum number of connections that is in use.
+    pub saturation: f64,
+}
+
+/// The outcome of the migrations check.
+#[derive(Serialize, ToSchema)]
+pub struct MigrationsCheck {
+    pub status: CheckStatus,
+
+    /// The migrations that still need to be applied to the database.
+    pub pending: Vec<String>,
+}
+
+/// Tells whether the process is alive.
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "operations",
+    responses((status = 200, description = "The process is alive.", body = HealthResponse))
+)]
+pub async fn liveness() -> impl IntoResponse {
+    Json(HealthResponse {
+        status: CheckStatus::Ok,
+    })
+}
+
+/// Tells whether the instance is ready to handle requests.
+///
+/// We respond with a 503 when one of the checks fails or when the application is shutting down.
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "operations",
+    responses(
+        (status = 200, description = "The instance can handle requests.", body = ReadinessResponse),
+        (status = 503, description = "A check failed or the instance is shutting down.", body = ReadinessResponse)
+    )
+)]
+pub async fn readiness(State(app_state): State<Arc<AppState>>) -> impl IntoResponse {
+    let pool = &app_state.connection_pool;
+    let shutting_down = app_state.is_shutting_down();
+
+    let started_at = Instant::now();
+    let database_result = tokio::time::timeout(DATABASE_CHECK_TIMEOUT, async {
+        db::ping(pool).await?;
+        db::pending_migrations(pool).await
+    })
+    .await;
+    let latency_ms = started_at.elapsed().as_millis();
+
+    let (database, migrations) = match database_result {
+        Ok(Ok(pending)) => (
+            DatabaseCheck {
+                status: CheckStatus::Ok,
+                latency_ms,
+                error: None,
+            },
+            MigrationsCheck {
+                status: if pending.is_empty() {
+                    CheckStatus::Ok
+                } else {
+                    CheckStatus::Failing
+                },
+                pending,
+            },
+        ),
+        Ok(Err(err)) => {
+            warn!("Readiness check failed: {}", err);
+            failed_database_checks(latency_ms, err.to_string())
+        }
+        Err(_) => {
+            warn!("Readiness check timed out");
+            failed_database_checks(latency_ms, "The database check timed out.".to_string())
+        }
+    };
+
+    let size = pool.size();
+    let idle = pool.num_idle();
+    let max_connections = pool.options().get_max_connections();
+
+    let pool_status = PoolStatus {
+        size,
+        idle,
+        max_connections,
+        saturation: (size as f64 - idle as f64).max(0.0) / f64::from(max_connections.max(1)),
+    };
+
+    let status = if !shutting_down
+        && database.status == CheckStatus::Ok
+        && migrations.status == CheckStatus::Ok
+    {
+        CheckStatus::Ok
+    } else {
+        CheckStatus::Failing
+    };
+
+    let status_code = match status {
+        CheckStatus::Ok => StatusCode::OK,
+        CheckStatus::Failing => StatusCode::SERVICE_UNAVAILABLE,
+    };
+
+    (
+        status_code,
+        Json(ReadinessResponse {
+            status,
+            shutting_down,
+            database,
+            pool: pool_status,
+            migrations,
+        }),
+    )
+}
+
+/// Reports both the database and the migrations as failing, because we couldn't check the migrations either.
+fn failed_database_checks(latency_ms: u128, error: String) -> (DatabaseCheck, MigrationsCheck) {
+    (
+        DatabaseCheck {
+            status: CheckStatus::Failing,
+            latency_ms,
+            error: Some(error),
+        },
+        MigrationsCheck {
+            status: CheckStatus::Failing,
+            pending: Vec::new(),
+        },
+    )
+}
@@ -0,0 +1,88 @@
+//! This module contains the admin endpoints to inspect the background job queue.
+//!
+//! Operators can see how many jobs are waiting, find out why jobs ended up in the dead letter queue, and retry or
+//! remove them once the cause is fixed. The endpoints need the admin token, see [`crate::auth::Administrator`]. The
+//! jobs themselves are run by [`crate::jobs`].
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+use tracing::{info, instrument};
+
+use crate::{auth::Administrator, db, entity::JobStatus, error::AppError, state::AppState};
+
+/// The number of jobs on a page.
+const JOBS_PAGE_SIZE: i32 = 50;
+
+/// Defines the querystring parameters for listing jobs.
+#[derive(Deserialize, Debug)]
+pub struct JobListQuery {
+    /// Only list the jobs with this status, like `dead`.
+    pub status: Option<JobStatus>,
+
+    /// The index of the page to retrieve.
+    #[serde(default)]
+    pub page: i32,
+}
+
+/// Lists the background jobs, oldest first.
+#[instrument(skip(app_state, _administrator))]
+pub async fn list_jobs(
+    State(app_state): State<Arc<AppState>>,
+    _administrator: Administrator,
+    Query(query): Query<JobListQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let jobs = db::list_jobs(
+        &app_state.connection_pool,
+        query.status,
+        query.page,
+        JOBS_PAGE_SIZE,
+    )
+    .await?;
+
+    Ok(Json(jobs))
+}
+
+/// Returns the number of pending, running and dead jobs.
+#[instrument(skip(app_state, _administrator))]
+pub async fn job_counts(
+    State(app_state): State<Arc<AppState>>,
+    _administrator: Administrator,
+) -> Result<impl IntoResponse, AppError> {
+    let counts = db::count_jobs_by_status(&app_state.connection_pool).await?;
+    Ok(Json(counts))
+}
+
+/// Moves a job from the dead letter queue back into the queue, with a fresh set of attempts.
+#[instrument(skip(app_state, _administrator))]
+pub async fn retry_job(
+    State(app_state): State<Arc<AppState>>,
+    _administrator: Administrator,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, AppError> {
+    let job = db::retry_dead_job(&app_state.connection_pool, id).await?;
+
+    info!(job_id = id, "Retrying dead job");
+
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+/// Removes a job from the dead letter queue without running it.
+#[instrument(skip(app_state, _administrator))]
+pub async fn delete_job(
+    State(app_state): State<Arc<AppState>>,
+    _administrator: Administrator,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, AppError> {
+    db::delete_dead_job(&app_state.connection_pool, id).await?;
+
+    info!(job_id = id, "Removed dead job");
+
+    Ok((StatusCode::NO_CONTENT, ()))
+}
@@ -0,0 +1,46 @@
+//! This module contains the endpoint that exposes the metrics of the application in the Prometheus text format.
+//!
+//! Most metrics are recorded while the application handles requests. The gauges for the connection pool and the
+//! number of tasks and jobs are recorded right before we render the metrics, so they're always up to date when they're scraped.
+//! Please check out the [`crate::monitoring`] module for the full list of metrics.
+//!
+//! The metrics reveal how the API is used, so on the regular server port they need the admin token. On a separate
+//! admin port, which isn't reachable through the public ingress, Prometheus can scrape them without a token.
+
+use std::sync::Arc;
+
+use axum::{extract::State, http::header, response::IntoResponse};
+use tracing::warn;
+
+use crate::{auth::Administrator, db, monitoring, state::AppState};
+
+/// Renders the metrics in the Prometheus text format for operators that send the admin token.
+pub async fn metrics(
+    State(app_state): State<Arc<AppState>>,
+    _administrator: Administrator,
+) -> impl IntoResponse {
+    internal_metrics(State(app_state)).await
+}
+
+/// Renders the metrics in the Prometheus text format, without checking the admin token.
+///
+/// This handler is only routed on the separate admin port.
+pub async fn internal_metrics(State(app_state): State<Arc<AppState>>) -> impl IntoResponse {
+    monitoring::record_pool_status(&app_state.connection_pool);
+
+    // A failing database shouldn't break the metrics endpoint, because that's exactly when we need the metrics.
+    match db::count_tasks_by_state(&app_state.connection_pool).await {
+        Ok((open, completed)) => monitoring::record_task_counts(open, completed),
+        Err(err) => warn!("Failed to count tasks for the metrics: {}", err),
+    }
+
+    match db::count_jobs_by_status(&app_state.connection_pool).await {
+        Ok(counts) => monitoring::record_job_counts(counts.pending, counts.running, counts.dead),
+        Err(err) => warn!("Failed to count jobs for the metrics: {}", err),
+    }
+
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        monitoring::prometheus_handle().render(),
+    )
+}
@@ -0,0 +1,136 @@
+//! This module contains the endpoints for the in-app inbox of the authenticated user.
+//!
+//! The inbox holds the notifications that were sent through the `in_app` channel, like reminders of tasks. Users mark
+//! notifications as read or unread, or mark the whole inbox as read at once.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+use tracing::instrument;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    access,
+    auth::AuthenticatedUser,
+    db,
+    entity::{Notification, PagedResult},
+    error::{AppError, ErrorDetails},
+    state::AppState,
+};
+
+/// The number of notifications on a page.
+const NOTIFICATIONS_PAGE_SIZE: i32 = 20;
+
+/// Defines the querystring parameters for listing notifications.
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct NotificationListQuery {
+    /// The index of the page to retrieve.
+    #[serde(default)]
+    pub page: i32,
+
+    /// Only list the notifications that weren't read yet.
+    #[serde(default)]
+    pub unread: bool,
+}
+
+/// Defines the fields of a notification that can be changed.
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct UpdateNotificationForm {
+    /// Whether the notification is read.
+    pub read: bool,
+}
+
+/// Lists the notifications of the authenticated user, newest first.
+#[utoipa::path(
+    get,
+    path = "/v1/notifications",
+    tag = "notifications",
+    params(NotificationListQuery),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses((status = 200, description = "A page of the inbox.", body = PagedResult<Notification>))
+)]
+#[instrument(skip(app_state))]
+pub async fn list_notifications(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+    Query(query): Query<NotificationListQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    access::require_unscoped(workspace_id)?;
+
+    let notifications = db::list_notifications(
+        &app_state.connection_pool,
+        user_id,
+        query.unread,
+        query.page,
+        NOTIFICATIONS_PAGE_SIZE,
+    )
+    .await?;
+
+    Ok(Json(notifications))
+}
+
+/// Marks a notification of the authenticated user as read or unread.
+#[utoipa::path(
+    patch,
+    path = "/v1/notifications/{id}",
+    tag = "notifications",
+    params(("id" = i64, Path, description = "The ID of the notification.")),
+    request_body = UpdateNotificationForm,
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 200, description = "The updated notification.", body = Notification),
+        (status = 404, description = "The notification doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn update_notification(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+    Path(id): Path<i64>,
+    Json(form): Json<UpdateNotificationForm>,
+) -> Result<impl IntoResponse, AppError> {
+    access::require_unscoped(workspace_id)?;
+
+    let notification =
+        db::mark_notification_read(&app_state.connection_pool, user_id, id, form.read).await?;
+
+    Ok(Json(notification))
+}
+
+/// Marks all notifications of the authenticated user as read.
+#[utoipa::path(
+    post,
+    path = "/v1/notifications/read",
+    tag = "notifications",
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses((status = 204, description = "All notifications are read."))
+)]
+#[instrument(skip(app_state))]
+pub async fn mark_all_read(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+) -> Result<impl IntoResponse, AppError> {
+    access::require_unscoped(workspace_id)?;
+
+    db::mark_all_notifications_read(&app_state.connection_pool, user_id).await?;
+    Ok((StatusCode::NO_CONTENT, ()))
+}
@@ -0,0 +1,183 @@
+//! This module contains the OpenAPI document of the API and the interactive documentation.
+//!
+//! The document is generated from the [`utoipa::path`] annotations on the handlers in [`crate::web`] and the types
+//! that derive [`utoipa::ToSchema`], so it can't drift away from the code like hand-written documentation does. The
+//! document is served at `/openapi.json`, and `/docs` renders it with a bundled Swagger UI. Swagger UI is compiled
+//! into the binary, so the documentation works without access to the internet.
+//!
+//! When you add a route to [`crate::web::create_router`], you need to add its handler to the `paths` of [`ApiDoc`] as
+//! well. The `openapi_test` integration test fails when the routes and the document don't match.
+
+use axum::{
+    http::{header, HeaderValue},
+    Router,
+};
+use tower_http::set_header::SetResponseHeaderLayer;
+use utoipa::{
+    openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi,
+};
+use utoipa_swagger_ui::SwaggerUi;
+
+/// The content security policy for the documentation. Swagger UI needs to load its own scripts and styles, which the
+/// policy for the API doesn't allow.
+const DOCS_CONTENT_SECURITY_POLICY: &str =
+    "default-src 'self'; style-src 'self' 'unsafe-inline'; img-src 'self' data:; frame-ancestors 'none'";
+
+/// The OpenAPI document of the API.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Task management API",
+        description = "A REST API to manage your tasks. Clients that make too many requests get a 429 response, and \
+                       requests the server can't answer within the timeout get a 503 response."
+    ),
+    paths(
+        super::list_tasks,
+        super::task_details,
+        super::create_task,
+        super::update_task,
+        super::delete_todo,
+        super::register_user,
+        super::verify_user,
+        super::resend_verification,
+        super::current_user,
+        super::update_current_user,
+        super::delete_current_user,
+        super::export_current_user,
+        super::issue_token,
+        super::oidc_login,
+        super::oidc_callback,
+        super::events::task_events,
+        super::webhooks::list_webhooks,
+        super::webhooks::create_webhook,
+        super::webhooks::webhook_details,
+        super::webhooks::update_webhook,
+        super::webhooks::delete_webhook,
+        super::webhooks::list_deliveries,
+        super::webhooks::redeliver,
+        super::reminders::list_reminders,
+        super::reminders::create_reminder,
+        super::reminders::delete_reminder,
+        super::notifications::list_notifications,
+        super::notifications::update_notification,
+        super::notifications::mark_all_read,
+        super::projects::list_projects,
+        super::projects::create_project,
+        super::projects::project_details,
+        super::projects::update_project,
+        super::projects::delete_project,
+        super::projects::list_project_tasks,
+        super::projects::add_project_task,
+        super::projects::remove_project_task,
+        super::shares::list_task_shares,
+        super::shares::share_task,
+        super::shares::revoke_task_share,
+        super::shares::list_project_shares,
+        super::shares::share_project,
+        super::shares::revoke_project_share,
+        super::assignments::list_assignees,
+        super::assignments::assign_user,
+        super::assignments::unassign_user,
+        super::assignments::list_watchers,
+        super::assignments::watch_task,
+        super::assignments::unwatch_task,
+        super::comments::list_comments,
+        super::comments::create_comment,
+        super::comments::update_comment,
+        super::comments::delete_comment,
+        super::comments::list_comment_revisions,
+        super::attachments::list_attachments,
+        super::attachments::upload_attachment,
+        super::attachments::delete_attachment,
+        super::attachments::attachment_url,
+        super::attachments::download_attachment,
+        super::dependencies::add_blocker,
+        super::dependencies::remove_blocker,
+        super::dependencies::task_graph,
+        super::workspaces::list_workspaces,
+        super::workspaces::create_workspace,
+        super::workspaces::workspace_details,
+        super::workspaces::update_workspace,
+        super::workspaces::delete_workspace,
+        super::workspaces::list_members,
+        super::workspaces::update_member,
+        super::workspaces::remove_member,
+        super::workspaces::list_invitations,
+        super::workspaces::invite_member,
+        super::workspaces::withdraw_invitation,
+        super::workspaces::list_my_invitations,
+        super::workspaces::accept_invitation,
+        super::workspaces::decline_invitation,
+        super::workspaces::list_keys,
+        super::workspaces::create_key,
+        super::workspaces::revoke_key,
+        super::health::liveness,
+        super::health::readiness,
+    ),
+    modifiers(&SecuritySchemes),
+    tags(
+        (name = "tasks", description = "Manage the tasks of the authenticated user."),
+        (name = "users", description = "Register users and manage their profile."),
+        (name = "auth", description = "Obtain bearer tokens."),
+        (name = "webhooks", description = "Receive the changes to your tasks on your own server."),
+        (name = "reminders", description = "Get reminded of tasks before they're due."),
+        (name = "notifications", description = "Read the notifications in your in-app inbox."),
+        (name = "projects", description = "Group tasks in projects."),
+        (name = "sharing", description = "Share tasks and projects with other users."),
+        (name = "workspaces", description = "Manage workspaces, their members, invitations and API keys."),
+        (name = "assignments", description = "Assign users to tasks and watch tasks."),
+        (name = "comments", description = "Discuss tasks in comments."),
+        (name = "attachments", description = "Attach files to tasks."),
+        (name = "dependencies", description = "Make tasks wait for other tasks."),
+        (name = "operations", description = "Endpoints for the container platform.")
+    )
+)]
+pub struct ApiDoc;
+
+/// Adds the ways clients can authenticate to the document.
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::with_description(
+                "X-Api-Key",
+                "The API key you received when you registered.",
+            ))),
+        );
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .description(Some("An access token from `POST /v1/auth/token`."))
+                    .build(),
+            ),
+        );
+        components.add_security_scheme(
+            "client_certificate",
+            SecurityScheme::MutualTls {
+                description: Some(
+                    "A client certificate with the email address of the user, when the server terminates TLS."
+                        .to_string(),
+                ),
+                extensions: None,
+            },
+        );
+    }
+}
+
+/// Creates the router that serves the OpenAPI document and the interactive documentation.
+pub fn create_docs_router() -> Router {
+    Router::from(SwaggerUi::new("/docs").url("/openapi.json", ApiDoc::openapi())).layer(
+        SetResponseHeaderLayer::overriding(
+            header::CONTENT_SECURITY_POLICY,
+            HeaderValue::from_static(DOCS_CONTENT_SECURITY_POLICY),
+        ),
+    )
+}
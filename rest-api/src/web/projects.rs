@@ -0,0 +1,325 @@
+//! This module contains the endpoints to manage projects and the tasks in them.
+//!
+//! A project groups tasks of its owner. Users see their own projects and the projects others shared with them, and
+//! sharing a project gives access to every task in it, see the `shares` submodule. Editors of a project can rename it
+//! and move their own tasks in and out of it. Only the owner can remove the project. The tasks stay when the project
+//! is removed, they no longer belong to a project.
+//!
+//! Projects in a workspace are accessible to every member, and only hold tasks of the same workspace.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+use tracing::instrument;
+use utoipa::{IntoParams, ToSchema};
+
+use super::Pagination;
+use crate::{
+    access,
+    auth::{AuthenticatedUser, VerifiedUser},
+    db,
+    entity::{PagedResult, Project, Role, Task, WorkspaceRole},
+    error::{AppError, ErrorDetails},
+    state::AppState,
+};
+
+/// The number of tasks on a page of a project.
+const PROJECT_TASKS_PAGE_SIZE: i32 = 10;
+
+/// Defines the querystring parameters for listing projects.
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ProjectListQuery {
+    /// Only list the projects in this workspace.
+    pub workspace: Option<i32>,
+}
+
+/// Defines the fields to create a project.
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct CreateProjectForm {
+    /// The name of the project.
+    pub name: String,
+
+    /// The workspace to create the project in. Without a workspace, the project is a personal project.
+    #[serde(default)]
+    pub workspace_id: Option<i32>,
+}
+
+/// Defines the fields to rename a project.
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct UpdateProjectForm {
+    /// The new name of the project.
+    pub name: String,
+}
+
+/// Lists the projects of the authenticated user and the projects others shared with them.
+#[utoipa::path(
+    get,
+    path = "/v1/projects",
+    tag = "projects",
+    params(ProjectListQuery),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses((status = 200, description = "The projects the user can access.", body = Vec<Project>))
+)]
+#[instrument(skip(app_state))]
+pub async fn list_projects(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+    Query(query): Query<ProjectListQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let workspace_id = access::scope(workspace_id, query.workspace)?;
+
+    let projects = db::list_projects(&app_state.connection_pool, user_id, workspace_id).await?;
+    Ok(Json(projects))
+}
+
+/// Creates a project for the authenticated user.
+#[utoipa::path(
+    post,
+    path = "/v1/projects",
+    tag = "projects",
+    request_body = CreateProjectForm,
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 201, description = "The project was created.", body = Project),
+        (status = 403, description = "The email address isn't verified.", body = ErrorDetails),
+        (status = 404, description = "The workspace doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state, form))]
+pub async fn create_project(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Json(form): Json<CreateProjectForm>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    let workspace_id = access::scope(workspace_id, form.workspace_id)?;
+
+    if let Some(workspace_id) = workspace_id {
+        access::workspace_access(pool, user_id, None, workspace_id, WorkspaceRole::Member).await?;
+    }
+
+    let project_id = db::insert_project(pool, user_id, workspace_id, form.name).await?;
+    let project = db::find_project(pool, user_id, project_id).await?;
+
+    Ok((StatusCode::CREATED, Json(project)))
+}
+
+/// Retrieves a project the authenticated user can access.
+#[utoipa::path(
+    get,
+    path = "/v1/projects/{id}",
+    tag = "projects",
+    params(("id" = i32, Path, description = "The ID of the project.")),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 200, description = "The project.", body = Project),
+        (status = 404, description = "The project doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn project_details(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    access::project_access(pool, user_id, workspace_id, id, Role::Viewer).await?;
+
+    let project = db::find_project(pool, user_id, id).await?;
+    Ok(Json(project))
+}
+
+/// Renames a project. Owners and editors of the project can rename it.
+#[utoipa::path(
+    put,
+    path = "/v1/projects/{id}",
+    tag = "projects",
+    params(("id" = i32, Path, description = "The ID of the project.")),
+    request_body = UpdateProjectForm,
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 200, description = "The renamed project.", body = Project),
+        (status = 403, description = "The project is shared with the user as a viewer.", body = ErrorDetails),
+        (status = 404, description = "The project doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state, form))]
+pub async fn update_project(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path(id): Path<i32>,
+    Json(form): Json<UpdateProjectForm>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    let access = access::project_access(pool, user_id, workspace_id, id, Role::Editor).await?;
+
+    db::update_project(pool, access.owner_id, id, form.name).await?;
+    let project = db::find_project(pool, user_id, id).await?;
+
+    Ok(Json(project))
+}
+
+/// Removes a project. Only the owner can remove it, the tasks in the project stay.
+#[utoipa::path(
+    delete,
+    path = "/v1/projects/{id}",
+    tag = "projects",
+    params(("id" = i32, Path, description = "The ID of the project.")),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 204, description = "The project was removed."),
+        (status = 403, description = "Only the owner can remove the project.", body = ErrorDetails),
+        (status = 404, description = "The project doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn delete_project(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    let access = access::project_access(pool, user_id, workspace_id, id, Role::Owner).await?;
+
+    db::delete_project(pool, access.owner_id, id).await?;
+
+    Ok((StatusCode::NO_CONTENT, ()))
+}
+
+/// Lists the tasks in a project the authenticated user can access.
+#[utoipa::path(
+    get,
+    path = "/v1/projects/{id}/tasks",
+    tag = "projects",
+    params(("id" = i32, Path, description = "The ID of the project."), Pagination),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 200, description = "A page with the tasks in the project.", body = PagedResult<Task>),
+        (status = 404, description = "The project doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn list_project_tasks(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+    Path(id): Path<i32>,
+    Query(pagination): Query<Pagination>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    access::project_access(pool, user_id, workspace_id, id, Role::Viewer).await?;
+
+    let tasks = db::list_project_tasks(pool, id, pagination.page, PROJECT_TASKS_PAGE_SIZE).await?;
+
+    Ok(Json(tasks))
+}
+
+/// Moves a task of the authenticated user into a project they can edit.
+///
+/// Only the owner of a task can move it, because moving it into a shared project shares the task as well. The task and
+/// the project must belong to the same workspace, or both be personal.
+#[utoipa::path(
+    put,
+    path = "/v1/projects/{id}/tasks/{task_id}",
+    tag = "projects",
+    params(
+        ("id" = i32, Path, description = "The ID of the project."),
+        ("task_id" = i32, Path, description = "The ID of the task.")
+    ),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 204, description = "The task is in the project."),
+        (status = 400, description = "The task and the project belong to different workspaces.", body = ErrorDetails),
+        (status = 403, description = "The user doesn't own the task or can't edit the project.", body = ErrorDetails),
+        (status = 404, description = "The project or task doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn add_project_task(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path((id, task_id)): Path<(i32, i32)>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    let project = access::project_access(pool, user_id, workspace_id, id, Role::Editor).await?;
+    let task = access::task_access(pool, user_id, workspace_id, task_id, Role::Owner).await?;
+
+    if project.workspace_id != task.workspace_id {
+        return Err(AppError::WorkspaceMismatch);
+    }
+
+    db::set_task_project(pool, task.owner_id, task_id, Some(id)).await?;
+
+    Ok((StatusCode::NO_CONTENT, ()))
+}
+
+/// Moves a task of the authenticated user out of a project.
+#[utoipa::path(
+    delete,
+    path = "/v1/projects/{id}/tasks/{task_id}",
+    tag = "projects",
+    params(
+        ("id" = i32, Path, description = "The ID of the project."),
+        ("task_id" = i32, Path, description = "The ID of the task.")
+    ),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 204, description = "The task is no longer in the project."),
+        (status = 403, description = "The user doesn't own the task.", body = ErrorDetails),
+        (status = 404, description = "The task isn't in the project.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn remove_project_task(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path((id, task_id)): Path<(i32, i32)>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    let task = access::task_access(pool, user_id, workspace_id, task_id, Role::Owner).await?;
+
+    if db::find_task(pool, task.owner_id, task_id)
+        .await?
+        .project_id
+        != Some(id)
+    {
+        return Err(AppError::TaskNotFound);
+    }
+
+    db::set_task_project(pool, task.owner_id, task_id, None).await?;
+
+    Ok((StatusCode::NO_CONTENT, ()))
+}
@@ -0,0 +1,217 @@
+//! This module contains the endpoints to manage the reminders of a task.
+//!
+//! A reminder fires either a number of minutes before the task is due, or at a time of day on the date the task is
+//! due, in the time zone of the user. Reminders of tasks without a deadline wait until the task gets one. The
+//! reminders themselves are sent by [`crate::reminders`].
+//!
+//! Reminders are sent to the owner of the task. Everyone who can see the task lists its reminders, and owners and
+//! editors of the task add and remove them.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+use crate::{
+    access,
+    auth::{AuthenticatedUser, VerifiedUser},
+    db,
+    entity::{Reminder, ReminderChannel, Role},
+    error::{AppError, ErrorDetails},
+    state::AppState,
+};
+
+/// Defines the fields that can be used to add a reminder to a task.
+///
+/// Set either `minutes_before` or `time_of_day`. Without channels, the reminder ends up in the in-app inbox.
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct CreateReminderForm {
+    /// The number of minutes before the deadline to fire, like `1440` for a day before.
+    pub minutes_before: Option<i32>,
+
+    /// The local time on the date the task is due to fire, like `09:00`.
+    #[schema(value_type = Option<String>, example = "09:00")]
+    pub time_of_day: Option<chrono::NaiveTime>,
+
+    /// The ways the reminder reaches the user.
+    #[serde(default = "default_channels")]
+    pub channels: Vec<ReminderChannel>,
+}
+
+/// Returns the channels of a reminder that doesn't list any.
+fn default_channels() -> Vec<ReminderChannel> {
+    vec![ReminderChannel::InApp]
+}
+
+/// Checks that a reminder fires at exactly one moment through at least one channel, and removes channels that are
+/// listed more than once.
+fn validate_reminder(form: &CreateReminderForm) -> Result<Vec<ReminderChannel>, AppError> {
+    let fires_once = match (form.minutes_before, form.time_of_day) {
+        (Some(minutes_before), None) => minutes_before >= 0,
+        (None, Some(_)) => true,
+        _ => false,
+    };
+
+    let mut channels = Vec::with_capacity(form.channels.len());
+
+    for &channel in &form.channels {
+        if !channels.contains(&channel) {
+            channels.push(channel);
+        }
+    }
+
+    if !fires_once || channels.is_empty() {
+        return Err(AppError::InvalidReminder);
+    }
+
+    Ok(channels)
+}
+
+/// Lists the reminders of a task.
+#[utoipa::path(
+    get,
+    path = "/v1/todos/{id}/reminders",
+    tag = "reminders",
+    params(("id" = i32, Path, description = "The ID of the task.")),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 200, description = "The reminders of the task.", body = Vec<Reminder>),
+        (status = 404, description = "The task doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn list_reminders(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    let access = access::task_access(pool, user_id, workspace_id, id, Role::Viewer).await?;
+
+    let reminders = db::list_reminders(pool, access.owner_id, id).await?;
+    Ok(Json(reminders))
+}
+
+/// Adds a reminder to a task. Owners and editors of the task can add reminders.
+///
+/// The response shows when the reminder fires in UTC, which is empty while the task has no deadline.
+#[utoipa::path(
+    post,
+    path = "/v1/todos/{id}/reminders",
+    tag = "reminders",
+    params(("id" = i32, Path, description = "The ID of the task.")),
+    request_body = CreateReminderForm,
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 201, description = "The reminder was added.", body = Reminder),
+        (status = 400, description = "The reminder doesn't fire at exactly one moment or has no channels.", body = ErrorDetails),
+        (status = 403, description = "The task is shared with the user as a viewer.", body = ErrorDetails),
+        (status = 404, description = "The task doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state, form))]
+pub async fn create_reminder(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path(id): Path<i32>,
+    Json(form): Json<CreateReminderForm>,
+) -> Result<impl IntoResponse, AppError> {
+    let channels = validate_reminder(&form)?;
+
+    let pool = &app_state.connection_pool;
+    let access = access::task_access(pool, user_id, workspace_id, id, Role::Editor).await?;
+
+    let reminder = db::insert_reminder(
+        pool,
+        access.owner_id,
+        id,
+        form.minutes_before,
+        form.time_of_day,
+        &channels,
+    )
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(reminder)))
+}
+
+/// Removes a reminder from a task. Owners and editors of the task can remove reminders.
+#[utoipa::path(
+    delete,
+    path = "/v1/todos/{id}/reminders/{reminder_id}",
+    tag = "reminders",
+    params(
+        ("id" = i32, Path, description = "The ID of the task."),
+        ("reminder_id" = i32, Path, description = "The ID of the reminder.")
+    ),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 204, description = "The reminder was removed."),
+        (status = 403, description = "The task is shared with the user as a viewer.", body = ErrorDetails),
+        (status = 404, description = "The task or reminder doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn delete_reminder(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path((id, reminder_id)): Path<(i32, i32)>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    let access = access::task_access(pool, user_id, workspace_id, id, Role::Editor).await?;
+
+    db::delete_reminder(pool, access.owner_id, id, reminder_id).await?;
+    Ok((StatusCode::NO_CONTENT, ()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn form(minutes_before: Option<i32>, time_of_day: Option<&str>) -> CreateReminderForm {
+        CreateReminderForm {
+            minutes_before,
+            time_of_day: time_of_day.map(|time| time.parse().unwrap()),
+            channels: vec![ReminderChannel::Email, ReminderChannel::Email],
+        }
+    }
+
+    #[test]
+    fn reminder_fires_at_exactly_one_moment() {
+        assert!(validate_reminder(&form(Some(1440), None)).is_ok());
+        assert!(validate_reminder(&form(None, Some("09:00"))).is_ok());
+        assert!(validate_reminder(&form(None, None)).is_err());
+        assert!(validate_reminder(&form(Some(60), Some("09:00"))).is_err());
+        assert!(validate_reminder(&form(Some(-5), None)).is_err());
+    }
+
+    #[test]
+    fn duplicate_channels_are_removed() {
+        let channels = validate_reminder(&form(Some(60), None)).unwrap();
+        assert_eq!(channels, vec![ReminderChannel::Email]);
+
+        let mut without_channels = form(Some(60), None);
+        without_channels.channels.clear();
+
+        assert!(matches!(
+            validate_reminder(&without_channels),
+            Err(AppError::InvalidReminder)
+        ));
+    }
+}
@@ -0,0 +1,312 @@
+//! This module contains the endpoints to share tasks and projects with other users.
+//!
+//! The owner of a task or project invites another registered user by their email address, as a viewer or an editor.
+//! Inviting the same user again changes their role, and removing the share revokes their access right away. The
+//! invited user gets an email, and finds the shared items in their own lists. Only the owner manages the shares of an
+//! item, see [`crate::access`] for the rules.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+use tracing::{instrument, warn};
+use utoipa::ToSchema;
+
+use crate::{
+    access,
+    auth::{AuthenticatedUser, VerifiedUser},
+    db,
+    entity::{Role, Share, SharedItem},
+    error::{AppError, ErrorDetails},
+    mail::Email,
+    state::AppState,
+};
+
+/// Defines the fields to share a task or project with another user.
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct ShareForm {
+    /// The email address of the user to share the item with.
+    pub email_address: String,
+
+    /// What the user can do with the item, `viewer` or `editor`.
+    pub role: Role,
+}
+
+/// Checks that the user owns the shared item, within the scope of their credentials.
+async fn require_owner(
+    app_state: &AppState,
+    user_id: i32,
+    scope: Option<i32>,
+    item: SharedItem,
+) -> Result<(), AppError> {
+    let pool = &app_state.connection_pool;
+
+    match item {
+        SharedItem::Task(task_id) => {
+            access::task_access(pool, user_id, scope, task_id, Role::Owner).await?
+        }
+        SharedItem::Project(project_id) => {
+            access::project_access(pool, user_id, scope, project_id, Role::Owner).await?
+        }
+    };
+
+    Ok(())
+}
+
+/// Shares an item of the user and lets the invited user know by email.
+///
+/// The share is in place once it's stored. Failing to send the email doesn't undo it, the invited user finds the item
+/// in their lists either way.
+async fn share_item(
+    app_state: &AppState,
+    user_id: i32,
+    scope: Option<i32>,
+    item: SharedItem,
+    form: ShareForm,
+) -> Result<Share, AppError> {
+    if form.role == Role::Owner {
+        return Err(AppError::InvalidShare);
+    }
+
+    require_owner(app_state, user_id, scope, item).await?;
+
+    let share = db::insert_share(
+        &app_state.connection_pool,
+        user_id,
+        item,
+        &form.email_address,
+        form.role,
+    )
+    .await?;
+
+    let (kind, path) = match item {
+        SharedItem::Task(task_id) => ("a task", format!("/v1/todos/{}", task_id)),
+        SharedItem::Project(project_id) => ("a project", format!("/v1/projects/{}", project_id)),
+    };
+
+    let email = Email {
+        to: share.email_address.clone(),
+        subject: format!("Someone shared {} with you", kind),
+        body: format!(
+            "You can now access {} as {}. You find it in your lists, or at GET {}.",
+            kind,
+            match share.role {
+                Role::Editor => "an editor",
+                _ => "a viewer",
+            },
+            path
+        ),
+    };
+
+    if let Err(err) = app_state.mailer.send(&email).await {
+        warn!(
+            share_id = share.id,
+            "Failed to send the invitation: {}", err
+        );
+    }
+
+    Ok(share)
+}
+
+/// Lists the users a task of the authenticated user is shared with.
+#[utoipa::path(
+    get,
+    path = "/v1/todos/{id}/shares",
+    tag = "sharing",
+    params(("id" = i32, Path, description = "The ID of the task.")),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 200, description = "The shares of the task.", body = Vec<Share>),
+        (status = 403, description = "Only the owner can see the shares.", body = ErrorDetails),
+        (status = 404, description = "The task doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn list_task_shares(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let item = SharedItem::Task(id);
+    require_owner(&app_state, user_id, workspace_id, item).await?;
+
+    let shares = db::list_shares(&app_state.connection_pool, item).await?;
+    Ok(Json(shares))
+}
+
+/// Shares a task of the authenticated user with another user, or changes the role of a user it's shared with.
+#[utoipa::path(
+    post,
+    path = "/v1/todos/{id}/shares",
+    tag = "sharing",
+    params(("id" = i32, Path, description = "The ID of the task.")),
+    request_body = ShareForm,
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 201, description = "The task is shared.", body = Share),
+        (status = 400, description = "The user can't share the task with themselves, or as an owner.", body = ErrorDetails),
+        (status = 403, description = "Only the owner can share the task.", body = ErrorDetails),
+        (status = 404, description = "The task or the user doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state, form))]
+pub async fn share_task(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path(id): Path<i32>,
+    Json(form): Json<ShareForm>,
+) -> Result<impl IntoResponse, AppError> {
+    let share = share_item(
+        &app_state,
+        user_id,
+        workspace_id,
+        SharedItem::Task(id),
+        form,
+    )
+    .await?;
+    Ok((StatusCode::CREATED, Json(share)))
+}
+
+/// Revokes the access of a user to a task of the authenticated user.
+#[utoipa::path(
+    delete,
+    path = "/v1/todos/{id}/shares/{share_id}",
+    tag = "sharing",
+    params(
+        ("id" = i32, Path, description = "The ID of the task."),
+        ("share_id" = i32, Path, description = "The ID of the share.")
+    ),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 204, description = "The share was removed."),
+        (status = 403, description = "Only the owner can revoke access.", body = ErrorDetails),
+        (status = 404, description = "The task or the share doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn revoke_task_share(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path((id, share_id)): Path<(i32, i32)>,
+) -> Result<impl IntoResponse, AppError> {
+    let item = SharedItem::Task(id);
+    require_owner(&app_state, user_id, workspace_id, item).await?;
+
+    db::delete_share(&app_state.connection_pool, item, share_id).await?;
+
+    Ok((StatusCode::NO_CONTENT, ()))
+}
+
+/// Lists the users a project of the authenticated user is shared with.
+#[utoipa::path(
+    get,
+    path = "/v1/projects/{id}/shares",
+    tag = "sharing",
+    params(("id" = i32, Path, description = "The ID of the project.")),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 200, description = "The shares of the project.", body = Vec<Share>),
+        (status = 403, description = "Only the owner can see the shares.", body = ErrorDetails),
+        (status = 404, description = "The project doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn list_project_shares(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let item = SharedItem::Project(id);
+    require_owner(&app_state, user_id, workspace_id, item).await?;
+
+    let shares = db::list_shares(&app_state.connection_pool, item).await?;
+    Ok(Json(shares))
+}
+
+/// Shares a project of the authenticated user, and with that every task in it, with another user.
+#[utoipa::path(
+    post,
+    path = "/v1/projects/{id}/shares",
+    tag = "sharing",
+    params(("id" = i32, Path, description = "The ID of the project.")),
+    request_body = ShareForm,
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 201, description = "The project is shared.", body = Share),
+        (status = 400, description = "The user can't share the project with themselves, or as an owner.", body = ErrorDetails),
+        (status = 403, description = "Only the owner can share the project.", body = ErrorDetails),
+        (status = 404, description = "The project or the user doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state, form))]
+pub async fn share_project(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path(id): Path<i32>,
+    Json(form): Json<ShareForm>,
+) -> Result<impl IntoResponse, AppError> {
+    let share = share_item(
+        &app_state,
+        user_id,
+        workspace_id,
+        SharedItem::Project(id),
+        form,
+    )
+    .await?;
+    Ok((StatusCode::CREATED, Json(share)))
+}
+
+/// Revokes the access of a user to a project of the authenticated user.
+#[utoipa::path(
+    delete,
+    path = "/v1/projects/{id}/shares/{share_id}",
+    tag = "sharing",
+    params(
+        ("id" = i32, Path, description = "The ID of the project."),
+        ("share_id" = i32, Path, description = "The ID of the share.")
+    ),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 204, description = "The share was removed."),
+        (status = 403, description = "Only the owner can revoke access.", body = ErrorDetails),
+        (status = 404, description = "The project or the share doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn revoke_project_share(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path((id, share_id)): Path<(i32, i32)>,
+) -> Result<impl IntoResponse, AppError> {
+    let item = SharedItem::Project(id);
+    require_owner(&app_state, user_id, workspace_id, item).await?;
+
+    db::delete_share(&app_state.connection_pool, item, share_id).await?;
+
+    Ok((StatusCode::NO_CONTENT, ()))
+}
@@ -0,0 +1,357 @@
+//! This module contains the endpoints to manage the webhooks of the authenticated user.
+//!
+//! Users subscribe a URL to one or more events, like `task.created`. The secret to verify the deliveries with is only
+//! returned when the webhook is created, just like the API key of a user. The delivery log shows every event that was
+//! sent to a webhook, and any delivery can be sent again. The deliveries themselves are sent by [`crate::webhooks`].
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+use utoipa::ToSchema;
+
+use super::Pagination;
+use crate::{
+    access,
+    auth::{AuthenticatedUser, VerifiedUser},
+    db,
+    entity::{PagedResult, TaskEventType, Webhook, WebhookDelivery},
+    error::{AppError, ErrorDetails},
+    state::AppState,
+    webhooks,
+};
+
+/// The number of deliveries on a page of the delivery log.
+const DELIVERIES_PAGE_SIZE: i32 = 20;
+
+/// Defines the fields that can be used to create a new webhook.
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct CreateWebhookForm {
+    /// The URL the events are posted to.
+    pub url: String,
+
+    /// The events to post to the URL.
+    pub events: Vec<TaskEventType>,
+}
+
+/// Defines the fields of a webhook that can be changed.
+///
+/// All fields are optional, fields that are missing are left unchanged.
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct UpdateWebhookForm {
+    pub url: Option<String>,
+    pub events: Option<Vec<TaskEventType>>,
+    pub active: Option<bool>,
+}
+
+/// Defines the response structure for a webhook that has been created.
+#[derive(Serialize, ToSchema)]
+pub struct WebhookCreatedResponse {
+    #[serde(flatten)]
+    pub webhook: Webhook,
+
+    /// The secret to verify the signature of the deliveries with. It's only shown this once.
+    pub secret: String,
+}
+
+/// Checks that a webhook subscribes to at least one event, and removes events that are listed more than once.
+fn unique_events(events: Vec<TaskEventType>) -> Result<Vec<TaskEventType>, AppError> {
+    let mut unique = Vec::with_capacity(events.len());
+
+    for event in events {
+        if !unique.contains(&event) {
+            unique.push(event);
+        }
+    }
+
+    if unique.is_empty() {
+        return Err(AppError::MissingWebhookEvents);
+    }
+
+    Ok(unique)
+}
+
+/// Lists the webhooks of the authenticated user.
+#[utoipa::path(
+    get,
+    path = "/v1/webhooks",
+    tag = "webhooks",
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses((status = 200, description = "The webhooks of the user.", body = Vec<Webhook>))
+)]
+#[instrument(skip(app_state))]
+pub async fn list_webhooks(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+) -> Result<impl IntoResponse, AppError> {
+    access::require_unscoped(workspace_id)?;
+
+    let webhooks = db::list_webhooks(&app_state.connection_pool, user_id).await?;
+    Ok(Json(webhooks))
+}
+
+/// Subscribes a URL to events of the authenticated user.
+///
+/// The response contains the secret the deliveries are signed with. Like the API key of a user, we only return it
+/// once, so the user needs to store it right away.
+#[utoipa::path(
+    post,
+    path = "/v1/webhooks",
+    tag = "webhooks",
+    request_body = CreateWebhookForm,
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 201, description = "The webhook was created.", body = WebhookCreatedResponse),
+        (status = 400, description = "The URL is invalid or there are no events.", body = ErrorDetails),
+        (status = 403, description = "The email address isn't verified.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state, form))]
+pub async fn create_webhook(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Json(form): Json<CreateWebhookForm>,
+) -> Result<impl IntoResponse, AppError> {
+    access::require_unscoped(workspace_id)?;
+
+    let url = webhooks::validate_url(&form.url, app_state.webhooks.config().private)?;
+    let events = unique_events(form.events)?;
+    let secret = webhooks::generate_secret();
+
+    let webhook = db::insert_webhook(
+        &app_state.connection_pool,
+        user_id,
+        url.to_string(),
+        secret.clone(),
+        &events,
+    )
+    .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(WebhookCreatedResponse { webhook, secret }),
+    ))
+}
+
+/// Retrieves a single webhook of the authenticated user.
+#[utoipa::path(
+    get,
+    path = "/v1/webhooks/{id}",
+    tag = "webhooks",
+    params(("id" = i32, Path, description = "The ID of the webhook.")),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 200, description = "The webhook.", body = Webhook),
+        (status = 404, description = "The webhook doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn webhook_details(
+    State(app_state): State<Arc<AppState>>,
+    Path(id): Path<i32>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+) -> Result<impl IntoResponse, AppError> {
+    access::require_unscoped(workspace_id)?;
+
+    let webhook = db::find_webhook(&app_state.connection_pool, user_id, id).await?;
+    Ok(Json(webhook))
+}
+
+/// Changes the URL or events of a webhook, or pauses and resumes it with `active`.
+///
+/// Events that happen while a webhook is inactive aren't delivered. Deliveries that were already queued wait until
+/// the webhook is active again.
+#[utoipa::path(
+    patch,
+    path = "/v1/webhooks/{id}",
+    tag = "webhooks",
+    params(("id" = i32, Path, description = "The ID of the webhook.")),
+    request_body = UpdateWebhookForm,
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 200, description = "The updated webhook.", body = Webhook),
+        (status = 400, description = "The URL is invalid or there are no events.", body = ErrorDetails),
+        (status = 404, description = "The webhook doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state, form))]
+pub async fn update_webhook(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path(id): Path<i32>,
+    Json(form): Json<UpdateWebhookForm>,
+) -> Result<impl IntoResponse, AppError> {
+    access::require_unscoped(workspace_id)?;
+
+    let webhook = db::find_webhook(&app_state.connection_pool, user_id, id).await?;
+
+    let url = match form.url {
+        Some(url) => webhooks::validate_url(&url, app_state.webhooks.config().private)?.to_string(),
+        None => webhook.url,
+    };
+
+    let events = unique_events(form.events.unwrap_or(webhook.events))?;
+    let active = form.active.unwrap_or(webhook.active);
+
+    let webhook = db::update_webhook(
+        &app_state.connection_pool,
+        user_id,
+        id,
+        url,
+        &events,
+        active,
+    )
+    .await?;
+
+    Ok(Json(webhook))
+}
+
+/// Removes a webhook together with its delivery log.
+#[utoipa::path(
+    delete,
+    path = "/v1/webhooks/{id}",
+    tag = "webhooks",
+    params(("id" = i32, Path, description = "The ID of the webhook.")),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 204, description = "The webhook was removed."),
+        (status = 404, description = "The webhook doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn delete_webhook(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    access::require_unscoped(workspace_id)?;
+
+    db::delete_webhook(&app_state.connection_pool, user_id, id).await?;
+    Ok((StatusCode::NO_CONTENT, ()))
+}
+
+/// Lists the deliveries of a webhook, newest first.
+#[utoipa::path(
+    get,
+    path = "/v1/webhooks/{id}/deliveries",
+    tag = "webhooks",
+    params(("id" = i32, Path, description = "The ID of the webhook."), Pagination),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 200, description = "A page of the delivery log.", body = PagedResult<WebhookDelivery>),
+        (status = 404, description = "The webhook doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn list_deliveries(
+    State(app_state): State<Arc<AppState>>,
+    Path(id): Path<i32>,
+    Query(pagination): Query<Pagination>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+) -> Result<impl IntoResponse, AppError> {
+    access::require_unscoped(workspace_id)?;
+
+    let webhook = db::find_webhook(&app_state.connection_pool, user_id, id).await?;
+
+    let deliveries = db::list_webhook_deliveries(
+        &app_state.connection_pool,
+        webhook.id,
+        pagination.page,
+        DELIVERIES_PAGE_SIZE,
+    )
+    .await?;
+
+    Ok(Json(deliveries))
+}
+
+/// Sends an event to a webhook again.
+///
+/// This queues a new delivery with the same event ID and body, which is sent even when the original delivery
+/// succeeded. The new delivery refers to the original one in `redelivery_of`.
+#[utoipa::path(
+    post,
+    path = "/v1/webhooks/{id}/deliveries/{delivery_id}/redeliver",
+    tag = "webhooks",
+    params(
+        ("id" = i32, Path, description = "The ID of the webhook."),
+        ("delivery_id" = i64, Path, description = "The ID of the delivery to send again.")
+    ),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 202, description = "The event is queued for delivery.", body = WebhookDelivery),
+        (status = 404, description = "The webhook or delivery doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn redeliver(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path((id, delivery_id)): Path<(i32, i64)>,
+) -> Result<impl IntoResponse, AppError> {
+    access::require_unscoped(workspace_id)?;
+
+    let webhook = db::find_webhook(&app_state.connection_pool, user_id, id).await?;
+
+    let delivery =
+        db::redeliver_webhook_delivery(&app_state.connection_pool, webhook.id, delivery_id).await?;
+
+    Ok((StatusCode::ACCEPTED, Json(delivery)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicate_events_are_removed() {
+        let events = unique_events(vec![
+            TaskEventType::TaskCreated,
+            TaskEventType::TaskDeleted,
+            TaskEventType::TaskCreated,
+        ])
+        .unwrap();
+
+        assert_eq!(
+            events,
+            vec![TaskEventType::TaskCreated, TaskEventType::TaskDeleted]
+        );
+    }
+
+    #[test]
+    fn webhook_without_events_is_rejected() {
+        assert!(matches!(
+            unique_events(Vec::new()),
+            Err(AppError::MissingWebhookEvents)
+        ));
+    }
+}
@@ -0,0 +1,695 @@
+//! This module contains the endpoints to manage workspaces, their members, invitations and API keys.
+//!
+//! A workspace is shared by a team. Every member has a role: members work on the tasks and projects in the workspace,
+//! admins invite, change and remove members as well, and owners can rename and remove the workspace. Nobody can hand
+//! out a role higher than their own, and a workspace always keeps at least one owner.
+//!
+//! People are invited by their email address. Once they're registered and verified that address, they find the
+//! invitation at `/v1/invitations` and accept or decline it.
+//!
+//! Members can create API keys that only give access to the workspace, for example for an integration of the team.
+//! The key acts on behalf of the member who created it and is removed when they leave the workspace.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{instrument, warn};
+use utoipa::ToSchema;
+
+use crate::{
+    access,
+    auth::{AuthenticatedUser, VerifiedUser},
+    db,
+    entity::{
+        ApiKey, Workspace, WorkspaceInvitation, WorkspaceKey, WorkspaceMember, WorkspaceRole,
+    },
+    error::{AppError, ErrorDetails},
+    mail::Email,
+    state::AppState,
+};
+
+/// Defines the fields to create or rename a workspace.
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct WorkspaceForm {
+    /// The name of the workspace.
+    pub name: String,
+}
+
+/// Defines the fields to change the role of a member.
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct UpdateMemberForm {
+    /// The new role of the member.
+    pub role: WorkspaceRole,
+}
+
+/// Defines the fields to invite someone to a workspace.
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct InvitationForm {
+    /// The email address to send the invitation to.
+    pub email_address: String,
+
+    /// The role the user gets when they accept the invitation.
+    #[serde(default = "default_role")]
+    pub role: WorkspaceRole,
+}
+
+/// Returns the role of an invitation that doesn't specify one.
+fn default_role() -> WorkspaceRole {
+    WorkspaceRole::Member
+}
+
+/// Defines the fields to create an API key for a workspace.
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct CreateKeyForm {
+    /// A name to recognize the key by.
+    pub name: String,
+}
+
+/// Defines the response with a new API key for a workspace. The key is only shown in this response.
+#[derive(Serialize, ToSchema)]
+pub struct KeyCreatedResponse {
+    /// The ID of the key, to revoke it with.
+    pub id: i32,
+
+    /// The workspace the key gives access to.
+    pub workspace_id: i32,
+
+    /// The name of the key.
+    pub name: String,
+
+    /// The API key to send in the `X-Api-Key` header.
+    pub api_key: String,
+}
+
+/// Checks that a member can hand out or take away a role.
+///
+/// Nobody can hand out a role higher than their own, or change members with a higher role than their own.
+fn can_manage(actor: WorkspaceRole, role: WorkspaceRole) -> Result<(), AppError> {
+    if actor >= WorkspaceRole::Admin && actor >= role {
+        Ok(())
+    } else {
+        Err(AppError::AccessDenied)
+    }
+}
+
+/// Lists the workspaces of the authenticated user.
+#[utoipa::path(
+    get,
+    path = "/v1/workspaces",
+    tag = "workspaces",
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses((status = 200, description = "The workspaces the user is a member of.", body = Vec<Workspace>))
+)]
+#[instrument(skip(app_state))]
+pub async fn list_workspaces(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+) -> Result<impl IntoResponse, AppError> {
+    let mut workspaces = db::list_workspaces(&app_state.connection_pool, user_id).await?;
+
+    if let Some(scope) = workspace_id {
+        workspaces.retain(|workspace| workspace.id == scope);
+    }
+
+    Ok(Json(workspaces))
+}
+
+/// Creates a workspace with the authenticated user as its owner.
+#[utoipa::path(
+    post,
+    path = "/v1/workspaces",
+    tag = "workspaces",
+    request_body = WorkspaceForm,
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 201, description = "The workspace was created.", body = Workspace),
+        (status = 403, description = "The email address isn't verified.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state, form))]
+pub async fn create_workspace(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Json(form): Json<WorkspaceForm>,
+) -> Result<impl IntoResponse, AppError> {
+    access::require_unscoped(workspace_id)?;
+
+    let pool = &app_state.connection_pool;
+
+    let workspace_id = db::insert_workspace(pool, user_id, form.name).await?;
+    let workspace = db::find_workspace(pool, user_id, workspace_id).await?;
+
+    Ok((StatusCode::CREATED, Json(workspace)))
+}
+
+/// Retrieves a workspace of the authenticated user.
+#[utoipa::path(
+    get,
+    path = "/v1/workspaces/{id}",
+    tag = "workspaces",
+    params(("id" = i32, Path, description = "The ID of the workspace.")),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 200, description = "The workspace.", body = Workspace),
+        (status = 404, description = "The workspace doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn workspace_details(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    access::workspace_access(pool, user_id, workspace_id, id, WorkspaceRole::Member).await?;
+
+    let workspace = db::find_workspace(pool, user_id, id).await?;
+    Ok(Json(workspace))
+}
+
+/// Renames a workspace. Admins and owners can rename it.
+#[utoipa::path(
+    put,
+    path = "/v1/workspaces/{id}",
+    tag = "workspaces",
+    params(("id" = i32, Path, description = "The ID of the workspace.")),
+    request_body = WorkspaceForm,
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 200, description = "The renamed workspace.", body = Workspace),
+        (status = 403, description = "The user isn't an admin of the workspace.", body = ErrorDetails),
+        (status = 404, description = "The workspace doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state, form))]
+pub async fn update_workspace(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path(id): Path<i32>,
+    Json(form): Json<WorkspaceForm>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    access::workspace_access(pool, user_id, workspace_id, id, WorkspaceRole::Admin).await?;
+
+    db::update_workspace(pool, id, form.name).await?;
+    let workspace = db::find_workspace(pool, user_id, id).await?;
+
+    Ok(Json(workspace))
+}
+
+/// Removes a workspace with all its tasks and projects. Only owners can remove it.
+#[utoipa::path(
+    delete,
+    path = "/v1/workspaces/{id}",
+    tag = "workspaces",
+    params(("id" = i32, Path, description = "The ID of the workspace.")),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 204, description = "The workspace was removed."),
+        (status = 403, description = "The user isn't an owner of the workspace.", body = ErrorDetails),
+        (status = 404, description = "The workspace doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn delete_workspace(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    access::workspace_access(pool, user_id, workspace_id, id, WorkspaceRole::Owner).await?;
+
+    db::delete_workspace(pool, id).await?;
+
+    Ok((StatusCode::NO_CONTENT, ()))
+}
+
+/// Lists the members of a workspace.
+#[utoipa::path(
+    get,
+    path = "/v1/workspaces/{id}/members",
+    tag = "workspaces",
+    params(("id" = i32, Path, description = "The ID of the workspace.")),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 200, description = "The members of the workspace.", body = Vec<WorkspaceMember>),
+        (status = 404, description = "The workspace doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn list_members(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    access::workspace_access(pool, user_id, workspace_id, id, WorkspaceRole::Member).await?;
+
+    let members = db::list_workspace_members(pool, id).await?;
+    Ok(Json(members))
+}
+
+/// Changes the role of a member. Admins and owners can change members up to their own role.
+#[utoipa::path(
+    put,
+    path = "/v1/workspaces/{id}/members/{user_id}",
+    tag = "workspaces",
+    params(
+        ("id" = i32, Path, description = "The ID of the workspace."),
+        ("user_id" = i32, Path, description = "The ID of the member.")
+    ),
+    request_body = UpdateMemberForm,
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 204, description = "The role was changed."),
+        (status = 403, description = "The role of the user doesn't allow the change.", body = ErrorDetails),
+        (status = 404, description = "The workspace or the member doesn't exist.", body = ErrorDetails),
+        (status = 409, description = "The workspace would have no owner left.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn update_member(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path((id, member_id)): Path<(i32, i32)>,
+    Json(form): Json<UpdateMemberForm>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    let actor =
+        access::workspace_access(pool, user_id, workspace_id, id, WorkspaceRole::Member).await?;
+
+    let current = db::find_workspace_role(pool, member_id, id)
+        .await?
+        .ok_or(AppError::MemberNotFound)?;
+
+    can_manage(actor, current)?;
+    can_manage(actor, form.role)?;
+
+    db::update_workspace_member(pool, id, member_id, form.role).await?;
+
+    Ok((StatusCode::NO_CONTENT, ()))
+}
+
+/// Removes a member from a workspace. Members can leave on their own, admins and owners can remove members up to
+/// their own role.
+#[utoipa::path(
+    delete,
+    path = "/v1/workspaces/{id}/members/{user_id}",
+    tag = "workspaces",
+    params(
+        ("id" = i32, Path, description = "The ID of the workspace."),
+        ("user_id" = i32, Path, description = "The ID of the member.")
+    ),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 204, description = "The member was removed."),
+        (status = 403, description = "The role of the user doesn't allow removing the member.", body = ErrorDetails),
+        (status = 404, description = "The workspace or the member doesn't exist.", body = ErrorDetails),
+        (status = 409, description = "The workspace would have no owner left.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn remove_member(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path((id, member_id)): Path<(i32, i32)>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    let actor =
+        access::workspace_access(pool, user_id, workspace_id, id, WorkspaceRole::Member).await?;
+
+    if member_id != user_id {
+        let current = db::find_workspace_role(pool, member_id, id)
+            .await?
+            .ok_or(AppError::MemberNotFound)?;
+
+        can_manage(actor, current)?;
+    }
+
+    db::delete_workspace_member(pool, id, member_id).await?;
+
+    Ok((StatusCode::NO_CONTENT, ()))
+}
+
+/// Lists the open invitations of a workspace. Admins and owners can see them.
+#[utoipa::path(
+    get,
+    path = "/v1/workspaces/{id}/invitations",
+    tag = "workspaces",
+    params(("id" = i32, Path, description = "The ID of the workspace.")),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 200, description = "The open invitations.", body = Vec<WorkspaceInvitation>),
+        (status = 403, description = "The user isn't an admin of the workspace.", body = ErrorDetails),
+        (status = 404, description = "The workspace doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn list_invitations(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    access::workspace_access(pool, user_id, workspace_id, id, WorkspaceRole::Admin).await?;
+
+    let invitations = db::list_workspace_invitations(pool, id).await?;
+    Ok(Json(invitations))
+}
+
+/// Invites someone to a workspace by email. Inviting the same address again changes the role of the invitation.
+///
+/// The invitation is in place once it's stored. Failing to send the email doesn't undo it, the invited user finds the
+/// invitation at `/v1/invitations` either way.
+#[utoipa::path(
+    post,
+    path = "/v1/workspaces/{id}/invitations",
+    tag = "workspaces",
+    params(("id" = i32, Path, description = "The ID of the workspace.")),
+    request_body = InvitationForm,
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 201, description = "The invitation was sent.", body = WorkspaceInvitation),
+        (status = 400, description = "The email address is invalid.", body = ErrorDetails),
+        (status = 403, description = "The role of the user doesn't allow the invitation.", body = ErrorDetails),
+        (status = 404, description = "The workspace doesn't exist.", body = ErrorDetails),
+        (status = 409, description = "The user is already a member.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state, form))]
+pub async fn invite_member(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path(id): Path<i32>,
+    Json(form): Json<InvitationForm>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    let actor =
+        access::workspace_access(pool, user_id, workspace_id, id, WorkspaceRole::Member).await?;
+
+    can_manage(actor, form.role)?;
+
+    if form.email_address.parse::<lettre::Address>().is_err() {
+        return Err(AppError::InvalidEmailAddress);
+    }
+
+    let invitation =
+        db::insert_workspace_invitation(pool, id, user_id, &form.email_address, form.role).await?;
+
+    let email = Email {
+        to: invitation.email_address.clone(),
+        subject: format!("You're invited to join {}", invitation.workspace_name),
+        body: format!(
+            "You're invited to join the workspace \"{}\". Register or log in with this email address, and accept \
+             the invitation with POST /v1/invitations/{}/accept.",
+            invitation.workspace_name, invitation.id
+        ),
+    };
+
+    if let Err(err) = app_state.mailer.send(&email).await {
+        warn!(
+            invitation_id = invitation.id,
+            "Failed to send the invitation: {}", err
+        );
+    }
+
+    Ok((StatusCode::CREATED, Json(invitation)))
+}
+
+/// Withdraws an invitation to a workspace. Admins and owners can withdraw invitations.
+#[utoipa::path(
+    delete,
+    path = "/v1/workspaces/{id}/invitations/{invitation_id}",
+    tag = "workspaces",
+    params(
+        ("id" = i32, Path, description = "The ID of the workspace."),
+        ("invitation_id" = i32, Path, description = "The ID of the invitation.")
+    ),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 204, description = "The invitation was withdrawn."),
+        (status = 403, description = "The user isn't an admin of the workspace.", body = ErrorDetails),
+        (status = 404, description = "The workspace or the invitation doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn withdraw_invitation(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path((id, invitation_id)): Path<(i32, i32)>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    access::workspace_access(pool, user_id, workspace_id, id, WorkspaceRole::Admin).await?;
+
+    db::delete_workspace_invitation(pool, id, invitation_id).await?;
+
+    Ok((StatusCode::NO_CONTENT, ()))
+}
+
+/// Lists the invitations to the email address of the authenticated user.
+#[utoipa::path(
+    get,
+    path = "/v1/invitations",
+    tag = "workspaces",
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses((status = 200, description = "The invitations to the user.", body = Vec<WorkspaceInvitation>))
+)]
+#[instrument(skip(app_state))]
+pub async fn list_my_invitations(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+) -> Result<impl IntoResponse, AppError> {
+    access::require_unscoped(workspace_id)?;
+
+    let invitations = db::list_user_invitations(&app_state.connection_pool, user_id).await?;
+    Ok(Json(invitations))
+}
+
+/// Accepts an invitation, which makes the authenticated user a member of the workspace.
+///
+/// Only users that verified their email address can accept invitations, because the invitation is addressed to it.
+#[utoipa::path(
+    post,
+    path = "/v1/invitations/{id}/accept",
+    tag = "workspaces",
+    params(("id" = i32, Path, description = "The ID of the invitation.")),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 200, description = "The workspace the user joined.", body = Workspace),
+        (status = 404, description = "The invitation doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn accept_invitation(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    access::require_unscoped(workspace_id)?;
+
+    let pool = &app_state.connection_pool;
+
+    let workspace_id = db::accept_workspace_invitation(pool, user_id, id).await?;
+    let workspace = db::find_workspace(pool, user_id, workspace_id).await?;
+
+    Ok(Json(workspace))
+}
+
+/// Declines an invitation to the authenticated user.
+#[utoipa::path(
+    delete,
+    path = "/v1/invitations/{id}",
+    tag = "workspaces",
+    params(("id" = i32, Path, description = "The ID of the invitation.")),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 204, description = "The invitation was declined."),
+        (status = 404, description = "The invitation doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn decline_invitation(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    access::require_unscoped(workspace_id)?;
+
+    db::decline_workspace_invitation(&app_state.connection_pool, user_id, id).await?;
+    Ok((StatusCode::NO_CONTENT, ()))
+}
+
+/// Lists the API keys the authenticated user created for a workspace.
+#[utoipa::path(
+    get,
+    path = "/v1/workspaces/{id}/keys",
+    tag = "workspaces",
+    params(("id" = i32, Path, description = "The ID of the workspace.")),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 200, description = "The API keys of the user for the workspace.", body = Vec<WorkspaceKey>),
+        (status = 404, description = "The workspace doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn list_keys(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        user_id,
+        workspace_id,
+        ..
+    }: AuthenticatedUser,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    access::workspace_access(pool, user_id, workspace_id, id, WorkspaceRole::Member).await?;
+
+    let keys = db::list_workspace_keys(pool, id, user_id).await?;
+    Ok(Json(keys))
+}
+
+/// Creates an API key that acts on behalf of the authenticated user, but only within the workspace.
+#[utoipa::path(
+    post,
+    path = "/v1/workspaces/{id}/keys",
+    tag = "workspaces",
+    params(("id" = i32, Path, description = "The ID of the workspace.")),
+    request_body = CreateKeyForm,
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 201, description = "The key was created. It's only shown in this response.", body = KeyCreatedResponse),
+        (status = 404, description = "The workspace doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state, form))]
+pub async fn create_key(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path(id): Path<i32>,
+    Json(form): Json<CreateKeyForm>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    access::workspace_access(pool, user_id, workspace_id, id, WorkspaceRole::Member).await?;
+
+    let api_key = ApiKey::new();
+    let key = db::insert_workspace_key(pool, id, user_id, form.name, api_key.hash).await?;
+
+    let response = KeyCreatedResponse {
+        id: key.id,
+        workspace_id: key.workspace_id,
+        name: key.name,
+        api_key: api_key.key,
+    };
+
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+/// Revokes an API key the authenticated user created for a workspace.
+#[utoipa::path(
+    delete,
+    path = "/v1/workspaces/{id}/keys/{key_id}",
+    tag = "workspaces",
+    params(
+        ("id" = i32, Path, description = "The ID of the workspace."),
+        ("key_id" = i32, Path, description = "The ID of the key.")
+    ),
+    security(("api_key" = []), ("bearer" = []), ("client_certificate" = [])),
+    responses(
+        (status = 204, description = "The key was revoked."),
+        (status = 404, description = "The workspace or the key doesn't exist.", body = ErrorDetails)
+    )
+)]
+#[instrument(skip(app_state))]
+pub async fn revoke_key(
+    State(app_state): State<Arc<AppState>>,
+    VerifiedUser {
+        user_id,
+        workspace_id,
+    }: VerifiedUser,
+    Path((id, key_id)): Path<(i32, i32)>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &app_state.connection_pool;
+    access::workspace_access(pool, user_id, workspace_id, id, WorkspaceRole::Member).await?;
+
+    db::delete_workspace_key(pool, id, user_id, key_id).await?;
+
+    Ok((StatusCode::NO_CONTENT, ()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles_are_managed_up_to_the_own_role() {
+        assert!(can_manage(WorkspaceRole::Owner, WorkspaceRole::Owner).is_ok());
+        assert!(can_manage(WorkspaceRole::Admin, WorkspaceRole::Admin).is_ok());
+        assert!(can_manage(WorkspaceRole::Admin, WorkspaceRole::Member).is_ok());
+
+        assert!(matches!(
+            can_manage(WorkspaceRole::Admin, WorkspaceRole::Owner),
+            Err(AppError::AccessDenied)
+        ));
+        assert!(matches!(
+            can_manage(WorkspaceRole::Member, WorkspaceRole::Member),
+            Err(AppError::AccessDenied)
+        ));
+    }
+}
@@ -0,0 +1,566 @@
+//! This module delivers events to the webhooks users subscribed to.
+//!
+//! Events aren't sent from the request that causes them. [`publish`] stores a delivery for every matching webhook in
+//! the `webhook_deliveries` table, and the [`WebhookDispatcher`] sends the pending deliveries in the background. This
+//! way a slow or broken receiver never slows down the API, and deliveries survive a restart of the application.
+//!
+//! ## Retries
+//! A delivery succeeds when the receiver responds with a 2xx status code. Any other response, a timeout or a
+//! connection error counts as a failed attempt, after which we retry with an exponential backoff as configured in
+//! [`WebhookConfig`]. When all attempts failed, the delivery is marked as failed. Users can find every attempt in the
+//! delivery log and redeliver an event by hand.
+//!
+//! Multiple instances can run the dispatcher at the same time. They claim deliveries with `FOR UPDATE SKIP LOCKED`,
+//! so a delivery is only sent by one of them.
+//!
+//! ## Signatures
+//! Deliveries follow the [Standard Webhooks](https://www.standardwebhooks.com/) specification, so receivers can use
+//! one of its libraries to verify them. Every delivery has three headers:
+//!
+//! * `webhook-id`: the ID of the event, which stays the same when the event is delivered again.
+//! * `webhook-timestamp`: the time of the attempt in seconds since the Unix epoch.
+//! * `webhook-signature`: `v1,` followed by the base64 encoded HMAC-SHA256 of `{id}.{timestamp}.{body}`.
+//!
+//! Receivers should reject deliveries with a timestamp that is more than a few minutes old, so a captured delivery
+//! can't be replayed later.
+//!
+//! ## Private networks
+//! The API runs inside the network of the operator, so a webhook pointing to an internal address could be used to
+//! reach services that aren't meant to be public. Unless [`WebhookConfig::private`] is enabled, we reject URLs with a
+//! private address, and we check the addresses a host name resolves to before every delivery.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{redirect, Client, Url};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sqlx::PgPool;
+use tokio::task::JoinSet;
+use tracing::{error, info, instrument, warn};
+
+use crate::{
+    config::WebhookConfig,
+    db,
+    entity::{JobKind, PendingWebhookDelivery, TaskEventType},
+    error::{AppError, Result},
+    jobs::{JobContext, JobPayload},
+    monitoring,
+    shutdown::Shutdown,
+};
+
+/// The header with the ID of the event.
+pub const ID_HEADER: &str = "webhook-id";
+
+/// The header with the time the delivery was signed.
+pub const TIMESTAMP_HEADER: &str = "webhook-timestamp";
+
+/// The header with the signature of the delivery.
+pub const SIGNATURE_HEADER: &str = "webhook-signature";
+
+/// The prefix of webhook secrets, as used by the Standard Webhooks specification.
+const SECRET_PREFIX: &str = "whsec_";
+
+/// How long a claimed delivery stays locked in addition to the request timeout, before another instance retries it.
+const LEASE_MARGIN: Duration = Duration::from_secs(60);
+
+/// The maximum length of the error we store for a failed attempt.
+const MAX_ERROR_LENGTH: usize = 500;
+
+/// The body that is posted to a webhook.
+#[derive(Serialize)]
+struct EventPayload<'a, T: Serialize> {
+    /// The ID of the event.
+    id: &'a str,
+
+    /// The type of the event, like `task.created`.
+    #[serde(rename = "type")]
+    event_type: TaskEventType,
+
+    /// The time the event happened.
+    timestamp: chrono::DateTime<chrono::Utc>,
+
+    /// The resource the event is about.
+    data: T,
+}
+
+/// Generates a new secret to sign the deliveries of a webhook with.
+pub fn generate_secret() -> String {
+    let mut key = [0u8; 24];
+    rand::thread_rng().fill_bytes(&mut key);
+
+    format!("{}{}", SECRET_PREFIX, STANDARD.encode(key))
+}
+
+/// Calculates the value of the `webhook-signature` header for a delivery.
+///
+/// The key is the base64 encoded part of the secret after the `whsec_` prefix.
+pub fn sign(secret: &str, event_id: &str, timestamp: i64, body: &str) -> String {
+    let encoded_key = secret.strip_prefix(SECRET_PREFIX).unwrap_or(secret);
+    let key = STANDARD
+        .decode(encoded_key)
+        .unwrap_or_else(|_| encoded_key.as_bytes().to_vec());
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(&key).expect("HMAC accepts keys of any length.");
+    mac.update(format!("{}.{}.{}", event_id, timestamp, body).as_bytes());
+
+    format!("v1,{}", STANDARD.encode(mac.finalize().into_bytes()))
+}
+
+/// Checks that a URL can be used for a webhook.
+///
+/// The URL must use HTTP or HTTPS. Unless private addresses are allowed, the host can't be `localhost` or an IP
+/// address on a private network. Host names are checked again when they're resolved for a delivery.
+pub fn validate_url(url: &str, allow_private: bool) -> Result<Url> {
+    let url = Url::parse(url).map_err(|_| AppError::InvalidWebhookUrl)?;
+
+    if !matches!(url.scheme(), "http" | "https") || url.host().is_none() {
+        return Err(AppError::InvalidWebhookUrl);
+    }
+
+    if allow_private {
+        return Ok(url);
+    }
+
+    // IPv6 addresses are written between square brackets in URLs.
+    let host = url.host_str().unwrap_or_default();
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    let is_public = match host.parse::<IpAddr>() {
+        Ok(address) => is_public_address(address),
+        Err(_) => {
+            let domain = host.trim_end_matches('.').to_ascii_lowercase();
+            domain != "localhost" && !domain.ends_with(".localhost")
+        }
+    };
+
+    if !is_public {
+        return Err(AppError::InvalidWebhookUrl);
+    }
+
+    Ok(url)
+}
+
+/// Returns whether an IP address is reachable on the public internet.
+fn is_public_address(address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(address) => is_public_ipv4(address),
+        IpAddr::V6(address) => match address.to_ipv4_mapped() {
+            Some(address) => is_public_ipv4(address),
+            None => is_public_ipv6(address),
+        },
+    }
+}
+
+fn is_public_ipv4(address: Ipv4Addr) -> bool {
+    let [first, second, ..] = address.octets();
+
+    // 100.64.0.0/10 is shared address space for carrier-grade NAT.
+    let is_shared = first == 100 && (64..128).contains(&second);
+
+    !(address.is_unspecified()
+        || address.is_loopback()
+        || address.is_private()
+        || address.is_link_local()
+        || address.is_broadcast()
+        || address.is_documentation()
+        || address.is_multicast()
+        || is_shared)
+}
+
+fn is_public_ipv6(address: Ipv6Addr) -> bool {
+    let first_segment = address.segments()[0];
+
+    // fc00::/7 are unique local addresses, fe80::/10 are link-local addresses.
+    let is_unique_local = first_segment & 0xfe00 == 0xfc00;
+    let is_link_local = first_segment & 0xffc0 == 0xfe80;
+
+    !(address.is_unspecified()
+        || address.is_loopback()
+        || address.is_multicast()
+        || is_unique_local
+        || is_link_local)
+}
+
+/// Resolves host names for deliveries and leaves out the addresses on private networks.
+///
+/// A host name can resolve to a different address than when the webhook was created, so checking the URL once isn't
+/// enough to keep deliveries away from the internal network.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+
+        Box::pin(async move {
+            let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|address| is_public_address(address.ip()))
+                .collect();
+
+            if addresses.is_empty() {
+                return Err(format!("{} doesn't resolve to a public address", host).into());
+            }
+
+            let addresses: Addrs = Box::new(addresses.into_iter());
+            Ok(addresses)
+        })
+    }
+}
+
+/// Queues an event for every webhook of the user that subscribed to it.
+///
+/// The data is the resource the event is about, like the task that was created. It's serialized once, so every
+/// webhook receives exactly the same body. Changes to tasks don't call this directly, they queue a
+/// [`PublishWebhooks`] job in the same transaction instead.
+#[instrument(skip(pool, data))]
+pub async fn publish<T: Serialize>(
+    pool: &PgPool,
+    user_id: i32,
+    event: TaskEventType,
+    data: &T,
+) -> Result<()> {
+    PublishWebhooks::new(user_id, event, data)
+        .queue_deliveries(pool)
+        .await
+}
+
+/// The payload of the background job that queues an event for the webhooks of a user.
+///
+/// The body of the event, including its ID and timestamp, is prepared when the job is queued. When the job runs more
+/// than once, the webhooks that already have a delivery of the event are skipped.
+#[derive(Serialize, Deserialize)]
+pub struct PublishWebhooks {
+    /// The user whose webhooks receive the event.
+    pub user_id: i32,
+
+    /// The ID of the event.
+    pub event_id: String,
+
+    /// The type of the event.
+    pub event: TaskEventType,
+
+    /// The body that is posted to the webhooks.
+    pub payload: serde_json::Value,
+}
+
+impl PublishWebhooks {
+    /// Prepares an event about the given data for the webhooks of a user.
+    pub fn new<T: Serialize>(user_id: i32, event: TaskEventType, data: &T) -> Self {
+        Self::with_event_id(format!("evt_{}", random_id()), user_id, event, data)
+    }
+
+    /// Prepares an event with a known ID, so preparing the same event again leads to the same deliveries.
+    pub fn with_event_id<T: Serialize>(
+        event_id: String,
+        user_id: i32,
+        event: TaskEventType,
+        data: &T,
+    ) -> Self {
+        let payload = serde_json::to_value(EventPayload {
+            id: &event_id,
+            event_type: event,
+            timestamp: chrono::Utc::now(),
+            data,
+        })
+        .expect("Events can always be serialized.");
+
+        Self {
+            user_id,
+            event_id,
+            event,
+            payload,
+        }
+    }
+
+    /// Queues a delivery of the event for every webhook of the user that subscribed to it and doesn't have one yet.
+    pub async fn queue_deliveries(&self, pool: &PgPool) -> Result<()> {
+        db::insert_webhook_deliveries(
+            pool,
+            self.user_id,
+            &self.event_id,
+            self.event,
+            &self.payload,
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+impl JobPayload for PublishWebhooks {
+    const KIND: JobKind = JobKind::PublishWebhooks;
+
+    async fn perform(self, context: &JobContext) -> Result<()> {
+        self.queue_deliveries(&context.pool).await
+    }
+}
+
+/// Generates a random ID of 32 hexadecimal characters.
+fn random_id() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Sends the pending webhook deliveries.
+#[derive(Debug)]
+pub struct WebhookDispatcher {
+    client: Client,
+    config: WebhookConfig,
+}
+
+impl WebhookDispatcher {
+    /// Creates a new dispatcher with the given configuration.
+    ///
+    /// The HTTP client doesn't follow redirects, because a redirect could point to an address we would otherwise
+    /// refuse to deliver to.
+    pub fn new(config: WebhookConfig) -> Self {
+        let mut builder = Client::builder()
+            .timeout(Duration::from_secs(config.timeout))
+            .redirect(redirect::Policy::none())
+            .user_agent(concat!("todo-api-webhooks/", env!("CARGO_PKG_VERSION")));
+
+        if !config.private {
+            builder = builder.dns_resolver(Arc::new(PublicResolver));
+        }
+
+        let client = builder
+            .build()
+            .expect("The webhook HTTP client can always be created.");
+
+        Self { client, config }
+    }
+
+    /// Returns the configuration of the dispatcher.
+    pub fn config(&self) -> &WebhookConfig {
+        &self.config
+    }
+
+    /// Sends pending deliveries until the application shuts down.
+    ///
+    /// When a batch was full, we immediately look for more deliveries instead of waiting for the next interval.
+    /// The batch that is being sent when the shutdown starts is finished first.
+    pub async fn run(&self, pool: &PgPool, shutdown: &Shutdown) {
+        let interval = Duration::from_secs(self.config.interval);
+
+        info!("Delivering webhooks every {} seconds", interval.as_secs());
+
+        loop {
+            let delay = match self.deliver_pending(pool).await {
+                Ok(sent) if sent as i64 >= self.config.batch => Duration::ZERO,
+                Ok(_) => interval,
+                Err(err) => {
+                    error!("Failed to deliver webhooks: {}", err);
+                    interval
+                }
+            };
+
+            tokio::select! {
+                _ = shutdown.started() => break,
+                _ = tokio::time::sleep(delay) => {}
+            }
+        }
+
+        info!("Stopped delivering webhooks");
+    }
+
+    /// Claims a batch of deliveries that are due and sends them at the same time.
+    ///
+    /// This returns the number of deliveries that were attempted.
+    pub async fn deliver_pending(&self, pool: &PgPool) -> Result<usize> {
+        let lease = Duration::from_secs(self.config.timeout) + LEASE_MARGIN;
+        let lease_until = chrono::Utc::now().naive_utc()
+            + chrono::Duration::from_std(lease).expect("The lease fits in a chrono duration.");
+
+        let deliveries = db::claim_webhook_deliveries(pool, self.config.batch, lease_until).await?;
+        let count = deliveries.len();
+
+        let mut attempts = JoinSet::new();
+
+        for delivery in deliveries {
+            let client = self.client.clone();
+            let config = self.config.clone();
+            let pool = pool.clone();
+
+            attempts.spawn(async move {
+                let outcome = attempt(&client, &config, &delivery).await;
+
+                if let Err(err) = record_outcome(&pool, &config, &delivery, outcome).await {
+                    error!(
+                        delivery_id = delivery.id,
+                        "Failed to record the webhook outcome: {}", err
+                    );
+                }
+            });
+        }
+
+        while attempts.join_next().await.is_some() {}
+
+        Ok(count)
+    }
+}
+
+/// The result of a single attempt to deliver an event.
+enum Outcome {
+    /// The receiver responded with a 2xx status code.
+    Delivered(u16),
+
+    /// The receiver responded with another status code, or couldn't be reached at all.
+    Failed(Option<u16>, String),
+}
+
+/// Posts a delivery to its webhook.
+#[instrument(skip_all, fields(delivery_id = delivery.id, attempt = delivery.attempts))]
+async fn attempt(
+    client: &Client,
+    config: &WebhookConfig,
+    delivery: &PendingWebhookDelivery,
+) -> Outcome {
+    if let Err(err) = validate_url(&delivery.url, config.private) {
+        return Outcome::Failed(None, err.to_string());
+    }
+
+    let body = delivery.payload.to_string();
+    let timestamp = chrono::Utc::now().timestamp();
+    let signature = sign(&delivery.secret, &delivery.event_id, timestamp, &body);
+
+    let response = client
+        .post(&delivery.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(ID_HEADER, &delivery.event_id)
+        .header(TIMESTAMP_HEADER, timestamp.to_string())
+        .header(SIGNATURE_HEADER, signature)
+        .body(body)
+        .send()
+        .await;
+
+    match response {
+        Ok(response) if response.status().is_success() => {
+            Outcome::Delivered(response.status().as_u16())
+        }
+        Ok(response) => Outcome::Failed(
+            Some(response.status().as_u16()),
+            format!("The receiver responded with {}", response.status()),
+        ),
+        Err(err) => Outcome::Failed(None, error_chain(&err)),
+    }
+}
+
+/// Stores the outcome of an attempt and schedules the next attempt when it failed.
+async fn record_outcome(
+    pool: &PgPool,
+    config: &WebhookConfig,
+    delivery: &PendingWebhookDelivery,
+    outcome: Outcome,
+) -> Result<()> {
+    match outcome {
+        Outcome::Delivered(status) => {
+            monitoring::record_webhook_delivery("delivered");
+            db::mark_webhook_delivered(pool, delivery.id, i32::from(status)).await
+        }
+        Outcome::Failed(status, error) => {
+            let attempt = delivery.attempts.max(1) as u32;
+
+            let retry_at = (attempt < config.attempts).then(|| {
+                chrono::Utc::now().naive_utc()
+                    + chrono::Duration::from_std(config.backoff(attempt))
+                        .expect("The backoff fits in a chrono duration.")
+            });
+
+            match retry_at {
+                Some(retry_at) => {
+                    monitoring::record_webhook_delivery("retried");
+                    warn!(delivery_id = delivery.id, attempt, %retry_at, "Webhook delivery failed: {}", error);
+                }
+                None => {
+                    monitoring::record_webhook_delivery("failed");
+                    warn!(
+                        delivery_id = delivery.id,
+                        attempt, "Webhook delivery failed for the last time: {}", error
+                    );
+                }
+            }
+
+            let error: String = error.chars().take(MAX_ERROR_LENGTH).collect();
+
+            db::mark_webhook_attempt_failed(
+                pool,
+                delivery.id,
+                status.map(i32::from),
+                &error,
+                retry_at,
+            )
+            .await
+        }
+    }
+}
+
+/// Describes an error of the HTTP client together with its causes, which explain what actually went wrong.
+fn error_chain(err: &reqwest::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = std::error::Error::source(err);
+
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signature_matches_standard_webhooks_example() {
+        // The example from the Standard Webhooks specification.
+        let signature = sign(
+            "whsec_MfKQ9r8GKYqrTwjUPD8ILPZIo2LaLaSw",
+            "msg_p5jXN8AQM9LWM0D4loKWxJek",
+            1614265330,
+            r#"{"test": 2432232314}"#,
+        );
+
+        assert_eq!(signature, "v1,g0hM9SsE+OTPJTGt/tmIKtSyZlE3uFJELVlNIOLJ1OE=");
+    }
+
+    #[test]
+    fn generated_secrets_can_be_used_to_sign() {
+        let secret = generate_secret();
+
+        assert!(secret.starts_with(SECRET_PREFIX));
+        assert_ne!(secret, generate_secret());
+        assert!(STANDARD.decode(&secret[SECRET_PREFIX.len()..]).is_ok());
+    }
+
+    #[test]
+    fn private_urls_are_rejected() {
+        for url in [
+            "http://localhost/hook",
+            "http://127.0.0.1/hook",
+            "http://10.0.0.5/hook",
+            "http://192.168.1.1:8080/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://[::1]/hook",
+            "http://[::ffff:127.0.0.1]/hook",
+            "http://[fd00::1]/hook",
+            "ftp://example.org/hook",
+            "not a url",
+        ] {
+            assert!(validate_url(url, false).is_err(), "{}", url);
+        }
+
+        assert!(validate_url("https://hooks.example.org/tasks", false).is_ok());
+        assert!(validate_url("http://127.0.0.1:8080/hook", true).is_ok());
+        assert!(validate_url("ftp://127.0.0.1/hook", true).is_err());
+    }
+}
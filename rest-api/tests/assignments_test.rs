@@ -0,0 +1,202 @@
+//! This module contains a set of integration tests to verify that users can be assigned to tasks, and that the users
+//! involved hear about assignments and mentions in their inbox.
+//!
+//! The tests need the database, just like the tests in `integration_test.rs`. You can run them on their own using the
+//! following command:
+//!
+//! ```sh
+//! cargo test --test assignments_test
+//! ```
+
+mod common;
+
+use common::{connect_test_db, create_task, create_user, run_due_jobs};
+use sqlx::PgPool;
+use todo_api::{
+    db,
+    entity::{NotificationKind, Role, SharedItem},
+    error::AppError,
+};
+
+async fn update_description(pool: &PgPool, user_id: i32, task_id: i32, description: String) {
+    db::update_task(
+        pool,
+        user_id,
+        task_id,
+        "test".to_string(),
+        description,
+        false,
+        None,
+        false,
+    )
+    .await
+    .unwrap();
+}
+
+/// Returns the kinds of the notifications in the inbox of a user.
+async fn inbox(pool: &PgPool, user_id: i32) -> Vec<NotificationKind> {
+    let notifications = db::list_notifications(pool, user_id, false, 0, 20)
+        .await
+        .unwrap();
+
+    notifications
+        .items
+        .into_iter()
+        .map(|notification| notification.kind)
+        .collect()
+}
+
+#[tokio::test]
+async fn assignments_and_mentions_notify_the_users_involved() {
+    let pool = connect_test_db().await;
+    let (owner_id, _) = create_user(&pool).await;
+    let (editor_id, editor_email) = create_user(&pool).await;
+    let (watcher_id, watcher_email) = create_user(&pool).await;
+    let (outsider_id, outsider_email) = create_user(&pool).await;
+    let task_id = create_task(&pool, owner_id).await;
+
+    for (email_address, role) in [
+        (&editor_email, Role::Editor),
+        (&watcher_email, Role::Viewer),
+    ] {
+        db::insert_share(
+            &pool,
+            owner_id,
+            SharedItem::Task(task_id),
+            email_address,
+            role,
+        )
+        .await
+        .unwrap();
+    }
+
+    let handle = format!("editor_{}", editor_id);
+    db::update_user_handle(&pool, editor_id, Some(handle.clone()))
+        .await
+        .unwrap();
+    db::insert_task_watcher(&pool, task_id, watcher_id)
+        .await
+        .unwrap();
+
+    // The outsider can't see the task, so mentioning them doesn't notify them.
+    let description = format!(
+        "Can @{} look at this? cc @{}",
+        handle.to_uppercase(),
+        outsider_email
+    );
+    update_description(&pool, owner_id, task_id, description.clone()).await;
+
+    db::insert_task_assignee(&pool, owner_id, task_id, editor_id)
+        .await
+        .unwrap();
+
+    let assigned = db::list_tasks(&pool, editor_id, None, true, 0, 10)
+        .await
+        .unwrap();
+    assert_eq!(assigned.total_count, 1);
+    assert_eq!(assigned.items[0].id, task_id);
+    assert_eq!(
+        db::list_tasks(&pool, watcher_id, None, true, 0, 10)
+            .await
+            .unwrap()
+            .total_count,
+        0
+    );
+
+    // Saving the description again doesn't notify the users who were mentioned before.
+    update_description(&pool, owner_id, task_id, description).await;
+    run_due_jobs(&pool).await;
+
+    assert_eq!(
+        inbox(&pool, editor_id).await,
+        vec![
+            NotificationKind::TaskAssigned,
+            NotificationKind::TaskMentioned
+        ]
+    );
+    assert_eq!(
+        inbox(&pool, watcher_id).await,
+        vec![NotificationKind::TaskAssigned]
+    );
+    assert!(inbox(&pool, outsider_id).await.is_empty());
+    assert!(inbox(&pool, owner_id).await.is_empty());
+
+    // Users can unassign themselves, which the watchers hear about.
+    db::delete_task_assignee(&pool, editor_id, task_id, editor_id)
+        .await
+        .unwrap();
+    run_due_jobs(&pool).await;
+
+    assert_eq!(inbox(&pool, editor_id).await.len(), 2);
+    assert_eq!(
+        inbox(&pool, watcher_id).await,
+        vec![
+            NotificationKind::TaskUnassigned,
+            NotificationKind::TaskAssigned
+        ]
+    );
+
+    for user_id in [owner_id, editor_id, watcher_id, outsider_id] {
+        db::delete_user(&pool, user_id).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn only_users_who_can_access_the_task_are_assigned() {
+    let pool = connect_test_db().await;
+    let (owner_id, _) = create_user(&pool).await;
+    let (outsider_id, _) = create_user(&pool).await;
+    let task_id = create_task(&pool, owner_id).await;
+
+    assert!(matches!(
+        db::insert_task_assignee(&pool, owner_id, task_id, outsider_id).await,
+        Err(AppError::TaskNotShared)
+    ));
+    assert!(matches!(
+        db::insert_task_watcher(&pool, task_id, outsider_id).await,
+        Err(AppError::TaskNotShared)
+    ));
+    assert!(matches!(
+        db::delete_task_assignee(&pool, owner_id, task_id, outsider_id).await,
+        Err(AppError::AssigneeNotFound)
+    ));
+
+    // Assigning the same user twice is harmless.
+    for _ in 0..2 {
+        db::insert_task_assignee(&pool, owner_id, task_id, owner_id)
+            .await
+            .unwrap();
+    }
+
+    let assignees = db::list_task_assignees(&pool, task_id).await.unwrap();
+    assert_eq!(assignees.len(), 1);
+    assert_eq!(assignees[0].user_id, owner_id);
+
+    db::delete_user(&pool, owner_id).await.unwrap();
+    db::delete_user(&pool, outsider_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn handles_are_unique_regardless_of_casing() {
+    let pool = connect_test_db().await;
+    let (user_id, _) = create_user(&pool).await;
+    let (other_id, _) = create_user(&pool).await;
+
+    let handle = format!("handle_{}", user_id);
+    db::update_user_handle(&pool, user_id, Some(handle.clone()))
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        db::update_user_handle(&pool, other_id, Some(handle.to_uppercase())).await,
+        Err(AppError::HandleTaken)
+    ));
+
+    db::update_user_handle(&pool, user_id, None).await.unwrap();
+    db::update_user_handle(&pool, other_id, Some(handle))
+        .await
+        .unwrap();
+
+    db::delete_user(&pool, user_id).await.unwrap();
+    db::delete_user(&pool, other_id).await.unwrap();
+}
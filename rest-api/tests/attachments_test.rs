@@ -0,0 +1,205 @@
+//! This module contains a set of integration tests to verify that files can be attached to tasks, that the configured
+//! limits apply to uploads, that download URLs are signed and that files are removed with their task.
+//!
+//! The tests need the database, just like the tests in `integration_test.rs`. You can run them on their own using the
+//! following command:
+//!
+//! ```sh
+//! cargo test --test attachments_test
+//! ```
+//!
+//! The test for the S3 storage needs the MinIO server from `docker-compose.yml`, so it's ignored by default. You can
+//! run it with the following command:
+//!
+//! ```sh
+//! cargo test --test attachments_test -- --ignored
+//! ```
+
+mod common;
+
+use std::{path::Path, sync::Arc, time::Duration};
+
+use axum::{
+    body::{Body, Bytes},
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::{
+    connect_test_db, create_router, create_task, create_verified_user, read_body, read_json,
+    run_due_jobs_with, send,
+};
+use sqlx::PgPool;
+use todo_api::{
+    blobs::{BlobError, BlobStore, Download, LocalBlobStore, S3BlobStore},
+    config::S3Config,
+    db,
+    entity::ApiKey,
+};
+
+/// The boundary of the multipart requests the tests send.
+const BOUNDARY: &str = "todo-api-test-boundary";
+
+/// Creates a router that keeps attachments in the given directory. The regular body limit is smaller than the limit
+/// for attachments, so the tests show that uploads have their own limit.
+async fn create_attachments_router(pool: &PgPool, directory: &Path) -> Router {
+    create_router(
+        pool,
+        &format!(
+            "[http]\nlimit = 1024\n\n[attachments]\ndirectory = \"{}\"\nlimit = 4096\ntypes = [\"text/plain\"]\n",
+            directory.display()
+        ),
+    )
+    .await
+}
+
+/// Builds the request to upload a file to a task.
+fn upload_request(api_key: &str, task_id: i32, content_type: &str, data: &[u8]) -> Request<Body> {
+    let mut body = format!(
+        "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"notes.txt\"\r\nContent-Type: {content_type}\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend_from_slice(data);
+    body.extend_from_slice(format!("\r\n--{BOUNDARY}--\r\n").as_bytes());
+
+    Request::post(format!("/v1/todos/{}/attachments", task_id))
+        .header("X-Api-Key", api_key)
+        .header(
+            header::CONTENT_TYPE,
+            format!("multipart/form-data; boundary={}", BOUNDARY),
+        )
+        .header(header::CONTENT_LENGTH, body.len())
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn files_are_attached_downloaded_and_removed_with_their_task() {
+    let pool = connect_test_db().await;
+    let directory =
+        std::env::temp_dir().join(format!("todo-api-attachments-{}", ApiKey::new().key));
+    let router = create_attachments_router(&pool, &directory).await;
+    let (user_id, api_key) = create_verified_user(&pool).await;
+    let task_id = create_task(&pool, user_id).await;
+
+    // The file is larger than the regular body limit, but smaller than the limit for attachments.
+    let data = "a".repeat(3000);
+    let response = send(
+        &router,
+        upload_request(&api_key, task_id, "text/plain", data.as_bytes()),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let attachment = read_json(response).await;
+    assert_eq!(attachment["file_name"], "notes.txt");
+    assert_eq!(attachment["size"], 3000);
+    assert!(attachment.get("storage_key").is_none());
+
+    let response = send(
+        &router,
+        upload_request(&api_key, task_id, "text/html", b"<p>Hi</p>"),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    let response = send(
+        &router,
+        upload_request(&api_key, task_id, "text/plain", "a".repeat(5000).as_bytes()),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let attachments = db::list_attachments(&pool, task_id).await.unwrap();
+    assert_eq!(attachments.len(), 1);
+
+    // The local storage can't sign URLs, so the URL points to the download endpoint of the API.
+    let response = send(
+        &router,
+        Request::get(format!(
+            "/v1/todos/{}/attachments/{}/url",
+            task_id, attachment["id"]
+        ))
+        .header("X-Api-Key", &api_key)
+        .body(Body::empty())
+        .unwrap(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let url = read_json(response).await["url"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert!(url.starts_with(&format!("/v1/attachments/{}/content?", attachment["id"])));
+
+    let response = send(&router, Request::get(&url).body(Body::empty()).unwrap()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()[header::CONTENT_DISPOSITION]
+        .to_str()
+        .unwrap()
+        .contains("notes.txt"));
+    assert_eq!(read_body(response).await, data.as_bytes());
+
+    let tampered_url = url.replace("expires=", "expires=1");
+    let response = send(
+        &router,
+        Request::get(&tampered_url).body(Body::empty()).unwrap(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // Removing the task queues a job that removes the file from the store.
+    let blobs = Arc::new(LocalBlobStore::new(&directory));
+    let storage_key = attachments[0].storage_key.clone();
+    assert!(blobs.get(&storage_key).await.is_ok());
+
+    db::delete_task(&pool, user_id, task_id).await.unwrap();
+    run_due_jobs_with(&pool, blobs.clone()).await;
+
+    assert!(matches!(
+        blobs.get(&storage_key).await,
+        Err(BlobError::NotFound)
+    ));
+
+    db::delete_user(&pool, user_id).await.unwrap();
+    std::fs::remove_dir_all(&directory).ok();
+}
+
+#[tokio::test]
+#[ignore = "needs the MinIO server from docker-compose.yml"]
+async fn s3_storage_keeps_files_in_the_bucket() {
+    let blobs = S3BlobStore::new(S3Config {
+        endpoint: "http://localhost:9000".to_string(),
+        region: "us-east-1".to_string(),
+        bucket: "attachments".to_string(),
+        key: "minioadmin".to_string(),
+        secret: "minioadmin".to_string(),
+    })
+    .unwrap();
+    let key = format!("tests/{}", ApiKey::new().key);
+
+    blobs
+        .put(&key, "text/plain", Bytes::from_static(b"Hello"))
+        .await
+        .unwrap();
+    assert_eq!(blobs.get(&key).await.unwrap(), "Hello");
+
+    let download = Download {
+        content_type: "text/plain",
+        content_disposition: "attachment; filename=\"hello.txt\"",
+    };
+    let url = blobs
+        .presigned_url(&key, &download, Duration::from_secs(60))
+        .unwrap();
+    let response = reqwest::get(url).await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(
+        response.headers()[reqwest::header::CONTENT_DISPOSITION],
+        "attachment; filename=\"hello.txt\""
+    );
+    assert_eq!(response.text().await.unwrap(), "Hello");
+
+    blobs.delete(&key).await.unwrap();
+    blobs.delete(&key).await.unwrap();
+    assert!(matches!(blobs.get(&key).await, Err(BlobError::NotFound)));
+}
@@ -0,0 +1,134 @@
+//! This module contains a set of integration tests to verify that users can discuss tasks in comments, that only the
+//! author changes a comment, and that task lists include the number of comments.
+//!
+//! The tests need the database, just like the tests in `integration_test.rs`. You can run them on their own using the
+//! following command:
+//!
+//! ```sh
+//! cargo test --test comments_test
+//! ```
+
+mod common;
+
+use common::{connect_test_db, create_task, create_user, run_due_jobs};
+use todo_api::{
+    db,
+    entity::{NotificationKind, Role, SharedItem},
+    error::AppError,
+};
+
+#[tokio::test]
+async fn only_the_author_changes_a_comment() {
+    let pool = connect_test_db().await;
+    let (owner_id, _) = create_user(&pool).await;
+    let (viewer_id, viewer_email) = create_user(&pool).await;
+    let task_id = create_task(&pool, owner_id).await;
+
+    db::insert_share(
+        &pool,
+        owner_id,
+        SharedItem::Task(task_id),
+        &viewer_email,
+        Role::Viewer,
+    )
+    .await
+    .unwrap();
+
+    let comment = db::insert_comment(
+        &pool,
+        task_id,
+        owner_id,
+        format!(
+            "Can you **check** this, @{}? <script>alert(1)</script>",
+            viewer_email
+        ),
+    )
+    .await
+    .unwrap();
+    assert_eq!(comment.user_id, owner_id);
+    assert!(comment.body_html.contains("<strong>check</strong>"));
+    assert!(!comment.body_html.contains("script"));
+
+    assert!(matches!(
+        db::update_comment(
+            &pool,
+            task_id,
+            comment.id,
+            viewer_id,
+            "Mine now".to_string()
+        )
+        .await,
+        Err(AppError::NotCommentAuthor)
+    ));
+    assert!(matches!(
+        db::delete_comment(&pool, task_id, comment.id, viewer_id).await,
+        Err(AppError::NotCommentAuthor)
+    ));
+
+    // Edits keep the earlier version, and don't mention the viewer again.
+    let edited_body = format!("Can you check this, @{}?", viewer_email);
+    let edited = db::update_comment(&pool, task_id, comment.id, owner_id, edited_body.clone())
+        .await
+        .unwrap();
+    assert_eq!(edited.body, edited_body);
+    assert!(edited.date_modified.is_some());
+
+    let revisions = db::list_comment_revisions(&pool, task_id, comment.id)
+        .await
+        .unwrap();
+    assert_eq!(revisions.len(), 1);
+    assert_eq!(revisions[0].body, comment.body);
+
+    run_due_jobs(&pool).await;
+
+    let notifications = db::list_notifications(&pool, viewer_id, false, 0, 10)
+        .await
+        .unwrap();
+    assert_eq!(notifications.total_count, 1);
+    assert_eq!(notifications.items[0].kind, NotificationKind::TaskMentioned);
+
+    db::delete_comment(&pool, task_id, comment.id, owner_id)
+        .await
+        .unwrap();
+    assert!(matches!(
+        db::find_comment(&pool, task_id, comment.id).await,
+        Err(AppError::CommentNotFound)
+    ));
+
+    db::delete_user(&pool, owner_id).await.unwrap();
+    db::delete_user(&pool, viewer_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn task_lists_include_the_number_of_comments() {
+    let pool = connect_test_db().await;
+    let (user_id, _) = create_user(&pool).await;
+    let task_id = create_task(&pool, user_id).await;
+    let other_task_id = create_task(&pool, user_id).await;
+
+    for body in ["First", "Second"] {
+        db::insert_comment(&pool, task_id, user_id, body.to_string())
+            .await
+            .unwrap();
+    }
+
+    let comments = db::list_comments(&pool, task_id, 0, 1).await.unwrap();
+    assert_eq!(comments.total_count, 2);
+    assert_eq!(comments.items[0].body, "First");
+
+    let tasks = db::list_tasks(&pool, user_id, None, false, 0, 10)
+        .await
+        .unwrap();
+    let counts: Vec<(i32, Option<i64>)> = tasks
+        .items
+        .iter()
+        .map(|task| (task.id, task.comment_count))
+        .collect();
+    assert_eq!(counts, vec![(task_id, Some(2)), (other_task_id, Some(0))]);
+
+    // A single task doesn't include the count, so it's left out of the response.
+    let task = db::find_task(&pool, user_id, task_id).await.unwrap();
+    assert!(task.comment_count.is_none());
+
+    db::delete_user(&pool, user_id).await.unwrap();
+}
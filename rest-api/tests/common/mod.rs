@@ -0,0 +1,174 @@
+//! This module contains the helpers that the integration tests share, like connecting to the test database and
+//! creating users.
+//!
+//! Cargo compiles every file in the `tests` folder as a separate crate, and each of them uses a different part of this
+//! module. That's why we allow dead code here, instead of getting warnings for the helpers a test file doesn't use.
+
+#![allow(dead_code)]
+
+use std::sync::Arc;
+
+use axum::{
+    body::{Body, Bytes},
+    http::Request,
+    response::Response,
+    Router,
+};
+use dotenv::dotenv;
+use sqlx::PgPool;
+use todo_api::{
+    blobs::{BlobStore, LocalBlobStore},
+    config::{AppConfig, DatabaseConfig, JobsConfig},
+    db,
+    entity::ApiKey,
+    jobs::{JobContext, JobRunner},
+    mail::LogMailer,
+    state::AppState,
+    web,
+};
+use tower::ServiceExt;
+
+pub async fn connect_test_db() -> PgPool {
+    dotenv().ok();
+
+    let db_config = DatabaseConfig {
+        host: std::env::var("DB_HOST").unwrap().to_string(),
+        port: std::env::var("DB_PORT").unwrap().parse().unwrap(),
+        name: std::env::var("DB_NAME").unwrap().to_string(),
+        username: std::env::var("DB_USER").unwrap().to_string(),
+        password: std::env::var("DB_PASSWORD").unwrap().to_string(),
+        ..Default::default()
+    };
+
+    db::connect_db(&db_config).await.unwrap()
+}
+
+/// Returns the URL of the test database, built from the same environment variables as [`connect_test_db`].
+pub fn database_url() -> String {
+    dotenv().ok();
+
+    format!(
+        "postgres://{}:{}@{}:{}/{}",
+        std::env::var("DB_USER").unwrap(),
+        std::env::var("DB_PASSWORD").unwrap(),
+        std::env::var("DB_HOST").unwrap(),
+        std::env::var("DB_PORT").unwrap(),
+        std::env::var("DB_NAME").unwrap(),
+    )
+}
+
+/// Creates a user and returns its ID and email address.
+pub async fn create_user(pool: &PgPool) -> (i32, String) {
+    let email_address = format!("{}@example.org", ApiKey::new().key);
+
+    let user_id = db::insert_user(pool, email_address.clone(), ApiKey::new().hash, None)
+        .await
+        .unwrap();
+
+    (user_id, email_address)
+}
+
+/// Creates a verified user and returns its ID and API key.
+pub async fn create_verified_user(pool: &PgPool) -> (i32, String) {
+    let api_key = ApiKey::new();
+    let email_address = format!("{}@example.org", ApiKey::new().key);
+
+    let user_id = db::insert_user(pool, email_address.clone(), api_key.hash, None)
+        .await
+        .unwrap();
+    db::mark_email_verified(pool, user_id, &email_address)
+        .await
+        .unwrap();
+
+    (user_id, api_key.key)
+}
+
+/// Creates a personal task for a user and returns its ID.
+pub async fn create_task(pool: &PgPool, user_id: i32) -> i32 {
+    db::insert_task(
+        pool,
+        user_id,
+        None,
+        "test".to_string(),
+        "".to_string(),
+        None,
+    )
+    .await
+    .unwrap()
+}
+
+/// Creates a task in a workspace and returns its ID.
+pub async fn create_workspace_task(pool: &PgPool, user_id: i32, workspace_id: i32) -> i32 {
+    db::insert_task(
+        pool,
+        user_id,
+        Some(workspace_id),
+        "test".to_string(),
+        "".to_string(),
+        None,
+    )
+    .await
+    .unwrap()
+}
+
+/// Runs jobs until none are due anymore.
+pub async fn run_due_jobs(pool: &PgPool) {
+    run_due_jobs_with(
+        pool,
+        Arc::new(LocalBlobStore::new(
+            std::env::temp_dir().join("todo-api-blobs"),
+        )),
+    )
+    .await;
+}
+
+/// Runs jobs until none are due anymore, with the given store for the contents of attachments.
+pub async fn run_due_jobs_with(pool: &PgPool, blobs: Arc<dyn BlobStore>) {
+    let runner = JobRunner::new(JobsConfig::default());
+    let context = JobContext::new(pool.clone(), Arc::new(LogMailer), blobs);
+
+    while runner.run_next(&context).await.unwrap().is_some() {}
+}
+
+/// Creates the router of the API with the given settings on top of the database and signing key of the tests.
+///
+/// The settings are TOML, like `"[http]\nlimit = 1024\n"`.
+pub async fn create_router(pool: &PgPool, settings: &str) -> Router {
+    web::create_router(create_app_state(pool, settings).await)
+}
+
+/// Creates the state of the application with the given settings on top of the database and signing key of the tests.
+///
+/// The settings are TOML, like `"[admin]\ntoken = \"...\"\n"`.
+pub async fn create_app_state(pool: &PgPool, settings: &str) -> Arc<AppState> {
+    let config_file =
+        std::env::temp_dir().join(format!("todo-api-test-{}.toml", ApiKey::new().key));
+    std::fs::write(
+        &config_file,
+        format!(
+            "[database]\nurl = \"{}\"\n\n[auth.keys]\ntest = \"test-signing-key\"\n\n{}",
+            database_url(),
+            settings
+        ),
+    )
+    .unwrap();
+
+    let app_config = AppConfig::load_from(Some(&config_file)).unwrap();
+    std::fs::remove_file(&config_file).ok();
+
+    AppState::new(pool.clone(), &app_config).unwrap()
+}
+
+pub async fn send(router: &Router, request: Request<Body>) -> Response {
+    router.clone().oneshot(request).await.unwrap()
+}
+
+pub async fn read_json(response: Response) -> serde_json::Value {
+    serde_json::from_slice(&read_body(response).await).unwrap()
+}
+
+pub async fn read_body(response: Response) -> Bytes {
+    axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap()
+}
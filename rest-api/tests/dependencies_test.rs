@@ -0,0 +1,284 @@
+//! This module contains a set of integration tests to verify that tasks can wait for other tasks, that cycles are
+//! refused, also through tasks the user can't see, that the `blocked` flag follows the blocking tasks and that the
+//! dependency graph only shows what the user can see, up to a limit.
+//!
+//! The tests need the database, just like the tests in `integration_test.rs`. You can run them on their own using the
+//! following command:
+//!
+//! ```sh
+//! cargo test --test dependencies_test
+//! ```
+
+mod common;
+
+use common::{connect_test_db, create_user};
+use sqlx::PgPool;
+use todo_api::{
+    db,
+    entity::{Role, SharedItem},
+    error::AppError,
+};
+
+/// Creates a personal task with a title, so the graph shows which task is which.
+async fn create_named_task(pool: &PgPool, user_id: i32, title: &str) -> i32 {
+    db::insert_task(pool, user_id, None, title.to_string(), "".to_string(), None)
+        .await
+        .unwrap()
+}
+
+async fn set_completed(pool: &PgPool, user_id: i32, task_id: i32, completed: bool) {
+    update_completed(pool, user_id, task_id, completed, false)
+        .await
+        .unwrap();
+}
+
+async fn update_completed(
+    pool: &PgPool,
+    user_id: i32,
+    task_id: i32,
+    completed: bool,
+    force: bool,
+) -> Result<(), AppError> {
+    db::update_task(
+        pool,
+        user_id,
+        task_id,
+        "test".to_string(),
+        "".to_string(),
+        completed,
+        None,
+        force,
+    )
+    .await
+}
+
+async fn is_blocked(pool: &PgPool, user_id: i32, task_id: i32) -> bool {
+    db::find_task(pool, user_id, task_id).await.unwrap().blocked
+}
+
+#[tokio::test]
+async fn links_that_close_a_cycle_are_refused() {
+    let pool = connect_test_db().await;
+    let (user_id, _) = create_user(&pool).await;
+    let first = create_named_task(&pool, user_id, "first").await;
+    let second = create_named_task(&pool, user_id, "second").await;
+    let third = create_named_task(&pool, user_id, "third").await;
+
+    // The first task waits for the second, which waits for the third.
+    db::insert_task_dependency(&pool, first, second)
+        .await
+        .unwrap();
+    db::insert_task_dependency(&pool, second, third)
+        .await
+        .unwrap();
+    db::insert_task_dependency(&pool, first, second)
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        db::insert_task_dependency(&pool, third, first).await,
+        Err(AppError::DependencyCycle)
+    ));
+    assert!(matches!(
+        db::insert_task_dependency(&pool, first, first).await,
+        Err(AppError::DependencyCycle)
+    ));
+
+    db::delete_task_dependency(&pool, second, third)
+        .await
+        .unwrap();
+    assert!(matches!(
+        db::delete_task_dependency(&pool, second, third).await,
+        Err(AppError::DependencyNotFound)
+    ));
+
+    // Without the link in the middle, the third task can wait for the first.
+    db::insert_task_dependency(&pool, third, first)
+        .await
+        .unwrap();
+
+    db::delete_user(&pool, user_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn links_through_hidden_tasks_close_a_cycle_too() {
+    let pool = connect_test_db().await;
+    let (owner_id, _) = create_user(&pool).await;
+    let (editor_id, editor_email) = create_user(&pool).await;
+    let design = create_named_task(&pool, owner_id, "design").await;
+    let build = create_named_task(&pool, owner_id, "build").await;
+    let release = create_named_task(&pool, owner_id, "release").await;
+
+    // The release waits for the build, which waits for the design. The editor can't see the build.
+    db::insert_task_dependency(&pool, release, build)
+        .await
+        .unwrap();
+    db::insert_task_dependency(&pool, build, design)
+        .await
+        .unwrap();
+
+    for task_id in [design, release] {
+        db::insert_share(
+            &pool,
+            owner_id,
+            SharedItem::Task(task_id),
+            &editor_email,
+            Role::Editor,
+        )
+        .await
+        .unwrap();
+    }
+
+    let graph = db::find_task_graph(&pool, editor_id, None, design, 100)
+        .await
+        .unwrap();
+    assert_eq!(graph.nodes.len(), 1);
+
+    // The design can't wait for the release, even though the editor doesn't see how they're linked.
+    assert!(matches!(
+        db::insert_task_dependency(&pool, design, release).await,
+        Err(AppError::DependencyCycle)
+    ));
+
+    db::delete_user(&pool, owner_id).await.unwrap();
+    db::delete_user(&pool, editor_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn tasks_are_blocked_until_their_blockers_are_completed() {
+    let pool = connect_test_db().await;
+    let (user_id, _) = create_user(&pool).await;
+    let task = create_named_task(&pool, user_id, "release").await;
+    let blocker = create_named_task(&pool, user_id, "review").await;
+
+    db::insert_task_dependency(&pool, task, blocker)
+        .await
+        .unwrap();
+    assert!(is_blocked(&pool, user_id, task).await);
+    assert!(matches!(
+        update_completed(&pool, user_id, task, true, false).await,
+        Err(AppError::TaskBlocked)
+    ));
+
+    set_completed(&pool, user_id, blocker, true).await;
+    assert!(!is_blocked(&pool, user_id, task).await);
+
+    // Reopening the blocker blocks the task again, but a completed task can still be edited.
+    set_completed(&pool, user_id, task, true).await;
+    set_completed(&pool, user_id, blocker, false).await;
+    assert!(is_blocked(&pool, user_id, task).await);
+    set_completed(&pool, user_id, task, true).await;
+
+    // A blocked task can still be completed on purpose.
+    set_completed(&pool, user_id, task, false).await;
+    update_completed(&pool, user_id, task, true, true)
+        .await
+        .unwrap();
+
+    let tasks = db::list_tasks(&pool, user_id, None, false, 0, 10)
+        .await
+        .unwrap();
+    let flags: Vec<(i32, bool)> = tasks
+        .items
+        .iter()
+        .map(|task| (task.id, task.blocked))
+        .collect();
+    assert_eq!(flags, vec![(task, true), (blocker, false)]);
+
+    db::delete_user(&pool, user_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn the_graph_only_shows_tasks_the_user_can_see() {
+    let pool = connect_test_db().await;
+    let (owner_id, _) = create_user(&pool).await;
+    let (viewer_id, viewer_email) = create_user(&pool).await;
+    let design = create_named_task(&pool, owner_id, "design").await;
+    let build = create_named_task(&pool, owner_id, "build").await;
+    let release = create_named_task(&pool, owner_id, "release").await;
+    let unrelated = create_named_task(&pool, owner_id, "unrelated").await;
+
+    db::insert_task_dependency(&pool, build, design)
+        .await
+        .unwrap();
+    db::insert_task_dependency(&pool, release, build)
+        .await
+        .unwrap();
+    db::insert_task_dependency(&pool, unrelated, design)
+        .await
+        .unwrap();
+
+    // The graph of a task holds what it waits for and what waits for it, but not the other tasks that wait for its
+    // blockers.
+    let graph = db::find_task_graph(&pool, owner_id, None, build, 100)
+        .await
+        .unwrap();
+    let nodes: Vec<(i32, bool)> = graph
+        .nodes
+        .iter()
+        .map(|node| (node.id, node.blocked))
+        .collect();
+    assert_eq!(nodes, vec![(design, false), (build, true), (release, true)]);
+    let edges: Vec<(i32, i32)> = graph
+        .edges
+        .iter()
+        .map(|edge| (edge.task_id, edge.blocked_by_id))
+        .collect();
+    assert_eq!(edges, vec![(build, design), (release, build)]);
+    assert!(!graph.truncated);
+
+    db::insert_share(
+        &pool,
+        owner_id,
+        SharedItem::Task(build),
+        &viewer_email,
+        Role::Viewer,
+    )
+    .await
+    .unwrap();
+
+    let graph = db::find_task_graph(&pool, viewer_id, None, build, 100)
+        .await
+        .unwrap();
+    assert_eq!(graph.nodes.len(), 1);
+    assert!(graph.nodes[0].blocked);
+    assert!(graph.edges.is_empty());
+
+    db::delete_user(&pool, owner_id).await.unwrap();
+    db::delete_user(&pool, viewer_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn large_graph_is_truncated() {
+    let pool = connect_test_db().await;
+    let (user_id, _) = create_user(&pool).await;
+    let mut tasks = Vec::new();
+
+    for index in 0..5 {
+        tasks.push(create_named_task(&pool, user_id, &format!("step {}", index)).await);
+    }
+
+    // Every step waits for the one before it.
+    for pair in tasks.windows(2) {
+        db::insert_task_dependency(&pool, pair[1], pair[0])
+            .await
+            .unwrap();
+    }
+
+    // The graph of the last step keeps the step itself, and the steps nearest to it.
+    let graph = db::find_task_graph(&pool, user_id, None, tasks[4], 3)
+        .await
+        .unwrap();
+    let nodes: Vec<i32> = graph.nodes.iter().map(|node| node.id).collect();
+    assert_eq!(nodes, vec![tasks[2], tasks[3], tasks[4]]);
+    assert_eq!(graph.edges.len(), 2);
+    assert!(graph.truncated);
+
+    let graph = db::find_task_graph(&pool, user_id, None, tasks[4], 5)
+        .await
+        .unwrap();
+    assert_eq!(graph.nodes.len(), 5);
+    assert!(!graph.truncated);
+
+    db::delete_user(&pool, user_id).await.unwrap();
+}
@@ -0,0 +1,282 @@
+//! This module contains a set of integration tests to verify that changes to tasks reach the event stream.
+//!
+//! The tests need the database, just like the tests in `integration_test.rs`, because the events travel through the
+//! `task_events` table and Postgres notifications. You can run them on their own using the following command:
+//!
+//! ```sh
+//! cargo test --test events_test
+//! ```
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use common::{connect_test_db, create_task, create_user, create_workspace_task};
+use sqlx::PgPool;
+use todo_api::{
+    config::EventsConfig,
+    db,
+    entity::{Role, SharedItem, TaskEvent, TaskEventType, WorkspaceRole},
+    events::{EventHub, Subscription},
+    shutdown::Shutdown,
+};
+
+/// Waits for the next event of a subscription, failing the test when it doesn't arrive in time.
+async fn next_event(subscription: &mut Subscription) -> Arc<TaskEvent> {
+    tokio::time::timeout(Duration::from_secs(10), subscription.next())
+        .await
+        .expect("The event arrived in time.")
+        .unwrap()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn changes_are_streamed_to_the_owner() {
+    let pool = connect_test_db().await;
+    let (user_id, _) = create_user(&pool).await;
+    let (other_user_id, _) = create_user(&pool).await;
+
+    let hub = Arc::new(EventHub::new(EventsConfig::default()));
+    let shutdown = Arc::new(Shutdown::new());
+
+    let listener = {
+        let (hub, pool, shutdown) = (hub.clone(), pool.clone(), shutdown.clone());
+        tokio::spawn(async move { hub.listen(&pool, &shutdown).await })
+    };
+
+    let mut subscription = hub.subscribe(pool.clone(), user_id, None, None);
+
+    // The listener needs a moment to connect, events before that aren't part of a live stream.
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    db::insert_task(
+        &pool,
+        other_user_id,
+        None,
+        "other".to_string(),
+        "".to_string(),
+        None,
+    )
+    .await
+    .unwrap();
+
+    let task_id = db::insert_task(
+        &pool,
+        user_id,
+        None,
+        "test".to_string(),
+        "".to_string(),
+        None,
+    )
+    .await
+    .unwrap();
+
+    db::update_task(
+        &pool,
+        user_id,
+        task_id,
+        "test".to_string(),
+        "".to_string(),
+        true,
+        None,
+        false,
+    )
+    .await
+    .unwrap();
+
+    let created = next_event(&mut subscription).await;
+    assert_eq!(created.event_type, TaskEventType::TaskCreated);
+    assert_eq!(created.task_id, task_id);
+    assert_eq!(created.payload["title"], "test");
+
+    let updated = next_event(&mut subscription).await;
+    assert_eq!(updated.event_type, TaskEventType::TaskUpdated);
+
+    let completed = next_event(&mut subscription).await;
+    assert_eq!(completed.event_type, TaskEventType::TaskCompleted);
+    assert!(completed.id > created.id);
+
+    shutdown.begin();
+    listener.await.unwrap();
+
+    db::delete_user(&pool, user_id).await.unwrap();
+    db::delete_user(&pool, other_user_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn subscription_resumes_after_last_event_id() {
+    let pool = connect_test_db().await;
+    let (user_id, _) = create_user(&pool).await;
+
+    let task_id = db::insert_task(
+        &pool,
+        user_id,
+        None,
+        "test".to_string(),
+        "".to_string(),
+        None,
+    )
+    .await
+    .unwrap();
+
+    db::delete_task(&pool, user_id, task_id).await.unwrap();
+
+    let events = db::list_task_events(&pool, user_id, None, 0, 10)
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 2);
+
+    // Nobody listens for notifications here, so the event can only come from the database.
+    let hub = EventHub::new(EventsConfig::default());
+    let mut subscription = hub.subscribe(pool.clone(), user_id, None, Some(events[0].id));
+
+    let deleted = next_event(&mut subscription).await;
+    assert_eq!(deleted.id, events[1].id);
+    assert_eq!(deleted.event_type, TaskEventType::TaskDeleted);
+    assert_eq!(deleted.payload["id"], task_id);
+
+    db::delete_user(&pool, user_id).await.unwrap();
+}
+
+/// Creates a workspace of the owner, with the other user as a member.
+async fn create_workspace(pool: &PgPool, owner_id: i32, member: &(i32, String)) -> i32 {
+    let workspace_id = db::insert_workspace(pool, owner_id, "Team".to_string())
+        .await
+        .unwrap();
+    let invitation = db::insert_workspace_invitation(
+        pool,
+        workspace_id,
+        owner_id,
+        &member.1,
+        WorkspaceRole::Member,
+    )
+    .await
+    .unwrap();
+    db::accept_workspace_invitation(pool, member.0, invitation.id)
+        .await
+        .unwrap();
+
+    workspace_id
+}
+
+#[tokio::test]
+async fn changes_are_streamed_to_everyone_who_can_access_the_task() {
+    let pool = connect_test_db().await;
+    let (owner_id, _) = create_user(&pool).await;
+    let member = create_user(&pool).await;
+    let (outsider_id, _) = create_user(&pool).await;
+    let workspace_id = create_workspace(&pool, owner_id, &member).await;
+
+    let hub = Arc::new(EventHub::new(EventsConfig::default()));
+    let shutdown = Arc::new(Shutdown::new());
+
+    let listener = {
+        let (hub, pool, shutdown) = (hub.clone(), pool.clone(), shutdown.clone());
+        tokio::spawn(async move { hub.listen(&pool, &shutdown).await })
+    };
+
+    let mut member_subscription = hub.subscribe(pool.clone(), member.0, None, None);
+    let mut scoped_subscription = hub.subscribe(pool.clone(), member.0, Some(workspace_id), None);
+    let mut outsider_subscription = hub.subscribe(pool.clone(), outsider_id, None, None);
+
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let personal_task_id = create_task(&pool, member.0).await;
+    let workspace_task_id = create_workspace_task(&pool, owner_id, workspace_id).await;
+    let outsider_task_id = create_task(&pool, outsider_id).await;
+
+    assert_eq!(
+        next_event(&mut member_subscription).await.task_id,
+        personal_task_id
+    );
+    assert_eq!(
+        next_event(&mut member_subscription).await.task_id,
+        workspace_task_id
+    );
+
+    // Credentials of the workspace don't receive the changes to the personal tasks of the member.
+    assert_eq!(
+        next_event(&mut scoped_subscription).await.task_id,
+        workspace_task_id
+    );
+
+    // Users outside of the workspace only receive the changes to their own tasks.
+    assert_eq!(
+        next_event(&mut outsider_subscription).await.task_id,
+        outsider_task_id
+    );
+
+    shutdown.begin();
+    listener.await.unwrap();
+
+    db::delete_workspace(&pool, workspace_id).await.unwrap();
+    db::delete_user(&pool, owner_id).await.unwrap();
+    db::delete_user(&pool, member.0).await.unwrap();
+    db::delete_user(&pool, outsider_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn resumed_subscription_follows_shares_and_scope() {
+    let pool = connect_test_db().await;
+    let (owner_id, _) = create_user(&pool).await;
+    let member = create_user(&pool).await;
+    let workspace_id = create_workspace(&pool, owner_id, &member).await;
+
+    let shared_task_id = create_task(&pool, owner_id).await;
+    // The other personal task of the owner isn't shared, so its events stay out of the stream of the member.
+    create_task(&pool, owner_id).await;
+    let workspace_task_id = create_workspace_task(&pool, owner_id, workspace_id).await;
+    db::insert_share(
+        &pool,
+        owner_id,
+        SharedItem::Task(shared_task_id),
+        &member.1,
+        Role::Viewer,
+    )
+    .await
+    .unwrap();
+
+    let events = db::list_task_events(&pool, owner_id, None, 0, i64::MAX)
+        .await
+        .unwrap();
+    let before = events[0].id - 1;
+
+    let task_ids = |events: Vec<TaskEvent>| events.into_iter().map(|event| event.task_id);
+
+    let visible: Vec<i32> = task_ids(
+        db::list_task_events(&pool, member.0, None, before, 10)
+            .await
+            .unwrap(),
+    )
+    .collect();
+    assert_eq!(visible, vec![shared_task_id, workspace_task_id]);
+
+    let scoped: Vec<i32> = task_ids(
+        db::list_task_events(&pool, member.0, Some(workspace_id), before, 10)
+            .await
+            .unwrap(),
+    )
+    .collect();
+    assert_eq!(scoped, vec![workspace_task_id]);
+
+    // The events of a removed workspace task still reach the members of the workspace.
+    db::delete_task(&pool, owner_id, workspace_task_id)
+        .await
+        .unwrap();
+
+    let hub = EventHub::new(EventsConfig::default());
+    let mut subscription = hub.subscribe(pool.clone(), member.0, Some(workspace_id), Some(before));
+
+    assert_eq!(
+        next_event(&mut subscription).await.task_id,
+        workspace_task_id
+    );
+    let deleted = next_event(&mut subscription).await;
+    assert_eq!(deleted.task_id, workspace_task_id);
+    assert_eq!(deleted.event_type, TaskEventType::TaskDeleted);
+
+    db::delete_workspace(&pool, workspace_id).await.unwrap();
+    db::delete_user(&pool, owner_id).await.unwrap();
+    db::delete_user(&pool, member.0).await.unwrap();
+}
@@ -0,0 +1,72 @@
+//! This module contains a set of integration tests to verify the health endpoints, including the readiness check that
+//! starts failing once the application is shutting down.
+//!
+//! The tests need the database, because the readiness check connects to it. You can run them on their own using the
+//! following command:
+//!
+//! ```sh
+//! cargo test --test health_test
+//! ```
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use common::{connect_test_db, create_app_state, read_json, send};
+use todo_api::web;
+
+fn get_request(path: &str) -> Request<Body> {
+    Request::get(path).body(Body::empty()).unwrap()
+}
+
+#[tokio::test]
+async fn liveness_reports_the_process_is_alive() {
+    let pool = connect_test_db().await;
+    let router = web::create_router(create_app_state(&pool, "").await);
+
+    let response = send(&router, get_request("/healthz")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = read_json(response).await;
+    assert_eq!(body["status"], "ok");
+}
+
+#[tokio::test]
+async fn readiness_reports_the_checks() {
+    let pool = connect_test_db().await;
+    let router = web::create_router(create_app_state(&pool, "").await);
+
+    let response = send(&router, get_request("/readyz")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = read_json(response).await;
+    assert_eq!(body["status"], "ok");
+    assert_eq!(body["shutting_down"], false);
+    assert_eq!(body["database"]["status"], "ok");
+    assert_eq!(body["migrations"]["status"], "ok");
+    assert_eq!(body["migrations"]["pending"], serde_json::json!([]));
+    assert!(body["pool"]["max_connections"].as_u64().unwrap() > 0);
+}
+
+#[tokio::test]
+async fn readiness_fails_while_shutting_down() {
+    let pool = connect_test_db().await;
+    let app_state = create_app_state(&pool, "").await;
+    let router = web::create_router(app_state.clone());
+
+    app_state.shutdown.begin();
+
+    let response = send(&router, get_request("/readyz")).await;
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    let body = read_json(response).await;
+    assert_eq!(body["status"], "failing");
+    assert_eq!(body["shutting_down"], true);
+    assert_eq!(body["database"]["status"], "ok");
+
+    // The process is still alive, so the platform doesn't restart it while in-flight requests drain.
+    let response = send(&router, get_request("/healthz")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
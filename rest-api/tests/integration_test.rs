@@ -21,6 +21,8 @@ use dotenv::dotenv;
 use sqlx::PgPool;
 use todo_api::config::DatabaseConfig;
 use todo_api::db::*;
+use todo_api::entity::{ApiKey, OidcLoginRequest};
+use todo_api::error::AppError;
 
 async fn connect_test_db() -> PgPool {
     dotenv().ok();
@@ -31,6 +33,7 @@ async fn connect_test_db() -> PgPool {
         name: std::env::var("DB_NAME").unwrap().to_string(),
         username: std::env::var("DB_USER").unwrap().to_string(),
         password: std::env::var("DB_PASSWORD").unwrap().to_string(),
+        ..Default::default()
     };
 
     connect_db(&db_config).await.unwrap()
@@ -43,8 +46,10 @@ async fn insert_todo_creates_record() {
     let inserted_task = insert_task(
         &connection_pool,
         1,
+        None,
         "test".to_string(),
         "test description".to_string(),
+        None,
     )
     .await
     .unwrap();
@@ -53,7 +58,7 @@ async fn insert_todo_creates_record() {
 
     assert_eq!(retrieved_task.title, "test");
     assert_eq!(retrieved_task.description, "test description");
-    assert_eq!(retrieved_task.completed, false);
+    assert!(!retrieved_task.completed);
 }
 
 #[tokio::test]
@@ -63,8 +68,10 @@ async fn update_todo_updates_record() {
     let inserted_task = insert_task(
         &connection_pool,
         1,
+        None,
         "test".to_string(),
         "test description".to_string(),
+        None,
     )
     .await
     .unwrap();
@@ -76,6 +83,8 @@ async fn update_todo_updates_record() {
         "test 2".to_string(),
         "test description 2".to_string(),
         true,
+        None,
+        false,
     )
     .await
     .unwrap();
@@ -84,7 +93,7 @@ async fn update_todo_updates_record() {
 
     assert_eq!(retrieved_task.title, "test 2");
     assert_eq!(retrieved_task.description, "test description 2");
-    assert_eq!(retrieved_task.completed, true);
+    assert!(retrieved_task.completed);
 }
 
 #[tokio::test]
@@ -94,8 +103,10 @@ async fn delete_task_removes_record() {
     let inserted_task = insert_task(
         &connection_pool,
         1,
+        None,
         "test".to_string(),
         "test description".to_string(),
+        None,
     )
     .await
     .unwrap();
@@ -113,12 +124,236 @@ async fn delete_task_removes_record() {
 async fn list_task_returns_items() {
     let connection_pool = connect_test_db().await;
 
-    insert_task(&connection_pool, 1, "test".to_string(), "test".to_string())
+    insert_task(
+        &connection_pool,
+        1,
+        None,
+        "test".to_string(),
+        "test".to_string(),
+        None,
+    )
+    .await
+    .unwrap();
+
+    let task_list = list_tasks(&connection_pool, 1, None, false, 0, 10)
         .await
         .unwrap();
 
-    let task_list = list_tasks(&connection_pool, 1, 0, 10).await.unwrap();
-
     assert_ne!(task_list.items.len(), 0);
     assert_ne!(task_list.total_count, 0);
 }
+
+#[tokio::test]
+async fn list_tasks_returns_pages_of_the_requested_size() {
+    let connection_pool = connect_test_db().await;
+    let email_address = format!("{}@example.org", ApiKey::new().key);
+
+    let user_id = insert_user(&connection_pool, email_address, ApiKey::new().hash, None)
+        .await
+        .unwrap();
+
+    for _ in 0..12 {
+        insert_task(
+            &connection_pool,
+            user_id,
+            None,
+            "test".to_string(),
+            "test".to_string(),
+            None,
+        )
+        .await
+        .unwrap();
+    }
+
+    let task_list = list_tasks(&connection_pool, user_id, None, false, 0, 20)
+        .await
+        .unwrap();
+    assert_eq!(task_list.items.len(), 12);
+    assert_eq!(task_list.total_count, 12);
+
+    let task_list = list_tasks(&connection_pool, user_id, None, false, 2, 5)
+        .await
+        .unwrap();
+    assert_eq!(task_list.items.len(), 2);
+
+    delete_user(&connection_pool, user_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn insert_user_rejects_duplicate_email_address() {
+    let connection_pool = connect_test_db().await;
+    let email_address = format!("{}@example.org", ApiKey::new().key);
+
+    insert_user(
+        &connection_pool,
+        email_address.clone(),
+        ApiKey::new().hash,
+        None,
+    )
+    .await
+    .unwrap();
+
+    let result = insert_user(
+        &connection_pool,
+        email_address.to_uppercase(),
+        ApiKey::new().hash,
+        None,
+    )
+    .await;
+
+    assert!(matches!(result, Err(AppError::EmailAddressTaken)));
+}
+
+#[tokio::test]
+async fn mark_email_verified_verifies_user() {
+    let connection_pool = connect_test_db().await;
+    let email_address = format!("{}@example.org", ApiKey::new().key);
+
+    let user_id = insert_user(
+        &connection_pool,
+        email_address.clone(),
+        ApiKey::new().hash,
+        None,
+    )
+    .await
+    .unwrap();
+
+    assert!(
+        !get_user_by_id(&connection_pool, user_id)
+            .await
+            .unwrap()
+            .email_verified
+    );
+
+    mark_email_verified(&connection_pool, user_id, &email_address)
+        .await
+        .unwrap();
+
+    assert!(
+        get_user_by_id(&connection_pool, user_id)
+            .await
+            .unwrap()
+            .email_verified
+    );
+}
+
+#[tokio::test]
+async fn delete_user_removes_user_and_tasks() {
+    let connection_pool = connect_test_db().await;
+    let email_address = format!("{}@example.org", ApiKey::new().key);
+
+    let user_id = insert_user(&connection_pool, email_address, ApiKey::new().hash, None)
+        .await
+        .unwrap();
+
+    let task_id = insert_task(
+        &connection_pool,
+        user_id,
+        None,
+        "test".to_string(),
+        "test".to_string(),
+        None,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        list_all_tasks(&connection_pool, user_id)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+
+    delete_user(&connection_pool, user_id).await.unwrap();
+
+    assert!(get_user_by_id(&connection_pool, user_id).await.is_err());
+    assert!(find_task(&connection_pool, user_id, task_id).await.is_err());
+}
+
+#[tokio::test]
+async fn insert_task_within_quota_stops_at_the_maximum() {
+    let connection_pool = connect_test_db().await;
+    let email_address = format!("{}@example.org", ApiKey::new().key);
+
+    let user_id = insert_user(&connection_pool, email_address, ApiKey::new().hash, None)
+        .await
+        .unwrap();
+
+    // The requests arrive at the same time, so they all count the tasks before any of them inserted one.
+    let inserts = (0..10).map(|_| {
+        insert_task_within_quota(
+            &connection_pool,
+            user_id,
+            None,
+            "test".to_string(),
+            "test".to_string(),
+            None,
+            Some(3),
+        )
+    });
+    let results = futures_util::future::join_all(inserts).await;
+
+    assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 3);
+    assert!(results
+        .iter()
+        .filter_map(|result| result.as_ref().err())
+        .all(|err| matches!(err, AppError::QuotaExceeded)));
+
+    delete_user(&connection_pool, user_id).await.unwrap();
+}
+
+fn create_oidc_login_request(age: chrono::Duration) -> OidcLoginRequest {
+    OidcLoginRequest {
+        state: ApiKey::new().key,
+        code_verifier: "verifier".to_string(),
+        nonce: "nonce".to_string(),
+        date_created: chrono::Utc::now().naive_utc() - age,
+    }
+}
+
+#[tokio::test]
+async fn expired_oidc_login_requests_are_removed() {
+    let connection_pool = connect_test_db().await;
+    let expired = create_oidc_login_request(chrono::Duration::hours(1));
+    let pending = create_oidc_login_request(chrono::Duration::zero());
+
+    insert_oidc_login_request(&connection_pool, &expired)
+        .await
+        .unwrap();
+
+    // Starting another login removes the login requests that expired.
+    insert_oidc_login_request(&connection_pool, &pending)
+        .await
+        .unwrap();
+
+    let remaining: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM oidc_login_requests WHERE state = $1")
+            .bind(&expired.state)
+            .fetch_one(&connection_pool)
+            .await
+            .unwrap();
+    assert_eq!(remaining, 0);
+
+    // A login request can only be used once.
+    let login_request = take_oidc_login_request(&connection_pool, &pending.state)
+        .await
+        .unwrap();
+    assert_eq!(login_request.unwrap().code_verifier, "verifier");
+    assert!(take_oidc_login_request(&connection_pool, &pending.state)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn pending_migrations_is_empty_for_migrated_database() {
+    let connection_pool = connect_test_db().await;
+
+    ping(&connection_pool).await.unwrap();
+
+    assert!(pending_migrations(&connection_pool)
+        .await
+        .unwrap()
+        .is_empty());
+}
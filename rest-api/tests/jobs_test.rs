@@ -0,0 +1,230 @@
+//! This module contains a set of integration tests to verify that background jobs are queued with the changes they
+//! belong to, and that failing jobs end up in the dead letter queue.
+//!
+//! The tests need the database, just like the tests in `integration_test.rs`. You can run them on their own using the
+//! following command:
+//!
+//! ```sh
+//! cargo test --test jobs_test
+//! ```
+
+mod common;
+
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use common::{connect_test_db, create_app_state, create_user};
+use sqlx::PgPool;
+use todo_api::{
+    blobs::LocalBlobStore,
+    config::JobsConfig,
+    db,
+    entity::{JobStatus, TaskEventType},
+    error::AppError,
+    jobs::{JobContext, JobPayload, JobRunner},
+    mail::LogMailer,
+    web,
+    webhooks::{self, PublishWebhooks},
+};
+use tower::ServiceExt;
+
+/// The admin token the tests configure.
+const ADMIN_TOKEN: &str = "an-admin-token-that-is-long-enough";
+
+/// The runner picks up every job that is due, including the ones of other tests. We run the tests one at a time, so
+/// a job is never handled by a runner with the settings of another test.
+static SERIAL: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Returns the status code of a request to the admin endpoints, with the given token configured and sent.
+async fn admin_status(configured: Option<&str>, sent: Option<&str>) -> StatusCode {
+    let settings = configured
+        .map(|token| format!("[admin]\ntoken = \"{}\"\n", token))
+        .unwrap_or_default();
+    let app_state = create_app_state(&connect_test_db().await, &settings).await;
+    let router = web::create_admin_router(app_state);
+
+    let mut request = Request::builder().uri("/admin/jobs/counts");
+
+    if let Some(token) = sent {
+        request = request.header("X-Admin-Token", token);
+    }
+
+    let response = router
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    response.status()
+}
+
+/// Runs jobs until none are due anymore.
+async fn run_due_jobs(pool: &PgPool, runner: &JobRunner) {
+    let context = JobContext::new(
+        pool.clone(),
+        Arc::new(LogMailer),
+        Arc::new(LocalBlobStore::new(
+            std::env::temp_dir().join("todo-api-blobs"),
+        )),
+    );
+
+    for _ in 0..10_000 {
+        if runner.run_next(&context).await.unwrap().is_none() {
+            return;
+        }
+    }
+
+    panic!("The jobs never ran out.");
+}
+
+#[tokio::test]
+async fn task_changes_are_published_to_webhooks_through_the_outbox() {
+    let _serial = SERIAL.lock().await;
+    let pool = connect_test_db().await;
+    let (user_id, _) = create_user(&pool).await;
+
+    let webhook = db::insert_webhook(
+        &pool,
+        user_id,
+        "https://hooks.example.org/tasks".to_string(),
+        webhooks::generate_secret(),
+        &[TaskEventType::TaskCreated, TaskEventType::TaskDeleted],
+    )
+    .await
+    .unwrap();
+
+    let task_id = db::insert_task(
+        &pool,
+        user_id,
+        None,
+        "test".to_string(),
+        "".to_string(),
+        None,
+    )
+    .await
+    .unwrap();
+
+    db::delete_task(&pool, user_id, task_id).await.unwrap();
+
+    run_due_jobs(&pool, &JobRunner::new(JobsConfig::default())).await;
+
+    let deliveries = db::list_webhook_deliveries(&pool, webhook.id, 0, 10)
+        .await
+        .unwrap()
+        .items;
+
+    assert_eq!(deliveries.len(), 2);
+    assert_eq!(deliveries[0].event_type, TaskEventType::TaskDeleted);
+    assert_eq!(deliveries[1].event_type, TaskEventType::TaskCreated);
+    assert_eq!(deliveries[1].payload["data"]["id"], task_id);
+
+    db::delete_user(&pool, user_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn publishing_the_same_event_twice_queues_one_delivery() {
+    let _serial = SERIAL.lock().await;
+    let pool = connect_test_db().await;
+    let (user_id, _) = create_user(&pool).await;
+
+    let webhook = db::insert_webhook(
+        &pool,
+        user_id,
+        "https://hooks.example.org/tasks".to_string(),
+        webhooks::generate_secret(),
+        &[TaskEventType::TaskDeleted],
+    )
+    .await
+    .unwrap();
+
+    let job = PublishWebhooks::new(
+        user_id,
+        TaskEventType::TaskDeleted,
+        &serde_json::json!({ "id": 1 }),
+    );
+    let repeated_job: PublishWebhooks =
+        serde_json::from_value(serde_json::to_value(&job).unwrap()).unwrap();
+
+    let context = JobContext::new(
+        pool.clone(),
+        Arc::new(LogMailer),
+        Arc::new(LocalBlobStore::new(
+            std::env::temp_dir().join("todo-api-blobs"),
+        )),
+    );
+
+    job.perform(&context).await.unwrap();
+    repeated_job.perform(&context).await.unwrap();
+
+    let deliveries = db::list_webhook_deliveries(&pool, webhook.id, 0, 10)
+        .await
+        .unwrap();
+
+    assert_eq!(deliveries.total_count, 1);
+
+    db::delete_user(&pool, user_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn failing_job_is_dead_lettered_and_can_be_retried() {
+    let _serial = SERIAL.lock().await;
+    let pool = connect_test_db().await;
+
+    // A payload that doesn't match the kind fails on every attempt.
+    let job_id: i64 = sqlx::query_scalar(
+        "INSERT INTO jobs (kind, payload, run_at, date_created) VALUES ('webhooks.publish', '{}', now(), now()) RETURNING id",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+
+    let runner = JobRunner::new(JobsConfig {
+        attempts: 1,
+        ..Default::default()
+    });
+
+    run_due_jobs(&pool, &runner).await;
+
+    let dead_jobs = db::list_jobs(&pool, Some(JobStatus::Dead), 0, 1000)
+        .await
+        .unwrap()
+        .items;
+    let dead_job = dead_jobs.iter().find(|job| job.id == job_id).unwrap();
+
+    assert_eq!(dead_job.attempts, 1);
+    assert!(dead_job.error.as_ref().unwrap().contains("payload"));
+
+    let retried_job = db::retry_dead_job(&pool, job_id).await.unwrap();
+
+    assert_eq!(retried_job.status, JobStatus::Pending);
+    assert_eq!(retried_job.attempts, 0);
+    assert!(matches!(
+        db::delete_dead_job(&pool, job_id).await,
+        Err(AppError::JobNotFound)
+    ));
+
+    run_due_jobs(&pool, &runner).await;
+    db::delete_dead_job(&pool, job_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn admin_endpoints_need_the_admin_token() {
+    assert_eq!(
+        admin_status(Some(ADMIN_TOKEN), Some(ADMIN_TOKEN)).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        admin_status(Some(ADMIN_TOKEN), Some("wrong")).await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        admin_status(Some(ADMIN_TOKEN), None).await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        admin_status(None, Some(ADMIN_TOKEN)).await,
+        StatusCode::NOT_FOUND
+    );
+}
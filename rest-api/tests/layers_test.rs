@@ -0,0 +1,219 @@
+//! This module contains a set of integration tests to verify the middleware that applies to every request.
+//!
+//! The tests wrap a small router with the middleware stack, so they don't need a database. You can run them on their
+//! own using the following command:
+//!
+//! ```sh
+//! cargo test --test layers_test
+//! ```
+
+use std::io::Write;
+use std::time::Duration;
+
+use axum::{
+    body::{Body, Bytes},
+    http::{header, Method, Request, StatusCode},
+    response::Response,
+    routing::{get, post},
+    Router,
+};
+use flate2::{write::GzEncoder, Compression};
+use todo_api::{
+    config::{CorsConfig, HttpConfig, SecurityHeadersConfig},
+    layers,
+};
+use tower::ServiceExt;
+
+fn create_router(config: HttpConfig) -> Router {
+    let router = Router::new()
+        .route("/echo", post(|body: Bytes| async move { body }))
+        .route("/large", get(|| async { "a".repeat(4096) }))
+        .route(
+            "/slow",
+            get(|| async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                "done"
+            }),
+        );
+
+    layers::apply(router, &config)
+}
+
+fn create_config() -> HttpConfig {
+    HttpConfig {
+        limit: 1024,
+        timeout: 1,
+        cors: CorsConfig {
+            origins: vec!["https://app.example.org".to_string()],
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+async fn send(router: Router, request: Request<Body>) -> Response {
+    router.oneshot(request).await.unwrap()
+}
+
+async fn read_body(response: Response) -> Bytes {
+    axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap()
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[tokio::test]
+async fn preflight_request_from_allowed_origin_succeeds() {
+    let request = Request::builder()
+        .method(Method::OPTIONS)
+        .uri("/echo")
+        .header(header::ORIGIN, "https://app.example.org")
+        .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+        .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "x-api-key")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = send(create_router(create_config()), request).await;
+    let headers = response.headers();
+
+    assert_eq!(
+        headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+        "https://app.example.org"
+    );
+    assert!(headers[header::ACCESS_CONTROL_ALLOW_HEADERS]
+        .to_str()
+        .unwrap()
+        .contains("x-api-key"));
+}
+
+#[tokio::test]
+async fn request_from_unknown_origin_gets_no_cors_headers() {
+    let request = Request::builder()
+        .uri("/large")
+        .header(header::ORIGIN, "https://evil.example.org")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = send(create_router(create_config()), request).await;
+
+    assert!(!response
+        .headers()
+        .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+}
+
+#[tokio::test]
+async fn large_response_is_compressed() {
+    let request = Request::builder()
+        .uri("/large")
+        .header(header::ACCEPT_ENCODING, "gzip")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = send(create_router(create_config()), request).await;
+
+    assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+    assert!(read_body(response).await.len() < 4096);
+}
+
+#[tokio::test]
+async fn compressed_request_is_decompressed() {
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/echo")
+        .header(header::CONTENT_ENCODING, "gzip")
+        .body(Body::from(gzip(b"hello")))
+        .unwrap();
+
+    let response = send(create_router(create_config()), request).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(read_body(response).await, "hello");
+}
+
+#[tokio::test]
+async fn body_over_limit_is_rejected() {
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/echo")
+        .header(header::CONTENT_LENGTH, 2048)
+        .body(Body::from(vec![b'a'; 2048]))
+        .unwrap();
+
+    let response = send(create_router(create_config()), request).await;
+
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn multipart_body_over_limit_is_rejected_outside_of_uploads() {
+    // Multipart requests skip the check of the `Content-Length` header, but routes without a limit of their own still
+    // stop reading the body at the limit of the stack.
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/echo")
+        .header(
+            header::CONTENT_TYPE,
+            "multipart/form-data; boundary=boundary",
+        )
+        .header(header::CONTENT_LENGTH, 2048)
+        .body(Body::from(vec![b'a'; 2048]))
+        .unwrap();
+
+    let response = send(create_router(create_config()), request).await;
+
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn decompressed_body_over_limit_is_rejected() {
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/echo")
+        .header(header::CONTENT_ENCODING, "gzip")
+        .body(Body::from(gzip(&[b'a'; 64 * 1024])))
+        .unwrap();
+
+    let response = send(create_router(create_config()), request).await;
+
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn slow_handler_times_out() {
+    let request = Request::builder().uri("/slow").body(Body::empty()).unwrap();
+
+    let response = send(create_router(create_config()), request).await;
+
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[tokio::test]
+async fn security_headers_are_added() {
+    let config = HttpConfig {
+        headers: SecurityHeadersConfig {
+            enabled: true,
+            hsts: Some(31_536_000),
+        },
+        ..create_config()
+    };
+
+    let request = Request::builder()
+        .uri("/large")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = send(create_router(config), request).await;
+    let headers = response.headers();
+
+    assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+    assert_eq!(headers[header::X_FRAME_OPTIONS], "DENY");
+    assert_eq!(
+        headers[header::STRICT_TRANSPORT_SECURITY],
+        "max-age=31536000; includeSubDomains"
+    );
+}
@@ -0,0 +1,72 @@
+//! This module contains a set of integration tests to verify the Prometheus metrics endpoint.
+//!
+//! The tests need the database, because the endpoint counts the tasks and jobs in it. You can run them on their own
+//! using the following command:
+//!
+//! ```sh
+//! cargo test --test metrics_test
+//! ```
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::{connect_test_db, create_app_state, read_body, send};
+use todo_api::{monitoring, web};
+
+/// The admin token of the tests.
+const ADMIN_TOKEN: &str = "test-admin-token-that-is-long-enough";
+
+/// Creates the router that serves the metrics and admin endpoints on the regular server port.
+async fn create_router() -> Router {
+    // The recorder only captures the metrics recorded after it's installed.
+    monitoring::prometheus_handle();
+
+    let pool = connect_test_db().await;
+    let settings = format!("[admin]\ntoken = \"{}\"\n", ADMIN_TOKEN);
+
+    web::create_router_with_admin(create_app_state(&pool, &settings).await)
+}
+
+fn metrics_request(admin_token: Option<&str>) -> Request<Body> {
+    let mut request = Request::get("/metrics");
+
+    if let Some(token) = admin_token {
+        request = request.header("X-Admin-Token", token);
+    }
+
+    request.body(Body::empty()).unwrap()
+}
+
+#[tokio::test]
+async fn metrics_are_labelled_with_the_route() {
+    let router = create_router().await;
+
+    let request = Request::get("/v1/todos/1").body(Body::empty()).unwrap();
+    send(&router, request).await;
+
+    let response = send(&router, metrics_request(Some(ADMIN_TOKEN))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()[header::CONTENT_TYPE]
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain; version=0.0.4"));
+
+    let body = String::from_utf8(read_body(response).await.to_vec()).unwrap();
+    assert!(body.contains("http_requests_total{"));
+    assert!(body.contains("path=\"/v1/todos/:id\""));
+}
+
+#[tokio::test]
+async fn metrics_need_the_admin_token_on_the_server_port() {
+    let router = create_router().await;
+
+    let response = send(&router, metrics_request(None)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = send(&router, metrics_request(Some("not-the-admin-token"))).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,233 @@
+//! This module contains a set of integration tests to verify the OpenID Connect login against a mock identity provider.
+//!
+//! The mock identity provider runs in-process on a random port. It serves a discovery document, a JWKS with the public
+//! key from the `fixtures` folder and a token endpoint that checks the PKCE code verifier before it issues an ID token.
+//!
+//! These tests don't need a database, so you can run them on their own using the following command:
+//!
+//! ```sh
+//! cargo test --test oidc_test
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::{get, post},
+    Form, Json, Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use reqwest::Url;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use todo_api::auth::oidc::{OidcClient, OidcError};
+use todo_api::config::{OidcClientConfig, OidcConfig};
+use tokio::net::TcpListener;
+
+const SIGNING_KEY: &str = include_str!("fixtures/oidc-signing-key.pem");
+const JWKS: &str = include_str!("fixtures/oidc-jwks.json");
+const CLIENT_ID: &str = "todo-api";
+
+/// The parts of the authorization request the mock identity provider needs to issue an ID token.
+#[derive(Default)]
+struct MockIssuerState {
+    issuer: String,
+    code_challenge: String,
+    nonce: String,
+    email_verified: bool,
+}
+
+type SharedMockIssuerState = Arc<Mutex<MockIssuerState>>;
+
+#[derive(Serialize)]
+struct DiscoveryDocument {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Serialize)]
+struct IdTokenClaims {
+    iss: String,
+    aud: String,
+    sub: String,
+    iat: i64,
+    exp: i64,
+    email: String,
+    email_verified: bool,
+    nonce: String,
+}
+
+#[derive(Serialize)]
+struct TokenResponse {
+    id_token: String,
+    token_type: String,
+}
+
+async fn discovery(State(state): State<SharedMockIssuerState>) -> impl IntoResponse {
+    let issuer = state.lock().unwrap().issuer.clone();
+
+    Json(DiscoveryDocument {
+        authorization_endpoint: format!("{}/authorize", issuer),
+        token_endpoint: format!("{}/token", issuer),
+        jwks_uri: format!("{}/jwks", issuer),
+        issuer,
+    })
+}
+
+async fn jwks() -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "application/json")], JWKS)
+}
+
+async fn token(
+    State(state): State<SharedMockIssuerState>,
+    Form(form): Form<HashMap<String, String>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let state = state.lock().unwrap();
+
+    let code_verifier = form.get("code_verifier").ok_or(StatusCode::BAD_REQUEST)?;
+    let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+
+    if code_challenge != state.code_challenge
+        || form.get("client_id").map(String::as_str) != Some(CLIENT_ID)
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let now = chrono::Utc::now().timestamp();
+
+    let claims = IdTokenClaims {
+        iss: state.issuer.clone(),
+        aud: CLIENT_ID.to_string(),
+        sub: "mock-subject".to_string(),
+        iat: now,
+        exp: now + 300,
+        email: "jane@example.org".to_string(),
+        email_verified: state.email_verified,
+        nonce: state.nonce.clone(),
+    };
+
+    let mut header = Header::new(Algorithm::RS256);
+    header.kid = Some("mock-key".to_string());
+
+    let encoding_key = EncodingKey::from_rsa_pem(SIGNING_KEY.as_bytes()).unwrap();
+    let id_token = jsonwebtoken::encode(&header, &claims, &encoding_key).unwrap();
+
+    Ok(Json(TokenResponse {
+        id_token,
+        token_type: "Bearer".to_string(),
+    }))
+}
+
+/// Starts the mock identity provider on a random port and returns its state.
+async fn start_mock_issuer(email_verified: bool) -> SharedMockIssuerState {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let issuer = format!("http://{}", listener.local_addr().unwrap());
+
+    let state = Arc::new(Mutex::new(MockIssuerState {
+        issuer,
+        email_verified,
+        ..Default::default()
+    }));
+
+    let router = Router::new()
+        .route("/.well-known/openid-configuration", get(discovery))
+        .route("/jwks", get(jwks))
+        .route("/token", post(token))
+        .with_state(state.clone());
+
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+    state
+}
+
+fn create_client(state: &SharedMockIssuerState) -> OidcClient {
+    OidcClient::new(OidcConfig {
+        issuer: state.lock().unwrap().issuer.clone(),
+        callback: "http://localhost:3000/v1/auth/oidc/callback".to_string(),
+        client: OidcClientConfig {
+            id: CLIENT_ID.to_string(),
+            secret: None,
+        },
+    })
+}
+
+/// Reads the PKCE code challenge and nonce from the authorization URL, just like a real identity provider would.
+fn remember_authorization_request(state: &SharedMockIssuerState, authorization_url: &str) {
+    let url = Url::parse(authorization_url).unwrap();
+    let query: HashMap<_, _> = url.query_pairs().into_owned().collect();
+
+    assert_eq!(query["code_challenge_method"], "S256");
+    assert_eq!(query["client_id"], CLIENT_ID);
+
+    let mut state = state.lock().unwrap();
+    state.code_challenge = query["code_challenge"].clone();
+    state.nonce = query["nonce"].clone();
+}
+
+#[tokio::test]
+async fn exchange_code_returns_verified_identity() {
+    let state = start_mock_issuer(true).await;
+    let client = create_client(&state);
+
+    let request = client.authorization_request().await.unwrap();
+    remember_authorization_request(&state, &request.url);
+
+    let identity = client
+        .exchange_code("mock-code", &request.code_verifier, &request.nonce)
+        .await
+        .unwrap();
+
+    assert_eq!(identity.email_address, "jane@example.org");
+    assert_eq!(identity.subject, "mock-subject");
+}
+
+#[tokio::test]
+async fn exchange_code_rejects_unverified_email() {
+    let state = start_mock_issuer(false).await;
+    let client = create_client(&state);
+
+    let request = client.authorization_request().await.unwrap();
+    remember_authorization_request(&state, &request.url);
+
+    let result = client
+        .exchange_code("mock-code", &request.code_verifier, &request.nonce)
+        .await;
+
+    assert!(matches!(result, Err(OidcError::EmailNotVerified)));
+}
+
+#[tokio::test]
+async fn exchange_code_rejects_other_nonce() {
+    let state = start_mock_issuer(true).await;
+    let client = create_client(&state);
+
+    let request = client.authorization_request().await.unwrap();
+    remember_authorization_request(&state, &request.url);
+
+    let result = client
+        .exchange_code("mock-code", &request.code_verifier, "other-nonce")
+        .await;
+
+    assert!(matches!(result, Err(OidcError::NonceMismatch)));
+}
+
+#[tokio::test]
+async fn exchange_code_fails_with_wrong_code_verifier() {
+    let state = start_mock_issuer(true).await;
+    let client = create_client(&state);
+
+    let request = client.authorization_request().await.unwrap();
+    remember_authorization_request(&state, &request.url);
+
+    let result = client
+        .exchange_code("mock-code", "wrong-code-verifier", &request.nonce)
+        .await;
+
+    assert!(matches!(result, Err(OidcError::Http(_))));
+}
@@ -0,0 +1,64 @@
+//! This module contains a set of integration tests to verify that the rate limits can't be avoided by making up
+//! credentials.
+//!
+//! The tests need the database, because the API keys are checked against it. You can run them on their own using the
+//! following command:
+//!
+//! ```sh
+//! cargo test --test ratelimit_test
+//! ```
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+};
+use common::{connect_test_db, create_router, create_verified_user, send};
+use todo_api::{db, entity::ApiKey};
+
+/// The number of requests a client can make to the `/v1/auth` routes in the tests.
+const BURST: u32 = 3;
+
+fn token_request(api_key: &str) -> Request<Body> {
+    Request::post("/v1/auth/token")
+        .header("X-Api-Key", api_key)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(format!(
+            "{{\"grant_type\": \"api_key\", \"api_key\": \"{}\"}}",
+            api_key
+        )))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn made_up_api_keys_share_the_bucket_of_the_client() {
+    let pool = connect_test_db().await;
+    let router = create_router(
+        &pool,
+        &format!("[ratelimit.auth]\nrate = 1\nburst = {}\n", BURST),
+    )
+    .await;
+    let (user_id, api_key) = create_verified_user(&pool).await;
+
+    for _ in 0..BURST {
+        let response = send(&router, token_request(&ApiKey::new().key)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    let response = send(&router, token_request(&ApiKey::new().key)).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    // An API key that exists gets a bucket of its own once it authenticated a request.
+    let request = Request::get("/v1/users/me")
+        .header("X-Api-Key", &api_key)
+        .body(Body::empty())
+        .unwrap();
+    let response = send(&router, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = send(&router, token_request(&api_key)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    db::delete_user(&pool, user_id).await.unwrap();
+}
@@ -0,0 +1,106 @@
+//! This module contains a set of integration tests to verify that secrets don't end up in the logs, following the
+//! policy in the `redact` module.
+//!
+//! The tests capture the log lines that are written while the API handles requests. Registering a user needs the
+//! database, just like the tests in `integration_test.rs`. You can run them on their own using the following command:
+//!
+//! ```sh
+//! cargo test --test redaction_test
+//! ```
+
+mod common;
+
+use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+};
+use common::{connect_test_db, create_router, send};
+use todo_api::{db, entity::ApiKey};
+use tracing::Level;
+use tracing_subscriber::fmt::MakeWriter;
+
+/// Collects the log lines that are written while it's the default subscriber.
+#[derive(Clone, Default)]
+struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+impl CapturedLogs {
+    fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+impl Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for CapturedLogs {
+    type Writer = Self;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+#[tokio::test]
+async fn tokens_are_not_logged() {
+    let logs = CapturedLogs::default();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(logs.clone())
+        .with_max_level(Level::TRACE)
+        .with_ansi(false)
+        .finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let pool = connect_test_db().await;
+    let router = create_router(&pool, "").await;
+    let email_address = format!("{}@example.org", ApiKey::new().key);
+
+    // Registering sends an email with a verification token through the default mailer, which writes to the log.
+    let response = send(
+        &router,
+        Request::post("/v1/users/register")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(format!(
+                "{{\"email_address\": \"{}\", \"password\": \"secret-password\"}}",
+                email_address
+            )))
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    // The identity provider sends the authorization code in the query string of the callback.
+    send(
+        &router,
+        Request::get("/v1/auth/oidc/callback?code=secret-authorization-code&state=secret-state")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+
+    let contents = logs.contents();
+    assert!(contents.contains("Sending email"));
+    assert!(contents.contains("/v1/auth/oidc/callback"));
+
+    // Every token we issue is a JSON Web Token, which starts with the encoded `{"` of its header.
+    assert!(!contents.contains("eyJ"));
+    assert!(!contents.contains("secret-authorization-code"));
+    assert!(!contents.contains("secret-state"));
+    assert!(!contents.contains("secret-password"));
+    assert!(!contents.contains(&email_address));
+
+    let user = db::get_user_by_email(&pool, &email_address).await.unwrap();
+    db::delete_user(&pool, user.id).await.unwrap();
+}
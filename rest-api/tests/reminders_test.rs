@@ -0,0 +1,232 @@
+//! This module contains a set of integration tests to verify that reminders are scheduled in the time zone of the
+//! user, and that a reminder that is due is delivered once, even when several schedulers run at the same time.
+//!
+//! The tests need the database, just like the tests in `integration_test.rs`. You can run them on their own using the
+//! following command:
+//!
+//! ```sh
+//! cargo test --test reminders_test
+//! ```
+
+mod common;
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use common::{connect_test_db, create_user, run_due_jobs};
+use todo_api::{
+    config::RemindersConfig,
+    db,
+    entity::{ReminderChannel, TaskEventType},
+    reminders::ReminderScheduler,
+    webhooks,
+};
+
+/// The scheduler and the job runner pick up the reminders and jobs of every test, so we run the tests one at a time.
+static SERIAL: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+fn date_time(month: u32, day: u32, hour: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2030, month, day)
+        .unwrap()
+        .and_hms_opt(hour, 0, 0)
+        .unwrap()
+}
+
+#[tokio::test]
+async fn reminders_are_scheduled_in_the_time_zone_of_the_user() {
+    let _serial = SERIAL.lock().await;
+    let pool = connect_test_db().await;
+    let (user_id, _) = create_user(&pool).await;
+
+    db::update_user_schedule(&pool, user_id, "Europe/Amsterdam", None)
+        .await
+        .unwrap();
+
+    let task_id = db::insert_task(
+        &pool,
+        user_id,
+        None,
+        "test".to_string(),
+        "".to_string(),
+        Some(date_time(6, 1, 15)),
+    )
+    .await
+    .unwrap();
+
+    let day_before = db::insert_reminder(
+        &pool,
+        user_id,
+        task_id,
+        Some(1440),
+        None,
+        &[ReminderChannel::InApp],
+    )
+    .await
+    .unwrap();
+
+    let morning = db::insert_reminder(
+        &pool,
+        user_id,
+        task_id,
+        None,
+        Some(NaiveTime::from_hms_opt(9, 0, 0).unwrap()),
+        &[ReminderChannel::InApp],
+    )
+    .await
+    .unwrap();
+
+    // Amsterdam is two hours ahead of UTC in summer.
+    assert_eq!(day_before.remind_at, Some(date_time(5, 31, 15)));
+    assert_eq!(morning.remind_at, Some(date_time(6, 1, 7)));
+
+    db::update_user_schedule(&pool, user_id, "America/New_York", None)
+        .await
+        .unwrap();
+
+    let reminders = db::list_reminders(&pool, user_id, task_id).await.unwrap();
+    assert_eq!(reminders[0].remind_at, Some(date_time(5, 31, 15)));
+    assert_eq!(reminders[1].remind_at, Some(date_time(6, 1, 13)));
+
+    db::update_task(
+        &pool,
+        user_id,
+        task_id,
+        "test".to_string(),
+        "".to_string(),
+        false,
+        None,
+        false,
+    )
+    .await
+    .unwrap();
+
+    let reminders = db::list_reminders(&pool, user_id, task_id).await.unwrap();
+    assert!(reminders
+        .iter()
+        .all(|reminder| reminder.remind_at.is_none()));
+
+    db::delete_user(&pool, user_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn due_reminder_is_delivered_once_through_every_channel() {
+    let _serial = SERIAL.lock().await;
+    let pool = connect_test_db().await;
+    let (user_id, _) = create_user(&pool).await;
+
+    let webhook = db::insert_webhook(
+        &pool,
+        user_id,
+        "https://hooks.example.org/reminders".to_string(),
+        webhooks::generate_secret(),
+        &[TaskEventType::TaskReminder],
+    )
+    .await
+    .unwrap();
+
+    let due_at = chrono::Utc::now().naive_utc() + chrono::Duration::minutes(10);
+
+    let task_id = db::insert_task(
+        &pool,
+        user_id,
+        None,
+        "test".to_string(),
+        "".to_string(),
+        Some(due_at),
+    )
+    .await
+    .unwrap();
+
+    db::insert_reminder(
+        &pool,
+        user_id,
+        task_id,
+        Some(60),
+        None,
+        &[ReminderChannel::InApp, ReminderChannel::Webhook],
+    )
+    .await
+    .unwrap();
+
+    // Two instances check for due reminders at the same time.
+    let first = ReminderScheduler::new(RemindersConfig::default());
+    let second = ReminderScheduler::new(RemindersConfig::default());
+    let (first_sent, second_sent) = tokio::join!(first.send_due(&pool), second.send_due(&pool));
+
+    assert_eq!(first_sent.unwrap() + second_sent.unwrap(), 1);
+    assert_eq!(first.send_due(&pool).await.unwrap(), 0);
+
+    run_due_jobs(&pool).await;
+
+    let notifications = db::list_notifications(&pool, user_id, true, 0, 10)
+        .await
+        .unwrap();
+    assert_eq!(notifications.total_count, 1);
+    assert_eq!(notifications.items[0].task_id, Some(task_id));
+
+    let deliveries = db::list_webhook_deliveries(&pool, webhook.id, 0, 10)
+        .await
+        .unwrap();
+    assert_eq!(deliveries.total_count, 1);
+    assert_eq!(deliveries.items[0].event_type, TaskEventType::TaskReminder);
+
+    let notification = db::mark_notification_read(&pool, user_id, notifications.items[0].id, true)
+        .await
+        .unwrap();
+    assert!(notification.date_read.is_some());
+
+    let unread = db::list_notifications(&pool, user_id, true, 0, 10)
+        .await
+        .unwrap();
+    assert_eq!(unread.total_count, 0);
+
+    db::delete_user(&pool, user_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn due_reminder_waits_for_the_end_of_the_quiet_hours() {
+    let _serial = SERIAL.lock().await;
+    let pool = connect_test_db().await;
+    let (user_id, _) = create_user(&pool).await;
+
+    // Quiet hours of two hours that started an hour ago.
+    let now = chrono::Utc::now().naive_utc();
+    let start = NaiveTime::from_hms_opt((now.hour() + 23) % 24, 0, 0).unwrap();
+    let end = NaiveTime::from_hms_opt((now.hour() + 1) % 24, 0, 0).unwrap();
+
+    db::update_user_schedule(&pool, user_id, "UTC", Some((start, end)))
+        .await
+        .unwrap();
+
+    let task_id = db::insert_task(
+        &pool,
+        user_id,
+        None,
+        "test".to_string(),
+        "".to_string(),
+        Some(now + chrono::Duration::minutes(10)),
+    )
+    .await
+    .unwrap();
+
+    db::insert_reminder(
+        &pool,
+        user_id,
+        task_id,
+        Some(60),
+        None,
+        &[ReminderChannel::InApp],
+    )
+    .await
+    .unwrap();
+
+    let scheduler = ReminderScheduler::new(RemindersConfig::default());
+    assert_eq!(scheduler.send_due(&pool).await.unwrap(), 0);
+
+    let reminders = db::list_reminders(&pool, user_id, task_id).await.unwrap();
+    let remind_at = reminders[0].remind_at.unwrap();
+
+    assert!(reminders[0].date_sent.is_none());
+    assert!(remind_at > now);
+    assert_eq!(remind_at.time(), end);
+
+    db::delete_user(&pool, user_id).await.unwrap();
+}
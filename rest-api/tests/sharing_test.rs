@@ -0,0 +1,284 @@
+//! This module contains a set of integration tests to verify that tasks and projects can be shared with other users,
+//! and that the access rules of the [`todo_api::access`] module follow the shares.
+//!
+//! The tests need the database, just like the tests in `integration_test.rs`. You can run them on their own using the
+//! following command:
+//!
+//! ```sh
+//! cargo test --test sharing_test
+//! ```
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+};
+use common::{
+    connect_test_db, create_router, create_task, create_user, create_verified_user, read_json, send,
+};
+use todo_api::{
+    access, db,
+    entity::{Role, SharedItem},
+    error::AppError,
+};
+
+#[tokio::test]
+async fn shared_task_follows_the_role_of_the_share() {
+    let pool = connect_test_db().await;
+    let (owner_id, _) = create_user(&pool).await;
+    let (user_id, email_address) = create_user(&pool).await;
+    let task_id = create_task(&pool, owner_id).await;
+
+    assert!(matches!(
+        access::task_access(&pool, user_id, None, task_id, Role::Viewer).await,
+        Err(AppError::TaskNotFound)
+    ));
+
+    let share = db::insert_share(
+        &pool,
+        owner_id,
+        SharedItem::Task(task_id),
+        &email_address.to_uppercase(),
+        Role::Viewer,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(share.user_id, user_id);
+
+    let access = access::task_access(&pool, user_id, None, task_id, Role::Viewer)
+        .await
+        .unwrap();
+    assert_eq!(access.owner_id, owner_id);
+    assert!(matches!(
+        access::task_access(&pool, user_id, None, task_id, Role::Editor).await,
+        Err(AppError::AccessDenied)
+    ));
+
+    let tasks = db::list_tasks(&pool, user_id, None, false, 0, 10)
+        .await
+        .unwrap();
+    assert_eq!(tasks.total_count, 1);
+    assert_eq!(tasks.items[0].id, task_id);
+
+    // Sharing the task again changes the role instead of adding a second share.
+    db::insert_share(
+        &pool,
+        owner_id,
+        SharedItem::Task(task_id),
+        &email_address,
+        Role::Editor,
+    )
+    .await
+    .unwrap();
+
+    let shares = db::list_shares(&pool, SharedItem::Task(task_id))
+        .await
+        .unwrap();
+    assert_eq!(shares.len(), 1);
+    assert!(
+        access::task_access(&pool, user_id, None, task_id, Role::Editor)
+            .await
+            .is_ok()
+    );
+    assert!(matches!(
+        access::task_access(&pool, user_id, None, task_id, Role::Owner).await,
+        Err(AppError::AccessDenied)
+    ));
+
+    db::delete_share(&pool, SharedItem::Task(task_id), shares[0].id)
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        access::task_access(&pool, user_id, None, task_id, Role::Viewer).await,
+        Err(AppError::TaskNotFound)
+    ));
+
+    db::delete_user(&pool, owner_id).await.unwrap();
+    db::delete_user(&pool, user_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn shared_project_gives_access_to_its_tasks() {
+    let pool = connect_test_db().await;
+    let (owner_id, _) = create_user(&pool).await;
+    let (user_id, email_address) = create_user(&pool).await;
+    let task_id = create_task(&pool, owner_id).await;
+
+    let project_id = db::insert_project(&pool, owner_id, None, "Home".to_string())
+        .await
+        .unwrap();
+    db::set_task_project(&pool, owner_id, task_id, Some(project_id))
+        .await
+        .unwrap();
+
+    db::insert_share(
+        &pool,
+        owner_id,
+        SharedItem::Project(project_id),
+        &email_address,
+        Role::Editor,
+    )
+    .await
+    .unwrap();
+
+    let projects = db::list_projects(&pool, user_id, None).await.unwrap();
+    assert_eq!(projects.len(), 1);
+    assert_eq!(projects[0].role, Role::Editor);
+
+    let access = access::task_access(&pool, user_id, None, task_id, Role::Editor)
+        .await
+        .unwrap();
+    assert_eq!(access.owner_id, owner_id);
+
+    // The tasks the user adds to the project can be edited by the owner of the project.
+    let own_task_id = create_task(&pool, user_id).await;
+    db::set_task_project(&pool, user_id, own_task_id, Some(project_id))
+        .await
+        .unwrap();
+
+    let tasks = db::list_project_tasks(&pool, project_id, 0, 10)
+        .await
+        .unwrap();
+    assert_eq!(tasks.total_count, 2);
+    assert!(
+        access::task_access(&pool, owner_id, None, own_task_id, Role::Editor)
+            .await
+            .is_ok()
+    );
+
+    // Removing the project keeps its tasks, but the share no longer gives access to them.
+    db::delete_project(&pool, owner_id, project_id)
+        .await
+        .unwrap();
+
+    assert!(db::find_task(&pool, owner_id, task_id).await.is_ok());
+    assert!(matches!(
+        access::task_access(&pool, user_id, None, task_id, Role::Viewer).await,
+        Err(AppError::TaskNotFound)
+    ));
+
+    db::delete_user(&pool, owner_id).await.unwrap();
+    db::delete_user(&pool, user_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn share_needs_another_registered_user() {
+    let pool = connect_test_db().await;
+    let (owner_id, email_address) = create_user(&pool).await;
+    let task_id = create_task(&pool, owner_id).await;
+
+    assert!(matches!(
+        db::insert_share(
+            &pool,
+            owner_id,
+            SharedItem::Task(task_id),
+            &email_address,
+            Role::Viewer
+        )
+        .await,
+        Err(AppError::InvalidShare)
+    ));
+
+    assert!(matches!(
+        db::insert_share(
+            &pool,
+            owner_id,
+            SharedItem::Task(task_id),
+            "nobody@example.org",
+            Role::Viewer
+        )
+        .await,
+        Err(AppError::UserNotFound)
+    ));
+
+    db::delete_user(&pool, owner_id).await.unwrap();
+}
+
+fn reminder_request(method: &str, uri: &str, api_key: &str) -> Request<Body> {
+    let body = match method {
+        "POST" => Body::from("{\"minutes_before\": 60}"),
+        _ => Body::empty(),
+    };
+
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("X-Api-Key", api_key)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .unwrap()
+}
+
+#[tokio::test]
+async fn reminders_of_shared_task_follow_the_role_of_the_share() {
+    let pool = connect_test_db().await;
+    let router = create_router(&pool, "").await;
+    let (owner_id, _) = create_user(&pool).await;
+    let (editor_id, editor_key) = create_verified_user(&pool).await;
+    let (viewer_id, viewer_key) = create_verified_user(&pool).await;
+    let (outsider_id, outsider_key) = create_verified_user(&pool).await;
+    let task_id = create_task(&pool, owner_id).await;
+
+    for (user_id, role) in [(editor_id, Role::Editor), (viewer_id, Role::Viewer)] {
+        let user = db::get_user_by_id(&pool, user_id).await.unwrap();
+        db::insert_share(
+            &pool,
+            owner_id,
+            SharedItem::Task(task_id),
+            &user.email_address,
+            role,
+        )
+        .await
+        .unwrap();
+    }
+
+    let uri = format!("/v1/todos/{}/reminders", task_id);
+
+    let response = send(&router, reminder_request("POST", &uri, &editor_key)).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let reminder_id = read_json(response).await["id"].as_i64().unwrap();
+
+    let response = send(&router, reminder_request("POST", &uri, &viewer_key)).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = send(&router, reminder_request("GET", &uri, &viewer_key)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(read_json(response).await[0]["id"], reminder_id);
+
+    // The reminder reaches the owner of the task.
+    let reminders = db::list_reminders(&pool, owner_id, task_id).await.unwrap();
+    assert_eq!(reminders.len(), 1);
+
+    let reminder_uri = format!("{}/{}", uri, reminder_id);
+
+    let response = send(&router, reminder_request("GET", &uri, &outsider_key)).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = send(
+        &router,
+        reminder_request("DELETE", &reminder_uri, &outsider_key),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = send(
+        &router,
+        reminder_request("DELETE", &reminder_uri, &viewer_key),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = send(
+        &router,
+        reminder_request("DELETE", &reminder_uri, &editor_key),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    for user_id in [owner_id, editor_id, viewer_id, outsider_id] {
+        db::delete_user(&pool, user_id).await.unwrap();
+    }
+}
@@ -0,0 +1,215 @@
+//! This module contains a set of integration tests to verify that spans are exported to an OpenTelemetry collector.
+//!
+//! The mock collector runs in-process on a random port. It implements both the gRPC and the HTTP flavour of the OTLP
+//! trace endpoint and keeps the spans it receives, so the tests can check what was exported.
+//!
+//! These tests don't need a database, so you can run them on their own using the following command:
+//!
+//! ```sh
+//! cargo test --test telemetry_test
+//! ```
+
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::{Request, StatusCode},
+    routing::{get, post},
+    Router,
+};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_proto::tonic::{
+    collector::trace::v1::{
+        trace_service_server::{TraceService, TraceServiceServer},
+        ExportTraceServiceRequest, ExportTraceServiceResponse,
+    },
+    common::v1::any_value::Value,
+    trace::v1::Span,
+};
+use opentelemetry_sdk::trace::TracerProvider;
+use prost::Message;
+use todo_api::config::{OtlpConfig, OtlpProtocol};
+use todo_api::telemetry::{create_tracer_provider, make_request_span};
+use tokio::net::TcpListener;
+use tonic::transport::server::TcpIncoming;
+use tower::ServiceExt;
+use tower_http::trace::TraceLayer;
+use tracing_subscriber::prelude::*;
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+const PARENT_SPAN_ID: &str = "00f067aa0ba902b7";
+
+/// Keeps the spans the mock collector received.
+#[derive(Clone, Default)]
+struct MockCollector {
+    spans: Arc<Mutex<Vec<Span>>>,
+}
+
+impl MockCollector {
+    fn collect(&self, request: ExportTraceServiceRequest) {
+        let mut spans = self.spans.lock().unwrap();
+
+        for resource_spans in request.resource_spans {
+            for scope_spans in resource_spans.scope_spans {
+                spans.extend(scope_spans.spans);
+            }
+        }
+    }
+
+    fn find_span(&self, name: &str) -> Option<Span> {
+        self.spans
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|span| span.name == name)
+            .cloned()
+    }
+}
+
+#[tonic::async_trait]
+impl TraceService for MockCollector {
+    async fn export(
+        &self,
+        request: tonic::Request<ExportTraceServiceRequest>,
+    ) -> Result<tonic::Response<ExportTraceServiceResponse>, tonic::Status> {
+        self.collect(request.into_inner());
+
+        Ok(tonic::Response::new(ExportTraceServiceResponse {
+            partial_success: None,
+        }))
+    }
+}
+
+async fn export_traces(State(collector): State<MockCollector>, body: Bytes) -> StatusCode {
+    match ExportTraceServiceRequest::decode(body) {
+        Ok(request) => {
+            collector.collect(request);
+            StatusCode::OK
+        }
+        Err(_) => StatusCode::BAD_REQUEST,
+    }
+}
+
+/// Starts the mock collector on a random port and returns its address.
+async fn start_mock_collector(protocol: OtlpProtocol, collector: MockCollector) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+
+    match protocol {
+        OtlpProtocol::Grpc => {
+            let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+
+            tokio::spawn(async move {
+                tonic::transport::Server::builder()
+                    .add_service(TraceServiceServer::new(collector))
+                    .serve_with_incoming(incoming)
+                    .await
+                    .unwrap()
+            });
+        }
+        OtlpProtocol::Http => {
+            let router = Router::new()
+                .route("/v1/traces", post(export_traces))
+                .with_state(collector);
+
+            tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        }
+    }
+
+    endpoint
+}
+
+/// Exports the spans that are still buffered, so the collector has received everything when this returns.
+async fn flush(tracer_provider: TracerProvider) {
+    tokio::task::spawn_blocking(move || tracer_provider.shutdown())
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+fn int_attribute(span: &Span, key: &str) -> Option<i64> {
+    span.attributes
+        .iter()
+        .find(|attribute| attribute.key == key)
+        .and_then(|attribute| attribute.value.as_ref())
+        .and_then(|value| match value.value {
+            Some(Value::IntValue(value)) => Some(value),
+            _ => None,
+        })
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn spans_are_exported_with_grpc() {
+    let collector = MockCollector::default();
+    let endpoint = start_mock_collector(OtlpProtocol::Grpc, collector.clone()).await;
+
+    let tracer_provider = create_tracer_provider(&OtlpConfig {
+        endpoint,
+        protocol: OtlpProtocol::Grpc,
+    })
+    .unwrap();
+
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer("test")));
+
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("update_task", user_id = 42, task_id = 7);
+        let _entered = span.enter();
+    });
+
+    flush(tracer_provider).await;
+
+    let span = collector.find_span("update_task").unwrap();
+
+    assert_eq!(int_attribute(&span, "user_id"), Some(42));
+    assert_eq!(int_attribute(&span, "task_id"), Some(7));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn request_span_continues_trace_of_caller_over_http() {
+    let collector = MockCollector::default();
+    let endpoint = start_mock_collector(OtlpProtocol::Http, collector.clone()).await;
+
+    let tracer_provider = create_tracer_provider(&OtlpConfig {
+        endpoint,
+        protocol: OtlpProtocol::Http,
+    })
+    .unwrap();
+
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer("test")));
+
+    let router = Router::new()
+        .route("/v1/todos", get(|| async { "ok" }))
+        .layer(TraceLayer::new_for_http().make_span_with(make_request_span));
+
+    {
+        let _default = tracing::subscriber::set_default(subscriber);
+
+        router
+            .oneshot(
+                Request::builder()
+                    .uri("/v1/todos")
+                    .header(
+                        "traceparent",
+                        format!("00-{}-{}-01", TRACE_ID, PARENT_SPAN_ID),
+                    )
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+    }
+
+    flush(tracer_provider).await;
+
+    let span = collector.find_span("request").unwrap();
+
+    assert_eq!(hex(&span.trace_id), TRACE_ID);
+    assert_eq!(hex(&span.parent_span_id), PARENT_SPAN_ID);
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
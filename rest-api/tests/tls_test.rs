@@ -0,0 +1,187 @@
+//! This module contains a set of integration tests to verify that the server terminates TLS correctly.
+//!
+//! The tests generate a certificate authority with a server and a client certificate on the fly, so they don't depend
+//! on files in the repository that expire. They don't need a database, so you can run them on their own using the
+//! following command:
+//!
+//! ```sh
+//! cargo test --test tls_test
+//! ```
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use axum::{http::Version, routing::get, Extension, Router};
+use rcgen::{BasicConstraints, Certificate, CertificateParams, IsCa, KeyPair, SanType};
+use todo_api::config::TlsConfig;
+use todo_api::tls::{self, ClientCertificate, ReloadableTlsConfig};
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+
+/// A certificate authority with a server certificate for `localhost` and a client certificate for an email address.
+struct TestCertificates {
+    directory: PathBuf,
+    ca_certificate: Certificate,
+    ca_key: KeyPair,
+}
+
+impl TestCertificates {
+    fn generate(name: &str) -> TestCertificates {
+        let directory =
+            std::env::temp_dir().join(format!("todo-api-tls-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca_certificate = ca_params.self_signed(&ca_key).unwrap();
+
+        std::fs::write(directory.join("ca.pem"), ca_certificate.pem()).unwrap();
+
+        let certificates = TestCertificates {
+            directory,
+            ca_certificate,
+            ca_key,
+        };
+
+        certificates.write_server_certificate();
+        certificates
+    }
+
+    /// Writes a new server certificate and key, like a certificate renewal would.
+    fn write_server_certificate(&self) {
+        let key = KeyPair::generate().unwrap();
+        let certificate = CertificateParams::new(vec!["localhost".to_string()])
+            .unwrap()
+            .signed_by(&key, &self.ca_certificate, &self.ca_key)
+            .unwrap();
+
+        std::fs::write(self.path("server.pem"), certificate.pem()).unwrap();
+        std::fs::write(self.path("server.key"), key.serialize_pem()).unwrap();
+    }
+
+    /// Creates a client certificate for the email address and returns it together with its key in PEM format.
+    fn client_identity(&self, email: &str) -> Vec<u8> {
+        let key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.subject_alt_names = vec![SanType::Rfc822Name(email.try_into().unwrap())];
+        let certificate = params
+            .signed_by(&key, &self.ca_certificate, &self.ca_key)
+            .unwrap();
+
+        format!("{}{}", certificate.pem(), key.serialize_pem()).into_bytes()
+    }
+
+    fn path(&self, name: &str) -> String {
+        self.directory.join(name).to_string_lossy().to_string()
+    }
+
+    fn tls_config(&self) -> TlsConfig {
+        TlsConfig {
+            cert: self.path("server.pem"),
+            key: self.path("server.key"),
+            ca: Some(self.path("ca.pem")),
+            reload: 30,
+            redirect: None,
+        }
+    }
+}
+
+/// Describes the connection and the client certificate the handler received.
+async fn describe_client(
+    version: Version,
+    client_certificate: Option<Extension<ClientCertificate>>,
+) -> String {
+    let email = client_certificate
+        .map(|Extension(certificate)| certificate.email)
+        .unwrap_or_else(|| "anonymous".to_string());
+
+    format!("{:?} {}", version, email)
+}
+
+/// Starts the server on a random port and returns its address and a sender to stop it.
+async fn start_server(tls: Arc<ReloadableTlsConfig>) -> (String, oneshot::Sender<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = format!(
+        "https://localhost:{}",
+        listener.local_addr().unwrap().port()
+    );
+    let router = Router::new().route("/", get(describe_client));
+    let (stop_sender, stop_receiver) = oneshot::channel::<()>();
+
+    tokio::spawn(tls::serve(listener, router, tls, async move {
+        let _ = stop_receiver.await;
+    }));
+
+    (address, stop_sender)
+}
+
+fn create_client(certificates: &TestCertificates, identity: Option<Vec<u8>>) -> reqwest::Client {
+    let ca = std::fs::read(certificates.path("ca.pem")).unwrap();
+    let mut builder = reqwest::Client::builder()
+        .add_root_certificate(reqwest::Certificate::from_pem(&ca).unwrap())
+        .use_rustls_tls();
+
+    if let Some(identity) = identity {
+        builder = builder.identity(reqwest::Identity::from_pem(&identity).unwrap());
+    }
+
+    builder.build().unwrap()
+}
+
+#[tokio::test]
+async fn client_certificate_is_passed_to_handler_over_http2() {
+    let certificates = TestCertificates::generate("client");
+    let tls = Arc::new(ReloadableTlsConfig::load(&certificates.tls_config()).unwrap());
+    let (address, _stop) = start_server(tls).await;
+
+    let client = create_client(
+        &certificates,
+        Some(certificates.client_identity("jane@example.org")),
+    );
+    let body = client
+        .get(&address)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert_eq!(body, "HTTP/2.0 jane@example.org");
+}
+
+#[tokio::test]
+async fn client_certificate_is_optional() {
+    let certificates = TestCertificates::generate("anonymous");
+    let tls = Arc::new(ReloadableTlsConfig::load(&certificates.tls_config()).unwrap());
+    let (address, _stop) = start_server(tls).await;
+
+    let client = create_client(&certificates, None);
+    let body = client
+        .get(&address)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert_eq!(body, "HTTP/2.0 anonymous");
+}
+
+#[tokio::test]
+async fn changed_certificate_is_reloaded() {
+    let certificates = TestCertificates::generate("reload");
+    let tls = ReloadableTlsConfig::load(&certificates.tls_config()).unwrap();
+    let original = tls.current();
+
+    assert!(!tls.reload_if_changed().unwrap());
+
+    // Make sure the modification time changes, even on file systems with a coarse resolution.
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    certificates.write_server_certificate();
+
+    assert!(tls.reload_if_changed().unwrap());
+    assert!(!Arc::ptr_eq(&original, &tls.current()));
+}
@@ -0,0 +1,378 @@
+//! This module contains a set of integration tests to verify that users keep control over their account, like when
+//! someone else registered their email address before them.
+//!
+//! The tests send requests to the API, so they need the database, just like the tests in `integration_test.rs`. You
+//! can run them on their own using the following command:
+//!
+//! ```sh
+//! cargo test --test users_test
+//! ```
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::{connect_test_db, create_router, create_task, create_user, read_json, send};
+use serde_json::{json, Value};
+use todo_api::{
+    db,
+    entity::{ApiKey, NotificationKind, ReminderChannel, Role, SharedItem, WorkspaceRole},
+};
+
+fn json_request(method: &str, uri: &str, body: Value) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+/// Registers a user with a password through the API and returns its API key.
+async fn register(router: &Router, email_address: &str, password: &str) -> String {
+    let response = send(
+        router,
+        json_request(
+            "POST",
+            "/v1/users/register",
+            json!({ "email_address": email_address, "password": password }),
+        ),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    read_json(response).await["api_key"]
+        .as_str()
+        .unwrap()
+        .to_string()
+}
+
+async fn password_grant(router: &Router, email_address: &str, password: &str) -> StatusCode {
+    let request = json_request(
+        "POST",
+        "/v1/auth/token",
+        json!({ "grant_type": "password", "email_address": email_address, "password": password }),
+    );
+
+    send(router, request).await.status()
+}
+
+#[tokio::test]
+async fn identity_provider_login_takes_unverified_account_away_from_its_registrant() {
+    let pool = connect_test_db().await;
+    let router = create_router(&pool, "").await;
+    let email_address = format!("{}@example.org", ApiKey::new().key);
+
+    // Someone registers the email address of somebody else, and holds on to the credentials.
+    let api_key = register(&router, &email_address, "attacker-password").await;
+
+    let response = send(
+        &router,
+        json_request(
+            "POST",
+            "/v1/auth/token",
+            json!({ "grant_type": "password", "email_address": email_address, "password": "attacker-password" }),
+        ),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let refresh_token = read_json(response).await["refresh_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    // The owner of the email address logs in with the identity provider.
+    let user_id = db::get_or_create_user_by_email(&pool, &email_address.to_uppercase())
+        .await
+        .unwrap();
+
+    let user = db::get_user_by_id(&pool, user_id).await.unwrap();
+    assert!(user.email_verified);
+    assert!(user.password_hash.is_none());
+
+    assert_eq!(
+        password_grant(&router, &email_address, "attacker-password").await,
+        StatusCode::UNAUTHORIZED
+    );
+
+    let response = send(
+        &router,
+        Request::get("/v1/users/me")
+            .header("X-Api-Key", &api_key)
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = send(
+        &router,
+        json_request(
+            "POST",
+            "/v1/auth/token",
+            json!({ "grant_type": "refresh_token", "refresh_token": refresh_token }),
+        ),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    db::delete_user(&pool, user_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn identity_provider_login_keeps_the_password_of_verified_account() {
+    let pool = connect_test_db().await;
+    let router = create_router(&pool, "").await;
+    let email_address = format!("{}@example.org", ApiKey::new().key);
+
+    register(&router, &email_address, "owner-password").await;
+    let user = db::get_user_by_email(&pool, &email_address).await.unwrap();
+    db::mark_email_verified(&pool, user.id, &email_address)
+        .await
+        .unwrap();
+
+    let user_id = db::get_or_create_user_by_email(&pool, &email_address)
+        .await
+        .unwrap();
+    assert_eq!(user_id, user.id);
+
+    assert_eq!(
+        password_grant(&router, &email_address, "owner-password").await,
+        StatusCode::OK
+    );
+
+    db::delete_user(&pool, user_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn changing_credentials_requires_the_current_password() {
+    let pool = connect_test_db().await;
+    let router = create_router(&pool, "").await;
+    let email_address = format!("{}@example.org", ApiKey::new().key);
+    let new_email_address = format!("{}@example.org", ApiKey::new().key);
+
+    let api_key = register(&router, &email_address, "owner-password").await;
+    let update = |body: Value| {
+        let mut request = json_request("PATCH", "/v1/users/me", body);
+        request
+            .headers_mut()
+            .insert("X-Api-Key", api_key.parse().unwrap());
+        request
+    };
+
+    for body in [
+        json!({ "password": "new-password" }),
+        json!({ "email_address": new_email_address, "password": "new-password" }),
+        json!({ "email_address": new_email_address, "current_password": "wrong-password" }),
+    ] {
+        let response = send(&router, update(body)).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    // Nothing was changed by the rejected requests.
+    let user = db::get_user_by_email(&pool, &email_address).await.unwrap();
+    assert_eq!(user.email_address, email_address);
+    assert_eq!(
+        password_grant(&router, &email_address, "owner-password").await,
+        StatusCode::OK
+    );
+
+    let response = send(
+        &router,
+        update(json!({
+            "email_address": new_email_address,
+            "password": "new-password",
+            "current_password": "owner-password"
+        })),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let user = db::get_user_by_id(&pool, user.id).await.unwrap();
+    assert_eq!(user.email_address, new_email_address);
+    assert!(!user.email_verified);
+    assert_eq!(
+        password_grant(&router, &new_email_address, "new-password").await,
+        StatusCode::OK
+    );
+
+    // Changes that don't touch the credentials don't need the password.
+    let response = send(&router, update(json!({ "time_zone": "Europe/Amsterdam" }))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    db::delete_user(&pool, user.id).await.unwrap();
+}
+
+/// Downloads the export of the user with the given API key.
+async fn export(router: &Router, api_key: &str) -> Value {
+    let response = send(
+        router,
+        Request::get("/v1/users/me/export")
+            .header("X-Api-Key", api_key)
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    read_json(response).await
+}
+
+#[tokio::test]
+async fn export_contains_the_data_of_every_feature() {
+    let pool = connect_test_db().await;
+    let router = create_router(&pool, "").await;
+    let email_address = format!("{}@example.org", ApiKey::new().key);
+    let api_key = register(&router, &email_address, "owner-password").await;
+    let user_id = db::get_user_by_email(&pool, &email_address)
+        .await
+        .unwrap()
+        .id;
+    let task_id = create_task(&pool, user_id).await;
+
+    let reminder = db::insert_reminder(
+        &pool,
+        user_id,
+        task_id,
+        Some(60),
+        None,
+        &[ReminderChannel::InApp],
+    )
+    .await
+    .unwrap();
+    db::insert_notification(
+        &pool,
+        user_id,
+        Some(task_id),
+        NotificationKind::TaskReminder,
+        "Reminder",
+        "The task is due in an hour.",
+        &format!("export-{}", task_id),
+    )
+    .await
+    .unwrap();
+
+    // The user shares their task with someone else, who shares a task of their own back.
+    let (other_id, other_email_address) = create_user(&pool).await;
+    let other_task_id = create_task(&pool, other_id).await;
+    db::insert_share(
+        &pool,
+        user_id,
+        SharedItem::Task(task_id),
+        &other_email_address,
+        Role::Viewer,
+    )
+    .await
+    .unwrap();
+    db::insert_share(
+        &pool,
+        other_id,
+        SharedItem::Task(other_task_id),
+        &email_address,
+        Role::Editor,
+    )
+    .await
+    .unwrap();
+    let project_id = db::insert_project(&pool, user_id, None, "Home".to_string())
+        .await
+        .unwrap();
+
+    // The user owns a workspace with a key, and is invited to the workspace of the other user.
+    let workspace_id = db::insert_workspace(&pool, user_id, "Team".to_string())
+        .await
+        .unwrap();
+    let workspace_key = ApiKey::new();
+    db::insert_workspace_key(
+        &pool,
+        workspace_id,
+        user_id,
+        "CI".to_string(),
+        workspace_key.hash.clone(),
+    )
+    .await
+    .unwrap();
+    let other_workspace_id = db::insert_workspace(&pool, other_id, "Other".to_string())
+        .await
+        .unwrap();
+    db::insert_workspace_invitation(
+        &pool,
+        other_workspace_id,
+        other_id,
+        &email_address,
+        WorkspaceRole::Member,
+    )
+    .await
+    .unwrap();
+
+    // The other user assigns the user to their shared task and mentions them in it, while the user watches it.
+    db::insert_task_assignee(&pool, other_id, other_task_id, user_id)
+        .await
+        .unwrap();
+    db::insert_task_watcher(&pool, other_task_id, user_id)
+        .await
+        .unwrap();
+    db::update_task(
+        &pool,
+        other_id,
+        other_task_id,
+        "test".to_string(),
+        format!("Can @{} look at this?", email_address),
+        false,
+        None,
+        false,
+    )
+    .await
+    .unwrap();
+
+    let comment = db::insert_comment(&pool, other_task_id, user_id, "First draft".to_string())
+        .await
+        .unwrap();
+    db::update_comment(
+        &pool,
+        other_task_id,
+        comment.id,
+        user_id,
+        "Second draft".to_string(),
+    )
+    .await
+    .unwrap();
+
+    let attachment = db::insert_attachment(
+        &pool,
+        other_task_id,
+        user_id,
+        "notes.txt",
+        "text/plain",
+        5,
+        &format!("export-{}", other_task_id),
+    )
+    .await
+    .unwrap();
+
+    let export = export(&router, &api_key).await;
+    assert_eq!(export["tasks"][0]["id"], task_id);
+    assert_eq!(export["tasks"][1]["id"], other_task_id);
+    assert_eq!(export["projects"][0]["id"], project_id);
+    assert_eq!(export["shares"].as_array().unwrap().len(), 2);
+    assert_eq!(export["workspaces"][0]["id"], workspace_id);
+    assert_eq!(export["workspaces"][0]["role"], "owner");
+    assert_eq!(export["workspace_keys"][0]["name"], "CI");
+    assert!(!export.to_string().contains(&workspace_key.hash));
+    assert_eq!(export["invitations"][0]["workspace_id"], other_workspace_id);
+    assert_eq!(export["assignments"][0]["task_id"], other_task_id);
+    assert_eq!(export["watches"][0]["task_id"], other_task_id);
+    assert_eq!(export["mentions"][0]["task_id"], other_task_id);
+    assert_eq!(export["comments"][0]["body"], "Second draft");
+    assert_eq!(export["comment_revisions"][0]["comment_id"], comment.id);
+    assert_eq!(export["comment_revisions"][0]["body"], "First draft");
+    assert_eq!(export["attachments"][0]["id"], attachment.id);
+    assert_eq!(export["attachments"][0]["file_name"], "notes.txt");
+    assert_eq!(export["reminders"][0]["id"], reminder.id);
+    assert_eq!(export["notifications"][0]["title"], "Reminder");
+
+    db::delete_user(&pool, user_id).await.unwrap();
+    db::delete_user(&pool, other_id).await.unwrap();
+}
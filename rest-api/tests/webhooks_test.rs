@@ -0,0 +1,278 @@
+//! This module contains a set of integration tests to verify that webhook deliveries are signed, retried and logged.
+//!
+//! The tests need the database, just like the tests in `integration_test.rs`. The webhooks point to a receiver that
+//! runs inside the test on a local port. You can run them on their own using the following command:
+//!
+//! ```sh
+//! cargo test --test webhooks_test
+//! ```
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    routing::post,
+    Router,
+};
+use common::{connect_test_db, create_user};
+use sqlx::PgPool;
+use todo_api::{
+    config::WebhookConfig,
+    db,
+    entity::{DeliveryStatus, TaskEventType, WebhookDelivery},
+    webhooks::{self, WebhookDispatcher},
+};
+use tokio::net::TcpListener;
+
+/// The dispatcher sends every delivery that is due, including the ones of other tests. We run the tests one at a
+/// time, so a test never records the outcome of a delivery that belongs to another test with its own settings.
+static SERIAL: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// The requests a receiver got, with their headers and body.
+type ReceivedRequests = Arc<Mutex<Vec<(HeaderMap, String)>>>;
+
+/// Starts a receiver that responds to every delivery with the given status code and returns its URL.
+async fn start_receiver(status: StatusCode) -> (String, ReceivedRequests) {
+    let received = ReceivedRequests::default();
+
+    let router =
+        Router::new()
+            .route(
+                "/hook",
+                post(
+                    move |State(received): State<ReceivedRequests>,
+                          headers: HeaderMap,
+                          body: Bytes| async move {
+                        let body = String::from_utf8(body.to_vec()).unwrap();
+                        received.lock().unwrap().push((headers, body));
+                        status
+                    },
+                ),
+            )
+            .with_state(received.clone());
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+    (format!("http://{}/hook", address), received)
+}
+
+fn create_dispatcher(attempts: u32) -> WebhookDispatcher {
+    WebhookDispatcher::new(WebhookConfig {
+        attempts,
+        delay: 60,
+        private: true,
+        ..Default::default()
+    })
+}
+
+/// Sends pending deliveries until the first delivery of the webhook was attempted, and returns that delivery.
+async fn deliver(
+    pool: &PgPool,
+    dispatcher: &WebhookDispatcher,
+    webhook_id: i32,
+) -> WebhookDelivery {
+    for _ in 0..50 {
+        dispatcher.deliver_pending(pool).await.unwrap();
+
+        let mut deliveries = db::list_webhook_deliveries(pool, webhook_id, 0, 10)
+            .await
+            .unwrap()
+            .items;
+
+        if deliveries.last().map(|delivery| delivery.attempts) > Some(0) {
+            return deliveries.pop().unwrap();
+        }
+    }
+
+    panic!("The delivery was never attempted.");
+}
+
+#[tokio::test]
+async fn published_event_is_delivered_with_signature() {
+    let _serial = SERIAL.lock().await;
+    let pool = connect_test_db().await;
+    let (user_id, _) = create_user(&pool).await;
+    let (url, received) = start_receiver(StatusCode::NO_CONTENT).await;
+
+    let secret = webhooks::generate_secret();
+    let webhook = db::insert_webhook(
+        &pool,
+        user_id,
+        url,
+        secret.clone(),
+        &[TaskEventType::TaskCreated],
+    )
+    .await
+    .unwrap();
+
+    webhooks::publish(
+        &pool,
+        user_id,
+        TaskEventType::TaskCreated,
+        &serde_json::json!({ "id": 1, "title": "test" }),
+    )
+    .await
+    .unwrap();
+
+    let delivery = deliver(&pool, &create_dispatcher(3), webhook.id).await;
+
+    assert_eq!(delivery.status, DeliveryStatus::Delivered);
+    assert_eq!(delivery.response_status, Some(204));
+
+    let (headers, body) = received.lock().unwrap()[0].clone();
+    let timestamp: i64 = headers["webhook-timestamp"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+
+    assert_eq!(headers["webhook-id"], delivery.event_id.as_str());
+    assert_eq!(
+        headers["webhook-signature"],
+        webhooks::sign(&secret, &delivery.event_id, timestamp, &body).as_str()
+    );
+
+    let payload: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(payload["type"], "task.created");
+    assert_eq!(payload["data"]["title"], "test");
+
+    db::delete_user(&pool, user_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn failed_delivery_is_retried_later() {
+    let _serial = SERIAL.lock().await;
+    let pool = connect_test_db().await;
+    let (user_id, _) = create_user(&pool).await;
+    let (url, _) = start_receiver(StatusCode::INTERNAL_SERVER_ERROR).await;
+
+    let webhook = db::insert_webhook(
+        &pool,
+        user_id,
+        url,
+        webhooks::generate_secret(),
+        &[TaskEventType::TaskDeleted],
+    )
+    .await
+    .unwrap();
+
+    webhooks::publish(
+        &pool,
+        user_id,
+        TaskEventType::TaskDeleted,
+        &serde_json::json!({ "id": 1 }),
+    )
+    .await
+    .unwrap();
+
+    let delivery = deliver(&pool, &create_dispatcher(3), webhook.id).await;
+
+    assert_eq!(delivery.status, DeliveryStatus::Pending);
+    assert_eq!(delivery.attempts, 1);
+    assert_eq!(delivery.response_status, Some(500));
+    assert!(
+        delivery.next_attempt_at > chrono::Utc::now().naive_utc() + chrono::Duration::seconds(30)
+    );
+
+    db::delete_user(&pool, user_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn delivery_fails_after_last_attempt_and_can_be_redelivered() {
+    let _serial = SERIAL.lock().await;
+    let pool = connect_test_db().await;
+    let (user_id, _) = create_user(&pool).await;
+    let (url, _) = start_receiver(StatusCode::BAD_GATEWAY).await;
+
+    let webhook = db::insert_webhook(
+        &pool,
+        user_id,
+        url,
+        webhooks::generate_secret(),
+        &[TaskEventType::TaskCompleted],
+    )
+    .await
+    .unwrap();
+
+    webhooks::publish(
+        &pool,
+        user_id,
+        TaskEventType::TaskCompleted,
+        &serde_json::json!({ "id": 1 }),
+    )
+    .await
+    .unwrap();
+
+    let delivery = deliver(&pool, &create_dispatcher(1), webhook.id).await;
+    assert_eq!(delivery.status, DeliveryStatus::Failed);
+
+    let redelivery = db::redeliver_webhook_delivery(&pool, webhook.id, delivery.id)
+        .await
+        .unwrap();
+
+    assert_eq!(redelivery.status, DeliveryStatus::Pending);
+    assert_eq!(redelivery.event_id, delivery.event_id);
+    assert_eq!(redelivery.redelivery_of, Some(delivery.id));
+
+    db::delete_user(&pool, user_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn events_are_only_queued_for_active_subscribed_webhooks() {
+    let _serial = SERIAL.lock().await;
+    let pool = connect_test_db().await;
+    let (user_id, _) = create_user(&pool).await;
+    let payload = serde_json::json!({ "id": 1 });
+
+    let webhook = db::insert_webhook(
+        &pool,
+        user_id,
+        "https://hooks.example.org/tasks".to_string(),
+        webhooks::generate_secret(),
+        &[TaskEventType::TaskCreated],
+    )
+    .await
+    .unwrap();
+
+    let queued = db::insert_webhook_deliveries(
+        &pool,
+        user_id,
+        "evt_1",
+        TaskEventType::TaskDeleted,
+        &payload,
+    )
+    .await
+    .unwrap();
+    assert_eq!(queued, 0);
+
+    db::update_webhook(
+        &pool,
+        user_id,
+        webhook.id,
+        webhook.url,
+        &webhook.events,
+        false,
+    )
+    .await
+    .unwrap();
+
+    let queued = db::insert_webhook_deliveries(
+        &pool,
+        user_id,
+        "evt_2",
+        TaskEventType::TaskCreated,
+        &payload,
+    )
+    .await
+    .unwrap();
+    assert_eq!(queued, 0);
+
+    db::delete_user(&pool, user_id).await.unwrap();
+}
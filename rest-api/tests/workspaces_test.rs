@@ -0,0 +1,364 @@
+//! This module contains a set of integration tests to verify that workspaces give their members access to the tasks in
+//! them, that the roles of the members are enforced, and that row-level security backs up the queries.
+//!
+//! The tests need the database, just like the tests in `integration_test.rs`. You can run them on their own using the
+//! following command:
+//!
+//! ```sh
+//! cargo test --test workspaces_test
+//! ```
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+};
+use common::{
+    connect_test_db, create_router, create_task, create_user, create_verified_user,
+    create_workspace_task, send,
+};
+use sqlx::Connection;
+use todo_api::{
+    access, db,
+    entity::{ApiKey, Role, WorkspaceRole},
+    error::AppError,
+};
+
+#[tokio::test]
+async fn invited_members_work_on_the_tasks_of_the_workspace() {
+    let pool = connect_test_db().await;
+    let (owner_id, _) = create_user(&pool).await;
+    let (user_id, email_address) = create_user(&pool).await;
+
+    let workspace_id = db::insert_workspace(&pool, owner_id, "Team".to_string())
+        .await
+        .unwrap();
+    let task_id = create_workspace_task(&pool, owner_id, workspace_id).await;
+
+    assert!(matches!(
+        access::task_access(&pool, user_id, None, task_id, Role::Viewer).await,
+        Err(AppError::TaskNotFound)
+    ));
+
+    let invitation = db::insert_workspace_invitation(
+        &pool,
+        workspace_id,
+        owner_id,
+        &email_address.to_uppercase(),
+        WorkspaceRole::Member,
+    )
+    .await
+    .unwrap();
+
+    let invitations = db::list_user_invitations(&pool, user_id).await.unwrap();
+    assert_eq!(invitations.len(), 1);
+    assert_eq!(invitations[0].workspace_name, "Team");
+
+    let joined = db::accept_workspace_invitation(&pool, user_id, invitation.id)
+        .await
+        .unwrap();
+    assert_eq!(joined, workspace_id);
+    assert!(db::list_user_invitations(&pool, user_id)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Members edit the tasks of the workspace, but only admins and owners remove them.
+    let access = access::task_access(&pool, user_id, None, task_id, Role::Editor)
+        .await
+        .unwrap();
+    assert_eq!(access.owner_id, owner_id);
+    assert!(matches!(
+        access::task_access(&pool, user_id, None, task_id, Role::Owner).await,
+        Err(AppError::AccessDenied)
+    ));
+
+    let tasks = db::list_tasks(&pool, user_id, Some(workspace_id), false, 0, 10)
+        .await
+        .unwrap();
+    assert_eq!(tasks.total_count, 1);
+    assert_eq!(tasks.items[0].id, task_id);
+
+    assert!(matches!(
+        db::insert_workspace_invitation(
+            &pool,
+            workspace_id,
+            owner_id,
+            &email_address,
+            WorkspaceRole::Admin
+        )
+        .await,
+        Err(AppError::AlreadyMember)
+    ));
+
+    db::update_workspace_member(&pool, workspace_id, user_id, WorkspaceRole::Admin)
+        .await
+        .unwrap();
+    assert!(
+        access::task_access(&pool, user_id, None, task_id, Role::Owner)
+            .await
+            .is_ok()
+    );
+
+    // Leaving the workspace takes the access away again.
+    db::delete_workspace_member(&pool, workspace_id, user_id)
+        .await
+        .unwrap();
+    assert!(matches!(
+        access::task_access(&pool, user_id, None, task_id, Role::Viewer).await,
+        Err(AppError::TaskNotFound)
+    ));
+
+    db::delete_workspace(&pool, workspace_id).await.unwrap();
+    db::delete_user(&pool, owner_id).await.unwrap();
+    db::delete_user(&pool, user_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn workspace_keeps_an_owner() {
+    let pool = connect_test_db().await;
+    let (owner_id, _) = create_user(&pool).await;
+
+    let workspace_id = db::insert_workspace(&pool, owner_id, "Team".to_string())
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        db::update_workspace_member(&pool, workspace_id, owner_id, WorkspaceRole::Admin).await,
+        Err(AppError::LastWorkspaceOwner)
+    ));
+    assert!(matches!(
+        db::delete_workspace_member(&pool, workspace_id, owner_id).await,
+        Err(AppError::LastWorkspaceOwner)
+    ));
+    assert_eq!(
+        db::find_workspace_role(&pool, owner_id, workspace_id)
+            .await
+            .unwrap(),
+        Some(WorkspaceRole::Owner)
+    );
+
+    db::delete_workspace(&pool, workspace_id).await.unwrap();
+    db::delete_user(&pool, owner_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn workspace_key_only_reaches_its_workspace() {
+    let pool = connect_test_db().await;
+    let (user_id, _) = create_user(&pool).await;
+
+    let workspace_id = db::insert_workspace(&pool, user_id, "Team".to_string())
+        .await
+        .unwrap();
+    let workspace_task_id = create_workspace_task(&pool, user_id, workspace_id).await;
+    let personal_task_id = create_task(&pool, user_id).await;
+
+    let api_key = ApiKey::new();
+    db::insert_workspace_key(
+        &pool,
+        workspace_id,
+        user_id,
+        "CI".to_string(),
+        api_key.hash.clone(),
+    )
+    .await
+    .unwrap();
+
+    let key = db::get_workspace_key(&pool, &api_key.hash).await.unwrap();
+    assert_eq!(key.user_id, user_id);
+    assert_eq!(key.workspace_id, workspace_id);
+
+    let scope = Some(key.workspace_id);
+    assert!(
+        access::task_access(&pool, user_id, scope, workspace_task_id, Role::Owner)
+            .await
+            .is_ok()
+    );
+    assert!(matches!(
+        access::task_access(&pool, user_id, scope, personal_task_id, Role::Viewer).await,
+        Err(AppError::TaskNotFound)
+    ));
+
+    db::delete_workspace(&pool, workspace_id).await.unwrap();
+    db::delete_user(&pool, user_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn row_level_security_hides_the_tasks_of_other_users() {
+    let pool = connect_test_db().await;
+    let (user_id, _) = create_user(&pool).await;
+    let (other_id, _) = create_user(&pool).await;
+
+    let task_id = create_task(&pool, user_id).await;
+    let other_task_id = create_task(&pool, other_id).await;
+
+    // The test user is a superuser, which isn't subject to the policies. The role only exists within the transaction.
+    let mut connection = pool.acquire().await.unwrap();
+    let mut transaction = connection.begin().await.unwrap();
+
+    sqlx::query("CREATE ROLE todo_api_rls_test NOLOGIN")
+        .execute(&mut *transaction)
+        .await
+        .unwrap();
+    sqlx::query(
+        "GRANT SELECT ON tasks, projects, shares, workspace_members, workspaces TO todo_api_rls_test",
+    )
+    .execute(&mut *transaction)
+    .await
+    .unwrap();
+    sqlx::query("SET LOCAL ROLE todo_api_rls_test")
+        .execute(&mut *transaction)
+        .await
+        .unwrap();
+    sqlx::query("SELECT set_config('app.user_id', $1, true)")
+        .bind(user_id.to_string())
+        .execute(&mut *transaction)
+        .await
+        .unwrap();
+
+    let visible: Vec<i32> =
+        sqlx::query_scalar("SELECT id FROM tasks WHERE id = ANY($1) ORDER BY id")
+            .bind(vec![task_id, other_task_id])
+            .fetch_all(&mut *transaction)
+            .await
+            .unwrap();
+    assert_eq!(visible, vec![task_id]);
+
+    transaction.rollback().await.unwrap();
+    drop(connection);
+
+    db::delete_user(&pool, user_id).await.unwrap();
+    db::delete_user(&pool, other_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn last_owner_stays_while_workspace_has_other_members() {
+    let pool = connect_test_db().await;
+    let (owner_id, _) = create_user(&pool).await;
+    let (member_id, email_address) = create_user(&pool).await;
+
+    let workspace_id = db::insert_workspace(&pool, owner_id, "Team".to_string())
+        .await
+        .unwrap();
+    let solo_workspace_id = db::insert_workspace(&pool, owner_id, "Solo".to_string())
+        .await
+        .unwrap();
+    let invitation = db::insert_workspace_invitation(
+        &pool,
+        workspace_id,
+        owner_id,
+        &email_address,
+        WorkspaceRole::Member,
+    )
+    .await
+    .unwrap();
+    db::accept_workspace_invitation(&pool, member_id, invitation.id)
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        db::delete_user(&pool, owner_id).await,
+        Err(AppError::LastWorkspaceOwner)
+    ));
+    assert!(db::get_user_by_id(&pool, owner_id).await.is_ok());
+
+    // Once someone else owns the workspace, the user can go, and takes the workspace only they were in with them.
+    db::update_workspace_member(&pool, workspace_id, member_id, WorkspaceRole::Owner)
+        .await
+        .unwrap();
+    db::delete_user(&pool, owner_id).await.unwrap();
+
+    assert_eq!(
+        db::find_workspace_role(&pool, member_id, workspace_id)
+            .await
+            .unwrap(),
+        Some(WorkspaceRole::Owner)
+    );
+    assert!(matches!(
+        db::delete_workspace(&pool, solo_workspace_id).await,
+        Err(AppError::WorkspaceNotFound)
+    ));
+
+    db::delete_workspace(&pool, workspace_id).await.unwrap();
+    db::delete_user(&pool, member_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn workspace_key_is_refused_outside_of_its_workspace() {
+    let pool = connect_test_db().await;
+    let router = create_router(&pool, "[ratelimit.users]\nrate = 100\nburst = 100\n").await;
+    let (user_id, personal_key) = create_verified_user(&pool).await;
+
+    let workspace_id = db::insert_workspace(&pool, user_id, "Team".to_string())
+        .await
+        .unwrap();
+    let workspace_key = ApiKey::new();
+    db::insert_workspace_key(
+        &pool,
+        workspace_id,
+        user_id,
+        "CI".to_string(),
+        workspace_key.hash.clone(),
+    )
+    .await
+    .unwrap();
+
+    let routes = [
+        ("GET", "/v1/users/me", ""),
+        ("PATCH", "/v1/users/me", "{}"),
+        ("DELETE", "/v1/users/me", ""),
+        ("GET", "/v1/users/me/export", ""),
+        ("GET", "/v1/webhooks", ""),
+        (
+            "POST",
+            "/v1/webhooks",
+            "{\"url\": \"https://hooks.example.org\", \"events\": [\"task.created\"]}",
+        ),
+        ("GET", "/v1/webhooks/1", ""),
+        ("PATCH", "/v1/webhooks/1", "{}"),
+        ("DELETE", "/v1/webhooks/1", ""),
+        ("GET", "/v1/webhooks/1/deliveries?page=0", ""),
+        ("POST", "/v1/webhooks/1/deliveries/1/redeliver", ""),
+        ("GET", "/v1/notifications", ""),
+        ("PATCH", "/v1/notifications/1", "{\"read\": true}"),
+        ("POST", "/v1/notifications/read", ""),
+        ("POST", "/v1/workspaces", "{\"name\": \"Other\"}"),
+        ("GET", "/v1/invitations", ""),
+        ("POST", "/v1/invitations/1/accept", ""),
+        ("DELETE", "/v1/invitations/1", ""),
+    ];
+
+    for (method, uri, body) in routes {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("X-Api-Key", &workspace_key.key)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .unwrap();
+
+        let response = send(&router, request).await;
+        assert_eq!(
+            response.status(),
+            StatusCode::FORBIDDEN,
+            "{} {}",
+            method,
+            uri
+        );
+    }
+
+    // The account is still there, and the personal API key of the user can reach it.
+    let response = send(
+        &router,
+        Request::get("/v1/users/me")
+            .header("X-Api-Key", &personal_key)
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    db::delete_workspace(&pool, workspace_id).await.unwrap();
+    db::delete_user(&pool, user_id).await.unwrap();
+}